@@ -11,7 +11,7 @@ interface SocketState {
 }
 
 interface ClientEvent {
-  type: "subscribe" | "unsubscribe" | "message" | "typing";
+  type: "subscribe" | "unsubscribe" | "message" | "typing" | "save_draft" | "clear_draft";
   chat_id?: string;
   content?: string;
   is_typing?: boolean;
@@ -19,7 +19,17 @@ interface ClientEvent {
 }
 
 interface ServerEvent {
-  type: "hello" | "subscribed" | "unsubscribed" | "message" | "typing" | "error";
+  type:
+    | "hello"
+    | "subscribed"
+    | "unsubscribed"
+    | "message"
+    | "typing"
+    | "draft_saved"
+    | "draft_cleared"
+    | "read_receipt"
+    | "error";
+  event_id: string;
   version?: string;
   chat_id?: string;
   message_id?: string;
@@ -27,6 +37,9 @@ interface ServerEvent {
   content?: string;
   model?: string;
   timestamp?: string;
+  updated_at?: string;
+  last_read_message_id?: string;
+  read_at?: string;
   is_typing?: boolean;
   message?: string;
 }
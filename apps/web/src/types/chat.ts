@@ -17,6 +17,7 @@ export interface Message {
   message_type?: "text" | "system" | "file";
   thread_id?: string;
   reply_to_id?: string;
+  version?: number;
   created_at?: string;
   updated_at?: string;
   pending?: boolean;
@@ -28,6 +29,7 @@ export interface Chat {
   title: string;
   chat_type: "direct" | "group" | "system";
   messages?: Message[];
+  version?: number;
   created_at: string;
   updated_at: string;
   folderId?: string;
@@ -90,6 +92,12 @@ export interface MessageEdit {
   old_content: string;
   new_content: string;
   edited_at: string;
+  diff?: DiffSegment[];
+}
+
+export interface DiffSegment {
+  op: "equal" | "insert" | "delete";
+  text: string;
 }
 
 export interface MessageDeletion {
@@ -0,0 +1,239 @@
+//! Long-lived API keys for programmatic access, limited by scopes.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{sqlite::SqliteRow, Row};
+use tracing::info;
+
+use crate::{new_public_id, AuthError, AuthSession, Authenticator, User};
+
+/// Every API key starts with this, which is how they are told apart from session
+/// tokens.
+pub const API_KEY_PREFIX: &str = "sbk_";
+
+// Characters of the key kept in the clear for display, prefix included
+const DISPLAY_PREFIX_CHARS: usize = 12;
+
+/// What an API key may do, as `<resource>:<access>`. A `write` scope does not
+/// imply the matching `read` scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ApiKeyScope {
+    #[serde(rename = "chats:read")]
+    ChatsRead,
+    #[serde(rename = "chats:write")]
+    ChatsWrite,
+    #[serde(rename = "messages:read")]
+    MessagesRead,
+    #[serde(rename = "messages:write")]
+    MessagesWrite,
+    #[serde(rename = "folders:read")]
+    FoldersRead,
+    #[serde(rename = "folders:write")]
+    FoldersWrite,
+    #[serde(rename = "notifications:read")]
+    NotificationsRead,
+    #[serde(rename = "notifications:write")]
+    NotificationsWrite,
+    #[serde(rename = "permissions:read")]
+    PermissionsRead,
+    #[serde(rename = "permissions:write")]
+    PermissionsWrite,
+    #[serde(rename = "models:read")]
+    ModelsRead,
+}
+
+impl ApiKeyScope {
+    pub const ALL: [ApiKeyScope; 11] = [
+        ApiKeyScope::ChatsRead,
+        ApiKeyScope::ChatsWrite,
+        ApiKeyScope::MessagesRead,
+        ApiKeyScope::MessagesWrite,
+        ApiKeyScope::FoldersRead,
+        ApiKeyScope::FoldersWrite,
+        ApiKeyScope::NotificationsRead,
+        ApiKeyScope::NotificationsWrite,
+        ApiKeyScope::PermissionsRead,
+        ApiKeyScope::PermissionsWrite,
+        ApiKeyScope::ModelsRead,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiKeyScope::ChatsRead => "chats:read",
+            ApiKeyScope::ChatsWrite => "chats:write",
+            ApiKeyScope::MessagesRead => "messages:read",
+            ApiKeyScope::MessagesWrite => "messages:write",
+            ApiKeyScope::FoldersRead => "folders:read",
+            ApiKeyScope::FoldersWrite => "folders:write",
+            ApiKeyScope::NotificationsRead => "notifications:read",
+            ApiKeyScope::NotificationsWrite => "notifications:write",
+            ApiKeyScope::PermissionsRead => "permissions:read",
+            ApiKeyScope::PermissionsWrite => "permissions:write",
+            ApiKeyScope::ModelsRead => "models:read",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scope| scope.as_str() == value)
+    }
+}
+
+/// An API key without its secret, safe to show to its owner.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKeySummary {
+    pub public_id: String,
+    pub name: String,
+    /// The start of the key, e.g. `sbk_Xk3f9aQ2`.
+    pub key_prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl Authenticator {
+    /// Create a key for `user_id`. Returns the stored key and the plaintext, which is
+    /// not kept and cannot be shown again.
+    pub async fn create_api_key(
+        &self,
+        user_id: i64,
+        name: &str,
+        scopes: &[ApiKeyScope],
+    ) -> Result<(ApiKeySummary, String), AuthError> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let key = format!("{API_KEY_PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes));
+
+        let mut scopes = scopes.to_vec();
+        scopes.sort_by_key(|scope| scope.as_str());
+        scopes.dedup();
+
+        let summary = ApiKeySummary {
+            public_id: new_public_id(),
+            name: name.to_owned(),
+            key_prefix: key.chars().take(DISPLAY_PREFIX_CHARS).collect(),
+            scopes,
+            created_at: Utc::now(),
+            last_used_at: None,
+        };
+        sqlx::query(
+            "INSERT INTO api_keys (public_id, user_id, name, key_hash, key_prefix, scopes, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&summary.public_id)
+        .bind(user_id)
+        .bind(&summary.name)
+        .bind(hash_api_key(&key))
+        .bind(&summary.key_prefix)
+        .bind(join_scopes(&summary.scopes))
+        .bind(summary.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        info!(user_id, key = %summary.public_id, "created api key");
+        Ok((summary, key))
+    }
+
+    /// The user's keys that have not been revoked, oldest first.
+    pub async fn list_api_keys(&self, user_id: i64) -> Result<Vec<ApiKeySummary>, AuthError> {
+        let rows = sqlx::query(
+            "SELECT public_id, name, key_prefix, scopes, created_at, last_used_at FROM api_keys WHERE user_id = ? AND revoked_at IS NULL ORDER BY created_at, id",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(api_key_summary).collect()
+    }
+
+    /// Revoke one of the user's keys. Requests made with it fail from then on.
+    pub async fn revoke_api_key(&self, user_id: i64, public_id: &str) -> Result<(), AuthError> {
+        let result = sqlx::query(
+            "UPDATE api_keys SET revoked_at = ? WHERE public_id = ? AND user_id = ? AND revoked_at IS NULL",
+        )
+        .bind(Utc::now().to_rfc3339())
+        .bind(public_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AuthError::ApiKeyNotFound);
+        }
+
+        info!(user_id, key = %public_id, "revoked api key");
+        Ok(())
+    }
+
+    pub(crate) async fn authenticate_api_key(
+        &self,
+        key: &str,
+    ) -> Result<(User, AuthSession), AuthError> {
+        let row = sqlx::query(
+            "SELECT id, user_id, scopes FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL",
+        )
+        .bind(hash_api_key(key))
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(AuthError::InvalidApiKey)?;
+
+        let id: i64 = row.try_get("id")?;
+        let user_id: i64 = row.try_get("user_id")?;
+        let scopes: String = row.try_get("scopes")?;
+
+        sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE id = ?")
+            .bind(Utc::now().to_rfc3339())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        let user = self.fetch_user(user_id).await?;
+        let session = AuthSession {
+            token: key.to_owned(),
+            user_id,
+            // Keys do not expire; they stay valid until revoked
+            expires_at: DateTime::<Utc>::MAX_UTC,
+            scopes: Some(split_scopes(&scopes)),
+        };
+        Ok((user, session))
+    }
+}
+
+fn hash_api_key(key: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(key.as_bytes()))
+}
+
+fn join_scopes(scopes: &[ApiKeyScope]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Unknown entries are skipped so a scope removed later cannot be used
+fn split_scopes(scopes: &str) -> Vec<ApiKeyScope> {
+    scopes.split(',').filter_map(ApiKeyScope::parse).collect()
+}
+
+fn api_key_summary(row: &SqliteRow) -> Result<ApiKeySummary, AuthError> {
+    let scopes: String = row.try_get("scopes")?;
+    let created_at: String = row.try_get("created_at")?;
+    let last_used_at: Option<String> = row.try_get("last_used_at")?;
+
+    Ok(ApiKeySummary {
+        public_id: row.try_get("public_id")?,
+        name: row.try_get("name")?,
+        key_prefix: row.try_get("key_prefix")?,
+        scopes: split_scopes(&scopes),
+        created_at: parse_timestamp(&created_at)?,
+        last_used_at: last_used_at.as_deref().map(parse_timestamp).transpose()?,
+    })
+}
+
+pub(crate) fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, sqlx::Error> {
+    Ok(DateTime::parse_from_rfc3339(value)
+        .map_err(|error| sqlx::Error::Decode(error.into()))?
+        .with_timezone(&Utc))
+}
@@ -0,0 +1,90 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserEventType {
+    Created,
+    LoggedIn,
+    LoginFailed,
+}
+
+impl UserEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserEventType::Created => "created",
+            UserEventType::LoggedIn => "logged_in",
+            UserEventType::LoginFailed => "login_failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserEvent {
+    pub event_type: UserEventType,
+    /// Unknown for failed logins against an email with no account.
+    pub user_id: Option<i64>,
+    /// Identity provider involved, e.g. `password` or `github`.
+    pub provider: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl UserEvent {
+    pub fn new(event_type: UserEventType, user_id: Option<i64>, provider: &str) -> Self {
+        Self {
+            event_type,
+            user_id,
+            provider: provider.to_owned(),
+            occurred_at: Utc::now(),
+        }
+    }
+}
+
+/// Receives user lifecycle events from the [`Authenticator`](crate::Authenticator).
+///
+/// `publish` is called inline on the request path, so implementations must not block:
+/// anything slow (I/O, database writes) belongs on a channel or spawned task.
+pub trait UserEventSink: Send + Sync {
+    fn publish(&self, event: UserEvent);
+}
+
+/// Default sink: writes each event to the tracing log.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingUserEventSink;
+
+impl UserEventSink for TracingUserEventSink {
+    fn publish(&self, event: UserEvent) {
+        info!(
+            event = event.event_type.as_str(),
+            user_id = ?event.user_id,
+            provider = %event.provider,
+            "user event"
+        );
+    }
+}
+
+/// Keeps every event in memory; intended for tests.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryUserEventSink {
+    events: Arc<Mutex<Vec<UserEvent>>>,
+}
+
+impl MemoryUserEventSink {
+    pub fn events(&self) -> Vec<UserEvent> {
+        self.events
+            .lock()
+            .map(|events| events.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl UserEventSink for MemoryUserEventSink {
+    fn publish(&self, event: UserEvent) {
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event);
+        }
+    }
+}
@@ -2,13 +2,14 @@ use anyhow::Context;
 use argon2::password_hash::{
     rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
 };
-use argon2::Argon2;
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use base64::Engine as _;
 use chrono::{DateTime, Duration, Utc};
 use cuid2::CuidConstructor;
 use oauth2::basic::BasicClient;
 use oauth2::reqwest::async_http_client;
+use oauth2::url::Url;
 use oauth2::{
     AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, RedirectUrl, Scope,
     TokenResponse, TokenUrl,
@@ -18,11 +19,28 @@ use rand::RngCore;
 use reqwest::header::ACCEPT;
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, SqlitePool, Transaction};
-use switchboard_config::{AuthConfig, GithubAuthConfig};
+use std::sync::Arc;
+use switchboard_config::{
+    Argon2Config, AuthConfig, GithubAuthConfig, GoogleAuthConfig, OutboundHttpConfig,
+    USER_AGENT,
+};
 use thiserror::Error;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+mod api_keys;
+mod events;
+mod profile;
+mod sessions;
+
+pub use api_keys::{ApiKeyScope, ApiKeySummary, API_KEY_PREFIX};
+pub use events::{
+    MemoryUserEventSink, TracingUserEventSink, UserEvent, UserEventSink, UserEventType,
+};
+pub use profile::{sanitize_display_name, MAX_DISPLAY_NAME_CHARS};
+pub use sessions::SessionSummary;
 
 const GITHUB_USER_API: &str = "https://api.github.com/user";
+const GOOGLE_USERINFO_API: &str = "https://openidconnect.googleapis.com/v1/userinfo";
 
 static CUID: Lazy<CuidConstructor> = Lazy::new(CuidConstructor::new);
 
@@ -30,7 +48,14 @@ static CUID: Lazy<CuidConstructor> = Lazy::new(CuidConstructor::new);
 pub struct Authenticator {
     pool: SqlitePool,
     session_ttl: Duration,
+    idle_timeout: Option<Duration>,
+    sliding_expiration: bool,
+    allowed_redirect_uris: Vec<String>,
+    password_pepper: Option<String>,
+    argon2_config: Argon2Config,
     github: Option<GithubOAuth>,
+    google: Option<GoogleOAuth>,
+    events: Arc<dyn UserEventSink>,
 }
 
 #[derive(Debug, Error)]
@@ -41,8 +66,14 @@ pub enum AuthError {
     InvalidCredentials,
     #[error("github oauth is not configured")]
     GithubOauthDisabled,
+    #[error("redirect uri is not allowed: {0}")]
+    RedirectUriNotAllowed(String),
     #[error("github oauth error: {0}")]
     GithubOauth(#[from] anyhow::Error),
+    #[error("google oauth is not configured")]
+    GoogleOauthDisabled,
+    #[error("google oauth error: {0}")]
+    GoogleOauth(anyhow::Error),
     #[error("database error: {0}")]
     Database(#[from] sqlx::Error),
     #[error("password hashing failed: {0}")]
@@ -53,6 +84,18 @@ pub enum AuthError {
     SessionExpired,
     #[error("invalid session token")]
     InvalidSession,
+    #[error("this {0} account is already linked to another user")]
+    IdentityAlreadyLinked(&'static str),
+    #[error("no {0} identity is linked to this account")]
+    IdentityNotLinked(String),
+    #[error("cannot remove the only way to sign in to this account")]
+    LastLoginMethod,
+    #[error("api key not found")]
+    ApiKeyNotFound,
+    #[error("invalid or revoked api key")]
+    InvalidApiKey,
+    #[error("user not found")]
+    UserNotFound,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -69,10 +112,32 @@ pub struct AuthSession {
     pub token: String,
     pub user_id: i64,
     pub expires_at: DateTime<Utc>,
+    /// What the request may do when it was made with an API key; `None` for a login
+    /// session, which is unrestricted.
+    pub scopes: Option<Vec<ApiKeyScope>>,
+}
+
+impl AuthSession {
+    /// Whether the request may do what `scope` covers.
+    pub fn allows(&self, scope: ApiKeyScope) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.contains(&scope),
+            None => true,
+        }
+    }
 }
 
+/// A login method linked to a user. Carries no credentials, so it is safe to show
+/// to the user it belongs to.
+#[derive(Debug, Clone, Serialize)]
+pub struct IdentitySummary {
+    pub provider: String,
+    pub linked_at: DateTime<Utc>,
+}
+
+/// The account a user signed in with at an OAuth provider.
 #[derive(Debug, Clone)]
-pub struct GithubProfile {
+pub struct OAuthProfile {
     pub id: String,
     pub email: Option<String>,
     pub name: Option<String>,
@@ -81,15 +146,47 @@ pub struct GithubProfile {
 impl Authenticator {
     pub fn new(pool: SqlitePool, config: AuthConfig) -> Self {
         let session_ttl = Duration::seconds(config.session_ttl_seconds as i64);
-        let github = GithubOAuth::from_config(&config.github);
+        let idle_timeout = (config.idle_timeout_seconds > 0)
+            .then(|| Duration::seconds(config.idle_timeout_seconds as i64));
+        let github = GithubOAuth::from_config(&config.github, &OutboundHttpConfig::default());
+        let google = GoogleOAuth::from_config(&config.google, &OutboundHttpConfig::default());
+        // Deployments from before the allowlist existed have none configured, which
+        // silently turns their OAuth login off
+        if (github.is_some() || google.is_some()) && config.allowed_redirect_uris.is_empty() {
+            warn!(
+                "OAuth login is configured but auth.allowed_redirect_uris is empty, so every \
+                 login will be rejected; list the frontend's callback URLs there"
+            );
+        }
 
         Self {
             pool,
             session_ttl,
+            idle_timeout,
+            sliding_expiration: config.sliding_expiration,
+            allowed_redirect_uris: config.allowed_redirect_uris,
+            password_pepper: config.password_pepper,
+            argon2_config: config.argon2,
             github,
+            google,
+            events: Arc::new(TracingUserEventSink),
         }
     }
 
+    /// Replace the default tracing sink for user lifecycle events.
+    pub fn with_event_sink(mut self, sink: Arc<dyn UserEventSink>) -> Self {
+        self.events = sink;
+        self
+    }
+
+    /// Apply connect and request timeouts to the calls made to GitHub and Google during
+    /// login.
+    pub fn with_outbound_http(mut self, outbound: &OutboundHttpConfig) -> Self {
+        self.github = self.github.map(|github| github.with_outbound_http(outbound));
+        self.google = self.google.map(|google| google.with_outbound_http(outbound));
+        self
+    }
+
     pub fn pool(&self) -> SqlitePool {
         self.pool.clone()
     }
@@ -104,11 +201,44 @@ impl Authenticator {
         redirect_uri: &str,
     ) -> Result<String, AuthError> {
         let github = self.github.as_ref().ok_or(AuthError::GithubOauthDisabled)?;
+        self.ensure_redirect_uri_allowed(redirect_uri)?;
         github
             .authorize_url(state, redirect_uri)
             .map_err(AuthError::GithubOauth)
     }
 
+    pub fn google_enabled(&self) -> bool {
+        self.google.is_some()
+    }
+
+    pub fn google_authorization_url(
+        &self,
+        state: &str,
+        redirect_uri: &str,
+    ) -> Result<String, AuthError> {
+        let google = self.google.as_ref().ok_or(AuthError::GoogleOauthDisabled)?;
+        self.ensure_redirect_uri_allowed(redirect_uri)?;
+        google
+            .authorize_url(state, redirect_uri)
+            .map_err(AuthError::GoogleOauth)
+    }
+
+    /// Reject redirect URIs missing from `allowed_redirect_uris`, so the OAuth flow can't
+    /// be used to bounce a code or token to an attacker-controlled address.
+    pub fn ensure_redirect_uri_allowed(&self, redirect_uri: &str) -> Result<(), AuthError> {
+        let allowed = Url::parse(redirect_uri).is_ok_and(|candidate| {
+            self.allowed_redirect_uris
+                .iter()
+                .any(|pattern| redirect_uri_matches(pattern, &candidate))
+        });
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(AuthError::RedirectUriNotAllowed(redirect_uri.to_owned()))
+        }
+    }
+
     pub async fn register_with_password(
         &self,
         email: &str,
@@ -146,6 +276,7 @@ impl Authenticator {
 
         tx.commit().await?;
 
+        self.emit(UserEventType::Created, Some(user.id), "password");
         Ok(user)
     }
 
@@ -162,19 +293,34 @@ impl Authenticator {
         .await?;
 
         let Some(row) = identity else {
+            self.emit(UserEventType::LoginFailed, None, "password");
             return Err(AuthError::InvalidCredentials);
         };
 
+        let user_id: i64 = row.try_get("user_id")?;
         let secret: String = row.try_get("secret")?;
         let stored_hash = PasswordHash::new(&secret)?;
-        Argon2::default()
+        if self
+            .argon2()?
             .verify_password(password.as_bytes(), &stored_hash)
-            .map_err(|_| AuthError::InvalidCredentials)?;
+            .is_err()
+        {
+            self.emit(UserEventType::LoginFailed, Some(user_id), "password");
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        if self.needs_rehash(&stored_hash) {
+            // The login already succeeded; a failed upgrade is retried on the next one
+            if let Err(error) = self.rehash_password(email, password).await {
+                warn!(user_id, error = %error, "failed to rehash password");
+            }
+        }
 
-        let user_id: i64 = row.try_get("user_id")?;
         self.fetch_user(user_id).await?;
 
-        self.issue_session(user_id).await
+        let session = self.issue_session(user_id).await?;
+        self.emit(UserEventType::LoggedIn, Some(user_id), "password");
+        Ok(session)
     }
 
     pub async fn login_with_github_code(
@@ -183,6 +329,7 @@ impl Authenticator {
         redirect_uri: &str,
     ) -> Result<AuthSession, AuthError> {
         let github = self.github.as_ref().ok_or(AuthError::GithubOauthDisabled)?;
+        self.ensure_redirect_uri_allowed(redirect_uri)?;
 
         let profile = github
             .exchange_code(code, redirect_uri)
@@ -194,67 +341,220 @@ impl Authenticator {
 
     pub async fn login_with_github_profile(
         &self,
-        profile: GithubProfile,
+        profile: OAuthProfile,
+    ) -> Result<AuthSession, AuthError> {
+        self.login_with_oauth_profile("github", profile).await
+    }
+
+    pub async fn login_with_google_code(
+        &self,
+        code: &str,
+        redirect_uri: &str,
+    ) -> Result<AuthSession, AuthError> {
+        let google = self.google.as_ref().ok_or(AuthError::GoogleOauthDisabled)?;
+        self.ensure_redirect_uri_allowed(redirect_uri)?;
+
+        let profile = google
+            .exchange_code(code, redirect_uri)
+            .await
+            .map_err(AuthError::GoogleOauth)?;
+
+        self.login_with_google_profile(profile).await
+    }
+
+    /// `profile.email` must be one Google has verified, since it is used to find an
+    /// existing user to link the account to.
+    pub async fn login_with_google_profile(
+        &self,
+        profile: OAuthProfile,
+    ) -> Result<AuthSession, AuthError> {
+        self.login_with_oauth_profile("google", profile).await
+    }
+
+    /// Sign in as the user the `provider` account is linked to. An unlinked account is
+    /// linked to the user with the same email, or to a new user when there is none.
+    async fn login_with_oauth_profile(
+        &self,
+        provider: &'static str,
+        profile: OAuthProfile,
     ) -> Result<AuthSession, AuthError> {
         let mut tx = self.pool.begin().await?;
 
         if let Some(row) = sqlx::query(
-            "SELECT user_id FROM user_identities WHERE provider = 'github' AND provider_uid = ?",
+            "SELECT user_id FROM user_identities WHERE provider = ? AND provider_uid = ?",
         )
+        .bind(provider)
         .bind(&profile.id)
         .fetch_optional(&mut *tx)
         .await?
         {
             let user_id: i64 = row.try_get("user_id")?;
             tx.commit().await?;
-            return self.issue_session(user_id).await;
+            let session = self.issue_session(user_id).await?;
+            self.emit(UserEventType::LoggedIn, Some(user_id), provider);
+            return Ok(session);
         }
 
-        let (user, email) = if let Some(email) = profile.email.as_ref() {
-            if let Some(row) = sqlx::query("SELECT id FROM users WHERE email = ?")
+        let existing = match profile.email.as_ref() {
+            Some(email) => sqlx::query("SELECT id FROM users WHERE email = ?")
                 .bind(email)
                 .fetch_optional(&mut *tx)
                 .await?
-            {
-                let user_id: i64 = row.try_get("id")?;
-                let user = self.fetch_user(user_id).await?;
-                (user, Some(email.clone()))
-            } else {
-                let user = self
-                    .insert_user(&mut tx, Some(email.clone()), profile.name.clone())
-                    .await?;
-                (user, Some(email.clone()))
+                .map(|row| row.try_get::<i64, _>("id"))
+                .transpose()?,
+            None => None,
+        };
+        let created = existing.is_none();
+        let user = match existing {
+            // Read through the transaction: with a one-connection write pool, a second
+            // acquire from `self.pool` would wait on `tx` forever
+            Some(user_id) => fetch_user(&mut *tx, user_id).await?,
+            None => {
+                self.insert_user(&mut tx, profile.email.clone(), profile.name.clone())
+                    .await?
             }
-        } else {
-            let user = self
-                .insert_user(&mut tx, None, profile.name.clone())
-                .await?;
-            (user, None)
         };
+        let email = profile.email.clone();
 
-        let now = Utc::now().to_rfc3339();
-        sqlx::query(
-            "INSERT INTO user_identities (user_id, provider, provider_uid, secret, created_at, updated_at) VALUES (?, ?, ?, NULL, ?, ?)",
+        insert_identity(&mut tx, provider, user.id, &profile.id).await?;
+        tx.commit().await?;
+
+        info!(user = %user.public_id, email = ?email, provider, "linked oauth identity");
+        if created {
+            self.emit(UserEventType::Created, Some(user.id), provider);
+        }
+        let session = self.issue_session(user.id).await?;
+        self.emit(UserEventType::LoggedIn, Some(user.id), provider);
+        Ok(session)
+    }
+
+    /// Attach a GitHub account to a signed-in user. Unlike
+    /// [`Authenticator::login_with_github_code`], which logs in as whoever the account
+    /// is linked to, this fails when the account belongs to a different user.
+    pub async fn link_github_identity(
+        &self,
+        user_id: i64,
+        code: &str,
+        redirect_uri: &str,
+    ) -> Result<(), AuthError> {
+        let github = self.github.as_ref().ok_or(AuthError::GithubOauthDisabled)?;
+        self.ensure_redirect_uri_allowed(redirect_uri)?;
+
+        let profile = github
+            .exchange_code(code, redirect_uri)
+            .await
+            .map_err(AuthError::GithubOauth)?;
+
+        self.link_github_profile(user_id, profile).await
+    }
+
+    /// Linking an account the user already has is a no-op.
+    pub async fn link_github_profile(
+        &self,
+        user_id: i64,
+        profile: OAuthProfile,
+    ) -> Result<(), AuthError> {
+        let mut tx = self.pool.begin().await?;
+
+        let linked_to: Option<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM user_identities WHERE provider = 'github' AND provider_uid = ?",
         )
-        .bind(user.id)
-        .bind("github")
         .bind(&profile.id)
-        .bind(&now)
-        .bind(&now)
-        .execute(&mut *tx)
+        .fetch_optional(&mut *tx)
         .await?;
+        match linked_to {
+            Some(owner) if owner == user_id => return Ok(()),
+            Some(_) => return Err(AuthError::IdentityAlreadyLinked("github")),
+            None => {}
+        }
 
+        insert_identity(&mut tx, "github", user_id, &profile.id).await?;
         tx.commit().await?;
 
-        info!(user = %user.public_id, email = ?email, "linked github identity");
-        self.issue_session(user.id).await
+        info!(user_id, "linked github identity to existing user");
+        Ok(())
     }
 
-    pub async fn authenticate_token(&self, token: &str) -> Result<(User, AuthSession), AuthError> {
-        let row = sqlx::query("SELECT user_id, expires_at FROM sessions WHERE token = ?")
-            .bind(token)
-            .fetch_optional(&self.pool)
+    /// The user's identities, oldest first.
+    pub async fn list_identities(&self, user_id: i64) -> Result<Vec<IdentitySummary>, AuthError> {
+        let rows = sqlx::query(
+            "SELECT provider, created_at FROM user_identities WHERE user_id = ? ORDER BY created_at, id",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut identities = Vec::with_capacity(rows.len());
+        for row in rows {
+            let linked_at: String = row.try_get("created_at")?;
+            let linked_at = DateTime::parse_from_rfc3339(&linked_at)
+                .map_err(|error| sqlx::Error::Decode(error.into()))?
+                .with_timezone(&Utc);
+            identities.push(IdentitySummary {
+                provider: row.try_get("provider")?,
+                linked_at,
+            });
+        }
+        Ok(identities)
+    }
+
+    /// Remove the user's `provider` identity, as long as another identity (a password
+    /// or a different OAuth account) is left to sign in with.
+    pub async fn unlink_identity(&self, user_id: i64, provider: &str) -> Result<(), AuthError> {
+        let mut tx = self.pool.begin().await?;
+
+        let providers: Vec<String> =
+            sqlx::query_scalar("SELECT provider FROM user_identities WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_all(&mut *tx)
+                .await?;
+        if !providers.iter().any(|linked| linked == provider) {
+            return Err(AuthError::IdentityNotLinked(provider.to_owned()));
+        }
+        if providers.iter().all(|linked| linked == provider) {
+            return Err(AuthError::LastLoginMethod);
+        }
+
+        sqlx::query("DELETE FROM user_identities WHERE user_id = ? AND provider = ?")
+            .bind(user_id)
+            .bind(provider)
+            .execute(&mut *tx)
             .await?;
+        tx.commit().await?;
+
+        info!(user_id, provider, "unlinked identity");
+        Ok(())
+    }
+
+    /// Give the user a fresh public id and return it; the old one stops resolving.
+    /// Sessions, API keys and memberships refer to the internal id and keep working.
+    pub async fn rotate_public_id(&self, user_id: i64) -> Result<String, AuthError> {
+        let public_id = new_public_id();
+        let result = sqlx::query("UPDATE users SET public_id = ?, updated_at = ? WHERE id = ?")
+            .bind(&public_id)
+            .bind(Utc::now().to_rfc3339())
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(AuthError::UserNotFound);
+        }
+
+        info!(user_id, "rotated public id");
+        Ok(public_id)
+    }
+
+    pub async fn authenticate_token(&self, token: &str) -> Result<(User, AuthSession), AuthError> {
+        if token.starts_with(API_KEY_PREFIX) {
+            return self.authenticate_api_key(token).await;
+        }
+
+        let row = sqlx::query(
+            "SELECT user_id, created_at, expires_at, last_used_at FROM sessions WHERE token = ?",
+        )
+        .bind(token)
+        .fetch_optional(&self.pool)
+        .await?;
 
         let Some(row) = row else {
             return Err(AuthError::SessionNotFound);
@@ -262,24 +562,66 @@ impl Authenticator {
 
         let user_id: i64 = row.try_get("user_id")?;
         let expires_at: String = row.try_get("expires_at")?;
+        let last_used_at: Option<String> = row.try_get("last_used_at")?;
+        let last_used_at = match last_used_at {
+            Some(value) => value,
+            None => row.try_get("created_at")?,
+        };
 
         let expires_at = DateTime::parse_from_rfc3339(&expires_at)
             .map_err(|_| AuthError::InvalidSession)?
             .with_timezone(&Utc);
+        let last_used_at = DateTime::parse_from_rfc3339(&last_used_at)
+            .map_err(|_| AuthError::InvalidSession)?
+            .with_timezone(&Utc);
 
-        if expires_at <= Utc::now() {
+        let now = Utc::now();
+        let idle = self.idle_timeout.is_some_and(|idle_timeout| now - last_used_at > idle_timeout);
+        if expires_at <= now || idle {
             sqlx::query("DELETE FROM sessions WHERE token = ?")
                 .bind(token)
                 .execute(&self.pool)
                 .await?;
+            if idle {
+                debug!(user_id, "session logged out after idle timeout");
+            }
             return Err(AuthError::SessionExpired);
         }
 
+        // Sliding sessions are only extended past the halfway point, which keeps the
+        // stored expiry unchanged on most requests
+        let expires_at = if self.sliding_expiration && expires_at - now < self.session_ttl / 2 {
+            let extended = now + self.session_ttl;
+            sqlx::query("UPDATE sessions SET last_used_at = ?, expires_at = ? WHERE token = ?")
+                .bind(now.to_rfc3339())
+                .bind(extended.to_rfc3339())
+                .bind(token)
+                .execute(&self.pool)
+                .await?;
+            debug!(user_id, "extended sliding session");
+            extended
+        } else {
+            // Activity only matters to the idle timeout, which tolerates a stale value
+            // of up to a minute (or half the timeout), so most requests skip the write
+            let touch = self.idle_timeout.is_some_and(|idle_timeout| {
+                now - last_used_at >= Duration::minutes(1).min(idle_timeout / 2)
+            });
+            if touch {
+                sqlx::query("UPDATE sessions SET last_used_at = ? WHERE token = ?")
+                    .bind(now.to_rfc3339())
+                    .bind(token)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            expires_at
+        };
+
         let user = self.fetch_user(user_id).await?;
         let session = AuthSession {
             token: token.to_owned(),
             user_id,
             expires_at,
+            scopes: None,
         };
 
         Ok((user, session))
@@ -297,6 +639,7 @@ impl Authenticator {
     ) -> Result<User, AuthError> {
         let now = Utc::now().to_rfc3339();
         let public_id = new_public_id();
+        let display_name = display_name.as_deref().and_then(sanitize_display_name);
 
         sqlx::query(
             "INSERT INTO users (public_id, email, display_name, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
@@ -323,33 +666,7 @@ impl Authenticator {
     }
 
     async fn fetch_user(&self, id: i64) -> Result<User, AuthError> {
-        let row = sqlx::query(
-            "SELECT id, public_id, email, display_name, \n                CASE WHEN email IS NULL THEN 0 ELSE 1 END AS email_present,\n                CASE WHEN display_name IS NULL THEN 0 ELSE 1 END AS display_name_present\n             FROM users WHERE id = ?",
-        )
-            .bind(id)
-            .fetch_one(&self.pool)
-            .await?;
-
-        let email_present: i64 = row.try_get("email_present")?;
-        let email = if email_present != 0 {
-            Some(row.try_get::<String, _>("email")?)
-        } else {
-            None
-        };
-
-        let display_name_present: i64 = row.try_get("display_name_present")?;
-        let display_name = if display_name_present != 0 {
-            Some(row.try_get::<String, _>("display_name")?)
-        } else {
-            None
-        };
-
-        Ok(User {
-            id,
-            public_id: row.try_get("public_id")?,
-            email,
-            display_name,
-        })
+        fetch_user(&self.pool, id).await
     }
 
     async fn issue_session(&self, user_id: i64) -> Result<AuthSession, AuthError> {
@@ -371,15 +688,63 @@ impl Authenticator {
             token,
             user_id,
             expires_at,
+            scopes: None,
         })
     }
 
+    fn emit(&self, event_type: UserEventType, user_id: Option<i64>, provider: &str) {
+        self.events.publish(UserEvent::new(event_type, user_id, provider));
+    }
+
     fn hash_password(&self, password: &str) -> Result<String, argon2::password_hash::Error> {
         let salt = SaltString::generate(&mut OsRng);
-        let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+        let hash = self.argon2()?.hash_password(password.as_bytes(), &salt)?;
         Ok(hash.to_string())
     }
 
+    /// Whether `hash` was made with less memory or fewer iterations than configured
+    fn needs_rehash(&self, hash: &PasswordHash<'_>) -> bool {
+        Params::try_from(hash).map_or(true, |params| {
+            params.m_cost() < self.argon2_config.memory_kib
+                || params.t_cost() < self.argon2_config.iterations
+        })
+    }
+
+    async fn rehash_password(&self, email: &str, password: &str) -> Result<(), AuthError> {
+        let password_hash = self.hash_password(password)?;
+        sqlx::query(
+            "UPDATE user_identities SET secret = ?, updated_at = ? WHERE provider = 'password' AND provider_uid = ?",
+        )
+        .bind(password_hash)
+        .bind(Utc::now().to_rfc3339())
+        .bind(email)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The password hasher with the configured cost, keyed with the configured pepper
+    /// (Argon2's secret input) when there is one. Verification takes the cost from the
+    /// stored hash instead.
+    fn argon2(&self) -> Result<Argon2<'_>, argon2::password_hash::Error> {
+        let params = Params::new(
+            self.argon2_config.memory_kib,
+            self.argon2_config.iterations,
+            self.argon2_config.parallelism,
+            None,
+        )?;
+        let Some(pepper) = &self.password_pepper else {
+            return Ok(Argon2::new(Algorithm::default(), Version::default(), params));
+        };
+        let argon2 = Argon2::new_with_secret(
+            pepper.as_bytes(),
+            Algorithm::default(),
+            Version::default(),
+            params,
+        )?;
+        Ok(argon2)
+    }
+
     fn generate_session_token(&self) -> String {
         let mut bytes = [0u8; 32];
         rand::thread_rng().fill_bytes(&mut bytes);
@@ -391,20 +756,94 @@ fn new_public_id() -> String {
     CUID.create_id()
 }
 
+async fn fetch_user<'e, E>(executor: E, id: i64) -> Result<User, AuthError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let row = sqlx::query(
+        "SELECT id, public_id, email, display_name, \n                CASE WHEN email IS NULL THEN 0 ELSE 1 END AS email_present,\n                CASE WHEN display_name IS NULL THEN 0 ELSE 1 END AS display_name_present\n             FROM users WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_one(executor)
+    .await?;
+
+    let email_present: i64 = row.try_get("email_present")?;
+    let email = if email_present != 0 {
+        Some(row.try_get::<String, _>("email")?)
+    } else {
+        None
+    };
+
+    let display_name_present: i64 = row.try_get("display_name_present")?;
+    let display_name = if display_name_present != 0 {
+        Some(row.try_get::<String, _>("display_name")?)
+    } else {
+        None
+    };
+
+    Ok(User {
+        id,
+        public_id: row.try_get("public_id")?,
+        email,
+        display_name,
+    })
+}
+
+/// A concurrent link of the same account trips the unique index on
+/// `(provider, provider_uid)` and is reported as already linked.
+async fn insert_identity(
+    tx: &mut Transaction<'_, sqlx::Sqlite>,
+    provider: &'static str,
+    user_id: i64,
+    provider_uid: &str,
+) -> Result<(), AuthError> {
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "INSERT INTO user_identities (user_id, provider, provider_uid, secret, created_at, updated_at) VALUES (?, ?, ?, NULL, ?, ?)",
+    )
+    .bind(user_id)
+    .bind(provider)
+    .bind(provider_uid)
+    .bind(&now)
+    .bind(&now)
+    .execute(&mut **tx)
+    .await
+    .map_err(|error| {
+        if error.as_database_error().is_some_and(|db| db.is_unique_violation()) {
+            AuthError::IdentityAlreadyLinked(provider)
+        } else {
+            AuthError::Database(error)
+        }
+    })?;
+    Ok(())
+}
+
 #[derive(Clone)]
 struct GithubOAuth {
     client: BasicClient,
     http: reqwest::Client,
+    request_timeout: std::time::Duration,
+}
+
+/// `pattern` is either an exact URI or a prefix ending in `/*`. Both sides are parsed so
+/// that dot segments and default ports can't be used to sneak past the comparison.
+fn redirect_uri_matches(pattern: &str, candidate: &Url) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) if prefix.ends_with('/') => Url::parse(prefix).is_ok_and(|prefix| {
+            prefix.origin() == candidate.origin() && candidate.path().starts_with(prefix.path())
+        }),
+        _ => Url::parse(pattern).is_ok_and(|exact| &exact == candidate),
+    }
 }
 
 impl GithubOAuth {
-    fn from_config(config: &GithubAuthConfig) -> Option<Self> {
+    fn from_config(config: &GithubAuthConfig, outbound: &OutboundHttpConfig) -> Option<Self> {
         let client_id = config.client_id.clone()?;
         let client_secret = config.client_secret.clone()?;
-        Some(Self::new(client_id, client_secret))
+        Some(Self::new(client_id, client_secret, outbound))
     }
 
-    fn new(client_id: String, client_secret: String) -> Self {
+    fn new(client_id: String, client_secret: String, outbound: &OutboundHttpConfig) -> Self {
         let client = BasicClient::new(
             ClientId::new(client_id),
             Some(ClientSecret::new(client_secret)),
@@ -417,12 +856,27 @@ impl GithubOAuth {
         )
         .set_auth_type(oauth2::AuthType::RequestBody);
 
+        Self::with_client(client, outbound)
+    }
+
+    fn with_client(client: BasicClient, outbound: &OutboundHttpConfig) -> Self {
+        let request_timeout = std::time::Duration::from_secs(outbound.request_timeout_seconds);
         let http = reqwest::Client::builder()
-            .user_agent("switchboard-backend")
+            .user_agent(USER_AGENT)
+            .connect_timeout(std::time::Duration::from_secs(outbound.connect_timeout_seconds))
+            .timeout(request_timeout)
             .build()
             .expect("failed to build github http client");
 
-        Self { client, http }
+        Self {
+            client,
+            http,
+            request_timeout,
+        }
+    }
+
+    fn with_outbound_http(self, outbound: &OutboundHttpConfig) -> Self {
+        Self::with_client(self.client, outbound)
     }
 
     fn authorize_url(&self, state: &str, redirect_uri: &str) -> anyhow::Result<String> {
@@ -441,17 +895,21 @@ impl GithubOAuth {
         Ok(url.to_string())
     }
 
-    async fn exchange_code(&self, code: &str, redirect_uri: &str) -> anyhow::Result<GithubProfile> {
+    async fn exchange_code(&self, code: &str, redirect_uri: &str) -> anyhow::Result<OAuthProfile> {
         let redirect = RedirectUrl::new(redirect_uri.to_owned())
             .context("invalid redirect uri for github oauth")?;
 
-        let token_response = self
+        // The token exchange runs on oauth2's own HTTP client, which has no timeout of
+        // its own, so bound it here.
+        let exchange = self
             .client
             .clone()
             .set_redirect_uri(redirect)
             .exchange_code(AuthorizationCode::new(code.to_owned()))
-            .request_async(async_http_client)
+            .request_async(async_http_client);
+        let token_response = tokio::time::timeout(self.request_timeout, exchange)
             .await
+            .context("github oauth code exchange timed out")?
             .context("failed to exchange github oauth code")?;
 
         let access_token = token_response.access_token().secret();
@@ -472,7 +930,7 @@ impl GithubOAuth {
 
         debug!(login = %user.login, id = user.id, "fetched github user profile");
 
-        Ok(GithubProfile {
+        Ok(OAuthProfile {
             id: user.id.to_string(),
             email: user.email,
             name: user.name,
@@ -487,3 +945,123 @@ struct GithubUserResponse {
     name: Option<String>,
     email: Option<String>,
 }
+
+#[derive(Clone)]
+struct GoogleOAuth {
+    client: BasicClient,
+    http: reqwest::Client,
+    request_timeout: std::time::Duration,
+}
+
+impl GoogleOAuth {
+    fn from_config(config: &GoogleAuthConfig, outbound: &OutboundHttpConfig) -> Option<Self> {
+        let client_id = config.client_id.clone()?;
+        let client_secret = config.client_secret.clone()?;
+        Some(Self::new(client_id, client_secret, outbound))
+    }
+
+    fn new(client_id: String, client_secret: String, outbound: &OutboundHttpConfig) -> Self {
+        let client = BasicClient::new(
+            ClientId::new(client_id),
+            Some(ClientSecret::new(client_secret)),
+            AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())
+                .expect("invalid google auth url"),
+            Some(
+                TokenUrl::new("https://oauth2.googleapis.com/token".to_string())
+                    .expect("invalid google token url"),
+            ),
+        )
+        .set_auth_type(oauth2::AuthType::RequestBody);
+
+        Self::with_client(client, outbound)
+    }
+
+    fn with_client(client: BasicClient, outbound: &OutboundHttpConfig) -> Self {
+        let request_timeout = std::time::Duration::from_secs(outbound.request_timeout_seconds);
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(std::time::Duration::from_secs(outbound.connect_timeout_seconds))
+            .timeout(request_timeout)
+            .build()
+            .expect("failed to build google http client");
+
+        Self {
+            client,
+            http,
+            request_timeout,
+        }
+    }
+
+    fn with_outbound_http(self, outbound: &OutboundHttpConfig) -> Self {
+        Self::with_client(self.client, outbound)
+    }
+
+    fn authorize_url(&self, state: &str, redirect_uri: &str) -> anyhow::Result<String> {
+        let redirect = RedirectUrl::new(redirect_uri.to_owned())
+            .context("invalid redirect uri for google oauth")?;
+
+        let (url, _) = self
+            .client
+            .clone()
+            .set_redirect_uri(redirect)
+            .authorize_url(|| CsrfToken::new(state.to_owned()))
+            .add_scope(Scope::new("openid".to_string()))
+            .add_scope(Scope::new("email".to_string()))
+            .add_scope(Scope::new("profile".to_string()))
+            .url();
+
+        Ok(url.to_string())
+    }
+
+    async fn exchange_code(&self, code: &str, redirect_uri: &str) -> anyhow::Result<OAuthProfile> {
+        let redirect = RedirectUrl::new(redirect_uri.to_owned())
+            .context("invalid redirect uri for google oauth")?;
+
+        // Bounded here for the same reason as the GitHub exchange
+        let exchange = self
+            .client
+            .clone()
+            .set_redirect_uri(redirect)
+            .exchange_code(AuthorizationCode::new(code.to_owned()))
+            .request_async(async_http_client);
+        let token_response = tokio::time::timeout(self.request_timeout, exchange)
+            .await
+            .context("google oauth code exchange timed out")?
+            .context("failed to exchange google oauth code")?;
+
+        let access_token = token_response.access_token().secret();
+
+        let user: GoogleUserInfoResponse = self
+            .http
+            .get(GOOGLE_USERINFO_API)
+            .bearer_auth(access_token)
+            .send()
+            .await
+            .context("failed to call google userinfo api")?
+            .error_for_status()
+            .context("google userinfo api returned error")?
+            .json()
+            .await
+            .context("failed to decode google userinfo response")?;
+
+        debug!(sub = %user.sub, "fetched google user profile");
+
+        // An unverified address could belong to anyone, so it must not be used to find
+        // an existing user to link to
+        let email = user.email.filter(|_| user.email_verified);
+        Ok(OAuthProfile {
+            id: user.sub,
+            email,
+            name: user.name,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct GoogleUserInfoResponse {
+    sub: String,
+    name: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
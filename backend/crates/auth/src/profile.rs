@@ -0,0 +1,28 @@
+/// Longest display name kept, in characters; longer names are truncated.
+pub const MAX_DISPLAY_NAME_CHARS: usize = 64;
+
+/// Normalise a display name taken from user input or an identity provider.
+///
+/// Control characters and bidi overrides are dropped, any run of whitespace
+/// (including tabs and newlines) becomes a single space, and the result is trimmed
+/// and capped at [`MAX_DISPLAY_NAME_CHARS`]. Returns `None` when nothing is left.
+pub fn sanitize_display_name(value: &str) -> Option<String> {
+    let cleaned: String = value
+        .chars()
+        .filter(|c| c.is_whitespace() || !(c.is_control() || is_bidi_control(*c)))
+        .collect();
+
+    let collapsed = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let truncated: String = collapsed.chars().take(MAX_DISPLAY_NAME_CHARS).collect();
+    let trimmed = truncated.trim_end();
+
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_owned())
+    }
+}
+
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
@@ -0,0 +1,82 @@
+//! Listing and ending a user's login sessions.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Row};
+use tracing::info;
+
+use crate::{api_keys::parse_timestamp, AuthError, Authenticator};
+
+// Characters of the token kept in the clear for display
+const DISPLAY_PREFIX_CHARS: usize = 8;
+
+/// A login session without its token, safe to show to support staff.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub id: i64,
+    /// The start of the token, to tell sessions apart.
+    pub token_prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Only kept while sessions have an idle timeout, and then to within a minute.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl Authenticator {
+    /// The user's sessions that have not expired, newest first.
+    pub async fn list_sessions(&self, user_id: i64) -> Result<Vec<SessionSummary>, AuthError> {
+        let rows = sqlx::query(
+            "SELECT id, token, created_at, expires_at, last_used_at FROM sessions WHERE user_id = ? AND expires_at > ? ORDER BY created_at DESC, id DESC",
+        )
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(session_summary).collect()
+    }
+
+    /// End the session with `token`, logging it out. A session that has expired but
+    /// not yet been cleaned up is ended the same way.
+    pub async fn revoke_session(&self, token: &str) -> Result<(), AuthError> {
+        let user_id: Option<i64> =
+            sqlx::query_scalar("DELETE FROM sessions WHERE token = ? RETURNING user_id")
+                .bind(token)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some(user_id) = user_id else {
+            return Err(AuthError::SessionNotFound);
+        };
+
+        info!(user_id, "revoked session");
+        Ok(())
+    }
+
+    /// End every session of the user, logging them out everywhere. Returns how many
+    /// were ended. API keys are left alone.
+    pub async fn revoke_all_sessions(&self, user_id: i64) -> Result<u64, AuthError> {
+        let result = sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        let revoked = result.rows_affected();
+        info!(user_id, revoked, "revoked sessions");
+        Ok(revoked)
+    }
+}
+
+fn session_summary(row: &SqliteRow) -> Result<SessionSummary, AuthError> {
+    let token: String = row.try_get("token")?;
+    let created_at: String = row.try_get("created_at")?;
+    let expires_at: String = row.try_get("expires_at")?;
+    let last_used_at: Option<String> = row.try_get("last_used_at")?;
+
+    Ok(SessionSummary {
+        id: row.try_get("id")?,
+        token_prefix: token.chars().take(DISPLAY_PREFIX_CHARS).collect(),
+        created_at: parse_timestamp(&created_at)?,
+        expires_at: parse_timestamp(&expires_at)?,
+        last_used_at: last_used_at.as_deref().map(parse_timestamp).transpose()?,
+    })
+}
@@ -8,8 +8,12 @@ use sqlx::{
     Row, SqlitePool,
 };
 use std::str::FromStr;
-use switchboard_auth::{AuthError, Authenticator, GithubProfile};
-use switchboard_config::{AuthConfig, GithubAuthConfig};
+use std::sync::Arc;
+use switchboard_auth::{
+    sanitize_display_name, ApiKeyScope, AuthError, Authenticator, MemoryUserEventSink,
+    OAuthProfile, UserEventType, API_KEY_PREFIX, MAX_DISPLAY_NAME_CHARS,
+};
+use switchboard_config::{Argon2Config, AuthConfig, GithubAuthConfig, GoogleAuthConfig};
 use tempfile::TempDir;
 
 type TestResult<T = ()> = Result<T, Box<dyn std::error::Error>>;
@@ -19,17 +23,45 @@ static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("../../migrations");
 fn default_auth_config() -> AuthConfig {
     AuthConfig {
         session_ttl_seconds: 3_600,
+        idle_timeout_seconds: 0,
+        sliding_expiration: false,
+        oauth_state_ttl_seconds: 600,
+        allowed_redirect_uris: Vec::new(),
+        password_pepper: None,
+        argon2: Argon2Config::default(),
         github: GithubAuthConfig::default(),
+        google: GoogleAuthConfig::default(),
     }
 }
 
 fn github_auth_config() -> AuthConfig {
     AuthConfig {
         session_ttl_seconds: 3_600,
+        idle_timeout_seconds: 0,
+        sliding_expiration: false,
+        oauth_state_ttl_seconds: 600,
+        allowed_redirect_uris: vec![
+            "https://app.example.com/auth/callback".into(),
+            "https://preview.example.com/auth/*".into(),
+        ],
+        password_pepper: None,
+        argon2: Argon2Config::default(),
         github: GithubAuthConfig {
             client_id: Some("test-client-id".into()),
             client_secret: Some("test-client-secret".into()),
         },
+        google: GoogleAuthConfig::default(),
+    }
+}
+
+fn google_auth_config() -> AuthConfig {
+    AuthConfig {
+        google: GoogleAuthConfig {
+            client_id: Some("test-client-id".into()),
+            client_secret: Some("test-client-secret".into()),
+        },
+        github: GithubAuthConfig::default(),
+        ..github_auth_config()
     }
 }
 
@@ -225,6 +257,78 @@ async fn login_with_password_rejects_incorrect_secret() -> TestResult {
     Ok(())
 }
 
+#[tokio::test]
+async fn password_pepper_must_match_to_log_in() -> TestResult {
+    let peppered = |pepper: Option<&str>| AuthConfig {
+        password_pepper: pepper.map(str::to_owned),
+        ..default_auth_config()
+    };
+    let ctx = TestContext::new(peppered(Some("pepper-one"))).await?;
+    ctx.authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    ctx.authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    for pepper in [Some("pepper-two"), None] {
+        let authenticator = Authenticator::new(ctx.pool().clone(), peppered(pepper));
+        let err = authenticator
+            .login_with_password("alice@example.com", "s3cret")
+            .await
+            .expect_err("expected a different pepper to reject the password");
+        assert!(matches!(err, AuthError::InvalidCredentials), "{pepper:?}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn login_rehashes_passwords_stored_with_weaker_argon2_params() -> TestResult {
+    let with_cost = |memory_kib, iterations| AuthConfig {
+        argon2: Argon2Config {
+            memory_kib,
+            iterations,
+            parallelism: 1,
+        },
+        ..default_auth_config()
+    };
+    let ctx = TestContext::new(with_cost(1_024, 1)).await?;
+    ctx.authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    let stored_secret = || {
+        sqlx::query_scalar::<_, String>(
+            "SELECT secret FROM user_identities WHERE provider = 'password' AND provider_uid = ?",
+        )
+        .bind("alice@example.com")
+        .fetch_one(ctx.pool())
+    };
+    let weak = stored_secret().await?;
+    assert!(weak.contains("m=1024,t=1"), "{weak}");
+
+    let upgraded = Authenticator::new(ctx.pool().clone(), with_cost(2_048, 2));
+    upgraded
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    let rehashed = stored_secret().await?;
+    assert!(rehashed.contains("m=2048,t=2"), "{rehashed}");
+
+    // Already at the configured cost, and still verifiable by the old configuration
+    upgraded
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    assert_eq!(stored_secret().await?, rehashed);
+    ctx.authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    assert_eq!(stored_secret().await?, rehashed);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn login_with_password_rejects_unknown_email() -> TestResult {
     let ctx = TestContext::new_default().await?;
@@ -259,7 +363,7 @@ async fn login_with_github_code_returns_existing_identity_session() -> TestResul
 
     let session = ctx
         .authenticator()
-        .login_with_github_profile(GithubProfile {
+        .login_with_github_profile(OAuthProfile {
             id: "github-123".into(),
             email: Some("alice@example.com".into()),
             name: Some("Alice Example".into()),
@@ -289,7 +393,7 @@ async fn login_with_github_code_links_existing_user_by_email() -> TestResult {
 
     let session = ctx
         .authenticator()
-        .login_with_github_profile(GithubProfile {
+        .login_with_github_profile(OAuthProfile {
             id: "github-456".into(),
             email: Some("alice@example.com".into()),
             name: Some("Alice Example".into()),
@@ -315,13 +419,294 @@ async fn login_with_github_code_links_existing_user_by_email() -> TestResult {
     Ok(())
 }
 
+#[tokio::test]
+async fn oauth_email_link_works_with_a_single_connection_pool() -> TestResult {
+    // A split deployment's write pool holds one connection, which the linking
+    // transaction owns until it commits
+    let temp_dir = TempDir::new()?;
+    let db_url = format!("sqlite://{}", temp_dir.path().join("auth.sqlite").display());
+    let options = SqliteConnectOptions::from_str(&db_url)?
+        .create_if_missing(true)
+        .foreign_keys(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(std::time::Duration::from_secs(2))
+        .connect_with(options)
+        .await?;
+    MIGRATOR.run(&pool).await?;
+    let authenticator = Authenticator::new(pool, default_auth_config());
+
+    let existing = authenticator
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let session = authenticator
+        .login_with_github_profile(OAuthProfile {
+            id: "github-789".into(),
+            email: Some("alice@example.com".into()),
+            name: None,
+        })
+        .await?;
+
+    assert_eq!(session.user_id, existing.id);
+    Ok(())
+}
+
+#[tokio::test]
+async fn login_with_google_profile_links_existing_user_by_email() -> TestResult {
+    let ctx = TestContext::new(google_auth_config()).await?;
+    let existing = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let profile = OAuthProfile {
+        id: "google-123".into(),
+        email: Some("alice@example.com".into()),
+        name: Some("Alice Example".into()),
+    };
+
+    let session = ctx
+        .authenticator()
+        .login_with_google_profile(profile.clone())
+        .await?;
+    assert_eq!(session.user_id, existing.id);
+    let provider_uid: String = sqlx::query_scalar(
+        "SELECT provider_uid FROM user_identities WHERE user_id = ? AND provider = 'google'",
+    )
+    .bind(existing.id)
+    .fetch_one(ctx.pool())
+    .await?;
+    assert_eq!(provider_uid, "google-123");
+
+    // Signing in again finds the linked identity rather than linking a second one
+    let again = ctx
+        .authenticator()
+        .login_with_google_profile(profile)
+        .await?;
+    assert_eq!(again.user_id, existing.id);
+    let identities: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_identities")
+        .fetch_one(ctx.pool())
+        .await?;
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(ctx.pool())
+        .await?;
+    assert_eq!((identities, users), (2, 1), "password and google identities, one user");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn google_oauth_requires_client_credentials() -> TestResult {
+    let ctx = TestContext::new(github_auth_config()).await?;
+    assert!(!ctx.authenticator().google_enabled());
+    let err = ctx
+        .authenticator()
+        .google_authorization_url("state", "https://app.example.com/auth/callback")
+        .expect_err("google oauth is not configured");
+    assert!(matches!(err, AuthError::GoogleOauthDisabled));
+
+    let ctx = TestContext::new(google_auth_config()).await?;
+    assert!(ctx.authenticator().google_enabled());
+    let url = ctx
+        .authenticator()
+        .google_authorization_url("state", "https://app.example.com/auth/callback")?;
+    assert!(url.starts_with("https://accounts.google.com/"), "{url}");
+    let err = ctx
+        .authenticator()
+        .google_authorization_url("state", "https://evil.example.net/callback")
+        .expect_err("redirect should be rejected");
+    assert!(matches!(err, AuthError::RedirectUriNotAllowed(_)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn link_github_profile_attaches_identity_to_current_user() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let profile = OAuthProfile {
+        id: "github-789".into(),
+        email: Some("someone-else@example.com".into()),
+        name: None,
+    };
+
+    ctx.authenticator()
+        .link_github_profile(user.id, profile.clone())
+        .await?;
+    // Linking the same account again is a no-op
+    ctx.authenticator()
+        .link_github_profile(user.id, profile.clone())
+        .await?;
+
+    let owner: i64 = sqlx::query_scalar(
+        "SELECT user_id FROM user_identities WHERE provider = 'github' AND provider_uid = ?",
+    )
+    .bind("github-789")
+    .fetch_one(ctx.pool())
+    .await?;
+    assert_eq!(owner, user.id);
+
+    let session = ctx.authenticator().login_with_github_profile(profile).await?;
+    assert_eq!(session.user_id, user.id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn link_github_profile_rejects_account_owned_by_another_user() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let profile = OAuthProfile {
+        id: "github-321".into(),
+        email: Some("alice@example.com".into()),
+        name: Some("Alice Example".into()),
+    };
+    let alice = ctx
+        .authenticator()
+        .login_with_github_profile(profile.clone())
+        .await?;
+    let bob = ctx
+        .authenticator()
+        .register_with_password("bob@example.com", "s3cret")
+        .await?;
+
+    let err = ctx
+        .authenticator()
+        .link_github_profile(bob.id, profile)
+        .await
+        .expect_err("GitHub account already belongs to alice");
+    assert!(matches!(err, AuthError::IdentityAlreadyLinked("github")));
+
+    let owner: i64 = sqlx::query_scalar(
+        "SELECT user_id FROM user_identities WHERE provider = 'github' AND provider_uid = ?",
+    )
+    .bind("github-321")
+    .fetch_one(ctx.pool())
+    .await?;
+    assert_eq!(owner, alice.user_id, "identity must stay with its original owner");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn list_identities_reports_providers_without_secrets() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    ctx.authenticator()
+        .link_github_profile(
+            user.id,
+            OAuthProfile {
+                id: "github-111".into(),
+                email: None,
+                name: None,
+            },
+        )
+        .await?;
+
+    let identities = ctx.authenticator().list_identities(user.id).await?;
+    let providers: Vec<&str> = identities
+        .iter()
+        .map(|identity| identity.provider.as_str())
+        .collect();
+    assert_eq!(providers, vec!["password", "github"]);
+
+    let password_hash: String = sqlx::query_scalar(
+        "SELECT secret FROM user_identities WHERE user_id = ? AND provider = 'password'",
+    )
+    .bind(user.id)
+    .fetch_one(ctx.pool())
+    .await?;
+    let serialized = serde_json::to_string(&identities)?;
+    assert!(!serialized.contains(&password_hash), "password hash leaked");
+    assert!(!serialized.contains("secret"));
+    assert!(!serialized.contains("github-111"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unlink_identity_removes_github_when_password_remains() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    ctx.authenticator()
+        .link_github_profile(
+            user.id,
+            OAuthProfile {
+                id: "github-654".into(),
+                email: None,
+                name: None,
+            },
+        )
+        .await?;
+
+    ctx.authenticator().unlink_identity(user.id, "github").await?;
+
+    let providers: Vec<String> =
+        sqlx::query_scalar("SELECT provider FROM user_identities WHERE user_id = ?")
+            .bind(user.id)
+            .fetch_all(ctx.pool())
+            .await?;
+    assert_eq!(providers, vec!["password".to_string()]);
+
+    let session = ctx
+        .authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    assert_eq!(session.user_id, user.id);
+
+    let err = ctx
+        .authenticator()
+        .unlink_identity(user.id, "github")
+        .await
+        .expect_err("github identity was already removed");
+    assert!(matches!(err, AuthError::IdentityNotLinked(provider) if provider == "github"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unlink_identity_rejects_last_login_method() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let session = ctx
+        .authenticator()
+        .login_with_github_profile(OAuthProfile {
+            id: "github-987".into(),
+            email: Some("alice@example.com".into()),
+            name: None,
+        })
+        .await?;
+
+    let err = ctx
+        .authenticator()
+        .unlink_identity(session.user_id, "github")
+        .await
+        .expect_err("github is the only way to sign in");
+    assert!(matches!(err, AuthError::LastLoginMethod));
+
+    let identities: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM user_identities WHERE user_id = ?")
+            .bind(session.user_id)
+            .fetch_one(ctx.pool())
+            .await?;
+    assert_eq!(identities, 1, "identity must be kept");
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn login_with_github_code_creates_user_when_new_profile() -> TestResult {
     let ctx = TestContext::new_default().await?;
 
     let session = ctx
         .authenticator()
-        .login_with_github_profile(GithubProfile {
+        .login_with_github_profile(OAuthProfile {
             id: "github-789".into(),
             email: Some("new@example.com".into()),
             name: Some("New User".into()),
@@ -349,6 +734,59 @@ async fn login_with_github_code_creates_user_when_new_profile() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn sanitize_display_name_strips_control_characters() {
+    assert_eq!(
+        sanitize_display_name("Ali\u{0}ce\u{7} \u{1b}[31mRed").as_deref(),
+        Some("Alice [31mRed")
+    );
+    assert_eq!(sanitize_display_name("evil\u{202E}txt.exe").as_deref(), Some("eviltxt.exe"));
+    assert_eq!(sanitize_display_name("\u{1}\u{2}\u{7f}"), None);
+}
+
+#[test]
+fn sanitize_display_name_collapses_tabs_and_newlines() {
+    assert_eq!(
+        sanitize_display_name("  Ada\tLovelace \r\n\n  Byron  ").as_deref(),
+        Some("Ada Lovelace Byron")
+    );
+    assert_eq!(sanitize_display_name(" \n\t "), None);
+    assert_eq!(
+        sanitize_display_name("Zoë O'Brien-Ñúñez, Jr. 😀").as_deref(),
+        Some("Zoë O'Brien-Ñúñez, Jr. 😀")
+    );
+}
+
+#[test]
+fn sanitize_display_name_caps_length() {
+    let long = "ä".repeat(MAX_DISPLAY_NAME_CHARS + 10);
+    let sanitized = sanitize_display_name(&long).expect("name kept");
+    assert_eq!(sanitized.chars().count(), MAX_DISPLAY_NAME_CHARS);
+
+    let padded = format!("{} tail", "a".repeat(MAX_DISPLAY_NAME_CHARS - 1));
+    let sanitized = sanitize_display_name(&padded).expect("name kept");
+    assert!(!sanitized.ends_with(' '));
+}
+
+#[tokio::test]
+async fn github_profile_name_is_sanitized_on_signup() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+
+    let session = ctx
+        .authenticator()
+        .login_with_github_profile(OAuthProfile {
+            id: "github-multiline".into(),
+            email: Some("multiline@example.com".into()),
+            name: Some("Mallory\n[INFO] admin logged in\u{0}".into()),
+        })
+        .await?;
+
+    let user = ctx.authenticator().user_profile(session.user_id).await?;
+    assert_eq!(user.display_name.as_deref(), Some("Mallory [INFO] admin logged in"));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn login_with_github_code_requires_github_configuration() -> TestResult {
     let ctx = TestContext::new_default().await?;
@@ -361,13 +799,104 @@ async fn login_with_github_code_requires_github_configuration() -> TestResult {
     Ok(())
 }
 
+#[tokio::test]
+async fn github_authorization_url_enforces_redirect_allowlist() -> TestResult {
+    let ctx = TestContext::new(github_auth_config()).await?;
+    let authenticator = ctx.authenticator();
+
+    for allowed in [
+        "https://app.example.com/auth/callback",
+        "https://preview.example.com/auth/",
+        "https://preview.example.com/auth/branch/callback",
+    ] {
+        authenticator.github_authorization_url("state", allowed)?;
+    }
+
+    for rejected in [
+        "https://app.example.com/auth/callback/extra",
+        "https://app.example.com:8443/auth/callback",
+        "http://preview.example.com/auth/callback",
+        "https://preview.example.com/authx",
+        "not a url",
+    ] {
+        let err = authenticator
+            .github_authorization_url("state", rejected)
+            .expect_err("redirect should be rejected");
+        assert!(matches!(err, AuthError::RedirectUriNotAllowed(_)), "{rejected}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn login_with_github_code_rejects_unlisted_redirect() -> TestResult {
+    let ctx = TestContext::new(github_auth_config()).await?;
+    let err = ctx
+        .authenticator()
+        .login_with_github_code("dummy", "https://evil.example.net/callback")
+        .await
+        .expect_err("redirect should be rejected before exchanging the code");
+    assert!(matches!(err, AuthError::RedirectUriNotAllowed(_)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn authenticator_publishes_user_events_to_sink() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let sink = MemoryUserEventSink::default();
+    let authenticator = ctx
+        .authenticator()
+        .clone()
+        .with_event_sink(Arc::new(sink.clone()));
+
+    let user = authenticator
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    authenticator
+        .login_with_password("alice@example.com", "wrong")
+        .await
+        .expect_err("wrong password should fail");
+    authenticator
+        .login_with_password("nobody@example.com", "s3cret")
+        .await
+        .expect_err("unknown email should fail");
+    authenticator
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    let github_session = authenticator
+        .login_with_github_profile(OAuthProfile {
+            id: "github-events".into(),
+            email: None,
+            name: Some("Octo".into()),
+        })
+        .await?;
+
+    let recorded: Vec<_> = sink
+        .events()
+        .into_iter()
+        .map(|event| (event.event_type, event.user_id, event.provider))
+        .collect();
+    assert_eq!(
+        recorded,
+        vec![
+            (UserEventType::Created, Some(user.id), "password".to_string()),
+            (UserEventType::LoginFailed, Some(user.id), "password".to_string()),
+            (UserEventType::LoginFailed, None, "password".to_string()),
+            (UserEventType::LoggedIn, Some(user.id), "password".to_string()),
+            (UserEventType::Created, Some(github_session.user_id), "github".to_string()),
+            (UserEventType::LoggedIn, Some(github_session.user_id), "github".to_string()),
+        ]
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn login_with_github_profile_handles_missing_email() -> TestResult {
     let ctx = TestContext::new_default().await?;
 
     let session = ctx
         .authenticator()
-        .login_with_github_profile(GithubProfile {
+        .login_with_github_profile(OAuthProfile {
             id: "github-999".into(),
             email: None,
             name: Some("No Email".into()),
@@ -450,6 +979,179 @@ async fn authenticate_token_deletes_expired_sessions() -> TestResult {
     Ok(())
 }
 
+#[tokio::test]
+async fn authenticate_token_logs_out_idle_sessions() -> TestResult {
+    let ctx = TestContext::new(AuthConfig {
+        idle_timeout_seconds: 900,
+        ..default_auth_config()
+    })
+    .await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    // Well within the absolute TTL, but unused for longer than the idle window
+    let token = "idle-token";
+    let created_at = (Utc::now() - Duration::minutes(30)).to_rfc3339();
+    let last_used_at = (Utc::now() - Duration::minutes(20)).to_rfc3339();
+    let expires_at = (Utc::now() + Duration::minutes(30)).to_rfc3339();
+    sqlx::query(
+        "INSERT INTO sessions (user_id, token, created_at, expires_at, last_used_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(user.id)
+    .bind(token)
+    .bind(&created_at)
+    .bind(&expires_at)
+    .bind(&last_used_at)
+    .execute(ctx.pool())
+    .await?;
+
+    let err = ctx
+        .authenticator()
+        .authenticate_token(token)
+        .await
+        .expect_err("idle token should be rejected");
+    assert!(matches!(err, AuthError::SessionExpired));
+
+    let remaining: Option<i64> = sqlx::query_scalar("SELECT 1 FROM sessions WHERE token = ?")
+        .bind(token)
+        .fetch_optional(ctx.pool())
+        .await?;
+    assert!(remaining.is_none(), "idle session should be removed from the database");
+
+    // An active session keeps working and has its last use recorded
+    let token = "active-token";
+    let last_used_at = (Utc::now() - Duration::minutes(5)).to_rfc3339();
+    sqlx::query(
+        "INSERT INTO sessions (user_id, token, created_at, expires_at, last_used_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(user.id)
+    .bind(token)
+    .bind(&created_at)
+    .bind(&expires_at)
+    .bind(&last_used_at)
+    .execute(ctx.pool())
+    .await?;
+    ctx.authenticator().authenticate_token(token).await?;
+    let recorded = stored_last_used_at(&ctx, token).await?.expect("last use recorded");
+    let recorded_at = DateTime::parse_from_rfc3339(&recorded)?.with_timezone(&Utc);
+    assert!(recorded_at > Utc::now() - Duration::minutes(1));
+
+    // A use recorded under a minute ago is not rewritten
+    ctx.authenticator().authenticate_token(token).await?;
+    assert_eq!(stored_last_used_at(&ctx, token).await?, Some(recorded));
+
+    Ok(())
+}
+
+async fn stored_last_used_at(ctx: &TestContext, token: &str) -> TestResult<Option<String>> {
+    let last_used_at = sqlx::query_scalar("SELECT last_used_at FROM sessions WHERE token = ?")
+        .bind(token)
+        .fetch_one(ctx.pool())
+        .await?;
+    Ok(last_used_at)
+}
+
+#[tokio::test]
+async fn authenticate_token_skips_last_use_without_idle_timeout() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    ctx.authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let session = ctx
+        .authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    ctx.authenticator().authenticate_token(&session.token).await?;
+    assert_eq!(stored_last_used_at(&ctx, &session.token).await?, None);
+
+    Ok(())
+}
+
+async fn insert_session_expiring_in(
+    ctx: &TestContext,
+    user_id: i64,
+    token: &str,
+    remaining: Duration,
+) -> TestResult<String> {
+    let expires_at = (Utc::now() + remaining).to_rfc3339();
+    sqlx::query("INSERT INTO sessions (user_id, token, created_at, expires_at) VALUES (?, ?, ?, ?)")
+        .bind(user_id)
+        .bind(token)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&expires_at)
+        .execute(ctx.pool())
+        .await?;
+    Ok(expires_at)
+}
+
+async fn stored_expiry(ctx: &TestContext, token: &str) -> TestResult<String> {
+    let expires_at = sqlx::query_scalar("SELECT expires_at FROM sessions WHERE token = ?")
+        .bind(token)
+        .fetch_one(ctx.pool())
+        .await?;
+    Ok(expires_at)
+}
+
+#[tokio::test]
+async fn sliding_expiration_leaves_fresh_sessions_alone() -> TestResult {
+    let ctx = TestContext::new(AuthConfig {
+        sliding_expiration: true,
+        ..default_auth_config()
+    })
+    .await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    // 50 of the 60 minute TTL left
+    let token = "fresh-token";
+    let expires_at = insert_session_expiring_in(&ctx, user.id, token, Duration::minutes(50)).await?;
+    let (_, session) = ctx.authenticator().authenticate_token(token).await?;
+
+    assert_eq!(stored_expiry(&ctx, token).await?, expires_at);
+    assert_eq!(session.expires_at, DateTime::parse_from_rfc3339(&expires_at)?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sliding_expiration_extends_sessions_near_expiry() -> TestResult {
+    let ctx = TestContext::new(AuthConfig {
+        sliding_expiration: true,
+        ..default_auth_config()
+    })
+    .await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    // 10 of the 60 minute TTL left
+    let token = "expiring-token";
+    let expires_at = insert_session_expiring_in(&ctx, user.id, token, Duration::minutes(10)).await?;
+    let before = Utc::now();
+    let (_, session) = ctx.authenticator().authenticate_token(token).await?;
+
+    let stored = stored_expiry(&ctx, token).await?;
+    assert_ne!(stored, expires_at, "expiry should be written back");
+    assert_eq!(DateTime::parse_from_rfc3339(&stored)?, session.expires_at);
+    let full_ttl = Duration::seconds(ctx.config.session_ttl_seconds as i64);
+    assert!(session.expires_at >= before + full_ttl);
+
+    // Without sliding expiration the same session keeps its expiry
+    let fixed = Authenticator::new(ctx.pool().clone(), default_auth_config());
+    let token = "fixed-token";
+    let expires_at = insert_session_expiring_in(&ctx, user.id, token, Duration::minutes(10)).await?;
+    fixed.authenticate_token(token).await?;
+    assert_eq!(stored_expiry(&ctx, token).await?, expires_at);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn authenticate_token_rejects_unknown_token() -> TestResult {
     let ctx = TestContext::new_default().await?;
@@ -624,3 +1326,250 @@ async fn github_oauth_from_config_requires_client_credentials() -> TestResult {
     );
     Ok(())
 }
+
+#[tokio::test]
+async fn create_api_key_stores_only_a_hash() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    let (summary, key) = ctx
+        .authenticator()
+        .create_api_key(
+            user.id,
+            "ci",
+            &[ApiKeyScope::MessagesWrite, ApiKeyScope::ChatsRead, ApiKeyScope::ChatsRead],
+        )
+        .await?;
+    assert!(key.starts_with(API_KEY_PREFIX));
+    assert!(key.starts_with(&summary.key_prefix));
+    assert_eq!(summary.scopes, vec![ApiKeyScope::ChatsRead, ApiKeyScope::MessagesWrite]);
+
+    let key_hash: String = sqlx::query_scalar("SELECT key_hash FROM api_keys WHERE public_id = ?")
+        .bind(&summary.public_id)
+        .fetch_one(ctx.pool())
+        .await?;
+    assert_ne!(key_hash, key, "plaintext key must not be stored");
+
+    let listed = ctx.authenticator().list_api_keys(user.id).await?;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].public_id, summary.public_id);
+    assert!(!serde_json::to_string(&listed)?.contains(&key));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn authenticate_token_accepts_api_key_with_its_scopes() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let (summary, key) = ctx
+        .authenticator()
+        .create_api_key(user.id, "reporting", &[ApiKeyScope::ChatsRead])
+        .await?;
+
+    let (authed_user, session) = ctx.authenticator().authenticate_token(&key).await?;
+    assert_eq!(authed_user.id, user.id);
+    assert_eq!(session.scopes, Some(vec![ApiKeyScope::ChatsRead]));
+    assert!(session.allows(ApiKeyScope::ChatsRead));
+    assert!(!session.allows(ApiKeyScope::ChatsWrite));
+
+    let listed = ctx.authenticator().list_api_keys(user.id).await?;
+    assert_eq!(listed[0].public_id, summary.public_id);
+    assert!(listed[0].last_used_at.is_some(), "use should be recorded");
+
+    let err = ctx
+        .authenticator()
+        .authenticate_token(&format!("{API_KEY_PREFIX}not-a-real-key"))
+        .await
+        .expect_err("unknown key should be rejected");
+    assert!(matches!(err, AuthError::InvalidApiKey));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn revoked_api_key_no_longer_authenticates() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let alice = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let bob = ctx
+        .authenticator()
+        .register_with_password("bob@example.com", "s3cret")
+        .await?;
+    let (summary, key) = ctx
+        .authenticator()
+        .create_api_key(alice.id, "ci", &[ApiKeyScope::ChatsRead])
+        .await?;
+
+    let err = ctx
+        .authenticator()
+        .revoke_api_key(bob.id, &summary.public_id)
+        .await
+        .expect_err("another user's key cannot be revoked");
+    assert!(matches!(err, AuthError::ApiKeyNotFound));
+
+    ctx.authenticator()
+        .revoke_api_key(alice.id, &summary.public_id)
+        .await?;
+
+    let err = ctx
+        .authenticator()
+        .authenticate_token(&key)
+        .await
+        .expect_err("revoked key should be rejected");
+    assert!(matches!(err, AuthError::InvalidApiKey));
+    assert!(ctx.authenticator().list_api_keys(alice.id).await?.is_empty());
+
+    let err = ctx
+        .authenticator()
+        .revoke_api_key(alice.id, &summary.public_id)
+        .await
+        .expect_err("a key is revoked only once");
+    assert!(matches!(err, AuthError::ApiKeyNotFound));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn session_tokens_hold_every_scope() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    ctx.authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let session = ctx
+        .authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    let (_, session) = ctx.authenticator().authenticate_token(&session.token).await?;
+
+    assert!(session.scopes.is_none());
+    assert!(ApiKeyScope::ALL.into_iter().all(|scope| session.allows(scope)));
+    Ok(())
+}
+
+#[test]
+fn api_key_scopes_round_trip_through_their_names() {
+    for scope in ApiKeyScope::ALL {
+        assert_eq!(ApiKeyScope::parse(scope.as_str()), Some(scope));
+        let json = serde_json::to_string(&scope).expect("scope serializes");
+        assert_eq!(json, format!("\"{}\"", scope.as_str()));
+    }
+    assert_eq!(ApiKeyScope::parse("read"), None);
+}
+
+#[tokio::test]
+async fn revoke_session_logs_out_only_that_session() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let alice = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let phone = ctx
+        .authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    let laptop = ctx
+        .authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+
+    ctx.authenticator().revoke_session(&phone.token).await?;
+    let err = ctx
+        .authenticator()
+        .authenticate_token(&phone.token)
+        .await
+        .expect_err("revoked session should be rejected");
+    assert!(matches!(err, AuthError::SessionNotFound));
+    let (user, _) = ctx.authenticator().authenticate_token(&laptop.token).await?;
+    assert_eq!(user.id, alice.id);
+
+    let err = ctx
+        .authenticator()
+        .revoke_session(&phone.token)
+        .await
+        .expect_err("a revoked session cannot be revoked again");
+    assert!(matches!(err, AuthError::SessionNotFound));
+    let err = ctx
+        .authenticator()
+        .revoke_session("no-such-token")
+        .await
+        .expect_err("unknown tokens are not found");
+    assert!(matches!(err, AuthError::SessionNotFound));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn revoke_session_ends_expired_sessions() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let user = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let token = "expired-token";
+    insert_session_expiring_in(&ctx, user.id, token, Duration::minutes(-5)).await?;
+
+    ctx.authenticator().revoke_session(token).await?;
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions WHERE token = ?")
+        .bind(token)
+        .fetch_one(ctx.pool())
+        .await?;
+    assert_eq!(remaining, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn revoke_all_sessions_logs_a_user_out_everywhere() -> TestResult {
+    let ctx = TestContext::new_default().await?;
+    let alice = ctx
+        .authenticator()
+        .register_with_password("alice@example.com", "s3cret")
+        .await?;
+    let bob = ctx
+        .authenticator()
+        .register_with_password("bob@example.com", "s3cret")
+        .await?;
+    let first = ctx
+        .authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    let second = ctx
+        .authenticator()
+        .login_with_password("alice@example.com", "s3cret")
+        .await?;
+    let bobs = ctx
+        .authenticator()
+        .login_with_password("bob@example.com", "s3cret")
+        .await?;
+
+    let listed = ctx.authenticator().list_sessions(alice.id).await?;
+    assert_eq!(listed.len(), 2);
+    let serialized = serde_json::to_string(&listed)?;
+    assert!(!serialized.contains(&first.token));
+    assert!(!serialized.contains(&second.token));
+
+    assert_eq!(ctx.authenticator().revoke_all_sessions(alice.id).await?, 2);
+    for token in [&first.token, &second.token] {
+        let err = ctx
+            .authenticator()
+            .authenticate_token(token)
+            .await
+            .expect_err("revoked session should be rejected");
+        assert!(matches!(err, AuthError::SessionNotFound));
+    }
+    assert!(ctx.authenticator().list_sessions(alice.id).await?.is_empty());
+
+    let (user, _) = ctx.authenticator().authenticate_token(&bobs.token).await?;
+    assert_eq!(user.id, bob.id);
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Limiting what requests made with an API key can reach. Each route needs one scope
+//! for reads and one for writes; login sessions implicitly hold every scope.
+
+use axum::{
+    extract::{Request, State},
+    http::{Extensions, Method},
+    middleware::Next,
+    response::Response,
+};
+use switchboard_auth::{ApiKeyScope, API_KEY_PREFIX};
+
+use crate::{util::require_bearer, ApiError, AppState};
+
+/// The scopes a request holds, attached to its extensions by
+/// [`enforce_api_key_scopes`]. `None` for a login session, which holds them all.
+#[derive(Debug, Clone)]
+pub struct GrantedScopes(pub Option<Vec<ApiKeyScope>>);
+
+impl GrantedScopes {
+    pub fn allows(&self, scope: ApiKeyScope) -> bool {
+        match &self.0 {
+            Some(scopes) => scopes.contains(&scope),
+            None => true,
+        }
+    }
+}
+
+/// Reject the request with 403 unless it holds `scope`. Requests that did not pass
+/// through the router, and so carry no [`GrantedScopes`], are treated as sessions.
+pub fn require_scope(extensions: &Extensions, scope: ApiKeyScope) -> Result<(), ApiError> {
+    match extensions.get::<GrantedScopes>() {
+        Some(granted) if !granted.allows(scope) => Err(ApiError::forbidden(format!(
+            "api key is missing the `{}` scope",
+            scope.as_str()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Resolve an API key's scopes into the request extensions and check them against
+/// the route. Other requests are granted every scope and pass through untouched.
+pub(crate) async fn enforce_api_key_scopes(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let Some(key) = require_bearer(request.headers())
+        .ok()
+        .filter(|token| token.starts_with(API_KEY_PREFIX))
+    else {
+        request.extensions_mut().insert(GrantedScopes(None));
+        return Ok(next.run(request).await);
+    };
+
+    let scope = match route_access(request.uri().path()) {
+        RouteAccess::Public => return Ok(next.run(request).await),
+        RouteAccess::Closed => {
+            return Err(ApiError::forbidden("api keys cannot access this endpoint"));
+        }
+        RouteAccess::Scoped { read, write } => match *request.method() {
+            Method::GET | Method::HEAD | Method::OPTIONS => read,
+            _ => write,
+        },
+    };
+
+    let (_, session) = state.authenticate(&key).await?;
+    request.extensions_mut().insert(GrantedScopes(session.scopes));
+    require_scope(request.extensions(), scope)?;
+
+    Ok(next.run(request).await)
+}
+
+enum RouteAccess {
+    /// Needs no authentication.
+    Public,
+    /// Needs a login session: account and admin routes, so a leaked key cannot mint
+    /// more keys, and any route not listed here.
+    Closed,
+    Scoped {
+        read: ApiKeyScope,
+        write: ApiKeyScope,
+    },
+}
+
+fn route_access(path: &str) -> RouteAccess {
+    use ApiKeyScope::*;
+
+    let scoped = |read, write| RouteAccess::Scoped { read, write };
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["api", "auth" | "admin", ..] => RouteAccess::Closed,
+        ["api", "chat"] | ["api", "chats", _, "messages" | "draft", ..] => {
+            scoped(MessagesRead, MessagesWrite)
+        }
+        // Estimating a completion sends nothing to the provider
+        ["api", "chats", _, "completions", "estimate"] => scoped(MessagesRead, MessagesRead),
+        ["api", "chats" | "invites", ..] => scoped(ChatsRead, ChatsWrite),
+        ["api", "folders", ..] => scoped(FoldersRead, FoldersWrite),
+        ["api", "notifications", ..] => scoped(NotificationsRead, NotificationsWrite),
+        ["api", "permissions", ..] | ["api", "users", _, "permissions"] => {
+            scoped(PermissionsRead, PermissionsWrite)
+        }
+        ["api", "models"] => scoped(ModelsRead, ModelsRead),
+        ["api", ..] => RouteAccess::Closed,
+        _ => RouteAccess::Public,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes_for(path: &str) -> Option<(ApiKeyScope, ApiKeyScope)> {
+        match route_access(path) {
+            RouteAccess::Scoped { read, write } => Some((read, write)),
+            RouteAccess::Public | RouteAccess::Closed => None,
+        }
+    }
+
+    #[test]
+    fn routes_map_to_their_resource_scopes() {
+        use ApiKeyScope::*;
+
+        assert_eq!(scopes_for("/api/chats"), Some((ChatsRead, ChatsWrite)));
+        assert_eq!(scopes_for("/api/chats/c1/members"), Some((ChatsRead, ChatsWrite)));
+        assert_eq!(
+            scopes_for("/api/chats/c1/messages/m1/attachments"),
+            Some((MessagesRead, MessagesWrite))
+        );
+        assert_eq!(scopes_for("/api/chat"), Some((MessagesRead, MessagesWrite)));
+        assert_eq!(
+            scopes_for("/api/users/u1/permissions"),
+            Some((PermissionsRead, PermissionsWrite))
+        );
+    }
+
+    #[test]
+    fn unlisted_api_routes_are_closed_to_keys() {
+        assert!(matches!(route_access("/api/auth/api-keys"), RouteAccess::Closed));
+        assert!(matches!(route_access("/api/admin/audit"), RouteAccess::Closed));
+        assert!(matches!(route_access("/api/unknown"), RouteAccess::Closed));
+        assert!(matches!(route_access("/health"), RouteAccess::Public));
+    }
+}
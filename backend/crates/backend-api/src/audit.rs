@@ -0,0 +1,123 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, HeaderMap},
+};
+use switchboard_config::HttpConfig;
+
+use crate::AppState;
+
+pub const LOGIN_SUCCEEDED: &str = "login_succeeded";
+pub const LOGIN_FAILED: &str = "login_failed";
+pub const PERMISSION_GRANTED: &str = "permission_granted";
+pub const PERMISSION_REVOKED: &str = "permission_revoked";
+pub const MEMBER_ROLE_CHANGED: &str = "member_role_changed";
+pub const IDENTITY_LINKED: &str = "identity_linked";
+pub const IDENTITY_UNLINKED: &str = "identity_unlinked";
+pub const API_KEY_CREATED: &str = "api_key_created";
+pub const API_KEY_REVOKED: &str = "api_key_revoked";
+pub const SESSIONS_LISTED: &str = "sessions_listed";
+pub const SESSIONS_REVOKED: &str = "sessions_revoked";
+pub const PUBLIC_ID_ROTATED: &str = "public_id_rotated";
+
+pub const AUDIT_ACTIONS: &[&str] = &[
+    LOGIN_SUCCEEDED,
+    LOGIN_FAILED,
+    PERMISSION_GRANTED,
+    PERMISSION_REVOKED,
+    MEMBER_ROLE_CHANGED,
+    IDENTITY_LINKED,
+    IDENTITY_UNLINKED,
+    API_KEY_CREATED,
+    API_KEY_REVOKED,
+    SESSIONS_LISTED,
+    SESSIONS_REVOKED,
+    PUBLIC_ID_ROTATED,
+];
+
+/// Append a row to the audit log. Failures are logged and swallowed: auditing must
+/// never fail the operation being audited.
+pub async fn record_audit(
+    state: &AppState,
+    actor_user_id: Option<i64>,
+    action: &str,
+    target: Option<&str>,
+    ip: Option<&str>,
+) {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO audit_log (actor_user_id, action, target, ip, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(actor_user_id)
+    .bind(action)
+    .bind(target)
+    .bind(ip)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(state.db_pool())
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!("Failed to record audit entry {}: {}", action, e);
+    }
+}
+
+/// The address a request came from, for audit entries. Forwarding headers are only
+/// believed when the socket peer is one of `http.trusted_proxies`; otherwise the peer
+/// itself is the client. `None` when the server runs without connection info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIp(pub Option<String>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Infallible> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let ip = peer.map(|peer| client_ip(&parts.headers, peer, &state.config().http));
+        Ok(Self(ip.map(|ip| ip.to_string())))
+    }
+}
+
+/// The client behind `peer`. From a trusted proxy, `X-Forwarded-For` is read right to
+/// left and the first hop that is not itself a trusted proxy wins, so a client cannot
+/// spoof its address by sending the header itself; `X-Real-IP` is the fallback.
+pub fn client_ip(headers: &HeaderMap, peer: IpAddr, http: &HttpConfig) -> IpAddr {
+    if !http.trusts_proxy(peer) {
+        return peer;
+    }
+
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    if !hops.is_empty() {
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            // Anything left of a malformed hop was written by the client
+            let Ok(hop) = hop.trim().parse() else { break };
+            client = hop;
+            if !http.trusts_proxy(hop) {
+                break;
+            }
+        }
+        return client;
+    }
+
+    headers
+        .get("x-real-ip")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(peer)
+}
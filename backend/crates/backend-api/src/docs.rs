@@ -5,65 +5,137 @@ use utoipa::{Modify, OpenApi};
 #[openapi(
     paths(
         crate::routes::health::health_check,
+        crate::routes::health::liveness_check,
+        crate::routes::health::readiness_check,
+        crate::routes::health::tasks_check,
         crate::routes::auth::github_login,
         crate::routes::auth::github_callback,
+        crate::routes::auth::link_github,
+        crate::routes::auth::list_identities,
+        crate::routes::auth::unlink_identity,
+        crate::routes::auth::create_api_key,
+        crate::routes::auth::list_api_keys,
+        crate::routes::auth::revoke_api_key,
+        crate::routes::users::get_current_user,
+        crate::routes::users::export_current_user,
+        crate::routes::users::rotate_public_id,
         crate::routes::models::list_models,
         crate::routes::chat::chat_completion,
+        crate::routes::chat::estimate_completion,
+        crate::routes::chat::suggest_replies,
+        crate::routes::guest::guest_chat,
         crate::routes::folders::list_folders,
         crate::routes::folders::create_folder,
         crate::routes::folders::get_folder,
         crate::routes::folders::update_folder,
         crate::routes::folders::delete_folder,
+        crate::routes::prompt_templates::list_templates,
+        crate::routes::prompt_templates::create_template,
+        crate::routes::prompt_templates::get_template,
+        crate::routes::prompt_templates::update_template,
+        crate::routes::prompt_templates::delete_template,
+        crate::routes::prompt_templates::export_templates,
+        crate::routes::prompt_templates::import_templates,
         crate::routes::chats::list_chats,
+        crate::routes::chats::count_chats,
         crate::routes::chats::create_chat,
         crate::routes::chats::get_chat,
         crate::routes::chats::update_chat,
+        crate::routes::chats::update_retention,
+        crate::routes::chats::update_chat_model,
+        crate::routes::chats::get_chat_stats,
+        crate::routes::chats::get_typing_users,
         crate::routes::chats::delete_chat,
         crate::routes::chats::create_invite,
         crate::routes::chats::list_invites,
+        crate::routes::chats::list_user_invites,
         crate::routes::chats::accept_invite,
         crate::routes::chats::reject_invite,
         crate::routes::chats::list_members,
+        crate::routes::chats::get_my_membership,
         crate::routes::chats::update_member_role,
         crate::routes::chats::remove_member,
         crate::routes::messages::get_messages,
+        crate::routes::messages::count_messages,
         crate::routes::messages::create_message,
+        crate::routes::messages::create_messages_batch,
+        crate::routes::messages::regenerate_last_message,
         crate::routes::messages::update_message,
         crate::routes::messages::delete_message,
         crate::routes::messages::get_message_edits,
+        crate::routes::drafts::get_draft,
+        crate::routes::drafts::save_draft,
+        crate::routes::drafts::clear_draft,
+        crate::routes::reads::get_read_state,
+        crate::routes::reads::mark_chat_read,
         crate::routes::attachments::get_message_attachments,
+        crate::routes::attachments::get_attachment_metadata,
         crate::routes::attachments::create_message_attachment,
         crate::routes::attachments::delete_attachment,
         crate::routes::notifications::get_notifications,
         crate::routes::notifications::get_unread_count,
         crate::routes::notifications::mark_notification_read,
         crate::routes::notifications::mark_all_read,
+        crate::routes::notifications::mark_read_by_filter,
         crate::routes::notifications::delete_notification,
         crate::routes::permissions::get_user_permissions,
         crate::routes::permissions::get_resource_permissions,
         crate::routes::permissions::grant_permission,
         crate::routes::permissions::revoke_permission,
+        crate::routes::admin::get_audit_log,
+        crate::routes::admin::list_user_sessions,
+        crate::routes::admin::revoke_user_sessions,
         crate::routes::websocket::websocket_handler
     ),
     components(
         schemas(
             crate::error::ErrorResponse,
+            crate::error::ValidationErrorResponse,
+            crate::error::ValidationErrorBody,
+            crate::error::FieldError,
             crate::routes::health::HealthResponse,
+            crate::routes::health::ReadinessResponse,
+            crate::routes::health::DependencyHealth,
+            crate::routes::health::TasksHealthResponse,
+            crate::routes::health::TaskHealth,
             crate::routes::auth::GithubLoginResponse,
             crate::routes::auth::GithubCallbackRequest,
             crate::routes::auth::SessionResponse,
             crate::routes::auth::UserResponse,
+            crate::routes::auth::IdentityResponse,
+            crate::routes::auth::IdentitiesResponse,
+            crate::routes::auth::CreateApiKeyRequest,
+            crate::routes::auth::ApiKeyResponse,
+            crate::routes::auth::CreatedApiKeyResponse,
+            crate::routes::auth::ApiKeysResponse,
+            crate::routes::users::UserProfileResponse,
+            crate::routes::users::ChatExport,
+            crate::routes::users::AttachmentManifestEntry,
             crate::routes::chat::ChatCompletionForm,
             crate::routes::chat::ChatCompletionResponse,
+            crate::routes::chat::EstimateCompletionRequest,
+            crate::routes::chat::CompletionEstimateResponse,
+            crate::routes::chat::ReplySuggestionsResponse,
+            crate::routes::guest::GuestChatRequest,
+            crate::routes::guest::GuestChatResponse,
             crate::routes::models::ModelsResponse,
             crate::routes::models::ModelSummary,
             crate::routes::models::ModelPricing,
             crate::routes::models::Folder,
             crate::routes::folders::FoldersResponse,
             crate::routes::folders::FolderResponse,
+            crate::routes::prompt_templates::PromptTemplate,
+            crate::routes::prompt_templates::CreatePromptTemplateRequest,
+            crate::routes::prompt_templates::UpdatePromptTemplateRequest,
+            crate::routes::prompt_templates::PromptTemplatesResponse,
+            crate::routes::prompt_templates::PromptTemplateResponse,
+            crate::routes::prompt_templates::TemplateBundle,
+            crate::routes::prompt_templates::BundledTemplate,
+            crate::routes::prompt_templates::ImportTemplatesResponse,
             crate::routes::models::Chat,
             crate::routes::models::User,
             crate::routes::models::Message,
+            crate::routes::models::MessageUsage,
             crate::routes::models::MessageEdit,
             crate::routes::models::MessageDeletion,
             crate::routes::models::MessageAttachment,
@@ -80,26 +152,50 @@ use utoipa::{Modify, OpenApi};
             crate::routes::models::InvitesResponse,
             crate::routes::models::InviteResponse,
             crate::routes::models::ChatMember,
+            crate::routes::models::ChatType,
+            crate::routes::models::MessageStatus,
+            crate::routes::models::MemberRole,
             crate::routes::models::UpdateMemberRoleRequest,
             crate::routes::models::MembersResponse,
             crate::routes::models::MemberResponse,
             crate::routes::models::CreateMessageRequest,
+            crate::routes::models::DiffSegment,
+            crate::routes::models::BatchMessage,
+            crate::routes::models::BatchCreateMessagesRequest,
             crate::routes::models::UpdateMessageRequest,
             crate::routes::models::CreateAttachmentRequest,
             crate::routes::models::CreateNotificationRequest,
             crate::routes::models::MarkNotificationReadRequest,
+            crate::routes::models::MarkNotificationsReadRequest,
             crate::routes::models::NotificationsResponse,
             crate::routes::models::NotificationResponse,
             crate::routes::models::CreatePermissionRequest,
             crate::routes::models::PermissionsResponse,
             crate::routes::models::PermissionResponse,
+            crate::routes::models::AuditLogEntry,
+            crate::routes::models::AuditLogResponse,
+            crate::routes::admin::UserSessionResponse,
+            crate::routes::admin::UserSessionsResponse,
+            crate::routes::admin::RevokedSessionsResponse,
             crate::routes::models::MessageEditsResponse,
+            crate::routes::models::MessageDraft,
+            crate::routes::models::SaveDraftRequest,
+            crate::routes::models::UpdateRetentionRequest,
+            crate::routes::models::UpdateChatModelRequest,
+            crate::routes::models::DraftResponse,
+            crate::routes::models::ChatReadState,
             crate::routes::models::AttachmentResponse,
             crate::routes::models::AttachmentsResponse,
             crate::routes::models::MessageResponse,
+            crate::routes::models::RegenerateMessageRequest,
+            crate::routes::models::RegenerateMessageResponse,
             crate::routes::models::MessagesResponse,
+            crate::routes::models::CountResponse,
             crate::routes::chats::ChatsResponse,
             crate::routes::chats::ChatDetailResponse,
+            crate::routes::chats::ChatStats,
+            crate::routes::chats::ChatStatsResponse,
+            crate::routes::chats::TypingUsersResponse,
             crate::routes::notifications::UnreadCountResponse,
             crate::routes::notifications::BulkUpdateResponse
         )
@@ -107,9 +203,12 @@ use utoipa::{Modify, OpenApi};
     tags(
         (name = "Health", description = "Service health endpoints"),
         (name = "Auth", description = "Authentication and session management"),
+        (name = "Users", description = "User profiles"),
         (name = "Models", description = "Model catalogue"),
         (name = "Chat", description = "LLM chat completions"),
+        (name = "Guest", description = "Unauthenticated demo completions"),
         (name = "Folders", description = "Folder management"),
+        (name = "Prompt Templates", description = "Reusable prompts with variables"),
         (name = "Chats", description = "Chat workspace operations"),
         (name = "Chat Invites", description = "Inviting users to chats"),
         (name = "Chat Members", description = "Managing chat membership"),
@@ -117,6 +216,7 @@ use utoipa::{Modify, OpenApi};
         (name = "Attachments", description = "Message attachment operations"),
         (name = "Notifications", description = "User notifications"),
         (name = "Permissions", description = "Resource permission management"),
+        (name = "Admin", description = "Workspace administration"),
         (name = "WebSocket", description = "Realtime updates stream")
     ),
     modifiers(&SecurityAddon)
@@ -1,22 +1,64 @@
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use denkwerk::LLMError;
 use serde::Serialize;
 use switchboard_auth::AuthError;
-use switchboard_orchestrator::OrchestratorError;
+use switchboard_orchestrator::{OrchestratorError, ProviderErrorKind, RateLimitInfo};
 use tracing::error;
 use utoipa::ToSchema;
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorResponse {
     pub error: String,
+    /// Machine-readable cause for provider failures, e.g. `context_length_exceeded`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+}
+
+/// Body of a validation failure: one entry per invalid input so clients can point
+/// at each offending field.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ValidationErrorResponse {
+    pub error: ValidationErrorBody,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ValidationErrorBody {
+    /// Always `validation`.
+    pub code: &'static str,
+    pub message: String,
+    pub fields: Vec<FieldError>,
+}
+
+/// A rejected input, addressed by its path in the request body, e.g. `title` or
+/// `messages[2].role`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ApiError {
     pub status: StatusCode,
     pub message: String,
+    pub headers: HeaderMap,
+    /// Per-field failures; when present the body uses the validation shape.
+    pub fields: Vec<FieldError>,
+    pub code: Option<&'static str>,
+    /// Set when the failure was SQLite reporting the database busy or locked, so
+    /// `retry_on_busy` runs the operation again. Not part of the response.
+    pub database_busy: bool,
 }
 
 impl ApiError {
@@ -24,9 +66,53 @@ impl ApiError {
         Self {
             status,
             message: message.into(),
+            headers: HeaderMap::new(),
+            fields: Vec::new(),
+            code: None,
+            database_busy: false,
+        }
+    }
+
+    /// A 400 listing every invalid field, rendered as
+    /// `{ "error": { "code": "validation", "message", "fields": [{ "field", "message" }] } }`.
+    pub fn validation(fields: Vec<FieldError>) -> Self {
+        let message = match fields.as_slice() {
+            [field] => format!("{}: {}", field.field, field.message),
+            _ => format!("{} fields are invalid", fields.len()),
+        };
+        Self {
+            fields,
+            ..Self::bad_request(message)
+        }
+    }
+
+    /// `Err(ApiError::validation(fields))` unless `fields` is empty.
+    pub fn check_fields(fields: Vec<FieldError>) -> Result<(), Self> {
+        if fields.is_empty() {
+            Ok(())
+        } else {
+            Err(Self::validation(fields))
         }
     }
 
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Mark the error as retryable when the database `error` behind it was SQLite
+    /// reporting the database busy or locked.
+    pub fn busy_from(mut self, error: &sqlx::Error) -> Self {
+        self.database_busy = crate::util::is_busy(error);
+        self
+    }
+
+    /// Attach upstream rate-limit headers to the error response.
+    pub fn with_rate_limit(mut self, rate_limit: &RateLimitInfo) -> Self {
+        self.headers.extend(rate_limit_headers(rate_limit));
+        self
+    }
+
     pub fn bad_request(message: impl Into<String>) -> Self {
         Self::new(StatusCode::BAD_REQUEST, message)
     }
@@ -43,6 +129,14 @@ impl ApiError {
         Self::new(StatusCode::FORBIDDEN, message)
     }
 
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, message)
+    }
+
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::PAYLOAD_TOO_LARGE, message)
+    }
+
     pub fn internal_server_error(message: impl Into<String>) -> Self {
         Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
     }
@@ -50,13 +144,36 @@ impl ApiError {
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        if !self.fields.is_empty() {
+            let body = Json(ValidationErrorResponse {
+                error: ValidationErrorBody {
+                    code: "validation",
+                    message: self.message,
+                    fields: self.fields,
+                },
+            });
+            return (self.status, self.headers, body).into_response();
+        }
+
         let body = Json(ErrorResponse {
             error: self.message,
+            code: self.code,
         });
-        (self.status, body).into_response()
+        (self.status, self.headers, body).into_response()
     }
 }
 
+/// `X-RateLimit-*` and `Retry-After` response headers for upstream rate-limit state.
+pub fn rate_limit_headers(rate_limit: &RateLimitInfo) -> HeaderMap {
+    rate_limit
+        .response_headers()
+        .into_iter()
+        .filter_map(|(name, value)| {
+            Some((HeaderName::from_static(name), HeaderValue::from_str(&value).ok()?))
+        })
+        .collect()
+}
+
 impl From<anyhow::Error> for ApiError {
     fn from(error: anyhow::Error) -> Self {
         error!(error = ?error, "internal error");
@@ -66,21 +183,44 @@ impl From<anyhow::Error> for ApiError {
 
 impl From<LLMError> for ApiError {
     fn from(error: LLMError) -> Self {
-        error!(error = ?error, "llm error");
-        Self::new(StatusCode::BAD_GATEWAY, error.to_string())
+        OrchestratorError::from(error).into()
     }
 }
 
 impl From<OrchestratorError> for ApiError {
     fn from(error: OrchestratorError) -> Self {
         error!(error = ?error, "orchestrator error");
-        let status = match error {
-            OrchestratorError::ProviderNotFound(_) => StatusCode::BAD_REQUEST,
-            OrchestratorError::OpenRouterApiKeyMissing
-            | OrchestratorError::OpenRouterUnavailable => StatusCode::SERVICE_UNAVAILABLE,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        let code = error.kind().map(ProviderErrorKind::as_str);
+        let mut api_error = match &error {
+            OrchestratorError::ProviderRateLimited(rate_limit) => {
+                Self::new(StatusCode::TOO_MANY_REQUESTS, error.to_string())
+                    .with_rate_limit(rate_limit)
+            }
+            _ => Self::new(orchestrator_error_status(&error), error.to_string()),
         };
-        Self::new(status, error.to_string())
+        api_error.code = code;
+        api_error
+    }
+}
+
+fn orchestrator_error_status(error: &OrchestratorError) -> StatusCode {
+    match error {
+        OrchestratorError::ProviderNotFound(_) | OrchestratorError::ModelNotFound(_) => {
+            StatusCode::BAD_REQUEST
+        }
+        OrchestratorError::ModelNotAllowed(_) => StatusCode::FORBIDDEN,
+        OrchestratorError::ContextLengthExceeded(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        OrchestratorError::CompletionCapacityExceeded
+        | OrchestratorError::ProviderRateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+        OrchestratorError::OpenRouterApiKeyMissing | OrchestratorError::OpenRouterUnavailable => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        // Our upstream credentials or the upstream itself; nothing the client can fix
+        OrchestratorError::ProviderAuthFailed(_)
+        | OrchestratorError::ProviderServerError(_)
+        | OrchestratorError::ProviderRejected { .. }
+        | OrchestratorError::Completion(_) => StatusCode::BAD_GATEWAY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
 
@@ -88,13 +228,24 @@ impl From<AuthError> for ApiError {
     fn from(error: AuthError) -> Self {
         error!(error = ?error, "auth error");
         let status = match error {
-            AuthError::GithubOauthDisabled => StatusCode::SERVICE_UNAVAILABLE,
-            AuthError::GithubOauth(_) => StatusCode::BAD_GATEWAY,
+            AuthError::GithubOauthDisabled | AuthError::GoogleOauthDisabled => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            AuthError::GithubOauth(_) | AuthError::GoogleOauth(_) => StatusCode::BAD_GATEWAY,
             AuthError::InvalidCredentials
             | AuthError::SessionNotFound
             | AuthError::SessionExpired
-            | AuthError::InvalidSession => StatusCode::UNAUTHORIZED,
-            AuthError::UserExists => StatusCode::BAD_REQUEST,
+            | AuthError::InvalidSession
+            | AuthError::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            AuthError::UserExists | AuthError::RedirectUriNotAllowed(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            AuthError::IdentityNotLinked(_)
+            | AuthError::ApiKeyNotFound
+            | AuthError::UserNotFound => StatusCode::NOT_FOUND,
+            AuthError::IdentityAlreadyLinked(_) | AuthError::LastLoginMethod => {
+                StatusCode::CONFLICT
+            }
             AuthError::Database(_) | AuthError::PasswordHash(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
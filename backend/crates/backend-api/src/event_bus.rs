@@ -0,0 +1,167 @@
+//! Cross-instance fan-out of [`ServerEvent`](crate::ServerEvent)s.
+//!
+//! Each instance delivers events to its own connections directly and also publishes
+//! them on the bus, on a channel per chat or user. Every instance subscribes to all
+//! of those channels and hands events published elsewhere to its local connections.
+
+use std::{future::Future, time::Duration};
+
+use axum::async_trait;
+use futures_util::{stream::BoxStream, StreamExt};
+use redis::{aio::ConnectionManager, AsyncCommands, Client};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, ServerEventEnvelope};
+
+pub const CHANNEL_PREFIX: &str = "switchboard:events:";
+
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+/// Connections an event is addressed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventTarget {
+    Chat(String),
+    User(i64),
+}
+
+impl EventTarget {
+    pub fn channel(&self) -> String {
+        match self {
+            EventTarget::Chat(chat_id) => format!("{CHANNEL_PREFIX}chat:{chat_id}"),
+            EventTarget::User(user_id) => format!("{CHANNEL_PREFIX}user:{user_id}"),
+        }
+    }
+
+    pub fn from_channel(channel: &str) -> Option<Self> {
+        let target = channel.strip_prefix(CHANNEL_PREFIX)?;
+        if let Some(chat_id) = target.strip_prefix("chat:") {
+            return Some(EventTarget::Chat(chat_id.to_string()));
+        }
+        let user_id = target.strip_prefix("user:")?.parse().ok()?;
+        Some(EventTarget::User(user_id))
+    }
+}
+
+/// Transport shared by every instance of a deployment.
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    async fn publish(&self, channel: String, payload: String) -> anyhow::Result<()>;
+
+    /// `(channel, payload)` for everything published under [`CHANNEL_PREFIX`],
+    /// including this instance's own messages.
+    async fn subscribe(&self) -> anyhow::Result<BoxStream<'static, (String, String)>>;
+}
+
+/// [`EventBus`] over Redis pub/sub.
+pub struct RedisEventBus {
+    client: Client,
+    connection: ConnectionManager,
+}
+
+impl RedisEventBus {
+    /// Publishes through `connection`; subscriptions open their own connection to `url`.
+    pub fn new(url: &str, connection: ConnectionManager) -> redis::RedisResult<Self> {
+        Ok(Self {
+            client: Client::open(url)?,
+            connection,
+        })
+    }
+}
+
+#[async_trait]
+impl EventBus for RedisEventBus {
+    async fn publish(&self, channel: String, payload: String) -> anyhow::Result<()> {
+        let mut connection = self.connection.clone();
+        connection.publish::<_, _, ()>(channel, payload).await?;
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> anyhow::Result<BoxStream<'static, (String, String)>> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.psubscribe(format!("{CHANNEL_PREFIX}*")).await?;
+
+        let messages = pubsub.into_on_message().filter_map(|message| async move {
+            let payload = message.get_payload::<String>().ok()?;
+            Some((message.get_channel_name().to_string(), payload))
+        });
+        Ok(messages.boxed())
+    }
+}
+
+#[derive(Serialize)]
+struct OutgoingPayload<'a> {
+    origin: &'a str,
+    envelope: &'a ServerEventEnvelope,
+}
+
+#[derive(Deserialize)]
+struct IncomingPayload {
+    origin: String,
+    envelope: ServerEventEnvelope,
+}
+
+pub(crate) fn encode(origin: &str, envelope: &ServerEventEnvelope) -> serde_json::Result<String> {
+    serde_json::to_string(&OutgoingPayload { origin, envelope })
+}
+
+/// Deliver events other instances publish to this instance's connections until
+/// `shutdown` resolves. Does nothing unless the state has an event bus.
+pub async fn run_event_bus<S>(state: AppState, shutdown: S)
+where
+    S: Future<Output = ()>,
+{
+    let Some(bus) = state.event_bus() else {
+        return;
+    };
+    tokio::pin!(shutdown);
+    let mut retry_delay = Duration::from_secs(1);
+
+    loop {
+        match bus.subscribe().await {
+            Ok(mut messages) => {
+                retry_delay = Duration::from_secs(1);
+                loop {
+                    tokio::select! {
+                        _ = &mut shutdown => return,
+                        message = messages.next() => match message {
+                            Some((channel, payload)) => {
+                                deliver_remote(&state, &channel, &payload).await;
+                            }
+                            None => break,
+                        },
+                    }
+                }
+                tracing::warn!("event bus subscription ended, resubscribing");
+            }
+            Err(e) => tracing::warn!("Failed to subscribe to the event bus: {}", e),
+        }
+
+        tokio::select! {
+            _ = &mut shutdown => return,
+            _ = tokio::time::sleep(retry_delay) => {}
+        }
+        retry_delay = (retry_delay * 2).min(MAX_RESUBSCRIBE_DELAY);
+    }
+}
+
+async fn deliver_remote(state: &AppState, channel: &str, payload: &str) {
+    let Some(target) = EventTarget::from_channel(channel) else {
+        return;
+    };
+    let incoming = match serde_json::from_str::<IncomingPayload>(payload) {
+        Ok(incoming) => incoming,
+        Err(e) => {
+            tracing::warn!("Dropping malformed event from channel {}: {}", channel, e);
+            return;
+        }
+    };
+    // This instance already delivered its own events locally
+    if incoming.origin == state.instance_id() {
+        return;
+    }
+
+    match target {
+        EventTarget::Chat(chat_id) => state.deliver_to_chat(&chat_id, &incoming.envelope).await,
+        EventTarget::User(user_id) => state.deliver_to_user(user_id, &incoming.envelope).await,
+    }
+}
@@ -0,0 +1,107 @@
+//! Per-user, per-chat limits on how fast messages can be sent, so a runaway client
+//! cannot flood a chat or set off a burst of completions. Guests are limited the same
+//! way per client IP. Separate from any HTTP rate limiting in front of the server.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::http::{header::RETRY_AFTER, HeaderValue, StatusCode};
+
+use crate::ApiError;
+
+// Buckets kept before idle ones are dropped
+const PRUNE_THRESHOLD: usize = 4_096;
+
+/// A token bucket for every key, by default a (user, chat) pair: it holds `burst`
+/// sends and refills at `per_minute`.
+#[derive(Debug)]
+pub struct MessageFloodGuard<K = (i64, i64)> {
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+impl<K> Default for MessageFloodGuard<K> {
+    fn default() -> Self {
+        Self {
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// A send refused by [`MessageFloodGuard::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageFlooded {
+    /// How long until the next send would be allowed.
+    pub retry_after: Duration,
+}
+
+impl<K: Eq + Hash> MessageFloodGuard<K> {
+    /// Spend one send for `key`. Always allowed when `burst` or `per_minute` is `0`.
+    pub fn check(&self, burst: u32, per_minute: u32, key: K) -> Result<(), MessageFlooded> {
+        if burst == 0 || per_minute == 0 {
+            return Ok(());
+        }
+
+        let capacity = f64::from(burst);
+        let per_second = f64::from(per_minute) / 60.0;
+        let now = Instant::now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| bucket.refilled(now, per_second, capacity) < capacity);
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        bucket.tokens = bucket.refilled(now, per_second, capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens < 1.0 {
+            let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / per_second);
+            return Err(MessageFlooded { retry_after });
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+impl Bucket {
+    fn refilled(&self, now: Instant, per_second: f64, capacity: f64) -> f64 {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        (self.tokens + elapsed * per_second).min(capacity)
+    }
+}
+
+impl MessageFlooded {
+    /// Whole seconds to wait, rounded up so that retrying then succeeds.
+    pub fn retry_after_seconds(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+impl From<MessageFlooded> for ApiError {
+    fn from(flooded: MessageFlooded) -> Self {
+        let seconds = flooded.retry_after_seconds();
+        let mut error = ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("sending messages too quickly; retry in {seconds}s"),
+        )
+        .with_code("message_rate_limited");
+        error.headers.insert(RETRY_AFTER, HeaderValue::from(seconds));
+        error
+    }
+}
@@ -0,0 +1,71 @@
+use switchboard_config::IdsConfig;
+
+use crate::ApiError;
+
+/// Resources whose public ids can carry a type prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Chat,
+    Message,
+    Folder,
+    Invite,
+    PromptTemplate,
+}
+
+impl ResourceKind {
+    pub const ALL: [ResourceKind; 5] = [
+        ResourceKind::Chat,
+        ResourceKind::Message,
+        ResourceKind::Folder,
+        ResourceKind::Invite,
+        ResourceKind::PromptTemplate,
+    ];
+
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            ResourceKind::Chat => "chat_",
+            ResourceKind::Message => "msg_",
+            ResourceKind::Folder => "fld_",
+            ResourceKind::Invite => "inv_",
+            ResourceKind::PromptTemplate => "tpl_",
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ResourceKind::Chat => "chat",
+            ResourceKind::Message => "message",
+            ResourceKind::Folder => "folder",
+            ResourceKind::Invite => "invite",
+            ResourceKind::PromptTemplate => "prompt template",
+        }
+    }
+
+    /// A fresh public id, prefixed when `config.prefixed_public_ids` is set.
+    pub fn new_public_id(self, config: &IdsConfig) -> String {
+        let id = cuid2::create_id();
+        if config.prefixed_public_ids {
+            format!("{}{}", self.prefix(), id)
+        } else {
+            id
+        }
+    }
+
+    /// Reject an id that carries another resource's prefix before it reaches the
+    /// database. Unprefixed ids are accepted so ids minted before prefixing was
+    /// switched on keep resolving.
+    pub fn check_public_id(self, public_id: &str) -> Result<(), ApiError> {
+        let foreign = ResourceKind::ALL
+            .into_iter()
+            .find(|kind| *kind != self && public_id.starts_with(kind.prefix()));
+
+        match foreign {
+            Some(kind) => Err(ApiError::bad_request(format!(
+                "expected a {} id but got a {} id",
+                self.as_str(),
+                kind.as_str()
+            ))),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,37 +1,87 @@
+mod api_keys;
+pub mod audit;
 mod docs;
 mod error;
+pub mod event_bus;
+pub mod flood;
+pub mod ids;
+mod remote;
 mod state;
+pub mod typing;
 mod util;
 
+pub mod retention;
 pub mod routes;
+pub mod streaming;
+pub mod titles;
 
+pub use api_keys::{require_scope, GrantedScopes};
 pub use docs::ApiDoc;
-pub use error::ApiError;
-pub use state::{AppState, ClientEvent, OAuthStateStore, ServerEvent};
-pub use util::require_bearer;
+pub use error::{ApiError, FieldError};
+pub use state::{AppState, ClientEvent, OAuthStateStore, ServerEvent, ServerEventEnvelope};
+pub use util::{require_bearer, retry_on_busy, with_transaction, AuthUser, BusyError};
 
 use axum::{
-    http::header::{AUTHORIZATION, CONTENT_TYPE},
+    extract::DefaultBodyLimit,
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        HeaderValue, StatusCode,
+    },
+    middleware,
+    response::{IntoResponse, Response},
     routing::{delete, get, post, put},
     Router,
 };
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::{
+    cors::{AllowOrigin, Any, CorsLayer},
+    limit::RequestBodyLimitLayer,
+};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 pub fn build_router(state: AppState) -> Router {
     let docs = SwaggerUi::new("/docs").url("/docs/openapi.json", docs::ApiDoc::openapi());
+    let max_body_bytes = state.config().http.max_body_bytes;
+    let max_upload_body_bytes = state.config().http.max_upload_body_bytes;
+    let cors = cors_layer(&state.config().http.allowed_origins);
+
+    // Upload routes carry file payloads and get a separate, larger body limit.
+    let uploads = Router::new()
+        .route("/api/chat", post(routes::chat::chat_completion))
+        .route(
+            "/api/chats/:chat_id/messages/:message_id/attachments",
+            post(routes::attachments::create_message_attachment),
+        )
+        .layer(RequestBodyLimitLayer::new(max_upload_body_bytes));
 
     Router::new()
         .route("/health", get(routes::health::health_check))
+        .route("/health/live", get(routes::health::liveness_check))
+        .route("/health/ready", get(routes::health::readiness_check))
+        .route("/health/tasks", get(routes::health::tasks_check))
         .route("/api/auth/github/login", get(routes::auth::github_login))
         .route(
             "/api/auth/github/callback",
             post(routes::auth::github_callback),
         )
+        .route("/api/auth/github/link", post(routes::auth::link_github))
+        .route("/api/auth/identities", get(routes::auth::list_identities))
+        .route(
+            "/api/auth/identities/:provider",
+            delete(routes::auth::unlink_identity),
+        )
+        .route(
+            "/api/auth/api-keys",
+            get(routes::auth::list_api_keys)
+                .post(routes::auth::create_api_key),
+        )
+        .route(
+            "/api/auth/api-keys/:key_id",
+            delete(routes::auth::revoke_api_key),
+        )
         .route("/api/auth/dev/token", get(routes::auth::dev_token))
         .route("/api/models", get(routes::models::list_models))
-        .route("/api/chat", post(routes::chat::chat_completion))
+        .route("/api/guest/chat", post(routes::guest::guest_chat))
         // Folder routes
         .route("/api/folders", get(routes::folders::list_folders))
         .route("/api/folders", post(routes::folders::create_folder))
@@ -44,12 +94,54 @@ pub fn build_router(state: AppState) -> Router {
             "/api/folders/:folder_id",
             delete(routes::folders::delete_folder),
         )
+        // Prompt template routes
+        .route(
+            "/api/prompt-templates",
+            get(routes::prompt_templates::list_templates),
+        )
+        .route(
+            "/api/prompt-templates",
+            post(routes::prompt_templates::create_template),
+        )
+        .route(
+            "/api/prompt-templates/export",
+            get(routes::prompt_templates::export_templates),
+        )
+        .route(
+            "/api/prompt-templates/import",
+            post(routes::prompt_templates::import_templates),
+        )
+        .route(
+            "/api/prompt-templates/:template_id",
+            get(routes::prompt_templates::get_template),
+        )
+        .route(
+            "/api/prompt-templates/:template_id",
+            put(routes::prompt_templates::update_template),
+        )
+        .route(
+            "/api/prompt-templates/:template_id",
+            delete(routes::prompt_templates::delete_template),
+        )
         // Chat routes
         .route("/api/chats", get(routes::chats::list_chats))
         .route("/api/chats", post(routes::chats::create_chat))
+        .route("/api/chats/count", get(routes::chats::count_chats))
         .route("/api/chats/:chat_id", get(routes::chats::get_chat))
         .route("/api/chats/:chat_id", put(routes::chats::update_chat))
         .route("/api/chats/:chat_id", delete(routes::chats::delete_chat))
+        .route("/api/chats/:chat_id/retention", put(routes::chats::update_retention))
+        .route("/api/chats/:chat_id/model", put(routes::chats::update_chat_model))
+        .route("/api/chats/:chat_id/stats", get(routes::chats::get_chat_stats))
+        .route("/api/chats/:chat_id/typing", get(routes::chats::get_typing_users))
+        .route(
+            "/api/chats/:chat_id/completions/estimate",
+            post(routes::chat::estimate_completion),
+        )
+        .route(
+            "/api/chats/:chat_id/suggestions",
+            get(routes::chat::suggest_replies),
+        )
         // Invite routes
         .route(
             "/api/chats/:chat_id/invites",
@@ -59,6 +151,7 @@ pub fn build_router(state: AppState) -> Router {
             "/api/chats/:chat_id/invites",
             post(routes::chats::create_invite),
         )
+        .route("/api/invites", get(routes::chats::list_user_invites))
         .route(
             "/api/invites/:invite_id/accept",
             post(routes::chats::accept_invite),
@@ -72,6 +165,10 @@ pub fn build_router(state: AppState) -> Router {
             "/api/chats/:chat_id/members",
             get(routes::chats::list_members),
         )
+        .route(
+            "/api/chats/:chat_id/members/me",
+            get(routes::chats::get_my_membership),
+        )
         .route(
             "/api/chats/:chat_id/members/:member_user_id",
             put(routes::chats::update_member_role),
@@ -89,6 +186,18 @@ pub fn build_router(state: AppState) -> Router {
             "/api/chats/:chat_id/messages",
             post(routes::messages::create_message),
         )
+        .route(
+            "/api/chats/:chat_id/messages/count",
+            get(routes::messages::count_messages),
+        )
+        .route(
+            "/api/chats/:chat_id/messages/batch",
+            post(routes::messages::create_messages_batch),
+        )
+        .route(
+            "/api/chats/:chat_id/messages/regenerate",
+            post(routes::messages::regenerate_last_message),
+        )
         .route(
             "/api/chats/:chat_id/messages/:message_id",
             put(routes::messages::update_message),
@@ -101,19 +210,25 @@ pub fn build_router(state: AppState) -> Router {
             "/api/chats/:chat_id/messages/:message_id/edits",
             get(routes::messages::get_message_edits),
         )
+        // Draft routes
+        .route("/api/chats/:chat_id/draft", get(routes::drafts::get_draft))
+        .route("/api/chats/:chat_id/draft", put(routes::drafts::save_draft))
+        .route("/api/chats/:chat_id/draft", delete(routes::drafts::clear_draft))
+        .route("/api/chats/:chat_id/read", get(routes::reads::get_read_state))
+        .route("/api/chats/:chat_id/read", post(routes::reads::mark_chat_read))
         // Attachment routes
         .route(
             "/api/chats/:chat_id/messages/:message_id/attachments",
             get(routes::attachments::get_message_attachments),
         )
-        .route(
-            "/api/chats/:chat_id/messages/:message_id/attachments",
-            post(routes::attachments::create_message_attachment),
-        )
         .route(
             "/api/chats/:chat_id/messages/:message_id/attachments/:attachment_id",
             delete(routes::attachments::delete_attachment),
         )
+        .route(
+            "/api/chats/:chat_id/messages/:message_id/attachments/:attachment_id/meta",
+            get(routes::attachments::get_attachment_metadata),
+        )
         // Notification routes
         .route(
             "/api/notifications",
@@ -127,6 +242,10 @@ pub fn build_router(state: AppState) -> Router {
             "/api/notifications/mark-all-read",
             post(routes::notifications::mark_all_read),
         )
+        .route(
+            "/api/notifications/mark-read",
+            post(routes::notifications::mark_read_by_filter),
+        )
         .route(
             "/api/notifications/:notification_id",
             put(routes::notifications::mark_notification_read),
@@ -135,6 +254,10 @@ pub fn build_router(state: AppState) -> Router {
             "/api/notifications/:notification_id",
             delete(routes::notifications::delete_notification),
         )
+        // User routes
+        .route("/api/users/me", get(routes::users::get_current_user))
+        .route("/api/users/me/export", get(routes::users::export_current_user))
+        .route("/api/users/me/public-id", post(routes::users::rotate_public_id))
         // Permission routes
         .route(
             "/api/users/:user_id/permissions",
@@ -152,16 +275,59 @@ pub fn build_router(state: AppState) -> Router {
             "/api/permissions/:resource_type/:resource_id/:user_id",
             delete(routes::permissions::revoke_permission),
         )
+        // Admin routes
+        .route("/api/admin/audit", get(routes::admin::get_audit_log))
+        .route(
+            "/api/admin/users/:user_id/sessions",
+            get(routes::admin::list_user_sessions)
+                .delete(routes::admin::revoke_user_sessions),
+        )
         // WebSocket route
         .route("/ws", get(routes::websocket::websocket_handler))
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .merge(uploads)
         .merge(docs)
+        .layer(DefaultBodyLimit::disable())
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            api_keys::enforce_api_key_scopes,
+        ))
+        .layer(middleware::map_response(payload_too_large_envelope))
         .with_state(state)
-        .layer(cors_layer())
+        .layer(cors)
+}
+
+/// Body limit rejections are produced by tower-http and axum extractors as plain text;
+/// rewrite them into the standard error envelope.
+async fn payload_too_large_envelope(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return ApiError::payload_too_large("request body too large").into_response();
+    }
+
+    response
 }
 
-fn cors_layer() -> CorsLayer {
+fn cors_layer(allowed_origins: &[String]) -> CorsLayer {
+    let allow_origin = if allowed_origins.is_empty() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(allowed_origins.iter().filter_map(|origin| {
+            let value = HeaderValue::from_str(origin).ok();
+            if value.is_none() {
+                tracing::warn!(origin = origin.as_str(), "Ignoring invalid allowed origin");
+            }
+            value
+        }))
+    };
+
     CorsLayer::new()
-        .allow_origin(Any)
+        .allow_origin(allow_origin)
         .allow_methods([
             axum::http::Method::GET,
             axum::http::Method::POST,
@@ -0,0 +1,177 @@
+//! Server-side fetching of attachment content from client-supplied URLs.
+//!
+//! Every hop is checked before connecting: only http(s) is allowed, and each address
+//! the host resolves to must be public. The connection is then pinned to the vetted
+//! address so a second DNS lookup cannot swap in an internal one.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use axum::http::StatusCode;
+use reqwest::{
+    header::{CONTENT_TYPE, LOCATION},
+    redirect::Policy,
+    Client,
+};
+use switchboard_config::{OutboundHttpConfig, USER_AGENT};
+use url::{Host, Url};
+
+use crate::ApiError;
+
+const MAX_REDIRECTS: usize = 3;
+
+pub(crate) struct RemoteFile {
+    pub bytes: Vec<u8>,
+    /// The response's MIME type without parameters, when it sent one.
+    pub content_type: Option<String>,
+}
+
+pub(crate) async fn fetch_attachment(
+    source_url: &str,
+    max_bytes: usize,
+    outbound: &OutboundHttpConfig,
+) -> Result<RemoteFile, ApiError> {
+    let mut url =
+        Url::parse(source_url).map_err(|_| ApiError::bad_request("source_url is not a valid URL"))?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let address = resolve_public_address(&url).await?;
+
+        let mut client = Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(Duration::from_secs(outbound.connect_timeout_seconds))
+            .timeout(Duration::from_secs(outbound.request_timeout_seconds))
+            // Redirects are followed below so each hop is checked
+            .redirect(Policy::none());
+        if let Some(Host::Domain(domain)) = url.host() {
+            client = client.resolve(domain, address);
+        }
+        let client = client.build().map_err(|e| {
+            tracing::error!("Failed to build attachment fetch client: {}", e);
+            ApiError::internal_server_error("Failed to fetch source_url")
+        })?;
+
+        let mut response = client.get(url.clone()).send().await.map_err(|e| {
+            tracing::warn!("Failed to fetch attachment source {}: {}", url, e);
+            ApiError::new(StatusCode::BAD_GATEWAY, "Failed to fetch source_url")
+        })?;
+
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| url.join(value).ok())
+                .ok_or_else(|| {
+                    ApiError::new(StatusCode::BAD_GATEWAY, "source_url returned a bad redirect")
+                })?;
+            url = location;
+            continue;
+        }
+        if !status.is_success() {
+            return Err(ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                format!("source_url returned {}", status),
+            ));
+        }
+
+        if response.content_length().is_some_and(|length| length > max_bytes as u64) {
+            return Err(too_large(max_bytes));
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .filter(|value| !value.is_empty());
+
+        // Content-Length can be missing or wrong, so the limit is enforced while reading
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| {
+            tracing::warn!("Failed to read attachment source {}: {}", url, e);
+            ApiError::new(StatusCode::BAD_GATEWAY, "Failed to fetch source_url")
+        })? {
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(too_large(max_bytes));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        return Ok(RemoteFile {
+            bytes,
+            content_type,
+        });
+    }
+
+    Err(ApiError::new(StatusCode::BAD_GATEWAY, "source_url redirected too many times"))
+}
+
+fn too_large(max_bytes: usize) -> ApiError {
+    ApiError::payload_too_large(format!("attachment exceeds the limit of {max_bytes} bytes"))
+}
+
+async fn resolve_public_address(url: &Url) -> Result<SocketAddr, ApiError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ApiError::bad_request("source_url must use http or https"));
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let addresses: Vec<SocketAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|_| ApiError::bad_request("source_url host could not be resolved"))?
+            .collect(),
+        None => return Err(ApiError::bad_request("source_url must include a host")),
+    };
+
+    // All records must be public, otherwise a host that also resolves internally could
+    // be used to reach the internal address
+    match addresses.first() {
+        Some(address) if addresses.iter().all(|address| is_public_ip(address.ip())) => {
+            Ok(*address)
+        }
+        _ => Err(ApiError::bad_request("source_url must point to a public address")),
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking
+                || (a == 198 && (b == 18 || b == 19))
+                // Reserved
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local
+                || (first & 0xfe00) == 0xfc00
+                // Link local
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
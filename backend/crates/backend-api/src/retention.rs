@@ -0,0 +1,57 @@
+use std::{future::Future, time::Duration};
+
+use crate::AppState;
+
+/// Delete every message older than its chat's `message_retention_days`. Chats without
+/// a retention policy are left alone. Returns the number of messages removed.
+pub async fn purge_expired_messages(pool: &sqlx::SqlitePool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM messages
+        WHERE id IN (
+            SELECT m.id FROM messages m
+            JOIN chats c ON c.id = m.chat_id
+            WHERE c.message_retention_days IS NOT NULL
+              AND julianday(m.created_at) < julianday('now') - c.message_retention_days
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Name of the retention sweeper in the task registry.
+pub const RETENTION_TASK: &str = "message_retention";
+
+/// Purge expired messages every `interval` until `shutdown` resolves.
+pub async fn run_retention_sweeper<S>(state: AppState, interval: Duration, shutdown: S)
+where
+    S: Future<Output = ()>,
+{
+    tokio::pin!(shutdown);
+    state.tasks().register(RETENTION_TASK, interval);
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = ticker.tick() => {}
+        }
+
+        let result = purge_expired_messages(state.db_pool()).await;
+        match &result {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!(purged, "purged messages past chat retention"),
+            Err(e) => tracing::error!("Failed to purge expired messages: {}", e),
+        }
+        let outcome = result
+            .map(|purged| format!("purged {purged} messages"))
+            .map_err(|e| e.to_string());
+        state.tasks().record(RETENTION_TASK, outcome);
+    }
+
+    tracing::debug!("retention sweeper stopped");
+}
@@ -0,0 +1,266 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use switchboard_auth::SessionSummary;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    audit::{self, record_audit, ClientIp, AUDIT_ACTIONS},
+    routes::{
+        models::{AuditLogEntry, AuditLogResponse},
+        permissions::PermissionsService,
+    },
+    util::AuthUser,
+    ApiError, AppState,
+};
+
+const MAX_AUDIT_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct AuditLogQuery {
+    /// Public id of the acting user.
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    /// RFC 3339 lower bound (inclusive) on `created_at`.
+    pub since: Option<String>,
+    /// RFC 3339 upper bound (exclusive) on `created_at`.
+    pub until: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserSessionResponse {
+    pub id: i64,
+    /// The start of the session token; the full token is never returned.
+    pub token_prefix: String,
+    pub created_at: String,
+    pub expires_at: String,
+    /// Only recorded while sessions have an idle timeout, to within a minute.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<String>,
+}
+
+impl From<SessionSummary> for UserSessionResponse {
+    fn from(value: SessionSummary) -> Self {
+        Self {
+            id: value.id,
+            token_prefix: value.token_prefix,
+            created_at: value.created_at.to_rfc3339(),
+            expires_at: value.expires_at.to_rfc3339(),
+            last_used_at: value.last_used_at.map(|value| value.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserSessionsResponse {
+    pub sessions: Vec<UserSessionResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RevokedSessionsResponse {
+    /// Sessions ended.
+    pub revoked: u64,
+}
+
+// List audit log entries, newest first
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit",
+    tag = "Admin",
+    security(("bearerAuth" = [])),
+    params(AuditLogQuery),
+    responses(
+        (status = 200, description = "Audit log entries, newest first", body = AuditLogResponse),
+        (status = 400, description = "Invalid filter", body = crate::error::ErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Workspace admin permission required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch audit log", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_audit_log(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<AuditLogResponse>, ApiError> {
+    if !PermissionsService::is_workspace_admin(state.db_pool(), user.id).await? {
+        return Err(ApiError::forbidden("Workspace admin permission required"));
+    }
+
+    if let Some(action) = query.action.as_deref() {
+        if !AUDIT_ACTIONS.contains(&action) {
+            return Err(ApiError::bad_request(format!("Invalid action: {}", action)));
+        }
+    }
+    let since = parse_bound("since", query.since.as_deref())?;
+    let until = parse_bound("until", query.until.as_deref())?;
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_AUDIT_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let actor_id = match query.actor.as_deref() {
+        Some(actor) => {
+            let actor_id: Option<i64> =
+                sqlx::query_scalar("SELECT id FROM users WHERE public_id = ?")
+                    .bind(actor)
+                    .fetch_optional(state.db_read_pool())
+                    .await
+                    .map_err(|e| {
+                        tracing::error!("Failed to resolve audit actor: {}", e);
+                        ApiError::internal_server_error("Failed to fetch audit log")
+                    })?;
+            // An unknown actor can't have any entries.
+            match actor_id {
+                Some(actor_id) => Some(actor_id),
+                None => return Ok(Json(AuditLogResponse { entries: Vec::new() })),
+            }
+        }
+        None => None,
+    };
+
+    let entries = sqlx::query_as::<_, AuditLogEntry>(
+        r#"
+        SELECT id, actor_user_id, action, target, ip, created_at
+        FROM audit_log
+        WHERE (? IS NULL OR actor_user_id = ?)
+          AND (? IS NULL OR action = ?)
+          AND (? IS NULL OR created_at >= ?)
+          AND (? IS NULL OR created_at < ?)
+        ORDER BY created_at DESC, id DESC
+        LIMIT ? OFFSET ?
+        "#,
+    )
+    .bind(actor_id)
+    .bind(actor_id)
+    .bind(&query.action)
+    .bind(&query.action)
+    .bind(&since)
+    .bind(&since)
+    .bind(&until)
+    .bind(&until)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(state.db_replica_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch audit log: {}", e);
+        ApiError::internal_server_error("Failed to fetch audit log")
+    })?;
+
+    Ok(Json(AuditLogResponse { entries }))
+}
+
+// List a user's active sessions, newest first
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{user_id}/sessions",
+    tag = "Admin",
+    security(("bearerAuth" = [])),
+    params(
+        ("user_id" = String, Path, description = "User public identifier")
+    ),
+    responses(
+        (status = 200, description = "The user's active sessions, without their tokens", body = UserSessionsResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Workspace admin permission required", body = crate::error::ErrorResponse),
+        (status = 404, description = "User not found", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch sessions", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn list_user_sessions(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    AuthUser(admin): AuthUser,
+    Path(user_id): Path<String>,
+) -> Result<Json<UserSessionsResponse>, ApiError> {
+    if !PermissionsService::is_workspace_admin(state.db_pool(), admin.id).await? {
+        return Err(ApiError::forbidden("Workspace admin permission required"));
+    }
+    let target_id = resolve_user(&state, &user_id).await?;
+
+    let sessions = state.authenticator().list_sessions(target_id).await?;
+
+    record_audit(
+        &state,
+        Some(admin.id),
+        audit::SESSIONS_LISTED,
+        Some(&user_id),
+        ip.as_deref(),
+    )
+    .await;
+
+    Ok(Json(UserSessionsResponse {
+        sessions: sessions.into_iter().map(UserSessionResponse::from).collect(),
+    }))
+}
+
+// End every session of a user, logging them out everywhere
+#[utoipa::path(
+    delete,
+    path = "/api/admin/users/{user_id}/sessions",
+    tag = "Admin",
+    security(("bearerAuth" = [])),
+    params(
+        ("user_id" = String, Path, description = "User public identifier")
+    ),
+    responses(
+        (status = 200, description = "Sessions revoked", body = RevokedSessionsResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Workspace admin permission required", body = crate::error::ErrorResponse),
+        (status = 404, description = "User not found", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to revoke sessions", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn revoke_user_sessions(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    AuthUser(admin): AuthUser,
+    Path(user_id): Path<String>,
+) -> Result<Json<RevokedSessionsResponse>, ApiError> {
+    if !PermissionsService::is_workspace_admin(state.db_pool(), admin.id).await? {
+        return Err(ApiError::forbidden("Workspace admin permission required"));
+    }
+    let target_id = resolve_user(&state, &user_id).await?;
+
+    let revoked = state.authenticator().revoke_all_sessions(target_id).await?;
+
+    record_audit(
+        &state,
+        Some(admin.id),
+        audit::SESSIONS_REVOKED,
+        Some(&user_id),
+        ip.as_deref(),
+    )
+    .await;
+
+    Ok(Json(RevokedSessionsResponse { revoked }))
+}
+
+async fn resolve_user(state: &AppState, public_id: &str) -> Result<i64, ApiError> {
+    let user_id: Option<i64> = sqlx::query_scalar("SELECT id FROM users WHERE public_id = ?")
+        .bind(public_id)
+        .fetch_optional(state.db_read_pool())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to resolve user {}: {}", public_id, e);
+            ApiError::internal_server_error("Failed to resolve user")
+        })?;
+
+    user_id.ok_or_else(|| ApiError::not_found("User not found"))
+}
+
+/// Normalise a time filter to the RFC 3339 UTC form `created_at` is stored in, so the
+/// string comparison in SQL orders correctly.
+fn parse_bound(name: &str, value: Option<&str>) -> Result<Option<String>, ApiError> {
+    value
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|time| time.with_timezone(&chrono::Utc).to_rfc3339())
+                .map_err(|_| ApiError::bad_request(format!("Invalid {} timestamp", name)))
+        })
+        .transpose()
+}
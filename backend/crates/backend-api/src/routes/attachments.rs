@@ -3,8 +3,10 @@ use axum::{
     http::HeaderMap,
     Json,
 };
+use base64::{engine::general_purpose, Engine as _};
 
 use crate::{
+    remote,
     routes::models::{
         AttachmentResponse, AttachmentsResponse, CreateAttachmentRequest, MessageAttachment,
     },
@@ -12,6 +14,92 @@ use crate::{
     ApiError, AppState,
 };
 
+// The stored form of an attachment: its MIME type, URL and size in bytes
+struct StoredAttachment {
+    file_type: String,
+    file_url: String,
+    file_size_bytes: i64,
+}
+
+// External file_urls are kept as given; inline and fetched content is stored as a
+// data URL so it behaves like any other attachment afterwards
+async fn store_attachment(
+    state: &AppState,
+    req: &CreateAttachmentRequest,
+) -> Result<StoredAttachment, ApiError> {
+    let max_bytes = state.config().chat.max_attachment_bytes;
+
+    let (file_type, bytes) = match (&req.file_url, &req.content, &req.source_url) {
+        (Some(file_url), None, None) => {
+            // The file is not fetched, so the claimed size and type are all there is
+            // to check against the limits
+            let file_size_bytes = req
+                .file_size_bytes
+                .ok_or_else(|| ApiError::bad_request("file_size_bytes is required with file_url"))?;
+            if file_size_bytes < 0 {
+                return Err(ApiError::bad_request("file_size_bytes must not be negative"));
+            }
+            check_attachment_size(file_size_bytes as u64, max_bytes)?;
+            return Ok(StoredAttachment {
+                file_type: allowed_attachment_type(state, &req.file_type)?,
+                file_url: file_url.clone(),
+                file_size_bytes,
+            });
+        }
+        (None, Some(content), None) => {
+            let bytes = general_purpose::STANDARD
+                .decode(content)
+                .map_err(|_| ApiError::bad_request("content must be base64 encoded"))?;
+            check_attachment_size(bytes.len() as u64, max_bytes)?;
+            (req.file_type.clone(), bytes)
+        }
+        (None, None, Some(source_url)) => {
+            let file =
+                remote::fetch_attachment(source_url, max_bytes, &state.config().outbound).await?;
+            (file.content_type.unwrap_or_else(|| req.file_type.clone()), file.bytes)
+        }
+        _ => {
+            return Err(ApiError::bad_request(
+                "exactly one of file_url, content or source_url is required",
+            ));
+        }
+    };
+
+    let file_type = allowed_attachment_type(state, &file_type)?;
+    Ok(StoredAttachment {
+        file_url: format!("data:{};base64,{}", file_type, general_purpose::STANDARD.encode(&bytes)),
+        file_type,
+        file_size_bytes: bytes.len() as i64,
+    })
+}
+
+fn check_attachment_size(size: u64, max_bytes: usize) -> Result<(), ApiError> {
+    if size > max_bytes as u64 {
+        return Err(ApiError::payload_too_large(format!(
+            "attachment exceeds the limit of {max_bytes} bytes"
+        )));
+    }
+    Ok(())
+}
+
+// The normalised MIME type, if `[chat] allowed_attachment_types` lets it through
+fn allowed_attachment_type(state: &AppState, file_type: &str) -> Result<String, ApiError> {
+    let file_type = file_type.trim().to_ascii_lowercase();
+    let allowed = state
+        .config()
+        .chat
+        .allowed_attachment_types
+        .iter()
+        .any(|pattern| match pattern.strip_suffix("/*") {
+            Some(prefix) => file_type.split_once('/').is_some_and(|(kind, _)| kind == prefix),
+            None => *pattern == file_type,
+        });
+    if !allowed {
+        return Err(ApiError::bad_request(format!("attachment type {file_type} is not allowed")));
+    }
+    Ok(file_type)
+}
+
 // Get attachments for a message
 #[utoipa::path(
     get,
@@ -55,7 +143,9 @@ pub async fn get_message_attachments(
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     // Get the message ID
     let message_db_id: Option<i64> =
@@ -91,6 +181,78 @@ pub async fn get_message_attachments(
     Ok(Json(AttachmentsResponse { attachments }))
 }
 
+// Get metadata for a single attachment without fetching its contents
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/messages/{message_id}/attachments/{attachment_id}/meta",
+    tag = "Attachments",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier"),
+        ("message_id" = String, Path, description = "Message public identifier"),
+        ("attachment_id" = i64, Path, description = "Attachment identifier")
+    ),
+    responses(
+        (status = 200, description = "Attachment metadata", body = AttachmentResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 404, description = "Attachment not found", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch attachment", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_attachment_metadata(
+    State(state): State<AppState>,
+    Path((chat_id, message_public_id, attachment_id)): Path<(String, String, i64)>,
+    headers: HeaderMap,
+) -> Result<Json<AttachmentResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    // Check if user is a member of the chat
+    let chat_db_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT c.id FROM chats c
+        JOIN chat_members cm ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(&chat_id)
+    .bind(user.id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to check chat membership: {}", e);
+        ApiError::internal_server_error("Failed to check chat membership")
+    })?;
+
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
+
+    // Only resolve attachments that belong to this message within this chat
+    let attachment = sqlx::query_as::<_, MessageAttachment>(
+        r#"
+        SELECT ma.id, ma.message_id, ma.file_name, ma.file_type, ma.file_url,
+               ma.file_size_bytes, ma.created_at
+        FROM message_attachments ma
+        JOIN messages m ON ma.message_id = m.id
+        WHERE ma.id = ? AND m.public_id = ? AND m.chat_id = ?
+        "#,
+    )
+    .bind(attachment_id)
+    .bind(&message_public_id)
+    .bind(chat_db_id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch attachment: {}", e);
+        ApiError::internal_server_error("Failed to fetch attachment")
+    })?
+    .ok_or_else(|| ApiError::not_found("Attachment not found"))?;
+
+    Ok(Json(AttachmentResponse { attachment }))
+}
+
 // Create attachment for a message
 #[utoipa::path(
     post,
@@ -108,7 +270,9 @@ pub async fn get_message_attachments(
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
         (status = 404, description = "Message not found", body = crate::error::ErrorResponse),
-        (status = 500, description = "Failed to create attachment", body = crate::error::ErrorResponse)
+        (status = 413, description = "Attachment too large", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to create attachment", body = crate::error::ErrorResponse),
+        (status = 502, description = "source_url could not be fetched", body = crate::error::ErrorResponse)
     )
 )]
 pub async fn create_message_attachment(
@@ -137,7 +301,9 @@ pub async fn create_message_attachment(
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     // Get the message ID
     let message_db_id: Option<i64> =
@@ -153,28 +319,57 @@ pub async fn create_message_attachment(
 
     let message_db_id = message_db_id.ok_or_else(|| ApiError::not_found("Message not found"))?;
 
+    let max_attachments = state.config().chat.max_attachments_per_message;
+    let existing_attachments: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM message_attachments WHERE message_id = ?")
+            .bind(message_db_id)
+            .fetch_one(state.db_pool())
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to count attachments: {}", e);
+                ApiError::internal_server_error("Failed to count attachments")
+            })?;
+
+    let limit_reached = || {
+        ApiError::bad_request(format!(
+            "message already has the maximum of {max_attachments} attachments"
+        ))
+    };
+    // Checked up front so a full message is refused before any content is fetched
+    if existing_attachments as usize >= max_attachments {
+        return Err(limit_reached());
+    }
+
+    let stored = store_attachment(&state, &req).await?;
     let now = chrono::Utc::now().to_rfc3339();
 
-    // Create the attachment
-    let attachment_db_id = sqlx::query(
+    // The count is checked again in the insert itself, so concurrent uploads cannot
+    // all pass the check above and exceed the limit together
+    let result = sqlx::query(
         r#"
         INSERT INTO message_attachments (message_id, file_name, file_type, file_url, file_size_bytes, created_at)
-        VALUES (?, ?, ?, ?, ?, ?)
+        SELECT ?, ?, ?, ?, ?, ?
+        WHERE (SELECT COUNT(*) FROM message_attachments WHERE message_id = ?) < ?
         "#
     )
     .bind(message_db_id)
     .bind(&req.file_name)
-    .bind(&req.file_type)
-    .bind(&req.file_url)
-    .bind(req.file_size_bytes)
+    .bind(&stored.file_type)
+    .bind(&stored.file_url)
+    .bind(stored.file_size_bytes)
     .bind(&now)
+    .bind(message_db_id)
+    .bind(max_attachments as i64)
     .execute(state.db_pool())
     .await
     .map_err(|e| {
         tracing::error!("Failed to create attachment: {}", e);
         ApiError::internal_server_error("Failed to create attachment")
-    })?
-    .last_insert_rowid();
+    })?;
+    if result.rows_affected() == 0 {
+        return Err(limit_reached());
+    }
+    let attachment_db_id = result.last_insert_rowid();
 
     // Fetch the created attachment
     let attachment = sqlx::query_as::<_, MessageAttachment>(
@@ -240,7 +435,9 @@ pub async fn delete_attachment(
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     // Get the message ID and verify attachment belongs to this message/chat
     let message_details: Option<(i64,)> = sqlx::query_as(
@@ -1,12 +1,19 @@
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
+    http::HeaderMap,
     Json,
 };
 use serde::{Deserialize, Serialize};
-use switchboard_auth::{AuthSession, User};
+use switchboard_auth::{ApiKeyScope, ApiKeySummary, AuthSession, IdentitySummary, User};
 use utoipa::{IntoParams, ToSchema};
 
-use crate::{ApiError, AppState};
+use crate::{
+    audit::{self, record_audit, ClientIp},
+    util::require_bearer,
+    ApiError, AppState, FieldError,
+};
+
+const MAX_API_KEY_NAME_CHARS: usize = 100;
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct GithubLoginResponse {
@@ -61,6 +68,75 @@ impl From<User> for UserResponse {
     }
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IdentityResponse {
+    pub provider: String,
+    pub linked_at: String,
+}
+
+impl From<IdentitySummary> for IdentityResponse {
+    fn from(value: IdentitySummary) -> Self {
+        Self {
+            provider: value.provider,
+            linked_at: value.linked_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IdentitiesResponse {
+    pub identities: Vec<IdentityResponse>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Scopes such as `chats:read` or `messages:write`.
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeyResponse {
+    pub id: String,
+    pub name: String,
+    /// The start of the key, to tell keys apart.
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<String>,
+}
+
+impl From<ApiKeySummary> for ApiKeyResponse {
+    fn from(value: ApiKeySummary) -> Self {
+        Self {
+            id: value.public_id,
+            name: value.name,
+            prefix: value.key_prefix,
+            scopes: value
+                .scopes
+                .iter()
+                .map(|scope| scope.as_str().to_owned())
+                .collect(),
+            created_at: value.created_at.to_rfc3339(),
+            last_used_at: value.last_used_at.map(|value| value.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+    /// The full key. It is only returned here and cannot be retrieved later.
+    pub key: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeysResponse {
+    pub api_keys: Vec<ApiKeyResponse>,
+}
+
 #[utoipa::path(
     get,
     path = "/api/auth/github/login",
@@ -68,6 +144,7 @@ impl From<User> for UserResponse {
     params(GithubLoginQuery),
     responses(
         (status = 200, description = "GitHub OAuth authorization URL", body = GithubLoginResponse),
+        (status = 400, description = "Redirect URI is not allowlisted", body = crate::error::ErrorResponse),
         (status = 503, description = "GitHub OAuth not configured", body = crate::error::ErrorResponse)
     )
 )]
@@ -111,17 +188,32 @@ pub async fn github_login(
 )]
 pub async fn github_callback(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     Json(payload): Json<GithubCallbackRequest>,
 ) -> Result<Json<SessionResponse>, ApiError> {
     if !state.oauth_state().consume(&payload.state).await {
         return Err(ApiError::bad_request("invalid or expired OAuth state"));
     }
 
-    let session = state
+    let session = match state
         .authenticator()
         .login_with_github_code(&payload.code, &payload.redirect_uri)
         .await
-        .map_err(ApiError::from)?;
+    {
+        Ok(session) => session,
+        Err(error) => {
+            record_audit(&state, None, audit::LOGIN_FAILED, Some("github"), ip.as_deref()).await;
+            return Err(ApiError::from(error));
+        }
+    };
+    record_audit(
+        &state,
+        Some(session.user_id),
+        audit::LOGIN_SUCCEEDED,
+        Some("github"),
+        ip.as_deref(),
+    )
+    .await;
     let user = state
         .authenticator()
         .user_profile(session.user_id)
@@ -131,6 +223,248 @@ pub async fn github_callback(
     Ok(Json(SessionResponse::new(session, user)))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/auth/github/link",
+    tag = "Auth",
+    request_body = GithubCallbackRequest,
+    responses(
+        (status = 200, description = "GitHub account linked to the current user"),
+        (status = 400, description = "Invalid OAuth payload", body = crate::error::ErrorResponse),
+        (status = 401, description = "Unauthorized", body = crate::error::ErrorResponse),
+        (status = 409, description = "GitHub account is linked to another user", body = crate::error::ErrorResponse),
+        (status = 503, description = "GitHub OAuth not configured", body = crate::error::ErrorResponse)
+    ),
+    security(("bearerAuth" = []))
+)]
+pub async fn link_github(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Json(payload): Json<GithubCallbackRequest>,
+) -> Result<(), ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    if !state.oauth_state().consume(&payload.state).await {
+        return Err(ApiError::bad_request("invalid or expired OAuth state"));
+    }
+
+    state
+        .authenticator()
+        .link_github_identity(user.id, &payload.code, &payload.redirect_uri)
+        .await?;
+
+    record_audit(
+        &state,
+        Some(user.id),
+        audit::IDENTITY_LINKED,
+        Some("github"),
+        ip.as_deref(),
+    )
+    .await;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/identities",
+    tag = "Auth",
+    responses(
+        (status = 200, description = "Login methods linked to the current user", body = IdentitiesResponse),
+        (status = 401, description = "Unauthorized", body = crate::error::ErrorResponse)
+    ),
+    security(("bearerAuth" = []))
+)]
+pub async fn list_identities(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<IdentitiesResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    let identities = state.authenticator().list_identities(user.id).await?;
+
+    Ok(Json(IdentitiesResponse {
+        identities: identities.into_iter().map(IdentityResponse::from).collect(),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/auth/identities/{provider}",
+    tag = "Auth",
+    params(
+        ("provider" = String, Path, description = "Identity provider, e.g. `github` or `password`")
+    ),
+    responses(
+        (status = 200, description = "Identity removed from the current user"),
+        (status = 401, description = "Unauthorized", body = crate::error::ErrorResponse),
+        (status = 404, description = "No identity for this provider", body = crate::error::ErrorResponse),
+        (status = 409, description = "Identity is the user's only way to sign in", body = crate::error::ErrorResponse)
+    ),
+    security(("bearerAuth" = []))
+)]
+pub async fn unlink_identity(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+) -> Result<(), ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    state
+        .authenticator()
+        .unlink_identity(user.id, &provider)
+        .await?;
+
+    record_audit(
+        &state,
+        Some(user.id),
+        audit::IDENTITY_UNLINKED,
+        Some(&provider),
+        ip.as_deref(),
+    )
+    .await;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/api-keys",
+    tag = "Auth",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 200, description = "API key created; the key is shown only in this response", body = CreatedApiKeyResponse),
+        (status = 400, description = "Invalid name or scopes", body = crate::error::ValidationErrorResponse),
+        (status = 401, description = "Unauthorized", body = crate::error::ErrorResponse),
+        (status = 403, description = "API keys cannot create API keys", body = crate::error::ErrorResponse)
+    ),
+    security(("bearerAuth" = []))
+)]
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreatedApiKeyResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    let mut errors = Vec::new();
+    let name = payload.name.trim();
+    if name.is_empty() {
+        errors.push(FieldError::new("name", "must not be empty"));
+    } else if name.chars().count() > MAX_API_KEY_NAME_CHARS {
+        errors.push(FieldError::new(
+            "name",
+            format!("must be at most {MAX_API_KEY_NAME_CHARS} characters"),
+        ));
+    }
+    if payload.scopes.is_empty() {
+        errors.push(FieldError::new("scopes", "must not be empty"));
+    }
+    let mut scopes = Vec::with_capacity(payload.scopes.len());
+    for (index, scope) in payload.scopes.iter().enumerate() {
+        match ApiKeyScope::parse(scope) {
+            Some(scope) => scopes.push(scope),
+            None => errors.push(FieldError::new(
+                format!("scopes[{index}]"),
+                "is not a known scope",
+            )),
+        }
+    }
+    ApiError::check_fields(errors)?;
+
+    let (summary, key) = state
+        .authenticator()
+        .create_api_key(user.id, name, &scopes)
+        .await?;
+
+    record_audit(
+        &state,
+        Some(user.id),
+        audit::API_KEY_CREATED,
+        Some(&summary.public_id),
+        ip.as_deref(),
+    )
+    .await;
+
+    Ok(Json(CreatedApiKeyResponse {
+        api_key: summary.into(),
+        key,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/api-keys",
+    tag = "Auth",
+    responses(
+        (status = 200, description = "The current user's API keys, without the keys themselves", body = ApiKeysResponse),
+        (status = 401, description = "Unauthorized", body = crate::error::ErrorResponse),
+        (status = 403, description = "API keys cannot list API keys", body = crate::error::ErrorResponse)
+    ),
+    security(("bearerAuth" = []))
+)]
+pub async fn list_api_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiKeysResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    let api_keys = state.authenticator().list_api_keys(user.id).await?;
+
+    Ok(Json(ApiKeysResponse {
+        api_keys: api_keys.into_iter().map(ApiKeyResponse::from).collect(),
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/auth/api-keys/{key_id}",
+    tag = "Auth",
+    params(
+        ("key_id" = String, Path, description = "API key ID")
+    ),
+    responses(
+        (status = 200, description = "API key revoked"),
+        (status = 401, description = "Unauthorized", body = crate::error::ErrorResponse),
+        (status = 403, description = "API keys cannot revoke API keys", body = crate::error::ErrorResponse),
+        (status = 404, description = "No such API key", body = crate::error::ErrorResponse)
+    ),
+    security(("bearerAuth" = []))
+)]
+pub async fn revoke_api_key(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Path(key_id): Path<String>,
+) -> Result<(), ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    state
+        .authenticator()
+        .revoke_api_key(user.id, &key_id)
+        .await?;
+
+    record_audit(
+        &state,
+        Some(user.id),
+        audit::API_KEY_REVOKED,
+        Some(&key_id),
+        ip.as_deref(),
+    )
+    .await;
+
+    Ok(())
+}
+
 // Development endpoint to create a test token
 #[cfg(debug_assertions)]
 #[utoipa::path(
@@ -142,7 +476,10 @@ pub async fn github_callback(
         (status = 500, description = "Failed to create development session", body = crate::error::ErrorResponse)
     )
 )]
-pub async fn dev_token(State(state): State<AppState>) -> Result<Json<SessionResponse>, ApiError> {
+pub async fn dev_token(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+) -> Result<Json<SessionResponse>, ApiError> {
     // Create a development user in the database first
     sqlx::query(
         r#"
@@ -184,11 +521,21 @@ pub async fn dev_token(State(state): State<AppState>) -> Result<Json<SessionResp
         ApiError::internal_server_error("Failed to create dev session")
     })?;
 
+    record_audit(
+        &state,
+        Some(1),
+        audit::LOGIN_SUCCEEDED,
+        Some("dev"),
+        ip.as_deref(),
+    )
+    .await;
+
     // Create session and user objects
     let session = AuthSession {
         token: session_token.clone(),
         user_id: 1,
         expires_at,
+        scopes: None,
     };
 
     let user = switchboard_auth::User {
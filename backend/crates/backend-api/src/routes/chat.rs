@@ -1,15 +1,20 @@
+use std::collections::HashMap;
+
 use axum::{
-    extract::{Multipart, State},
+    extract::{Multipart, Path, State},
     http::HeaderMap,
     Json,
 };
 use base64::{engine::general_purpose, Engine as _};
 use bytes::Bytes;
 use denkwerk::{ChatMessage, CompletionRequest, TokenUsage as ProviderTokenUsage};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::{util::require_bearer, ApiError, AppState};
+use crate::{
+    ids::ResourceKind, routes::prompt_templates, util::require_bearer, ApiError, AppState,
+    FieldError,
+};
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ChatCompletionResponse {
@@ -32,6 +37,12 @@ pub struct ChatCompletionForm {
     /// Optional image attachments encoded as data URLs or binary uploads.
     #[schema(nullable, value_type = Vec<String>)]
     pub images: Option<Vec<String>>,
+    /// A prompt template of the caller's to render in place of `prompt`.
+    #[schema(nullable)]
+    pub template_id: Option<String>,
+    /// JSON object of the template's variable values, such as `{"lang":"French"}`.
+    #[schema(nullable)]
+    pub variables: Option<String>,
 }
 
 #[utoipa::path(
@@ -45,9 +56,14 @@ pub struct ChatCompletionForm {
     ),
     responses(
         (status = 200, description = "LLM chat completion", body = ChatCompletionResponse),
-        (status = 400, description = "Invalid request payload", body = crate::error::ErrorResponse),
+        (status = 400, description = "Invalid request payload, unknown model or missing template variable", body = crate::error::ErrorResponse),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
-        (status = 500, description = "Provider error", body = crate::error::ErrorResponse)
+        (status = 403, description = "Model is not allowed on this server", body = crate::error::ErrorResponse),
+        (status = 404, description = "Prompt template not found", body = crate::error::ErrorResponse),
+        (status = 413, description = "Prompt exceeds the model's context length", body = crate::error::ErrorResponse),
+        (status = 429, description = "Completion capacity or provider rate limit exceeded", body = crate::error::ErrorResponse),
+        (status = 500, description = "Internal error", body = crate::error::ErrorResponse),
+        (status = 502, description = "Provider failed or rejected our credentials; `code` says which", body = crate::error::ErrorResponse)
     )
 )]
 pub async fn chat_completion(
@@ -56,11 +72,13 @@ pub async fn chat_completion(
     mut multipart: Multipart,
 ) -> Result<Json<ChatCompletionResponse>, ApiError> {
     let token = require_bearer(&headers)?;
-    let _ = state.authenticate(&token).await?;
+    let (user, _) = state.authenticate(&token).await?;
 
     let mut prompt = None;
     let mut model_field = None;
     let mut images: Vec<Bytes> = Vec::new();
+    let mut template_id = None;
+    let mut variables = HashMap::new();
 
     while let Some(field) = multipart
         .next_field()
@@ -90,11 +108,32 @@ pub async fn chat_completion(
                     .map_err(|_| ApiError::bad_request("invalid image"))?;
                 images.push(data);
             }
+            "template_id" => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|_| ApiError::bad_request("invalid template_id"))?;
+                template_id = Some(text);
+            }
+            "variables" => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|_| ApiError::bad_request("invalid variables"))?;
+                variables = serde_json::from_str(&text).map_err(|_| {
+                    ApiError::bad_request("variables must be a JSON object of strings")
+                })?;
+            }
             _ => {}
         }
     }
 
-    let prompt = prompt.ok_or_else(|| ApiError::bad_request("prompt is required"))?;
+    let prompt = match template_id {
+        Some(template_id) => {
+            prompt_templates::render_for_user(&state, user.id, &template_id, &variables).await?
+        }
+        None => prompt.ok_or_else(|| ApiError::bad_request("prompt is required"))?,
+    };
     let prompt_trimmed = prompt.trim();
     if prompt_trimmed.is_empty() && images.is_empty() {
         return Err(ApiError::bad_request("prompt or images are required"));
@@ -110,6 +149,7 @@ pub async fn chat_completion(
             )
         })?;
 
+    state.orchestrator().check_model_allowed(&model)?;
     let provider = state.orchestrator().provider_for_model(&model)?;
 
     let message = if images.is_empty() {
@@ -129,7 +169,9 @@ pub async fn chat_completion(
     };
 
     let request = CompletionRequest::new(model.clone(), vec![message]);
-    let completion = provider.complete(request).await?;
+    state.orchestrator().check_prompt_length(&request)?;
+    let _slot = state.orchestrator().acquire_completion_slot_for(&model).await?;
+    let completion = state.orchestrator().complete(provider.as_ref(), request).await?;
 
     let content = completion.message.text().unwrap_or_default().to_string();
     let reasoning = completion
@@ -143,3 +185,177 @@ pub async fn chat_completion(
         reasoning,
     }))
 }
+
+/// The completion request sent for a chat message. Shared with
+/// [`estimate_completion`] so estimates count exactly what would be sent.
+pub(crate) fn message_request(model: &str, content: &str) -> CompletionRequest {
+    CompletionRequest::new(model.to_string(), vec![ChatMessage::user(content)])
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EstimateCompletionRequest {
+    /// The message that would be sent.
+    pub content: String,
+    /// Optional model identifier. Defaults to the server configured model.
+    #[schema(nullable)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CompletionEstimateResponse {
+    pub model: String,
+    pub prompt_tokens: u32,
+    /// Cap on generated tokens from the configured completion defaults.
+    #[schema(nullable)]
+    pub max_tokens: Option<u32>,
+    /// USD for the prompt alone; null when the model's pricing is unknown.
+    #[schema(nullable)]
+    pub estimated_cost: Option<f64>,
+}
+
+// Prompt tokens and cost of sending a message in a chat, without calling the provider
+#[utoipa::path(
+    post,
+    path = "/api/chats/{chat_id}/completions/estimate",
+    tag = "Chat",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    request_body = EstimateCompletionRequest,
+    responses(
+        (status = 200, description = "Estimated prompt tokens and cost", body = CompletionEstimateResponse),
+        (status = 400, description = "Invalid request payload", body = crate::error::ErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 404, description = "Chat not found", body = crate::error::ErrorResponse),
+        (status = 500, description = "Internal error", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn estimate_completion(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<EstimateCompletionRequest>,
+) -> Result<Json<CompletionEstimateResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Chat.check_public_id(&chat_id)?;
+    if req.content.trim().is_empty() {
+        return Err(ApiError::validation(vec![FieldError::new("content", "must not be empty")]));
+    }
+
+    let is_member: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT c.id FROM chats c
+        JOIN chat_members cm ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(&chat_id)
+    .bind(user.id)
+    .fetch_optional(state.db_read_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to check chat membership: {}", e);
+        ApiError::internal_server_error("Failed to check chat membership")
+    })?;
+    if is_member.is_none() {
+        return Err(state.chat_access_denied(&chat_id).await);
+    }
+
+    let model = req
+        .model
+        .filter(|value| !value.trim().is_empty())
+        .or_else(|| state.orchestrator().active_model())
+        .ok_or_else(|| ApiError::internal_server_error("no active model configured"))?;
+
+    let request = message_request(&model, &req.content);
+    let estimate = state.orchestrator().estimate_completion(request).await;
+
+    Ok(Json(CompletionEstimateResponse {
+        model: estimate.model,
+        prompt_tokens: estimate.prompt_tokens,
+        max_tokens: estimate.max_tokens,
+        estimated_cost: estimate.estimated_cost,
+    }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReplySuggestionsResponse {
+    /// Empty when the suggestion model is unavailable or fails.
+    pub suggestions: Vec<String>,
+}
+
+// Short replies the caller might send next, generated from the chat's latest messages
+// and not stored
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/suggestions",
+    tag = "Chat",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "Suggested replies", body = ReplySuggestionsResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 404, description = "Chat not found, or reply suggestions are disabled", body = crate::error::ErrorResponse),
+        (status = 500, description = "Internal error", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn suggest_replies(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<ReplySuggestionsResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+    let config = &state.config().orchestrator.reply_suggestions;
+    if !config.enabled {
+        return Err(ApiError::not_found("Reply suggestions are disabled"));
+    }
+    ResourceKind::Chat.check_public_id(&chat_id)?;
+
+    let chat_db_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT c.id FROM chats c
+        JOIN chat_members cm ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(&chat_id)
+    .bind(user.id)
+    .fetch_optional(state.db_read_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to check chat membership: {}", e);
+        ApiError::internal_server_error("Failed to check chat membership")
+    })?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
+
+    let mut transcript: Vec<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT role, content FROM messages
+        WHERE chat_id = ? AND content != '' AND superseded_by IS NULL
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(chat_db_id)
+    .bind(config.context_messages as i64)
+    .fetch_all(state.db_read_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to load messages for suggestions: {}", e);
+        ApiError::internal_server_error("Failed to load messages")
+    })?;
+    if transcript.is_empty() {
+        return Ok(Json(ReplySuggestionsResponse { suggestions: Vec::new() }));
+    }
+    transcript.reverse();
+
+    let suggestions = state.orchestrator().suggest_replies(&transcript).await;
+    Ok(Json(ReplySuggestionsResponse { suggestions }))
+}
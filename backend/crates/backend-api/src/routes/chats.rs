@@ -1,25 +1,32 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::HeaderMap,
     Json,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use sqlx::Row;
-
-use uuid::Uuid;
+use sqlx::{Row, Sqlite, Transaction};
+use switchboard_auth::User;
 
 use crate::{
-    routes::models::{
-        Chat, ChatInvite, ChatMember, CreateChatRequest, CreateInviteRequest, InviteResponse,
-        InvitesResponse, MemberResponse, MembersResponse, UpdateChatRequest,
-        UpdateMemberRoleRequest,
+    audit::{self, record_audit, ClientIp},
+    ids::ResourceKind,
+    routes::{
+        drafts::member_chat_db_id,
+        messages::{posting_chat_db_id, MESSAGE_ROLES},
+        models::{
+            Chat, ChatInvite, ChatMember, ChatMessage, ChatType, CountResponse, CreateChatRequest,
+            CreateInviteRequest, InviteResponse, InvitesResponse, MemberResponse, MemberRole,
+            MembersResponse, UpdateChatModelRequest, UpdateChatRequest, UpdateMemberRoleRequest,
+            UpdateRetentionRequest,
+        },
+        notifications::NotificationService,
     },
     state::ServerEvent,
-    util::require_bearer,
-    ApiError, AppState,
+    util::{expected_version, require_bearer, with_transaction, AuthUser},
+    ApiError, AppState, FieldError,
 };
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ChatsResponse {
@@ -32,6 +39,28 @@ pub struct ChatDetailResponse {
     pub chat: Chat,
 }
 
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct ChatStats {
+    pub message_count: i64,
+    pub member_count: i64,
+    /// Prompt plus completion tokens recorded on the chat's messages.
+    pub total_tokens: i64,
+    /// Newest message write, or the chat's own last update if it has no messages.
+    pub last_activity_at: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChatStatsResponse {
+    pub stats: ChatStats,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TypingUsersResponse {
+    pub chat_id: String,
+    /// Members reported typing within the last few seconds, in ascending id order.
+    pub user_ids: Vec<i64>,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ChatWithMessages {
     pub id: i64,
@@ -42,14 +71,27 @@ pub struct ChatWithMessages {
     pub folder_id: Option<i64>,
     pub title: String,
     pub chat_type: String,
+    #[schema(nullable)]
+    pub message_retention_days: Option<i64>,
+    #[schema(nullable)]
+    pub default_model: Option<String>,
     pub created_at: String,
     pub updated_at: String,
     #[schema(default)]
     pub is_group: bool,
+    /// Members of the chat, the caller included.
+    pub member_count: i64,
     #[schema(nullable)]
     pub messages: Option<String>,
 }
 
+#[derive(Debug, sqlx::FromRow)]
+struct ChatListRow {
+    #[sqlx(flatten)]
+    chat: Chat,
+    member_count: i64,
+}
+
 // Helper function to fetch messages for a chat as JSON
 async fn fetch_chat_messages(
     chat_id: i64,
@@ -136,28 +178,37 @@ pub async fn list_chats(
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
 
-    let chats = sqlx::query_as::<_, Chat>(
+    // Member counts come from the same query so the list costs no extra round trips
+    let chats_query = sqlx::query_as::<_, ChatListRow>(
         r#"
-        SELECT c.id, c.public_id, c.user_id, c.folder_id, c.title, c.chat_type, c.created_at, c.updated_at
+        SELECT c.id, c.public_id, c.user_id, c.folder_id, c.title, c.chat_type,
+               c.message_retention_days, c.default_model, c.version, c.created_at, c.updated_at,
+               COUNT(members.user_id) AS member_count
         FROM chats c
-        WHERE c.id IN (
-            SELECT chat_id FROM chat_members WHERE user_id = ?
-        )
+        JOIN chat_members mine ON mine.chat_id = c.id AND mine.user_id = ?
+        JOIN chat_members members ON members.chat_id = c.id
+        GROUP BY c.id
         ORDER BY c.updated_at DESC
         "#
     )
-    .bind(user.id)
-    .fetch_all(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch chats: {}", e);
-        ApiError::internal_server_error("Failed to fetch chats")
-    })?;
+    .bind(user.id);
+    let rows = state
+        .timed_query("chats.list", chats_query.fetch_all(state.db_replica_pool()))
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch chats: {}", e);
+            ApiError::internal_server_error("Failed to fetch chats")
+        })?;
 
     // Add messages to each chat so the UI can hydrate its local stores on refresh
-    let mut chats_with_messages = Vec::with_capacity(chats.len());
-    for chat in chats {
-        let messages_json = fetch_chat_messages(chat.id, state.db_pool()).await?;
+    let mut chats_with_messages = Vec::with_capacity(rows.len());
+    for ChatListRow { chat, member_count } in rows {
+        let messages_json = state
+            .timed_query(
+                "chats.list.messages",
+                fetch_chat_messages(chat.id, state.db_replica_pool()),
+            )
+            .await?;
         let is_group = chat.chat_type.eq_ignore_ascii_case("group");
         let chat_with_messages = ChatWithMessages {
             id: chat.id,
@@ -166,9 +217,12 @@ pub async fn list_chats(
             folder_id: chat.folder_id,
             title: chat.title,
             chat_type: chat.chat_type,
+            message_retention_days: chat.message_retention_days,
+            default_model: chat.default_model,
             created_at: chat.created_at,
             updated_at: chat.updated_at,
             is_group,
+            member_count,
             messages: messages_json,
         };
         chats_with_messages.push(chat_with_messages);
@@ -179,6 +233,36 @@ pub async fn list_chats(
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/chats/count",
+    tag = "Chats",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "Number of chats the authenticated user is a member of", body = CountResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to count chats", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn count_chats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<CountResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chat_members WHERE user_id = ?")
+        .bind(user.id)
+        .fetch_one(state.db_read_pool())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to count chats: {}", e);
+            ApiError::internal_server_error("Failed to count chats")
+        })?;
+
+    Ok(Json(CountResponse { count }))
+}
+
 #[utoipa::path(
     post,
     path = "/api/chats",
@@ -187,7 +271,7 @@ pub async fn list_chats(
     request_body = CreateChatRequest,
     responses(
         (status = 200, description = "Chat created", body = ChatDetailResponse),
-        (status = 400, description = "Invalid chat payload", body = crate::error::ErrorResponse),
+        (status = 400, description = "Invalid chat payload", body = crate::error::ValidationErrorResponse),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to create chat", body = crate::error::ErrorResponse)
     )
@@ -199,8 +283,18 @@ pub async fn create_chat(
 ) -> Result<Json<ChatDetailResponse>, ApiError> {
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
-
-    let public_id = Uuid::new_v4().to_string();
+    let chat_config = &state.config().chat;
+    let chat_type = req
+        .chat_type
+        .clone()
+        .unwrap_or_else(|| chat_config.default_chat_type.clone());
+    ApiError::check_fields(chat_field_errors(
+        Some(&req.title),
+        Some(&chat_type),
+        &req.messages,
+    ))?;
+
+    let public_id = ResourceKind::Chat.new_public_id(&state.config().ids);
     let now = chrono::Utc::now().to_rfc3339();
 
     let folder_db_id = if let Some(folder_public_id) = &req.folder_id {
@@ -214,25 +308,45 @@ pub async fn create_chat(
                 tracing::error!("Failed to resolve folder: {}", e);
                 ApiError::internal_server_error("Failed to resolve folder")
             })?
+    } else if let Some(folder_name) = &chat_config.default_folder {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM folders WHERE user_id = ? AND parent_id IS NULL AND name = ?",
+        )
+        .bind(user.id)
+        .bind(folder_name)
+        .fetch_optional(state.db_pool())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to resolve default folder: {}", e);
+            ApiError::internal_server_error("Failed to resolve folder")
+        })?
     } else {
         None
     };
 
+    // The chat, its owner membership and any initial messages are written together
+    // so a chat can never exist without an owner.
+    let mut tx = state.db_pool().begin().await.map_err(|e| {
+        tracing::error!("Failed to begin chat creation transaction: {}", e);
+        ApiError::internal_server_error("Failed to create chat")
+    })?;
+
     // Create the chat first (user_id is now nullable, managed through chat_members)
     let chat_db_id = sqlx::query(
         r#"
-        INSERT INTO chats (public_id, user_id, folder_id, title, chat_type, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO chats (public_id, user_id, folder_id, title, chat_type, is_group, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(&public_id)
     .bind(user.id) // Set user_id for backwards compatibility
     .bind(folder_db_id)
     .bind(&req.title)
-    .bind(&req.chat_type)
+    .bind(&chat_type)
+    .bind(chat_type == ChatType::Group.as_str())
     .bind(&now)
     .bind(&now)
-    .execute(state.db_pool())
+    .execute(&mut *tx)
     .await
     .map_err(|e| {
         tracing::error!("Failed to create chat: {}", e);
@@ -240,9 +354,27 @@ pub async fn create_chat(
     })?
     .last_insert_rowid();
 
+    // Add creator as owner of the chat (for both regular and group chats)
+    sqlx::query(
+        r#"
+        INSERT INTO chat_members (chat_id, user_id, role, joined_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+    )
+    .bind(chat_db_id)
+    .bind(user.id)
+    .bind(MemberRole::Owner.as_str())
+    .bind(&now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to add chat owner: {}", e);
+        ApiError::internal_server_error("Failed to add chat owner")
+    })?;
+
     // Insert initial messages if provided
     for message in &req.messages {
-        let message_public_id = Uuid::new_v4().to_string();
+        let message_public_id = ResourceKind::Message.new_public_id(&state.config().ids);
         let message_type = if message.role.eq_ignore_ascii_case("system") {
             "system"
         } else {
@@ -250,8 +382,8 @@ pub async fn create_chat(
         };
         sqlx::query(
             r#"
-            INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, prompt_tokens, completion_tokens, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
         .bind(&message_public_id)
@@ -261,9 +393,11 @@ pub async fn create_chat(
         .bind(message_type)
         .bind(message.role.as_str())
         .bind(message.model.clone())
+        .bind(message.usage.as_ref().map(|usage| i64::from(usage.prompt_tokens)))
+        .bind(message.usage.as_ref().map(|usage| i64::from(usage.completion_tokens)))
         .bind(&now)
         .bind(&now)
-        .execute(state.db_pool())
+        .execute(&mut *tx)
         .await
         .map_err(|e| {
             tracing::error!("Failed to create initial message: {}", e);
@@ -271,22 +405,9 @@ pub async fn create_chat(
         })?;
     }
 
-    // Add creator as owner of the chat (for both regular and group chats)
-    sqlx::query(
-        r#"
-        INSERT INTO chat_members (chat_id, user_id, role, joined_at)
-        VALUES (?, ?, ?, ?)
-        "#,
-    )
-    .bind(chat_db_id)
-    .bind(user.id)
-    .bind("owner")
-    .bind(&now)
-    .execute(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to add chat owner: {}", e);
-        ApiError::internal_server_error("Failed to add chat owner")
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Failed to commit chat creation: {}", e);
+        ApiError::internal_server_error("Failed to create chat")
     })?;
 
     // Get messages for the newly created chat
@@ -305,7 +426,10 @@ pub async fn create_chat(
         user_id: Some(user.id),
         folder_id: folder_db_id,
         title: req.title.clone(),
-        chat_type: req.chat_type.clone(),
+        chat_type,
+        message_retention_days: None,
+        default_model: None,
+        version: 1,
         created_at: now.clone(),
         updated_at: now.clone(),
     };
@@ -342,7 +466,8 @@ pub async fn get_chat(
 
     let chat = sqlx::query_as::<_, Chat>(
         r#"
-        SELECT c.id, c.public_id, c.user_id, c.folder_id, c.title, c.chat_type, c.created_at, c.updated_at
+        SELECT c.id, c.public_id, c.user_id, c.folder_id, c.title, c.chat_type,
+               c.message_retention_days, c.default_model, c.version, c.created_at, c.updated_at
         FROM chats c
         JOIN chat_members cm ON c.id = cm.chat_id
         WHERE c.public_id = ? AND cm.user_id = ?
@@ -380,6 +505,7 @@ pub async fn get_chat(
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
         (status = 404, description = "Chat not found", body = crate::error::ErrorResponse),
+        (status = 409, description = "Chat changed since the expected version", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to update chat", body = crate::error::ErrorResponse)
     )
 )]
@@ -391,6 +517,12 @@ pub async fn update_chat(
 ) -> Result<Json<ChatDetailResponse>, ApiError> {
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
+    let expected_version = expected_version(&headers, req.version)?;
+    ApiError::check_fields(chat_field_errors(
+        req.title.as_deref(),
+        req.chat_type.as_deref(),
+        req.messages.as_deref().unwrap_or_default(),
+    ))?;
 
     let now = chrono::Utc::now().to_rfc3339();
 
@@ -421,10 +553,22 @@ pub async fn update_chat(
         }
     }
 
+    let chat_type = match req.chat_type.as_deref() {
+        Some(value) => {
+            let requested = ChatType::parse(value)
+                .ok_or_else(|| ApiError::bad_request(format!("Invalid chat_type: {value}")))?;
+            validate_chat_type_change(&state, &chat_id, user.id, requested).await?;
+            Some(requested.as_str())
+        }
+        None => None,
+    };
+
     let update_folder_flag: i32 = if folder_update_requested { 1 } else { 0 };
     let set_folder_null_flag: i32 = if folder_set_null { 1 } else { 0 };
 
-    sqlx::query(
+    // With an expected version the write only lands if nobody else edited the chat
+    // since the caller read it
+    let result = sqlx::query(
         r#"
         UPDATE chats
         SET title = COALESCE(?, title),
@@ -433,17 +577,24 @@ pub async fn update_chat(
                 WHEN ? = 1 THEN NULL
                 ELSE ?
             END,
+            chat_type = COALESCE(?, chat_type),
+            is_group = (COALESCE(?, chat_type) = 'group'),
+            version = version + 1,
             updated_at = ?
-        WHERE public_id = ? AND user_id = ?
+        WHERE public_id = ? AND user_id = ? AND (? IS NULL OR version = ?)
         "#,
     )
     .bind(&req.title)
     .bind(update_folder_flag)
     .bind(set_folder_null_flag)
     .bind(folder_db_id)
+    .bind(chat_type)
+    .bind(chat_type)
     .bind(&now)
     .bind(&chat_id)
     .bind(user.id)
+    .bind(expected_version)
+    .bind(expected_version)
     .execute(state.db_pool())
     .await
     .map_err(|e| {
@@ -451,9 +602,28 @@ pub async fn update_chat(
         ApiError::internal_server_error("Failed to update chat")
     })?;
 
+    if let Some(expected) = expected_version.filter(|_| result.rows_affected() == 0) {
+        let current: Option<i64> =
+            sqlx::query_scalar("SELECT version FROM chats WHERE public_id = ? AND user_id = ?")
+                .bind(&chat_id)
+                .bind(user.id)
+                .fetch_optional(state.db_pool())
+                .await
+                .map_err(|e| {
+                    tracing::error!("Failed to check chat version: {}", e);
+                    ApiError::internal_server_error("Failed to update chat")
+                })?;
+        if current.is_some_and(|current| current != expected) {
+            return Err(ApiError::conflict(format!(
+                "Chat has changed since version {expected}; refetch and retry"
+            )));
+        }
+    }
+
     let chat = sqlx::query_as::<_, Chat>(
         r#"
-        SELECT c.id, c.public_id, c.user_id, c.folder_id, c.title, c.chat_type, c.created_at, c.updated_at
+        SELECT c.id, c.public_id, c.user_id, c.folder_id, c.title, c.chat_type,
+               c.message_retention_days, c.default_model, c.version, c.created_at, c.updated_at
         FROM chats c
         JOIN chat_members cm ON c.id = cm.chat_id
         WHERE c.public_id = ? AND cm.user_id = ?
@@ -471,12 +641,344 @@ pub async fn update_chat(
 
     let member_ids = fetch_chat_member_ids(&state, chat.id).await?;
     let event = ServerEvent::ChatUpdated { chat: chat.clone() };
-    state.broadcast_to_chat(&chat_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
+
+    Ok(Json(ChatDetailResponse { chat }))
+}
+
+pub const MAX_MESSAGE_RETENTION_DAYS: i64 = 3650;
+
+#[utoipa::path(
+    put,
+    path = "/api/chats/{chat_id}/retention",
+    tag = "Chats",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    request_body = UpdateRetentionRequest,
+    responses(
+        (status = 200, description = "Retention policy updated", body = ChatDetailResponse),
+        (status = 400, description = "Retention out of range", body = crate::error::ValidationErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Owner or admin role required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to update retention", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn update_retention(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<UpdateRetentionRequest>,
+) -> Result<Json<ChatDetailResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    if let Some(days) = req.message_retention_days {
+        if !(1..=MAX_MESSAGE_RETENTION_DAYS).contains(&days) {
+            return Err(ApiError::validation(vec![FieldError::new(
+                "message_retention_days",
+                format!("must be between 1 and {}", MAX_MESSAGE_RETENTION_DAYS),
+            )]));
+        }
+    }
+
+    let user_role: Option<String> = sqlx::query_scalar(
+        r#"
+        SELECT cm.role FROM chats c
+        JOIN chat_members cm ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(&chat_id)
+    .bind(user.id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to check user role: {}", e);
+        ApiError::internal_server_error("Failed to check user role")
+    })?;
+
+    let Some(user_role) = user_role else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
+    if user_role != "owner" && user_role != "admin" {
+        return Err(ApiError::forbidden("Insufficient permissions"));
+    }
+
+    let chat = sqlx::query_as::<_, Chat>(
+        r#"
+        UPDATE chats
+        SET message_retention_days = ?, version = version + 1, updated_at = ?
+        WHERE public_id = ?
+        RETURNING id, public_id, user_id, folder_id, title, chat_type,
+                  message_retention_days, default_model, version, created_at, updated_at
+        "#,
+    )
+    .bind(req.message_retention_days)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&chat_id)
+    .fetch_one(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to update retention: {}", e);
+        ApiError::internal_server_error("Failed to update retention")
+    })?;
+
+    let member_ids = fetch_chat_member_ids(&state, chat.id).await?;
+    let event = ServerEvent::ChatUpdated { chat: chat.clone() };
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
+
+    Ok(Json(ChatDetailResponse { chat }))
+}
+
+/// Pin the model replies use when a message names none. Any member who may post can
+/// change it; a model listed on the message itself still takes precedence.
+#[utoipa::path(
+    put,
+    path = "/api/chats/{chat_id}/model",
+    tag = "Chats",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    request_body = UpdateChatModelRequest,
+    responses(
+        (status = 200, description = "Default model updated", body = ChatDetailResponse),
+        (status = 400, description = "Empty model name", body = crate::error::ValidationErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Model not allowed, not a member, or a viewer", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to update default model", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn update_chat_model(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<UpdateChatModelRequest>,
+) -> Result<Json<ChatDetailResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Chat.check_public_id(&chat_id)?;
+
+    let default_model = req.default_model.map(|model| model.trim().to_string());
+    if let Some(model) = &default_model {
+        if model.is_empty() {
+            return Err(ApiError::validation(vec![FieldError::new(
+                "default_model",
+                "must not be empty",
+            )]));
+        }
+        state.orchestrator().check_model_allowed(model)?;
+    }
+
+    // The pinned model decides what later replies cost, so viewers may not change it
+    let chat_db_id = posting_chat_db_id(&state, &chat_id, user.id).await?;
+
+    let chat = sqlx::query_as::<_, Chat>(
+        r#"
+        UPDATE chats
+        SET default_model = ?, version = version + 1, updated_at = ?
+        WHERE id = ?
+        RETURNING id, public_id, user_id, folder_id, title, chat_type,
+                  message_retention_days, default_model, version, created_at, updated_at
+        "#,
+    )
+    .bind(&default_model)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(chat_db_id)
+    .fetch_one(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to update default model: {}", e);
+        ApiError::internal_server_error("Failed to update default model")
+    })?;
+
+    let member_ids = fetch_chat_member_ids(&state, chat.id).await?;
+    let event = ServerEvent::ChatUpdated { chat: chat.clone() };
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
 
     Ok(Json(ChatDetailResponse { chat }))
 }
 
+/// Message, member and token totals for a chat, with the time it was last active.
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/stats",
+    tag = "Chats",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "Chat statistics", body = ChatStatsResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to compute chat stats", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_chat_stats(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<ChatStatsResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    // Check if user is a member of the chat
+    let chat_db_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT c.id FROM chats c
+        JOIN chat_members cm ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(&chat_id)
+    .bind(user.id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to check chat membership: {}", e);
+        ApiError::internal_server_error("Failed to check chat membership")
+    })?;
+
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
+
+    // One pass over the chat's messages; members and the fallback timestamp are
+    // indexed single-row lookups
+    let stats = sqlx::query_as::<_, ChatStats>(
+        r#"
+        SELECT COUNT(m.id) AS message_count,
+               (SELECT COUNT(*) FROM chat_members WHERE chat_id = ?) AS member_count,
+               COALESCE(SUM(COALESCE(m.prompt_tokens, 0) + COALESCE(m.completion_tokens, 0)), 0)
+                   AS total_tokens,
+               COALESCE(MAX(m.updated_at), (SELECT updated_at FROM chats WHERE id = ?))
+                   AS last_activity_at
+        FROM messages m
+        WHERE m.chat_id = ?
+        "#,
+    )
+    .bind(chat_db_id)
+    .bind(chat_db_id)
+    .bind(chat_db_id)
+    .fetch_one(state.db_read_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to compute stats for chat {}: {}", chat_db_id, e);
+        ApiError::internal_server_error("Failed to compute chat stats")
+    })?;
+
+    Ok(Json(ChatStatsResponse { stats }))
+}
+
+// Snapshot of who is typing in a chat, for clients that join after the typing events
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/typing",
+    tag = "Chats",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "Users currently typing", body = TypingUsersResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to check chat membership", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_typing_users(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(chat_id): Path<String>,
+) -> Result<Json<TypingUsersResponse>, ApiError> {
+    let chat_db_id = member_chat_db_id(&state, &chat_id, user.id).await?;
+    let user_ids = state.typing().typing_users(chat_db_id);
+
+    Ok(Json(TypingUsersResponse { chat_id, user_ids }))
+}
+
+/// Checks the chat fields present in a create or update request.
+fn chat_field_errors(
+    title: Option<&str>,
+    chat_type: Option<&str>,
+    messages: &[ChatMessage],
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if title.is_some_and(|title| title.trim().is_empty()) {
+        errors.push(FieldError::new("title", "must not be empty"));
+    }
+    if let Some(chat_type) = chat_type.filter(|value| ChatType::parse(value).is_none()) {
+        errors.push(FieldError::new("chat_type", format!("Invalid chat_type: {chat_type}")));
+    }
+    for (index, message) in messages.iter().enumerate() {
+        if !MESSAGE_ROLES.contains(&message.role.as_str()) {
+            errors.push(FieldError::new(
+                format!("messages[{index}].role"),
+                format!("must be one of: {}", MESSAGE_ROLES.join(", ")),
+            ));
+        }
+    }
+    errors
+}
+
+/// Direct chats may always become groups; a group may only collapse back into a direct
+/// chat while it has exactly two members. System chats are never converted.
+async fn validate_chat_type_change(
+    state: &AppState,
+    chat_id: &str,
+    user_id: i64,
+    requested: ChatType,
+) -> Result<(), ApiError> {
+    let current: Option<(i64, String)> =
+        sqlx::query_as("SELECT id, chat_type FROM chats WHERE public_id = ? AND user_id = ?")
+            .bind(chat_id)
+            .bind(user_id)
+            .fetch_optional(state.db_pool())
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to fetch chat type: {}", e);
+                ApiError::internal_server_error("Failed to fetch chat type")
+            })?;
+
+    let (chat_db_id, current) = current.ok_or_else(|| ApiError::not_found("Chat not found"))?;
+    let current = ChatType::parse(&current).ok_or_else(|| {
+        tracing::error!("Chat {} has unknown chat_type {}", chat_id, current);
+        ApiError::internal_server_error("Failed to fetch chat type")
+    })?;
+
+    match (current, requested) {
+        (current, requested) if current == requested => Ok(()),
+        (ChatType::Direct, ChatType::Group) => Ok(()),
+        (ChatType::Group, ChatType::Direct) => {
+            let member_count: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM chat_members WHERE chat_id = ?")
+                    .bind(chat_db_id)
+                    .fetch_one(state.db_pool())
+                    .await
+                    .map_err(|e| {
+                        tracing::error!("Failed to count chat members: {}", e);
+                        ApiError::internal_server_error("Failed to count chat members")
+                    })?;
+
+            if member_count == 2 {
+                Ok(())
+            } else {
+                Err(ApiError::bad_request(
+                    "A group chat can only become a direct chat when it has exactly two members",
+                ))
+            }
+        }
+        (current, requested) => Err(ApiError::bad_request(format!(
+            "Cannot convert a {} chat to {}",
+            current.as_str(),
+            requested.as_str()
+        ))),
+    }
+}
+
 #[utoipa::path(
     delete,
     path = "/api/chats/{chat_id}",
@@ -538,8 +1040,7 @@ pub async fn delete_chat(
     let event = ServerEvent::ChatDeleted {
         chat_id: chat_id.clone(),
     };
-    state.broadcast_to_chat(&chat_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
 
     {
         let mut broadcasters = state.chat_broadcasters.lock().await;
@@ -549,6 +1050,36 @@ pub async fn delete_chat(
     Ok(())
 }
 
+/// The user whose display name is `username`, ignoring case. Display names are not
+/// unique, so a name shared by several users is refused rather than guessed.
+async fn resolve_username(state: &AppState, username: &str) -> Result<i64, ApiError> {
+    let user_ids: Vec<i64> =
+        sqlx::query_scalar("SELECT id FROM users WHERE display_name = ? COLLATE NOCASE LIMIT 2")
+            .bind(username)
+            .fetch_all(state.db_pool())
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to resolve username: {}", e);
+                ApiError::internal_server_error("Failed to create invite")
+            })?;
+
+    match user_ids.as_slice() {
+        [user_id] => Ok(*user_id),
+        [] => Err(ApiError::not_found(format!("No user is called @{username}"))),
+        _ => Err(ApiError::conflict(format!(
+            "More than one user is called @{username}; invite them by email instead"
+        ))),
+    }
+}
+
+/// Whether an invite is addressed to `user`, by id or by their email.
+fn invite_is_for(user: &User, invitee_email: Option<&str>, invitee_user_id: Option<i64>) -> bool {
+    match invitee_user_id {
+        Some(invitee_user_id) => invitee_user_id == user.id,
+        None => invitee_email.is_some() && user.email.as_deref() == invitee_email,
+    }
+}
+
 #[utoipa::path(
     post,
     path = "/api/chats/{chat_id}/invites",
@@ -560,9 +1091,11 @@ pub async fn delete_chat(
     request_body = CreateInviteRequest,
     responses(
         (status = 200, description = "Invite created", body = InviteResponse),
+        (status = 400, description = "Neither or both of email and username given", body = crate::error::ValidationErrorResponse),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 403, description = "Insufficient permissions", body = crate::error::ErrorResponse),
-        (status = 404, description = "Chat not found", body = crate::error::ErrorResponse),
+        (status = 404, description = "Chat or username not found", body = crate::error::ErrorResponse),
+        (status = 409, description = "Several users have this username", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to create invite", body = crate::error::ErrorResponse)
     )
 )]
@@ -575,10 +1108,34 @@ pub async fn create_invite(
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
 
+    let email = req.email.as_deref().map(str::trim);
+    let username = req
+        .username
+        .as_deref()
+        .map(|name| name.trim().trim_start_matches('@'));
+    match (email, username) {
+        (Some(""), _) => {
+            return Err(ApiError::validation(vec![FieldError::new("email", "must not be empty")]));
+        }
+        (_, Some("")) => {
+            return Err(ApiError::validation(vec![FieldError::new(
+                "username",
+                "must not be empty",
+            )]));
+        }
+        (Some(_), None) | (None, Some(_)) => {}
+        _ => {
+            return Err(ApiError::validation(vec![FieldError::new(
+                "email",
+                "give either an email or a username",
+            )]));
+        }
+    }
+
     // Check if chat exists and is a group chat, and user is a member
-    let result: Option<(i64, String)> = sqlx::query_as(
+    let result: Option<(i64, String, String)> = sqlx::query_as(
         r#"
-        SELECT c.id, cm.role FROM chats c
+        SELECT c.id, c.title, cm.role FROM chats c
         JOIN chat_members cm ON c.id = cm.chat_id
         WHERE c.public_id = ? AND c.chat_type = 'group' AND cm.user_id = ?
         "#,
@@ -592,7 +1149,7 @@ pub async fn create_invite(
         ApiError::internal_server_error("Failed to check chat")
     })?;
 
-    let (chat_db_id, user_role) =
+    let (chat_db_id, chat_title, user_role) =
         result.ok_or_else(|| ApiError::not_found("Chat not found or not a group chat"))?;
 
     // Check if user has permission to invite (owner or admin)
@@ -602,17 +1159,26 @@ pub async fn create_invite(
         ));
     }
 
+    let invitee_user_id = match username {
+        Some(username) => Some(resolve_username(&state, username).await?),
+        None => None,
+    };
+    let invitee_email = email.map(str::to_owned);
+
     let now = chrono::Utc::now().to_rfc3339();
+    let public_id = ResourceKind::Invite.new_public_id(&state.config().ids);
 
     sqlx::query(
         r#"
-        INSERT INTO chat_invites (chat_id, inviter_id, invitee_email, status, created_at, updated_at)
-        VALUES (?, ?, ?, 'pending', ?, ?)
+        INSERT INTO chat_invites (public_id, chat_id, inviter_id, invitee_email, invitee_user_id, status, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, 'pending', ?, ?)
         "#
     )
+    .bind(&public_id)
     .bind(chat_db_id)
     .bind(user.id)
-    .bind(&req.email)
+    .bind(&invitee_email)
+    .bind(invitee_user_id)
     .bind(&now)
     .bind(&now)
     .execute(state.db_pool())
@@ -632,35 +1198,97 @@ pub async fn create_invite(
 
     let invite = ChatInvite {
         id: invite_id,
+        public_id,
         chat_id: chat_db_id,
         inviter_id: user.id,
-        invitee_email: req.email,
+        invitee_email,
+        invitee_user_id,
         status: "pending".to_string(),
         created_at: now.clone(),
         updated_at: now,
     };
 
+    // Someone invited by name has no email to look for, so tell them directly
+    if let Some(invitee_user_id) = invitee_user_id {
+        let inviter_name = user
+            .display_name
+            .as_deref()
+            .or(user.email.as_deref())
+            .unwrap_or("Someone");
+        NotificationService::notify_chat_invite(
+            state.db_pool(),
+            chat_db_id,
+            invitee_user_id,
+            &chat_title,
+            inviter_name,
+        )
+        .await?;
+    }
+
     let member_ids = fetch_chat_member_ids(&state, chat_db_id).await?;
     let event = ServerEvent::InviteCreated {
         chat_id: chat_id.clone(),
         invite: invite.clone(),
     };
-    state.broadcast_to_chat(&chat_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
 
     Ok(Json(InviteResponse { invite }))
 }
 
+pub const INVITE_STATUSES: &[&str] = &["pending", "accepted", "rejected", "expired"];
+const MAX_INVITE_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct ListInvitesQuery {
+    /// Only return invites with this status (`pending`, `accepted`, `rejected` or
+    /// `expired`).
+    pub status: Option<String>,
+    /// Order by `created_at`: `desc` (newest first, the default) or `asc`.
+    pub order: Option<String>,
+    /// Page size, 50 by default and at most 200.
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+struct InvitePage<'a> {
+    status: Option<&'a str>,
+    order: &'static str,
+    limit: i64,
+    offset: i64,
+}
+
+impl ListInvitesQuery {
+    fn page(&self) -> Result<InvitePage<'_>, ApiError> {
+        let status = self.status.as_deref();
+        if let Some(status) = status.filter(|status| !INVITE_STATUSES.contains(status)) {
+            return Err(ApiError::bad_request(format!("unknown status filter: {status}")));
+        }
+        let order = match self.order.as_deref() {
+            None | Some("desc") => "DESC",
+            Some("asc") => "ASC",
+            Some(other) => return Err(ApiError::bad_request(format!("unknown order: {other}"))),
+        };
+        Ok(InvitePage {
+            status,
+            order,
+            limit: self.limit.unwrap_or(50).clamp(1, MAX_INVITE_PAGE_SIZE),
+            offset: self.offset.unwrap_or(0).max(0),
+        })
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/api/chats/{chat_id}/invites",
     tag = "Chat Invites",
     security(("bearerAuth" = [])),
     params(
-        ("chat_id" = String, Path, description = "Chat public identifier")
+        ("chat_id" = String, Path, description = "Chat public identifier"),
+        ListInvitesQuery
     ),
     responses(
-        (status = 200, description = "List pending chat invites", body = InvitesResponse),
+        (status = 200, description = "A page of the chat's invites", body = InvitesResponse),
+        (status = 400, description = "Invalid filter", body = crate::error::ErrorResponse),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
         (status = 404, description = "Chat not found", body = crate::error::ErrorResponse),
@@ -671,9 +1299,11 @@ pub async fn list_invites(
     State(state): State<AppState>,
     Path(chat_id): Path<String>,
     headers: HeaderMap,
+    Query(query): Query<ListInvitesQuery>,
 ) -> Result<Json<InvitesResponse>, ApiError> {
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
+    let page = query.page()?;
 
     // Check if user is a member of the chat
     let chat_db_id: Option<i64> = sqlx::query_scalar(
@@ -692,23 +1322,82 @@ pub async fn list_invites(
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
-    let invites = sqlx::query_as::<_, ChatInvite>(
+    let sql = format!(
         r#"
-        SELECT id, chat_id, inviter_id, invitee_email, status, created_at, updated_at
+        SELECT id, public_id, chat_id, inviter_id, invitee_email, invitee_user_id, status, created_at, updated_at
         FROM chat_invites
-        WHERE chat_id = ?
-        ORDER BY created_at DESC
+        WHERE chat_id = ? AND (? IS NULL OR status = ?)
+        ORDER BY created_at {order}, id {order}
+        LIMIT ? OFFSET ?
         "#,
+        order = page.order
+    );
+    let invites = sqlx::query_as::<_, ChatInvite>(&sql)
+        .bind(chat_db_id)
+        .bind(page.status)
+        .bind(page.status)
+        .bind(page.limit)
+        .bind(page.offset)
+        .fetch_all(state.db_pool())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch invites: {}", e);
+            ApiError::internal_server_error("Failed to fetch invites")
+        })?;
+
+    Ok(Json(InvitesResponse { invites }))
+}
+
+// Invites addressed to the caller, by user or by email, across all chats
+#[utoipa::path(
+    get,
+    path = "/api/invites",
+    tag = "Chat Invites",
+    security(("bearerAuth" = [])),
+    params(ListInvitesQuery),
+    responses(
+        (status = 200, description = "A page of the caller's invites", body = InvitesResponse),
+        (status = 400, description = "Invalid filter", body = crate::error::ErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch invites", body = crate::error::ErrorResponse)
     )
-    .bind(chat_db_id)
-    .fetch_all(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch invites: {}", e);
-        ApiError::internal_server_error("Failed to fetch invites")
-    })?;
+)]
+pub async fn list_user_invites(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<ListInvitesQuery>,
+) -> Result<Json<InvitesResponse>, ApiError> {
+    let page = query.page()?;
+
+    // Matches invite_is_for: an invite naming a user is only theirs
+    let sql = format!(
+        r#"
+        SELECT id, public_id, chat_id, inviter_id, invitee_email, invitee_user_id, status, created_at, updated_at
+        FROM chat_invites
+        WHERE (invitee_user_id = ? OR (invitee_user_id IS NULL AND invitee_email = ?))
+          AND (? IS NULL OR status = ?)
+        ORDER BY created_at {order}, id {order}
+        LIMIT ? OFFSET ?
+        "#,
+        order = page.order
+    );
+    let invites = sqlx::query_as::<_, ChatInvite>(&sql)
+        .bind(user.id)
+        .bind(user.email.as_deref())
+        .bind(page.status)
+        .bind(page.status)
+        .bind(page.limit)
+        .bind(page.offset)
+        .fetch_all(state.db_pool())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch user invites: {}", e);
+            ApiError::internal_server_error("Failed to fetch invites")
+        })?;
 
     Ok(Json(InvitesResponse { invites }))
 }
@@ -719,7 +1408,7 @@ pub async fn list_invites(
     tag = "Chat Invites",
     security(("bearerAuth" = [])),
     params(
-        ("invite_id" = i64, Path, description = "Invite identifier")
+        ("invite_id" = String, Path, description = "Invite public identifier")
     ),
     responses(
         (status = 200, description = "Invite accepted"),
@@ -731,22 +1420,23 @@ pub async fn list_invites(
 )]
 pub async fn accept_invite(
     State(state): State<AppState>,
-    Path(invite_id): Path<i64>,
+    Path(invite_id): Path<String>,
     headers: HeaderMap,
 ) -> Result<(), ApiError> {
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Invite.check_public_id(&invite_id)?;
 
-    // Get the invite and check if the email matches
-    let invite: Option<(i64, String, String)> = sqlx::query_as(
+    // Get the invite and check it is addressed to this user
+    let invite: Option<(i64, i64, Option<String>, Option<i64>, String)> = sqlx::query_as(
         r#"
-        SELECT ci.chat_id, ci.invitee_email, c.public_id
+        SELECT ci.id, ci.chat_id, ci.invitee_email, ci.invitee_user_id, c.public_id
         FROM chat_invites ci
         JOIN chats c ON c.id = ci.chat_id
-        WHERE ci.id = ? AND ci.status = 'pending'
+        WHERE ci.public_id = ? AND ci.status = 'pending'
         "#,
     )
-    .bind(invite_id)
+    .bind(&invite_id)
     .fetch_optional(state.db_pool())
     .await
     .map_err(|e| {
@@ -754,67 +1444,67 @@ pub async fn accept_invite(
         ApiError::internal_server_error("Failed to fetch invite")
     })?;
 
-    let (chat_db_id, invitee_email, chat_public_id) =
+    let (invite_db_id, chat_db_id, invitee_email, invitee_user_id, chat_public_id) =
         invite.ok_or_else(|| ApiError::not_found("Invite not found"))?;
 
-    // Check if the user's email matches
-    if user.email.as_ref() != Some(&invitee_email) {
+    if !invite_is_for(&user, invitee_email.as_deref(), invitee_user_id) {
         return Err(ApiError::forbidden("Invite not for this user"));
     }
 
     let now = chrono::Utc::now().to_rfc3339();
 
-    // Update invite status
-    sqlx::query("UPDATE chat_invites SET status = 'accepted', updated_at = ? WHERE id = ?")
+    // Marking the invite accepted and adding the member happen together
+    let member = with_transaction(state.db_pool(), "Failed to accept invite", async |tx| {
+        sqlx::query("UPDATE chat_invites SET status = 'accepted', updated_at = ? WHERE id = ?")
+            .bind(&now)
+            .bind(invite_db_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to update invite: {}", e);
+                ApiError::internal_server_error("Failed to accept invite")
+            })?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO chat_members (chat_id, user_id, role, joined_at)
+            VALUES (?, ?, 'member', ?)
+            "#,
+        )
+        .bind(chat_db_id)
+        .bind(user.id)
         .bind(&now)
-        .bind(invite_id)
-        .execute(state.db_pool())
+        .execute(&mut **tx)
         .await
         .map_err(|e| {
-            tracing::error!("Failed to update invite: {}", e);
+            tracing::error!("Failed to add user to chat: {}", e);
             ApiError::internal_server_error("Failed to accept invite")
         })?;
 
-    // Add user to chat members
-    sqlx::query(
-        r#"
-        INSERT INTO chat_members (chat_id, user_id, role, joined_at)
-        VALUES (?, ?, 'member', ?)
-        "#,
-    )
-    .bind(chat_db_id)
-    .bind(user.id)
-    .bind(&now)
-    .execute(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to add user to chat: {}", e);
-        ApiError::internal_server_error("Failed to accept invite")
-    })?;
-
-    let member = sqlx::query_as::<_, ChatMember>(
-        r#"
-        SELECT id, chat_id, user_id, role, joined_at
-        FROM chat_members
-        WHERE chat_id = ? AND user_id = ?
-        "#,
-    )
-    .bind(chat_db_id)
-    .bind(user.id)
-    .fetch_one(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch new member: {}", e);
-        ApiError::internal_server_error("Failed to accept invite")
-    })?;
+        sqlx::query_as::<_, ChatMember>(
+            r#"
+            SELECT id, chat_id, user_id, role, joined_at
+            FROM chat_members
+            WHERE chat_id = ? AND user_id = ?
+            "#,
+        )
+        .bind(chat_db_id)
+        .bind(user.id)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch new member: {}", e);
+            ApiError::internal_server_error("Failed to accept invite")
+        })
+    })
+    .await?;
 
     let member_ids = fetch_chat_member_ids(&state, chat_db_id).await?;
     let event = ServerEvent::MemberUpdated {
         chat_id: chat_public_id.clone(),
         member: member.clone(),
     };
-    state.broadcast_to_chat(&chat_public_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_public_id, member_ids, &event).await;
 
     Ok(())
 }
@@ -825,7 +1515,7 @@ pub async fn accept_invite(
     tag = "Chat Invites",
     security(("bearerAuth" = [])),
     params(
-        ("invite_id" = i64, Path, description = "Invite identifier")
+        ("invite_id" = String, Path, description = "Invite public identifier")
     ),
     responses(
         (status = 200, description = "Invite rejected"),
@@ -837,17 +1527,18 @@ pub async fn accept_invite(
 )]
 pub async fn reject_invite(
     State(state): State<AppState>,
-    Path(invite_id): Path<i64>,
+    Path(invite_id): Path<String>,
     headers: HeaderMap,
 ) -> Result<(), ApiError> {
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Invite.check_public_id(&invite_id)?;
 
-    // Get the invite and check if the email matches
-    let invitee_email: Option<String> = sqlx::query_scalar(
-        "SELECT invitee_email FROM chat_invites WHERE id = ? AND status = 'pending'",
+    // Get the invite and check it is addressed to this user
+    let invite: Option<(i64, Option<String>, Option<i64>)> = sqlx::query_as(
+        "SELECT id, invitee_email, invitee_user_id FROM chat_invites WHERE public_id = ? AND status = 'pending'",
     )
-    .bind(invite_id)
+    .bind(&invite_id)
     .fetch_optional(state.db_pool())
     .await
     .map_err(|e| {
@@ -855,10 +1546,10 @@ pub async fn reject_invite(
         ApiError::internal_server_error("Failed to fetch invite")
     })?;
 
-    let invitee_email = invitee_email.ok_or_else(|| ApiError::not_found("Invite not found"))?;
+    let (invite_db_id, invitee_email, invitee_user_id) =
+        invite.ok_or_else(|| ApiError::not_found("Invite not found"))?;
 
-    // Check if the user's email matches
-    if user.email.as_ref() != Some(&invitee_email) {
+    if !invite_is_for(&user, invitee_email.as_deref(), invitee_user_id) {
         return Err(ApiError::forbidden("Invite not for this user"));
     }
 
@@ -867,7 +1558,7 @@ pub async fn reject_invite(
     // Update invite status
     sqlx::query("UPDATE chat_invites SET status = 'rejected', updated_at = ? WHERE id = ?")
         .bind(&now)
-        .bind(invite_id)
+        .bind(invite_db_id)
         .execute(state.db_pool())
         .await
         .map_err(|e| {
@@ -919,7 +1610,9 @@ pub async fn list_members(
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     let members = sqlx::query_as::<_, ChatMember>(
         r#"
@@ -940,6 +1633,53 @@ pub async fn list_members(
     Ok(Json(MembersResponse { members }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/members/me",
+    tag = "Chat Members",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "The caller's membership", body = MemberResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 404, description = "Chat not found", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch membership", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_my_membership(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<MemberResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    let member = sqlx::query_as::<_, ChatMember>(
+        r#"
+        SELECT cm.id, cm.chat_id, cm.user_id, cm.role, cm.joined_at
+        FROM chat_members cm
+        JOIN chats c ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(&chat_id)
+    .bind(user.id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch membership: {}", e);
+        ApiError::internal_server_error("Failed to fetch membership")
+    })?;
+
+    match member {
+        Some(member) => Ok(Json(MemberResponse { member })),
+        None => Err(state.chat_access_denied(&chat_id).await),
+    }
+}
+
 #[utoipa::path(
     put,
     path = "/api/chats/{chat_id}/members/{member_user_id}",
@@ -961,6 +1701,7 @@ pub async fn list_members(
 )]
 pub async fn update_member_role(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     Path((chat_id, member_user_id)): Path<(String, i64)>,
     headers: HeaderMap,
     Json(req): Json<UpdateMemberRoleRequest>,
@@ -985,14 +1726,16 @@ pub async fn update_member_role(
         ApiError::internal_server_error("Failed to check user role")
     })?;
 
-    let user_role = user_role.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(user_role) = user_role else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     if user_role != "owner" && user_role != "admin" {
         return Err(ApiError::forbidden("Insufficient permissions"));
     }
 
     // Validate role
-    if req.role != "member" && req.role != "admin" && req.role != "owner" {
+    if MemberRole::parse(&req.role).is_none() {
         return Err(ApiError::bad_request("Invalid role"));
     }
 
@@ -1050,6 +1793,16 @@ pub async fn update_member_role(
         ApiError::internal_server_error("Failed to update member role")
     })?;
 
+    let target = format!("chat:{}:{}:{}", chat_id, member_user_id, req.role);
+    record_audit(
+        &state,
+        Some(user.id),
+        audit::MEMBER_ROLE_CHANGED,
+        Some(&target),
+        ip.as_deref(),
+    )
+    .await;
+
     // Return the updated member
     let member = sqlx::query_as::<_, ChatMember>(
         r#"
@@ -1074,8 +1827,7 @@ pub async fn update_member_role(
         chat_id: chat_id.clone(),
         member: member.clone(),
     };
-    state.broadcast_to_chat(&chat_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
 
     Ok(Json(MemberResponse { member }))
 }
@@ -1122,8 +1874,9 @@ pub async fn remove_member(
         ApiError::internal_server_error("Failed to check user role")
     })?;
 
-    let (chat_db_id, user_role) =
-        chat_info.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some((chat_db_id, user_role)) = chat_info else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     if user_role != "owner" && user_role != "admin" {
         return Err(ApiError::forbidden("Insufficient permissions"));
@@ -1153,30 +1906,116 @@ pub async fn remove_member(
                     ApiError::internal_server_error("Failed to validate removal")
                 })?;
 
-        if target_role.as_deref() == Some("owner") && owner_count <= 1 {
+        // The last owner may leave (ownership is handed over below) but cannot be removed
+        // by someone else.
+        if target_role.as_deref() == Some("owner")
+            && owner_count <= 1
+            && member_user_id != user.id
+        {
             return Err(ApiError::bad_request("Cannot remove the last owner"));
         }
     }
 
     let member_ids = fetch_chat_member_ids(&state, chat_db_id).await?;
 
-    // Remove the member
-    sqlx::query("DELETE FROM chat_members WHERE chat_id = ? AND user_id = ?")
-        .bind(chat_db_id)
-        .bind(member_user_id)
-        .execute(state.db_pool())
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to remove member: {}", e);
-            ApiError::internal_server_error("Failed to remove member")
-        })?;
+    // Removing the member and handing over ownership happen together, so the chat is
+    // never left without an owner
+    let new_owner = with_transaction(state.db_pool(), "Failed to remove member", async |tx| {
+        sqlx::query("DELETE FROM chat_members WHERE chat_id = ? AND user_id = ?")
+            .bind(chat_db_id)
+            .bind(member_user_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to remove member: {}", e);
+                ApiError::internal_server_error("Failed to remove member")
+            })?;
+
+        ensure_chat_has_owner(tx, chat_db_id).await
+    })
+    .await?;
 
     let event = ServerEvent::MemberRemoved {
         chat_id: chat_id.clone(),
         user_id: member_user_id,
     };
-    state.broadcast_to_chat(&chat_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
+
+    if let Some(new_owner) = new_owner {
+        tracing::info!(
+            "Promoted user {} to owner of chat {} after owner {} left",
+            new_owner.user_id,
+            chat_id,
+            member_user_id
+        );
+
+        let member_ids = fetch_chat_member_ids(&state, chat_db_id).await?;
+        let event = ServerEvent::OwnershipTransferred {
+            chat_id: chat_id.clone(),
+            previous_owner_id: member_user_id,
+            new_owner,
+        };
+        state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
+    }
 
     Ok(())
 }
+
+/// Promote the longest-tenured admin (or, failing that, member, and only then viewer)
+/// when a chat has been left without an owner, returning the new owner. No-op while
+/// an owner remains.
+async fn ensure_chat_has_owner(
+    tx: &mut Transaction<'static, Sqlite>,
+    chat_db_id: i64,
+) -> Result<Option<ChatMember>, ApiError> {
+    let owner_count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM chat_members WHERE chat_id = ? AND role = 'owner'",
+    )
+    .bind(chat_db_id)
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to count owners: {}", e);
+        ApiError::internal_server_error("Failed to verify chat ownership")
+    })?;
+
+    if owner_count > 0 {
+        return Ok(None);
+    }
+
+    let successor = sqlx::query_as::<_, ChatMember>(
+        r#"
+        SELECT id, chat_id, user_id, role, joined_at
+        FROM chat_members
+        WHERE chat_id = ?
+        ORDER BY CASE role WHEN 'admin' THEN 0 WHEN 'viewer' THEN 2 ELSE 1 END,
+                 joined_at ASC, id ASC
+        LIMIT 1
+        "#,
+    )
+    .bind(chat_db_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to select new chat owner: {}", e);
+        ApiError::internal_server_error("Failed to verify chat ownership")
+    })?;
+
+    let Some(mut successor) = successor else {
+        tracing::warn!("Chat {} has no remaining members to own it", chat_db_id);
+        return Ok(None);
+    };
+
+    sqlx::query("UPDATE chat_members SET role = ? WHERE id = ?")
+        .bind(MemberRole::Owner.as_str())
+        .bind(successor.id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to promote new chat owner: {}", e);
+            ApiError::internal_server_error("Failed to promote new chat owner")
+        })?;
+    successor.role = MemberRole::Owner.as_str().to_string();
+
+    Ok(Some(successor))
+}
@@ -0,0 +1,209 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+use crate::{
+    routes::models::{DraftResponse, MessageDraft, SaveDraftRequest},
+    util::AuthUser,
+    ApiError, AppState,
+};
+
+// Drafts service
+pub struct DraftsService;
+
+impl DraftsService {
+    // Create or replace the user's draft for a chat
+    pub async fn save_draft(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        chat_id: i64,
+        user_id: i64,
+        content: &str,
+    ) -> Result<MessageDraft, ApiError> {
+        let now = chrono::Utc::now().to_rfc3339();
+
+        sqlx::query_as::<_, MessageDraft>(
+            r#"
+            INSERT INTO message_drafts (user_id, chat_id, content, updated_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (user_id, chat_id)
+            DO UPDATE SET content = excluded.content, updated_at = excluded.updated_at
+            RETURNING chat_id, user_id, content, updated_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(chat_id)
+        .bind(content)
+        .bind(&now)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to save draft: {}", e);
+            ApiError::internal_server_error("Failed to save draft")
+        })
+    }
+
+    pub async fn get_draft(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<Option<MessageDraft>, ApiError> {
+        sqlx::query_as::<_, MessageDraft>(
+            r#"
+            SELECT chat_id, user_id, content, updated_at
+            FROM message_drafts
+            WHERE user_id = ? AND chat_id = ?
+            "#,
+        )
+        .bind(user_id)
+        .bind(chat_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch draft: {}", e);
+            ApiError::internal_server_error("Failed to fetch draft")
+        })
+    }
+
+    // Returns whether a draft existed
+    pub async fn clear_draft<'e, E>(
+        executor: E,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+    {
+        let result = sqlx::query("DELETE FROM message_drafts WHERE user_id = ? AND chat_id = ?")
+            .bind(user_id)
+            .bind(chat_id)
+            .execute(executor)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to clear draft: {}", e);
+                ApiError::internal_server_error("Failed to clear draft")
+            })?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+pub(crate) async fn member_chat_db_id(
+    state: &AppState,
+    chat_id: &str,
+    user_id: i64,
+) -> Result<i64, ApiError> {
+    let chat_db_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT c.id FROM chats c
+        JOIN chat_members cm ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(chat_id)
+    .bind(user_id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to check chat membership: {}", e);
+        ApiError::internal_server_error("Failed to check chat membership")
+    })?;
+
+    match chat_db_id {
+        Some(chat_db_id) => Ok(chat_db_id),
+        None => Err(state.chat_access_denied(chat_id).await),
+    }
+}
+
+// Get the caller's draft for a chat
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/draft",
+    tag = "Messages",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "Saved draft", body = DraftResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 404, description = "No draft saved for this chat", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch draft", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_draft(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(chat_id): Path<String>,
+) -> Result<Json<DraftResponse>, ApiError> {
+    let chat_db_id = member_chat_db_id(&state, &chat_id, user.id).await?;
+
+    let draft = DraftsService::get_draft(state.db_pool(), chat_db_id, user.id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Draft not found"))?;
+
+    Ok(Json(DraftResponse { draft }))
+}
+
+// Save (create or replace) the caller's draft for a chat
+#[utoipa::path(
+    put,
+    path = "/api/chats/{chat_id}/draft",
+    tag = "Messages",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    request_body = SaveDraftRequest,
+    responses(
+        (status = 200, description = "Draft saved", body = DraftResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to save draft", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn save_draft(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(chat_id): Path<String>,
+    Json(req): Json<SaveDraftRequest>,
+) -> Result<Json<DraftResponse>, ApiError> {
+    let chat_db_id = member_chat_db_id(&state, &chat_id, user.id).await?;
+
+    let draft =
+        DraftsService::save_draft(state.db_pool(), chat_db_id, user.id, &req.content).await?;
+
+    Ok(Json(DraftResponse { draft }))
+}
+
+// Discard the caller's draft for a chat
+#[utoipa::path(
+    delete,
+    path = "/api/chats/{chat_id}/draft",
+    tag = "Messages",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "Draft cleared"),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 404, description = "No draft saved for this chat", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to clear draft", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn clear_draft(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(chat_id): Path<String>,
+) -> Result<(), ApiError> {
+    let chat_db_id = member_chat_db_id(&state, &chat_id, user.id).await?;
+
+    if !DraftsService::clear_draft(state.db_pool(), chat_db_id, user.id).await? {
+        return Err(ApiError::not_found("Draft not found"));
+    }
+
+    Ok(())
+}
@@ -1,16 +1,16 @@
 use axum::{
     extract::{Path, State},
-    http::HeaderMap,
     Json,
 };
 use serde::Serialize;
 
-use uuid::Uuid;
+use switchboard_config::{ChatsOnFolderDelete, SubfoldersOnFolderDelete};
 
 use crate::{
+    ids::ResourceKind,
     routes::models::{CreateFolderRequest, Folder, UpdateFolderRequest},
     state::ServerEvent,
-    util::require_bearer,
+    util::{retry_on_busy, AuthUser},
     ApiError, AppState,
 };
 use utoipa::ToSchema;
@@ -25,6 +25,60 @@ pub struct FolderResponse {
     pub folder: Folder,
 }
 
+// Reject moving a folder under itself or any of its descendants by walking up the
+// ancestor chain of the new parent
+async fn ensure_not_descendant(
+    state: &AppState,
+    folder_db_id: i64,
+    new_parent_db_id: i64,
+) -> Result<(), ApiError> {
+    if folder_db_id == new_parent_db_id {
+        return Err(ApiError::bad_request("A folder cannot be its own parent"));
+    }
+
+    // UNION (not UNION ALL) stops the walk even if the table already holds a cycle
+    let would_cycle: bool = sqlx::query_scalar(
+        r#"
+        WITH RECURSIVE ancestors(id, parent_id) AS (
+            SELECT id, parent_id FROM folders WHERE id = ?
+            UNION
+            SELECT f.id, f.parent_id FROM folders f
+            JOIN ancestors a ON f.id = a.parent_id
+        )
+        SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = ?)
+        "#,
+    )
+    .bind(new_parent_db_id)
+    .bind(folder_db_id)
+    .fetch_one(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to walk folder ancestors: {}", e);
+        ApiError::internal_server_error("Failed to update folder")
+    })?;
+
+    if would_cycle {
+        return Err(ApiError::bad_request(
+            "A folder cannot be moved into one of its own subfolders",
+        ));
+    }
+    Ok(())
+}
+
+// Names are unique per user within a parent folder (see idx_folders_user_*_name)
+fn folder_write_error(error: sqlx::Error, name: &str, action: &str) -> ApiError {
+    if let sqlx::Error::Database(db_error) = &error {
+        if db_error.is_unique_violation() {
+            return ApiError::conflict(format!(
+                "A folder named '{}' already exists in this location",
+                name
+            ));
+        }
+    }
+    tracing::error!("Failed to {} folder: {}", action, error);
+    ApiError::internal_server_error(format!("Failed to {} folder", action))
+}
+
 #[utoipa::path(
     get,
     path = "/api/folders",
@@ -38,11 +92,8 @@ pub struct FolderResponse {
 )]
 pub async fn list_folders(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
 ) -> Result<Json<FoldersResponse>, ApiError> {
-    let token = require_bearer(&headers)?;
-    let (user, _) = state.authenticate(&token).await?;
-
     let folders = sqlx::query_as::<_, Folder>(
         r#"
         SELECT id, public_id, user_id, name, color, parent_id, collapsed, created_at, updated_at
@@ -72,18 +123,16 @@ pub async fn list_folders(
         (status = 200, description = "Folder created", body = FolderResponse),
         (status = 400, description = "Invalid folder payload", body = crate::error::ErrorResponse),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 409, description = "A sibling folder already has this name", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to create folder", body = crate::error::ErrorResponse)
     )
 )]
 pub async fn create_folder(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Json(req): Json<CreateFolderRequest>,
 ) -> Result<Json<FolderResponse>, ApiError> {
-    let token = require_bearer(&headers)?;
-    let (user, _) = state.authenticate(&token).await?;
-
-    let public_id = Uuid::new_v4().to_string();
+    let public_id = ResourceKind::Folder.new_public_id(&state.config().ids);
     let now = chrono::Utc::now().to_rfc3339();
 
     let parent_db_id = if let Some(parent_public_id) = &req.parent_id {
@@ -101,26 +150,25 @@ pub async fn create_folder(
         None
     };
 
-    sqlx::query(
-        r#"
-        INSERT INTO folders (public_id, user_id, name, color, parent_id, collapsed, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-        "#
-    )
-    .bind(&public_id)
-    .bind(user.id)
-    .bind(&req.name)
-    .bind(&req.color)
-    .bind(parent_db_id)
-    .bind(false)
-    .bind(&now)
-    .bind(&now)
-    .execute(state.db_pool())
+    retry_on_busy(|| {
+        sqlx::query(
+            r#"
+            INSERT INTO folders (public_id, user_id, name, color, parent_id, collapsed, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(&public_id)
+        .bind(user.id)
+        .bind(&req.name)
+        .bind(&req.color)
+        .bind(parent_db_id)
+        .bind(false)
+        .bind(&now)
+        .bind(&now)
+        .execute(state.db_pool())
+    })
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to create folder: {}", e);
-        ApiError::internal_server_error("Failed to create folder")
-    })?;
+    .map_err(|e| folder_write_error(e, &req.name, "create"))?;
 
     let folder_id = sqlx::query_scalar::<_, i64>("SELECT last_insert_rowid()")
         .fetch_one(state.db_pool())
@@ -168,11 +216,8 @@ pub async fn create_folder(
 pub async fn get_folder(
     State(state): State<AppState>,
     Path(folder_id): Path<String>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
 ) -> Result<Json<FolderResponse>, ApiError> {
-    let token = require_bearer(&headers)?;
-    let (user, _) = state.authenticate(&token).await?;
-
     let folder = sqlx::query_as::<_, Folder>(
         r#"
         SELECT id, public_id, user_id, name, color, parent_id, collapsed, created_at, updated_at
@@ -204,45 +249,81 @@ pub async fn get_folder(
     request_body = UpdateFolderRequest,
     responses(
         (status = 200, description = "Folder updated", body = FolderResponse),
-        (status = 400, description = "Invalid update payload", body = crate::error::ErrorResponse),
+        (status = 400, description = "Invalid update payload or a reparent that would create a cycle", body = crate::error::ErrorResponse),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
-        (status = 404, description = "Folder not found", body = crate::error::ErrorResponse),
+        (status = 404, description = "Folder or parent folder not found", body = crate::error::ErrorResponse),
+        (status = 409, description = "A sibling folder already has this name", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to update folder", body = crate::error::ErrorResponse)
     )
 )]
 pub async fn update_folder(
     State(state): State<AppState>,
     Path(folder_id): Path<String>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
     Json(req): Json<UpdateFolderRequest>,
 ) -> Result<Json<FolderResponse>, ApiError> {
-    let token = require_bearer(&headers)?;
-    let (user, _) = state.authenticate(&token).await?;
-
     let now = chrono::Utc::now().to_rfc3339();
 
+    let current = sqlx::query_as::<_, Folder>(
+        r#"
+        SELECT id, public_id, user_id, name, color, parent_id, collapsed, created_at, updated_at
+        FROM folders
+        WHERE public_id = ? AND user_id = ?
+        "#,
+    )
+    .bind(&folder_id)
+    .bind(user.id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch folder: {}", e);
+        ApiError::internal_server_error("Failed to fetch folder")
+    })?
+    .ok_or_else(|| ApiError::not_found("Folder not found"))?;
+
+    let parent_db_id = match &req.parent_id {
+        Some(Some(parent_public_id)) => {
+            let parent_db_id = sqlx::query_scalar::<_, i64>(
+                "SELECT id FROM folders WHERE public_id = ? AND user_id = ?",
+            )
+            .bind(parent_public_id)
+            .bind(user.id)
+            .fetch_optional(state.db_pool())
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to resolve parent folder: {}", e);
+                ApiError::internal_server_error("Failed to resolve parent folder")
+            })?
+            .ok_or_else(|| ApiError::not_found("Parent folder not found"))?;
+
+            ensure_not_descendant(&state, current.id, parent_db_id).await?;
+            Some(parent_db_id)
+        }
+        Some(None) | None => None,
+    };
+    let name = req.name.as_deref().unwrap_or(&current.name);
+
     sqlx::query(
         r#"
         UPDATE folders
         SET name = COALESCE(?, name),
             color = COALESCE(?, color),
             collapsed = COALESCE(?, collapsed),
+            parent_id = CASE WHEN ? THEN ? ELSE parent_id END,
             updated_at = ?
-        WHERE public_id = ? AND user_id = ?
+        WHERE id = ?
         "#,
     )
     .bind(&req.name)
     .bind(&req.color)
     .bind(req.collapsed)
+    .bind(req.parent_id.is_some())
+    .bind(parent_db_id)
     .bind(&now)
-    .bind(&folder_id)
-    .bind(user.id)
+    .bind(current.id)
     .execute(state.db_pool())
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to update folder: {}", e);
-        ApiError::internal_server_error("Failed to update folder")
-    })?;
+    .map_err(|e| folder_write_error(e, name, "update"))?;
 
     let folder = sqlx::query_as::<_, Folder>(
         r#"
@@ -281,35 +362,125 @@ pub async fn update_folder(
         (status = 200, description = "Folder deleted"),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 404, description = "Folder not found", body = crate::error::ErrorResponse),
+        (status = 409, description = "A subfolder would collide with a folder in the parent", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to delete folder", body = crate::error::ErrorResponse)
     )
 )]
 pub async fn delete_folder(
     State(state): State<AppState>,
     Path(folder_id): Path<String>,
-    headers: HeaderMap,
+    AuthUser(user): AuthUser,
 ) -> Result<(), ApiError> {
-    let token = require_bearer(&headers)?;
-    let (user, _) = state.authenticate(&token).await?;
+    let config = &state.config().folders;
+    let now = chrono::Utc::now().to_rfc3339();
 
-    let result = sqlx::query("DELETE FROM folders WHERE public_id = ? AND user_id = ?")
-        .bind(&folder_id)
-        .bind(user.id)
-        .execute(state.db_pool())
+    let (folder_db_id, parent_db_id): (i64, Option<i64>) =
+        sqlx::query_as("SELECT id, parent_id FROM folders WHERE public_id = ? AND user_id = ?")
+            .bind(&folder_id)
+            .bind(user.id)
+            .fetch_optional(state.db_pool())
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to fetch folder: {}", e);
+                ApiError::internal_server_error("Failed to delete folder")
+            })?
+            .ok_or_else(|| ApiError::not_found("Folder not found"))?;
+
+    // Chats are moved, subfolders reparented and the folder deleted together, so a
+    // failure part-way leaves everything where it was
+    let mut tx = state.db_pool().begin().await.map_err(|e| {
+        tracing::error!("Failed to begin folder deletion transaction: {}", e);
+        ApiError::internal_server_error("Failed to delete folder")
+    })?;
+
+    // Folders going away: this one, plus its whole subtree unless subfolders move up
+    let removed: Vec<(i64, String)> = match config.subfolders_on_delete {
+        SubfoldersOnFolderDelete::Delete => sqlx::query_as(
+            r#"
+            WITH RECURSIVE subtree(id, public_id) AS (
+                SELECT id, public_id FROM folders WHERE id = ?
+                UNION
+                SELECT f.id, f.public_id FROM folders f
+                JOIN subtree s ON f.parent_id = s.id
+            )
+            SELECT id, public_id FROM subtree
+            "#,
+        )
+        .bind(folder_db_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to collect subfolders: {}", e);
+            ApiError::internal_server_error("Failed to delete folder")
+        })?,
+        SubfoldersOnFolderDelete::MoveToParent => vec![(folder_db_id, folder_id.clone())],
+    };
+
+    let chat_folder_db_id = match config.chats_on_delete {
+        ChatsOnFolderDelete::Detach => None,
+        ChatsOnFolderDelete::MoveToParent => parent_db_id,
+    };
+    for (removed_db_id, _) in &removed {
+        sqlx::query("UPDATE chats SET folder_id = ?, updated_at = ? WHERE folder_id = ?")
+            .bind(chat_folder_db_id)
+            .bind(&now)
+            .bind(removed_db_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to move chats out of folder: {}", e);
+                ApiError::internal_server_error("Failed to delete folder")
+            })?;
+    }
+
+    let mut moved = Vec::new();
+    if config.subfolders_on_delete == SubfoldersOnFolderDelete::MoveToParent {
+        moved = sqlx::query_as::<_, Folder>(
+            r#"
+            UPDATE folders
+            SET parent_id = ?, updated_at = ?
+            WHERE parent_id = ?
+            RETURNING id, public_id, user_id, name, color, parent_id, collapsed,
+                      created_at, updated_at
+            "#,
+        )
+        .bind(parent_db_id)
+        .bind(&now)
+        .bind(folder_db_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
+            if matches!(&e, sqlx::Error::Database(db) if db.is_unique_violation()) {
+                return ApiError::conflict("A subfolder would collide with a folder in the parent");
+            }
+            tracing::error!("Failed to reparent subfolders: {}", e);
+            ApiError::internal_server_error("Failed to delete folder")
+        })?;
+    }
+
+    sqlx::query("DELETE FROM folders WHERE id = ?")
+        .bind(folder_db_id)
+        .execute(&mut *tx)
         .await
         .map_err(|e| {
             tracing::error!("Failed to delete folder: {}", e);
             ApiError::internal_server_error("Failed to delete folder")
         })?;
 
-    if result.rows_affected() == 0 {
-        return Err(ApiError::not_found("Folder not found"));
-    }
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Failed to commit folder deletion: {}", e);
+        ApiError::internal_server_error("Failed to delete folder")
+    })?;
 
-    let event = ServerEvent::FolderDeleted {
-        folder_id: folder_id.clone(),
-    };
-    state.broadcast_to_user(user.id, &event).await;
+    for folder in moved {
+        state.broadcast_to_user(user.id, &ServerEvent::FolderUpdated { folder }).await;
+    }
+    for (_, removed_public_id) in removed {
+        let event = ServerEvent::FolderDeleted {
+            folder_id: removed_public_id,
+        };
+        state.broadcast_to_user(user.id, &event).await;
+    }
 
     Ok(())
 }
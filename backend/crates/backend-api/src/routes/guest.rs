@@ -0,0 +1,76 @@
+//! Completions for visitors without an account, when `[chat.guest]` is enabled.
+//! Nothing a guest sends is stored, and guests cannot reach any user's data.
+
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{audit::ClientIp, routes::chat, ApiError, AppState, FieldError};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GuestChatRequest {
+    pub prompt: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GuestChatResponse {
+    pub model: String,
+    pub content: String,
+}
+
+// Answer a guest's prompt without storing it
+#[utoipa::path(
+    post,
+    path = "/api/guest/chat",
+    tag = "Guest",
+    request_body = GuestChatRequest,
+    responses(
+        (status = 200, description = "Completion generated", body = GuestChatResponse),
+        (status = 400, description = "Invalid prompt", body = crate::error::ErrorResponse),
+        (status = 404, description = "Guest chat is disabled", body = crate::error::ErrorResponse),
+        (status = 429, description = "Too many guest requests from this address", body = crate::error::ErrorResponse),
+        (status = 500, description = "Completion failed", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn guest_chat(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(req): Json<GuestChatRequest>,
+) -> Result<Json<GuestChatResponse>, ApiError> {
+    let guest = &state.config().chat.guest;
+    if !guest.enabled {
+        return Err(ApiError::not_found("Not found"));
+    }
+
+    // Without connection info every guest shares one bucket, which errs on the strict side
+    state.check_guest_flood(ip.unwrap_or_default())?;
+
+    let prompt = req.prompt.trim();
+    let mut errors = Vec::new();
+    if prompt.is_empty() {
+        errors.push(FieldError::new("prompt", "must not be empty"));
+    } else if prompt.chars().count() > guest.max_prompt_chars {
+        errors.push(FieldError::new(
+            "prompt",
+            format!("must be at most {} characters", guest.max_prompt_chars),
+        ));
+    }
+    ApiError::check_fields(errors)?;
+
+    let model = guest
+        .model
+        .clone()
+        .unwrap_or_else(|| state.config().orchestrator.default_model.clone());
+    state.orchestrator().check_model_allowed(&model)?;
+    let provider = state.orchestrator().provider_for_model(&model)?;
+
+    let request = chat::message_request(&model, prompt);
+    state.orchestrator().check_prompt_length(&request)?;
+    let _slot = state.orchestrator().acquire_completion_slot_for(&model).await?;
+    let completion = state.orchestrator().complete(provider.as_ref(), request).await?;
+
+    Ok(Json(GuestChatResponse {
+        model,
+        content: completion.message.text().unwrap_or_default().to_string(),
+    }))
+}
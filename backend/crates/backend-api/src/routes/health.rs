@@ -1,14 +1,90 @@
-use axum::Json;
-use chrono::Utc;
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 use utoipa::ToSchema;
 
+use crate::AppState;
+
+const DATABASE_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
     pub timestamp: String,
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// Overall status: `ok`, `degraded` (a non-critical dependency failed) or `down`.
+    pub status: String,
+    pub timestamp: String,
+    pub dependencies: Vec<DependencyHealth>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DependencyHealth {
+    pub name: String,
+    pub status: String,
+    /// Critical dependencies take the whole service down when they fail.
+    pub critical: bool,
+    /// `unavailable` for a failed dependency. The cause is only logged, since the
+    /// endpoint is unauthenticated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable)]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TasksHealthResponse {
+    /// `ok`, or `stale` when any task has missed its runs.
+    pub status: String,
+    pub timestamp: String,
+    pub tasks: Vec<TaskHealth>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TaskHealth {
+    pub name: String,
+    pub interval_secs: u64,
+    #[schema(nullable)]
+    pub last_run: Option<String>,
+    /// `ok` or `error`; absent until the first run finishes.
+    #[schema(nullable)]
+    pub last_result: Option<String>,
+    /// The last run's summary or error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable)]
+    pub detail: Option<String>,
+    /// No run has finished within twice the interval.
+    pub stale: bool,
+}
+
+impl DependencyHealth {
+    fn ok(name: &str, critical: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            status: "ok".to_string(),
+            critical,
+            detail: None,
+        }
+    }
+
+    fn down(name: &str, critical: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            status: "down".to_string(),
+            critical,
+            detail: Some("unavailable".to_string()),
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        self.status == "ok"
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/health",
@@ -23,3 +99,120 @@ pub async fn health_check() -> Json<HealthResponse> {
         timestamp: Utc::now().to_rfc3339(),
     })
 }
+
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "Health",
+    responses(
+        (status = 200, description = "Process liveness; does not touch dependencies", body = HealthResponse)
+    )
+)]
+pub async fn liveness_check() -> Json<HealthResponse> {
+    health_check().await
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "Health",
+    responses(
+        (status = 200, description = "Service ready, possibly degraded", body = ReadinessResponse),
+        (status = 503, description = "A critical dependency is down", body = ReadinessResponse)
+    )
+)]
+pub async fn readiness_check(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let dependencies = vec![probe_database(&state).await, probe_providers(&state)];
+
+    let critical_down = dependencies
+        .iter()
+        .any(|dependency| dependency.critical && !dependency.is_ok());
+    let any_down = dependencies.iter().any(|dependency| !dependency.is_ok());
+
+    let (status_code, status) = if critical_down {
+        (StatusCode::SERVICE_UNAVAILABLE, "down")
+    } else if any_down {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+
+    (
+        status_code,
+        Json(ReadinessResponse {
+            status: status.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            dependencies,
+        }),
+    )
+}
+
+async fn probe_database(state: &AppState) -> DependencyHealth {
+    let probe = sqlx::query_scalar::<_, i64>("SELECT 1").fetch_one(state.db_pool());
+
+    match tokio::time::timeout(DATABASE_PROBE_TIMEOUT, probe).await {
+        Ok(Ok(_)) => DependencyHealth::ok("database", true),
+        Ok(Err(error)) => {
+            tracing::error!("database readiness probe failed: {}", error);
+            DependencyHealth::down("database", true)
+        }
+        Err(_) => {
+            tracing::error!("database readiness probe timed out");
+            DependencyHealth::down("database", true)
+        }
+    }
+}
+
+fn probe_providers(state: &AppState) -> DependencyHealth {
+    match state.orchestrator().default_provider() {
+        Ok(_) => DependencyHealth::ok("providers", false),
+        Err(error) => {
+            tracing::error!("provider readiness probe failed: {}", error);
+            DependencyHealth::down("providers", false)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/tasks",
+    tag = "Health",
+    responses(
+        (status = 200, description = "Last run and staleness of each background task", body = TasksHealthResponse)
+    )
+)]
+pub async fn tasks_check(State(state): State<AppState>) -> Json<TasksHealthResponse> {
+    let tasks: Vec<TaskHealth> = state
+        .tasks()
+        .snapshot()
+        .into_iter()
+        .map(|task| {
+            let (last_result, detail) = match task.last_result {
+                Some(Ok(summary)) => (Some("ok"), Some(summary)),
+                Some(Err(error)) => (Some("error"), Some(error)),
+                None => (None, None),
+            };
+            TaskHealth {
+                name: task.name,
+                interval_secs: task.interval.as_secs(),
+                last_run: task
+                    .last_run
+                    .map(|at| DateTime::<Utc>::from(at).to_rfc3339()),
+                last_result: last_result.map(str::to_string),
+                detail,
+                stale: task.stale,
+            }
+        })
+        .collect();
+
+    let status = if tasks.iter().any(|task| task.stale) {
+        "stale"
+    } else {
+        "ok"
+    };
+    Json(TasksHealthResponse {
+        status: status.to_string(),
+        timestamp: Utc::now().to_rfc3339(),
+        tasks,
+    })
+}
@@ -1,20 +1,68 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::HeaderMap,
     Json,
 };
-use uuid::Uuid;
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+use utoipa::IntoParams;
 
 use crate::{
-    routes::models::{
-        CreateMessageRequest, Message, MessageEdit, MessageEditsResponse, MessageResponse,
-        MessagesResponse, UpdateMessageRequest,
+    ids::ResourceKind,
+    routes::{
+        drafts::DraftsService,
+        models::{
+            BatchCreateMessagesRequest, CountResponse, CreateMessageRequest, DiffSegment, Message,
+            MemberRole, MessageEdit, MessageEditsResponse, MessageResponse, MessageStatus,
+            MessageUsage, MessagesResponse, RegenerateMessageRequest, RegenerateMessageResponse,
+            UpdateMessageRequest,
+        },
+        websocket::{generate_reply, LastReply, ReplyJob},
     },
     state::ServerEvent,
-    util::require_bearer,
-    ApiError, AppState,
+    titles::spawn_title_untitled_chat,
+    util::{expected_version, require_bearer, retry_on_busy, with_transaction},
+    ApiError, AppState, FieldError,
 };
 
+pub const MESSAGE_ROLES: &[&str] = &["user", "assistant", "system"];
+pub const MESSAGE_TYPES: &[&str] = &["text", "system", "file"];
+/// Upper bound on the number of messages accepted by one batch request.
+pub const MAX_BATCH_MESSAGES: usize = 500;
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct GetMessagesQuery {
+    /// Only return messages with this role (`user`, `assistant` or `system`).
+    pub role: Option<String>,
+    /// Only return messages of this type (`text`, `system` or `file`).
+    pub message_type: Option<String>,
+    /// Include each message's token usage and estimated cost.
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct MessageRow {
+    #[sqlx(flatten)]
+    message: Message,
+    #[sqlx(flatten)]
+    usage: MessageUsage,
+}
+
+pub const EDIT_DIFF_MODES: &[&str] = &["line", "word"];
+const MAX_EDIT_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct GetMessageEditsQuery {
+    /// Include a computed diff for each edit, split by `line` or by `word`.
+    pub diff: Option<String>,
+    /// Page size, 50 by default and at most 200.
+    pub limit: Option<i64>,
+    /// Only return edits older than the edit with this id; pass the previous page's
+    /// `next_before` to continue.
+    pub before: Option<i64>,
+}
+
 // Get messages for a chat
 #[utoipa::path(
     get,
@@ -22,10 +70,12 @@ use crate::{
     tag = "Messages",
     security(("bearerAuth" = [])),
     params(
-        ("chat_id" = String, Path, description = "Chat public identifier")
+        ("chat_id" = String, Path, description = "Chat public identifier"),
+        GetMessagesQuery
     ),
     responses(
         (status = 200, description = "List chat messages", body = MessagesResponse),
+        (status = 400, description = "Unknown role or message_type filter, or a non-chat id", body = crate::error::ErrorResponse),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
         (status = 404, description = "Chat not found", body = crate::error::ErrorResponse),
@@ -36,9 +86,24 @@ pub async fn get_messages(
     State(state): State<AppState>,
     Path(chat_id): Path<String>,
     headers: HeaderMap,
+    Query(query): Query<GetMessagesQuery>,
 ) -> Result<Json<MessagesResponse>, ApiError> {
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Chat.check_public_id(&chat_id)?;
+
+    if let Some(role) = query.role.as_deref() {
+        if !MESSAGE_ROLES.contains(&role) {
+            return Err(ApiError::bad_request(format!("unknown role filter: {role}")));
+        }
+    }
+    if let Some(message_type) = query.message_type.as_deref() {
+        if !MESSAGE_TYPES.contains(&message_type) {
+            return Err(ApiError::bad_request(format!(
+                "unknown message_type filter: {message_type}"
+            )));
+        }
+    }
 
     // Check if user is a member of the chat
     let chat_db_id: Option<i64> = sqlx::query_scalar(
@@ -50,36 +115,107 @@ pub async fn get_messages(
     )
     .bind(&chat_id)
     .bind(user.id)
-    .fetch_optional(state.db_pool())
+    .fetch_optional(state.db_read_pool())
     .await
     .map_err(|e| {
         tracing::error!("Failed to check chat membership: {}", e);
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
-    let messages = sqlx::query_as::<_, Message>(
+    let messages_query = sqlx::query_as::<_, MessageRow>(
         r#"
         SELECT id, public_id, chat_id, user_id, content, role, model, message_type,
-               thread_id, reply_to_id, created_at, updated_at
+               thread_id, reply_to_id, version, superseded_by, status, created_at, updated_at,
+               prompt_tokens, completion_tokens, estimated_cost
         FROM messages
         WHERE chat_id = ?
-        ORDER BY created_at ASC
+          AND (? IS NULL OR role = ?)
+          AND (? IS NULL OR message_type = ?)
+        ORDER BY created_at ASC, id ASC
         "#,
     )
     .bind(chat_db_id)
-    .fetch_all(state.db_pool())
+    .bind(query.role.as_deref())
+    .bind(query.role.as_deref())
+    .bind(query.message_type.as_deref())
+    .bind(query.message_type.as_deref());
+    let rows = state
+        .timed_query("messages.list", messages_query.fetch_all(state.db_replica_pool()))
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch messages: {}", e);
+            ApiError::internal_server_error("Failed to fetch messages")
+        })?;
+
+    let messages = rows
+        .into_iter()
+        .map(|MessageRow { mut message, usage }| {
+            message.usage = query.include_usage.then_some(usage);
+            message
+        })
+        .collect();
+
+    Ok(Json(MessagesResponse { messages }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/messages/count",
+    tag = "Messages",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "Number of messages in the chat", body = CountResponse),
+        (status = 400, description = "Not a chat id", body = crate::error::ErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Not a member of this chat", body = crate::error::ErrorResponse),
+        (status = 404, description = "Chat not found or caller is not a member", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to count messages", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn count_messages(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<CountResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Chat.check_public_id(&chat_id)?;
+
+    // No row when the caller is not a member, so membership needs no separate query
+    let count: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT (SELECT COUNT(*) FROM messages WHERE chat_id = c.id)
+        FROM chats c
+        JOIN chat_members cm ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(&chat_id)
+    .bind(user.id)
+    .fetch_optional(state.db_read_pool())
     .await
     .map_err(|e| {
-        tracing::error!("Failed to fetch messages: {}", e);
-        ApiError::internal_server_error("Failed to fetch messages")
+        tracing::error!("Failed to count messages: {}", e);
+        ApiError::internal_server_error("Failed to count messages")
     })?;
 
-    Ok(Json(MessagesResponse { messages }))
+    let Some(count) = count else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
+    Ok(Json(CountResponse { count }))
 }
 
-async fn fetch_chat_member_ids(state: &AppState, chat_db_id: i64) -> Result<Vec<i64>, ApiError> {
+pub(crate) async fn fetch_chat_member_ids(
+    state: &AppState,
+    chat_db_id: i64,
+) -> Result<Vec<i64>, ApiError> {
     sqlx::query_scalar::<_, i64>(
         r#"
         SELECT user_id FROM chat_members
@@ -87,7 +223,7 @@ async fn fetch_chat_member_ids(state: &AppState, chat_db_id: i64) -> Result<Vec<
         "#,
     )
     .bind(chat_db_id)
-    .fetch_all(state.db_pool())
+    .fetch_all(state.db_read_pool())
     .await
     .map_err(|e| {
         tracing::error!(
@@ -99,6 +235,90 @@ async fn fetch_chat_member_ids(state: &AppState, chat_db_id: i64) -> Result<Vec<
     })
 }
 
+// Resolve the chat a user is posting to; they must be a member whose role allows
+// posting (viewers are read-only)
+pub(crate) async fn posting_chat_db_id(
+    state: &AppState,
+    chat_id: &str,
+    user_id: i64,
+) -> Result<i64, ApiError> {
+    let membership: Option<(i64, String)> = sqlx::query_as(
+        r#"
+        SELECT c.id, cm.role FROM chats c
+        JOIN chat_members cm ON c.id = cm.chat_id
+        WHERE c.public_id = ? AND cm.user_id = ?
+        "#,
+    )
+    .bind(chat_id)
+    .bind(user_id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to check chat membership: {}", e);
+        ApiError::internal_server_error("Failed to check chat membership")
+    })?;
+
+    let Some((chat_db_id, role)) = membership else {
+        return Err(state.chat_access_denied(chat_id).await);
+    };
+    if !MemberRole::parse(&role).is_some_and(MemberRole::can_post) {
+        return Err(ApiError::forbidden("Viewers cannot post messages"));
+    }
+    Ok(chat_db_id)
+}
+
+// Resolve an optional message reference (reply_to_id / thread_id) to its row id;
+// it must point at a message in the same chat
+async fn resolve_chat_message<'e, E>(
+    executor: E,
+    chat_db_id: i64,
+    field: &str,
+    public_id: &Option<String>,
+) -> Result<Option<i64>, ApiError>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let Some(public_id) = public_id else {
+        return Ok(None);
+    };
+
+    let resolved =
+        sqlx::query_scalar::<_, i64>("SELECT id FROM messages WHERE public_id = ? AND chat_id = ?")
+            .bind(public_id)
+            .bind(chat_db_id)
+            .fetch_optional(executor)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to resolve {} message: {}", field, e);
+                ApiError::internal_server_error(format!("Failed to resolve {} message", field))
+            })?;
+
+    resolved.map(Some).ok_or_else(|| {
+        ApiError::bad_request(format!("{field} does not reference a message in this chat"))
+    })
+}
+
+/// Checks a message's `role` and `message_type`. `prefix` addresses the message within
+/// the request body, e.g. `messages[3].` in a batch.
+fn message_field_errors(prefix: &str, message: &CreateMessageRequest) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if !MESSAGE_ROLES.contains(&message.role.as_str()) {
+        errors.push(FieldError::new(
+            format!("{prefix}role"),
+            format!("must be one of: {}", MESSAGE_ROLES.join(", ")),
+        ));
+    }
+    if let Some(message_type) = message.message_type.as_deref() {
+        if !MESSAGE_TYPES.contains(&message_type) {
+            errors.push(FieldError::new(
+                format!("{prefix}message_type"),
+                format!("must be one of: {}", MESSAGE_TYPES.join(", ")),
+            ));
+        }
+    }
+    errors
+}
+
 // Create a new message
 #[utoipa::path(
     post,
@@ -115,6 +335,7 @@ async fn fetch_chat_member_ids(state: &AppState, chat_db_id: i64) -> Result<Vec<
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
         (status = 404, description = "Chat not found", body = crate::error::ErrorResponse),
+        (status = 429, description = "Sending messages to this chat too quickly", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to create message", body = crate::error::ErrorResponse)
     )
 )]
@@ -126,104 +347,79 @@ pub async fn create_message(
 ) -> Result<Json<MessageResponse>, ApiError> {
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Chat.check_public_id(&chat_id)?;
+    ApiError::check_fields(message_field_errors("", &req))?;
 
-    // Check if user is a member of the chat
-    let chat_db_id: Option<i64> = sqlx::query_scalar(
-        r#"
-        SELECT c.id FROM chats c
-        JOIN chat_members cm ON c.id = cm.chat_id
-        WHERE c.public_id = ? AND cm.user_id = ?
-        "#,
-    )
-    .bind(&chat_id)
-    .bind(user.id)
-    .fetch_optional(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to check chat membership: {}", e);
-        ApiError::internal_server_error("Failed to check chat membership")
-    })?;
-
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    // Check if user is a member of the chat who may post
+    let chat_db_id = posting_chat_db_id(&state, &chat_id, user.id).await?;
+    // The role is client-supplied, so every post counts; replies the server generates
+    // itself are stored by the orchestrator path and never reach this handler
+    state.check_message_flood(user.id, chat_db_id)?;
 
-    let public_id = Uuid::new_v4().to_string();
+    let public_id = ResourceKind::Message.new_public_id(&state.config().ids);
     let now = chrono::Utc::now().to_rfc3339();
 
-    // Resolve reply_to_id if provided
-    let reply_to_db_id = if let Some(reply_to_public_id) = &req.reply_to_id {
-        sqlx::query_scalar::<_, i64>("SELECT id FROM messages WHERE public_id = ? AND chat_id = ?")
-            .bind(reply_to_public_id)
+    let message_type = req.message_type.unwrap_or_else(|| "text".to_string());
+
+    // The message is stored and the sender's draft cleared together. The whole
+    // transaction is retried while SQLite reports the database busy.
+    let message = retry_on_busy(|| {
+        with_transaction(state.db_pool(), "Failed to create message", async |tx| {
+            // reply_to_id and thread_id must reference messages in this chat
+            let reply_to_db_id =
+                resolve_chat_message(&mut **tx, chat_db_id, "reply_to_id", &req.reply_to_id)
+                    .await?;
+            let thread_db_id =
+                resolve_chat_message(&mut **tx, chat_db_id, "thread_id", &req.thread_id)
+                    .await?;
+
+            let message_db_id = sqlx::query(
+                r#"
+                INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, thread_id, reply_to_id, status, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#
+            )
+            .bind(&public_id)
             .bind(chat_db_id)
-            .fetch_optional(state.db_pool())
+            .bind(user.id)
+            .bind(&req.content)
+            .bind(&message_type)
+            .bind(&req.role)
+            .bind(&req.model)
+            .bind(thread_db_id)
+            .bind(reply_to_db_id)
+            .bind(MessageStatus::stored(&req.role).as_str())
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut **tx)
             .await
             .map_err(|e| {
-                tracing::error!("Failed to resolve reply_to message: {}", e);
-                ApiError::internal_server_error("Failed to resolve reply_to message")
+                tracing::error!("Failed to create message: {}", e);
+                ApiError::internal_server_error("Failed to create message").busy_from(&e)
             })?
-    } else {
-        None
-    };
+            .last_insert_rowid();
 
-    // Resolve thread_id if provided
-    let thread_db_id = if let Some(thread_public_id) = &req.thread_id {
-        sqlx::query_scalar::<_, i64>("SELECT id FROM messages WHERE public_id = ? AND chat_id = ?")
-            .bind(thread_public_id)
-            .bind(chat_db_id)
-            .fetch_optional(state.db_pool())
+            let message = sqlx::query_as::<_, Message>(
+                r#"
+                SELECT id, public_id, chat_id, user_id, content, role, model, message_type,
+                       thread_id, reply_to_id, version, superseded_by, status, created_at, updated_at
+                FROM messages
+                WHERE id = ?
+                "#,
+            )
+            .bind(message_db_id)
+            .fetch_one(&mut **tx)
             .await
             .map_err(|e| {
-                tracing::error!("Failed to resolve thread message: {}", e);
-                ApiError::internal_server_error("Failed to resolve thread message")
-            })?
-    } else {
-        None
-    };
-
-    let message_type = req.message_type.unwrap_or_else(|| "text".to_string());
-
-    // Create the message
-    let message_db_id = sqlx::query(
-        r#"
-        INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, thread_id, reply_to_id, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-        "#
-    )
-    .bind(&public_id)
-    .bind(chat_db_id)
-    .bind(user.id)
-    .bind(&req.content)
-    .bind(&message_type)
-    .bind(&req.role)
-    .bind(&req.model)
-    .bind(thread_db_id)
-    .bind(reply_to_db_id)
-    .bind(&now)
-    .bind(&now)
-    .execute(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to create message: {}", e);
-        ApiError::internal_server_error("Failed to create message")
-    })?
-    .last_insert_rowid();
+                tracing::error!("Failed to fetch created message: {}", e);
+                ApiError::internal_server_error("Failed to fetch created message")
+            })?;
 
-    // Fetch the created message
-    let message = sqlx::query_as::<_, Message>(
-        r#"
-        SELECT id, public_id, chat_id, user_id, content, role, model, message_type,
-               thread_id, reply_to_id, created_at, updated_at
-        FROM messages
-        WHERE id = ?
-        "#,
-    )
-    .bind(message_db_id)
-    .fetch_optional(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch created message: {}", e);
-        ApiError::internal_server_error("Failed to fetch created message")
-    })?
-    .ok_or_else(|| ApiError::internal_server_error("Failed to fetch created message"))?;
+            DraftsService::clear_draft(&mut **tx, chat_db_id, user.id).await?;
+            Ok(message)
+        })
+    })
+    .await?;
 
     let member_ids = fetch_chat_member_ids(&state, chat_db_id).await?;
     let event = ServerEvent::Message {
@@ -235,12 +431,248 @@ pub async fn create_message(
         timestamp: message.created_at.clone(),
         message_type: message.message_type.clone(),
     };
-    state.broadcast_to_chat(&chat_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
+
+    if message.role == "user" {
+        spawn_title_untitled_chat(&state, chat_db_id, &message.content);
+    }
 
     Ok(Json(MessageResponse { message }))
 }
 
+// Create several messages at once, e.g. when importing a conversation
+#[utoipa::path(
+    post,
+    path = "/api/chats/{chat_id}/messages/batch",
+    tag = "Messages",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    request_body = BatchCreateMessagesRequest,
+    responses(
+        (status = 200, description = "Messages created in request order", body = MessagesResponse),
+        (status = 400, description = "Invalid batch or message payload; nothing was created", body = crate::error::ErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 429, description = "Sending messages to this chat too quickly", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to create messages", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn create_messages_batch(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<BatchCreateMessagesRequest>,
+) -> Result<Json<MessagesResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Chat.check_public_id(&chat_id)?;
+
+    if req.messages.is_empty() {
+        return Err(ApiError::validation(vec![FieldError::new("messages", "must not be empty")]));
+    }
+    if req.messages.len() > MAX_BATCH_MESSAGES {
+        return Err(ApiError::validation(vec![FieldError::new(
+            "messages",
+            format!("may contain at most {MAX_BATCH_MESSAGES} messages"),
+        )]));
+    }
+
+    // Items without a timestamp are stamped with the arrival time; explicit ones must
+    // not go backwards so the stored order matches the request order
+    let now = chrono::Utc::now();
+    let mut errors = Vec::new();
+    let mut timestamps = Vec::with_capacity(req.messages.len());
+    let mut previous: Option<chrono::DateTime<chrono::Utc>> = None;
+    for (index, item) in req.messages.iter().enumerate() {
+        errors.extend(message_field_errors(&format!("messages[{index}]."), &item.message));
+
+        let created_at = match &item.created_at {
+            Some(raw) => match chrono::DateTime::parse_from_rfc3339(raw) {
+                Ok(created_at) => created_at.with_timezone(&chrono::Utc),
+                Err(_) => {
+                    errors.push(FieldError::new(
+                        format!("messages[{index}].created_at"),
+                        "must be an RFC3339 timestamp",
+                    ));
+                    continue;
+                }
+            },
+            None => now,
+        };
+        if previous.is_some_and(|previous| created_at < previous) {
+            errors.push(FieldError::new(
+                format!("messages[{index}].created_at"),
+                "is earlier than the previous message",
+            ));
+        }
+        previous = Some(created_at);
+        timestamps.push(created_at.to_rfc3339());
+    }
+    ApiError::check_fields(errors)?;
+
+    let chat_db_id = posting_chat_db_id(&state, &chat_id, user.id).await?;
+    // A batch counts as a single send
+    state.check_message_flood(user.id, chat_db_id)?;
+
+    // Any failure rolls the transaction back, so a batch is stored whole or not at all
+    let messages = with_transaction(state.db_pool(), "Failed to create messages", async |tx| {
+        let mut message_db_ids = Vec::with_capacity(req.messages.len());
+        for (index, (item, created_at)) in req.messages.iter().zip(&timestamps).enumerate() {
+            let message = &item.message;
+            let with_index =
+                |e: ApiError| ApiError::new(e.status, format!("messages[{index}]: {}", e.message));
+
+            let reply_to_db_id =
+                resolve_chat_message(&mut **tx, chat_db_id, "reply_to_id", &message.reply_to_id)
+                    .await
+                    .map_err(with_index)?;
+            let thread_db_id =
+                resolve_chat_message(&mut **tx, chat_db_id, "thread_id", &message.thread_id)
+                    .await
+                    .map_err(with_index)?;
+
+            let message_type = message.message_type.as_deref().unwrap_or("text");
+            let message_db_id = sqlx::query(
+                r#"
+                INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, thread_id, reply_to_id, status, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#
+            )
+            .bind(ResourceKind::Message.new_public_id(&state.config().ids))
+            .bind(chat_db_id)
+            .bind(user.id)
+            .bind(&message.content)
+            .bind(message_type)
+            .bind(&message.role)
+            .bind(&message.model)
+            .bind(thread_db_id)
+            .bind(reply_to_db_id)
+            .bind(MessageStatus::stored(&message.role).as_str())
+            .bind(created_at)
+            .bind(created_at)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to create batch message {}: {}", index, e);
+                ApiError::internal_server_error("Failed to create messages")
+            })?
+            .last_insert_rowid();
+            message_db_ids.push(message_db_id);
+        }
+
+        let mut messages = Vec::with_capacity(message_db_ids.len());
+        for message_db_id in message_db_ids {
+            let message = sqlx::query_as::<_, Message>(
+                r#"
+                SELECT id, public_id, chat_id, user_id, content, role, model, message_type,
+                       thread_id, reply_to_id, version, superseded_by, status, created_at, updated_at
+                FROM messages
+                WHERE id = ?
+                "#,
+            )
+            .bind(message_db_id)
+            .fetch_one(&mut **tx)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to fetch created batch message: {}", e);
+                ApiError::internal_server_error("Failed to fetch created messages")
+            })?;
+            messages.push(message);
+        }
+        Ok(messages)
+    })
+    .await?;
+
+    let member_ids = fetch_chat_member_ids(&state, chat_db_id).await?;
+    for message in &messages {
+        let event = ServerEvent::Message {
+            chat_id: chat_id.clone(),
+            message_id: message.public_id.clone(),
+            user_id: message.user_id,
+            content: message.content.clone(),
+            model: message.model.clone(),
+            timestamp: message.created_at.clone(),
+            message_type: message.message_type.clone(),
+        };
+        state.broadcast_to_chat_members(&chat_id, member_ids.clone(), &event).await;
+    }
+
+    Ok(Json(MessagesResponse { messages }))
+}
+
+// Answer the prompt of the chat's last assistant reply again. The new reply is
+// generated in the background and delivered over the chat's WebSocket events; the old
+// one is kept and marked superseded once the new one completes.
+#[utoipa::path(
+    post,
+    path = "/api/chats/{chat_id}/messages/regenerate",
+    tag = "Messages",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    request_body = RegenerateMessageRequest,
+    responses(
+        (status = 200, description = "Regeneration started", body = RegenerateMessageResponse),
+        (status = 400, description = "No reply to regenerate, or it is still being generated", body = crate::error::ErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden, or the model is not allowed", body = crate::error::ErrorResponse),
+        (status = 404, description = "Chat or model not found", body = crate::error::ErrorResponse),
+        (status = 429, description = "Sending messages to this chat too quickly", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to regenerate message", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn regenerate_last_message(
+    State(state): State<AppState>,
+    Path(chat_id): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<RegenerateMessageRequest>,
+) -> Result<Json<RegenerateMessageResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+    ResourceKind::Chat.check_public_id(&chat_id)?;
+    let chat_db_id = posting_chat_db_id(&state, &chat_id, user.id).await?;
+    // Each regeneration is another completion, so it spends a send like a new message
+    state.check_message_flood(user.id, chat_db_id)?;
+
+    let last = LastReply::find(state.db_pool(), chat_db_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to find last reply: {}", e);
+            ApiError::internal_server_error("Failed to regenerate message")
+        })?
+        .ok_or_else(|| ApiError::bad_request("No reply to regenerate"))?;
+    let (prompt, model) = last
+        .regeneration(&state, req.model)
+        .map_err(ApiError::bad_request)?;
+    // Checked here so the caller hears about it; nobody is listening for the
+    // background task's errors
+    state.orchestrator().check_model_allowed(&model)?;
+    state.orchestrator().provider_for_model(&model)?;
+
+    let message_id = ResourceKind::Message.new_public_id(&state.config().ids);
+    tokio::spawn(generate_reply(ReplyJob {
+        state: state.clone(),
+        chat_id: chat_id.clone(),
+        chat_db_id,
+        user_id: user.id,
+        model: model.clone(),
+        prompt,
+        reply_id: message_id.clone(),
+        supersedes: Some(last.id),
+        requester: None,
+        broadcaster: state.chat_broadcaster(&chat_id).await,
+    }));
+
+    Ok(Json(RegenerateMessageResponse {
+        message_id,
+        supersedes: last.public_id,
+        model,
+    }))
+}
+
 // Update a message (with audit trail)
 #[utoipa::path(
     put,
@@ -258,6 +690,7 @@ pub async fn create_message(
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
         (status = 404, description = "Message not found", body = crate::error::ErrorResponse),
+        (status = 409, description = "Message changed since the expected version", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to update message", body = crate::error::ErrorResponse)
     )
 )]
@@ -287,23 +720,36 @@ pub async fn update_message(
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     // Get the original message
-    let original_message: Option<(i64, String)> =
-        sqlx::query_as("SELECT id, content FROM messages WHERE public_id = ? AND chat_id = ?")
-            .bind(&message_public_id)
-            .bind(chat_db_id)
-            .fetch_optional(state.db_pool())
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to fetch original message: {}", e);
-                ApiError::internal_server_error("Failed to fetch original message")
-            })?;
+    let original_message: Option<(i64, String, i64)> = sqlx::query_as(
+        "SELECT id, content, version FROM messages WHERE public_id = ? AND chat_id = ?",
+    )
+    .bind(&message_public_id)
+    .bind(chat_db_id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch original message: {}", e);
+        ApiError::internal_server_error("Failed to fetch original message")
+    })?;
 
-    let (message_db_id, original_content) =
+    let (message_db_id, original_content, current_version) =
         original_message.ok_or_else(|| ApiError::not_found("Message not found"))?;
 
+    let expected_version = expected_version(&headers, req.version)?;
+    let conflict = |expected: i64| {
+        ApiError::conflict(format!(
+            "Message has changed since version {expected}; refetch and retry"
+        ))
+    };
+    if let Some(expected) = expected_version.filter(|expected| *expected != current_version) {
+        return Err(conflict(expected));
+    }
+
     // Check if user can edit this message (owner or admin)
     let can_edit: bool = if user.id
         == sqlx::query_scalar::<_, i64>("SELECT user_id FROM messages WHERE id = ?")
@@ -338,68 +784,74 @@ pub async fn update_message(
 
     let now = chrono::Utc::now().to_rfc3339();
 
-    // Create audit entry for the edit
-    sqlx::query(
-        r#"
-        INSERT INTO message_edits (message_id, edited_by_user_id, old_content, new_content, edited_at)
-        VALUES (?, ?, ?, ?, ?)
-        "#
-    )
-    .bind(message_db_id)
-    .bind(user.id)
-    .bind(&original_content)
-    .bind(&req.content)
-    .bind(&now)
-    .execute(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to create message edit audit: {}", e);
-        ApiError::internal_server_error("Failed to create message edit audit")
-    })?;
+    // The edit and its audit entry are written together. The version is checked again
+    // here in case another edit landed after the read above.
+    let message = with_transaction(state.db_pool(), "Failed to update message", async |tx| {
+        let result = sqlx::query(
+            r#"
+            UPDATE messages
+            SET content = ?, version = version + 1, updated_at = ?
+            WHERE id = ? AND (? IS NULL OR version = ?)
+            "#,
+        )
+        .bind(&req.content)
+        .bind(&now)
+        .bind(message_db_id)
+        .bind(expected_version)
+        .bind(expected_version)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to update message: {}", e);
+            ApiError::internal_server_error("Failed to update message")
+        })?;
 
-    // Update the message
-    sqlx::query(
-        r#"
-        UPDATE messages
-        SET content = ?, updated_at = ?
-        WHERE id = ?
-        "#,
-    )
-    .bind(&req.content)
-    .bind(&now)
-    .bind(message_db_id)
-    .execute(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to update message: {}", e);
-        ApiError::internal_server_error("Failed to update message")
-    })?;
+        if let Some(expected) = expected_version.filter(|_| result.rows_affected() == 0) {
+            return Err(conflict(expected));
+        }
 
-    // Fetch the updated message
-    let message = sqlx::query_as::<_, Message>(
-        r#"
-        SELECT id, public_id, chat_id, user_id, content, role, model, message_type,
-               thread_id, reply_to_id, created_at, updated_at
-        FROM messages
-        WHERE id = ?
-        "#,
-    )
-    .bind(message_db_id)
-    .fetch_optional(state.db_pool())
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch updated message: {}", e);
-        ApiError::internal_server_error("Failed to fetch updated message")
-    })?
-    .ok_or_else(|| ApiError::internal_server_error("Failed to fetch updated message"))?;
+        sqlx::query(
+            r#"
+            INSERT INTO message_edits (message_id, edited_by_user_id, old_content, new_content, edited_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(message_db_id)
+        .bind(user.id)
+        .bind(&original_content)
+        .bind(&req.content)
+        .bind(&now)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create message edit audit: {}", e);
+            ApiError::internal_server_error("Failed to create message edit audit")
+        })?;
+
+        sqlx::query_as::<_, Message>(
+            r#"
+            SELECT id, public_id, chat_id, user_id, content, role, model, message_type,
+                   thread_id, reply_to_id, version, superseded_by, status, created_at, updated_at
+            FROM messages
+            WHERE id = ?
+            "#,
+        )
+        .bind(message_db_id)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch updated message: {}", e);
+            ApiError::internal_server_error("Failed to fetch updated message")
+        })
+    })
+    .await?;
 
     let member_ids = fetch_chat_member_ids(&state, chat_db_id).await?;
     let event = ServerEvent::MessageUpdated {
         chat_id: chat_id.clone(),
         message: message.clone(),
     };
-    state.broadcast_to_chat(&chat_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
 
     Ok(Json(MessageResponse { message }))
 }
@@ -447,7 +899,9 @@ pub async fn delete_message(
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     // Get the message details
     let message_details: Option<(i64, i64)> =
@@ -524,8 +978,7 @@ pub async fn delete_message(
         chat_id: chat_id.clone(),
         message_id: message_public_id.clone(),
     };
-    state.broadcast_to_chat(&chat_id, &event).await;
-    state.broadcast_to_users(member_ids, &event).await;
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
 
     Ok(())
 }
@@ -538,10 +991,12 @@ pub async fn delete_message(
     security(("bearerAuth" = [])),
     params(
         ("chat_id" = String, Path, description = "Chat public identifier"),
-        ("message_id" = String, Path, description = "Message public identifier")
+        ("message_id" = String, Path, description = "Message public identifier"),
+        GetMessageEditsQuery
     ),
     responses(
-        (status = 200, description = "Message edit history", body = MessageEditsResponse),
+        (status = 200, description = "A page of the message's edit history, newest first", body = MessageEditsResponse),
+        (status = 400, description = "Unknown diff mode", body = crate::error::ErrorResponse),
         (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
         (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
         (status = 404, description = "Message not found", body = crate::error::ErrorResponse),
@@ -552,10 +1007,21 @@ pub async fn get_message_edits(
     State(state): State<AppState>,
     Path((chat_id, message_public_id)): Path<(String, String)>,
     headers: HeaderMap,
+    Query(query): Query<GetMessageEditsQuery>,
 ) -> Result<Json<MessageEditsResponse>, ApiError> {
     let token = require_bearer(&headers)?;
     let (user, _) = state.authenticate(&token).await?;
 
+    if let Some(mode) = query.diff.as_deref() {
+        if !EDIT_DIFF_MODES.contains(&mode) {
+            return Err(ApiError::bad_request(format!(
+                "Invalid diff mode '{}'; expected one of: {}",
+                mode,
+                EDIT_DIFF_MODES.join(", ")
+            )));
+        }
+    }
+
     // Check if user is a member of the chat
     let chat_db_id: Option<i64> = sqlx::query_scalar(
         r#"
@@ -573,7 +1039,9 @@ pub async fn get_message_edits(
         ApiError::internal_server_error("Failed to check chat membership")
     })?;
 
-    let chat_db_id = chat_db_id.ok_or_else(|| ApiError::forbidden("Not a member of this chat"))?;
+    let Some(chat_db_id) = chat_db_id else {
+        return Err(state.chat_access_denied(&chat_id).await);
+    };
 
     // Get the message ID
     let message_db_id: Option<i64> =
@@ -588,17 +1056,24 @@ pub async fn get_message_edits(
             })?;
 
     let message_db_id = message_db_id.ok_or_else(|| ApiError::not_found("Message not found"))?;
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_EDIT_PAGE_SIZE);
 
-    // Get edit history
-    let edits = sqlx::query_as::<_, MessageEdit>(
+    // One extra row tells whether an older page exists; ids break ties between edits
+    // saved in the same instant
+    let mut edits = sqlx::query_as::<_, MessageEdit>(
         r#"
         SELECT id, message_id, edited_by_user_id, old_content, new_content, edited_at
         FROM message_edits
         WHERE message_id = ?
-        ORDER BY edited_at DESC
+          AND (? IS NULL OR (edited_at, id) < (SELECT edited_at, id FROM message_edits WHERE id = ?))
+        ORDER BY edited_at DESC, id DESC
+        LIMIT ?
         "#,
     )
     .bind(message_db_id)
+    .bind(query.before)
+    .bind(query.before)
+    .bind(limit + 1)
     .fetch_all(state.db_pool())
     .await
     .map_err(|e| {
@@ -606,5 +1081,42 @@ pub async fn get_message_edits(
         ApiError::internal_server_error("Failed to fetch message edits")
     })?;
 
-    Ok(Json(MessageEditsResponse { edits }))
+    let has_more = edits.len() as i64 > limit;
+    edits.truncate(limit as usize);
+    let next_before = edits.last().filter(|_| has_more).map(|edit| edit.id);
+
+    if let Some(mode) = query.diff.as_deref() {
+        for edit in &mut edits {
+            edit.diff = Some(diff_segments(&edit.old_content, &edit.new_content, mode));
+        }
+    }
+
+    Ok(Json(MessageEditsResponse { edits, next_before }))
+}
+
+// Diff two versions of a message by line or by word, merging adjacent changes of
+// the same kind so clients get one segment per highlighted run
+pub fn diff_segments(old: &str, new: &str, mode: &str) -> Vec<DiffSegment> {
+    let diff = if mode == "word" {
+        TextDiff::from_words(old, new)
+    } else {
+        TextDiff::from_lines(old, new)
+    };
+
+    let mut segments: Vec<DiffSegment> = Vec::new();
+    for change in diff.iter_all_changes() {
+        let op = match change.tag() {
+            ChangeTag::Equal => "equal",
+            ChangeTag::Insert => "insert",
+            ChangeTag::Delete => "delete",
+        };
+        match segments.last_mut() {
+            Some(last) if last.op == op => last.text.push_str(change.value()),
+            _ => segments.push(DiffSegment {
+                op: op.to_string(),
+                text: change.value().to_string(),
+            }),
+        }
+    }
+    segments
 }
@@ -1,11 +1,17 @@
+pub mod admin;
 pub mod attachments;
 pub mod auth;
 pub mod chat;
 pub mod chats;
+pub mod drafts;
 pub mod folders;
+pub mod guest;
 pub mod health;
 pub mod messages;
 pub mod models;
 pub mod notifications;
 pub mod permissions;
+pub mod prompt_templates;
+pub mod reads;
+pub mod users;
 pub mod websocket;
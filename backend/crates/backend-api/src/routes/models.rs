@@ -1,10 +1,14 @@
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Json,
+};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use switchboard_orchestrator::OpenRouterModelSummary;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::{ApiError, AppState};
+use crate::{error::rate_limit_headers, ApiError, AppState};
 
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ModelsResponse {
@@ -69,6 +73,12 @@ pub struct Chat {
     pub folder_id: Option<i64>,
     pub title: String,
     pub chat_type: String,
+    /// Messages older than this many days are purged; `None` keeps them forever.
+    pub message_retention_days: Option<i64>,
+    /// Model replies use when a message names none; `None` uses the server default.
+    pub default_model: Option<String>,
+    /// Incremented on every edit; send it back with an update to detect conflicts.
+    pub version: i64,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -95,8 +105,37 @@ pub struct Message {
     pub message_type: String,
     pub thread_id: Option<i64>,
     pub reply_to_id: Option<i64>,
+    /// Incremented on every edit; send it back with an update to detect conflicts.
+    pub version: i64,
+    /// The reply that replaced this one when it was regenerated.
+    pub superseded_by: Option<i64>,
+    /// One of [`MessageStatus`]: `sent` for user messages, the reply's progress for
+    /// assistant ones.
+    pub status: String,
     pub created_at: String,
     pub updated_at: String,
+    /// Token usage and cost, present when requested with `include_usage`.
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<MessageUsage>,
+}
+
+/// What producing a message cost. Only assistant replies have usage; every field is
+/// null for other messages, and for replies whose provider reported none.
+#[derive(Debug, Default, Serialize, Deserialize, FromRow, ToSchema, Clone)]
+pub struct MessageUsage {
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    /// USD, priced when the reply finished.
+    pub estimated_cost: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema, Clone)]
+pub struct MessageDraft {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub content: String,
+    pub updated_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow, ToSchema, Clone)]
@@ -107,6 +146,17 @@ pub struct MessageEdit {
     pub old_content: String,
     pub new_content: String,
     pub edited_at: String,
+    /// Changes from `old_content` to `new_content`, present when a diff was requested.
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<Vec<DiffSegment>>,
+}
+
+/// A run of text that was kept (`equal`), added (`insert`) or removed (`delete`).
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq)]
+pub struct DiffSegment {
+    pub op: String,
+    pub text: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow, ToSchema, Clone)]
@@ -150,6 +200,16 @@ pub struct Permission {
     pub granted_at: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, FromRow, ToSchema, Clone)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub actor_user_id: Option<i64>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: String,
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateFolderRequest {
     pub name: String,
@@ -162,6 +222,20 @@ pub struct UpdateFolderRequest {
     pub name: Option<String>,
     pub color: Option<String>,
     pub collapsed: Option<bool>,
+    /// Parent folder public_id; `null` moves the folder to the top level and an
+    /// absent field leaves it where it is.
+    #[serde(default, deserialize_with = "present_field")]
+    #[schema(value_type = Option<String>, nullable)]
+    pub parent_id: Option<Option<String>>,
+}
+
+// Distinguishes an explicit `null` (Some(None)) from a missing field (None)
+fn present_field<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -169,13 +243,11 @@ pub struct CreateChatRequest {
     pub title: String,
     #[serde(default)]
     pub messages: Vec<ChatMessage>,
-    pub folder_id: Option<String>, // public_id
-    #[serde(default = "default_chat_type")]
-    pub chat_type: String,
-}
-
-fn default_chat_type() -> String {
-    "direct".to_string()
+    /// Public id of the folder to file the chat in; defaults to `chat.default_folder`.
+    pub folder_id: Option<String>,
+    /// Defaults to `chat.default_chat_type`.
+    #[serde(default)]
+    pub chat_type: Option<String>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -183,6 +255,10 @@ pub struct UpdateChatRequest {
     pub title: Option<String>,
     pub messages: Option<Vec<ChatMessage>>,
     pub folder_id: Option<String>, // public_id
+    pub chat_type: Option<String>,
+    /// The version the edit is based on; a stale version is rejected with 409.
+    /// The `If-Match` header may be used instead.
+    pub version: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -204,9 +280,13 @@ pub struct TokenUsage {
 #[derive(Debug, Serialize, Deserialize, FromRow, ToSchema, Clone)]
 pub struct ChatInvite {
     pub id: i64,
+    pub public_id: String,
     pub chat_id: i64,
     pub inviter_id: i64,
-    pub invitee_email: String,
+    /// Set for invites sent to an email address.
+    pub invitee_email: Option<String>,
+    /// Set for invites sent to an existing user by username.
+    pub invitee_user_id: Option<i64>,
     pub status: String,
     pub created_at: String,
     pub updated_at: String,
@@ -214,7 +294,11 @@ pub struct ChatInvite {
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateInviteRequest {
-    pub email: String,
+    /// Invite whoever signs in with this email.
+    pub email: Option<String>,
+    /// Invite an existing user by display name, e.g. `@ada`; the `@` is optional.
+    /// Exactly one of `email` and `username` must be given.
+    pub username: Option<String>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -227,6 +311,108 @@ pub struct InviteResponse {
     pub invite: ChatInvite,
 }
 
+/// Kind of chat, stored as lowercase text in `chats.chat_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatType {
+    Direct,
+    Group,
+    System,
+}
+
+impl ChatType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChatType::Direct => "direct",
+            ChatType::Group => "group",
+            ChatType::System => "system",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "direct" => Some(ChatType::Direct),
+            "group" => Some(ChatType::Group),
+            "system" => Some(ChatType::System),
+            _ => None,
+        }
+    }
+}
+
+/// Lifecycle of a message, stored as lowercase text in `messages.status`. User
+/// messages are `sent` once stored; until then they only exist on the client. Assistant
+/// replies start out `streaming` and become `complete` when generation finishes,
+/// `error` if the provider failed, or `interrupted` if the server stopped first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageStatus {
+    Sent,
+    Streaming,
+    Complete,
+    Interrupted,
+    Error,
+}
+
+impl MessageStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageStatus::Sent => "sent",
+            MessageStatus::Streaming => "streaming",
+            MessageStatus::Complete => "complete",
+            MessageStatus::Interrupted => "interrupted",
+            MessageStatus::Error => "error",
+        }
+    }
+
+    /// The status of a message stored whole with the given role: `sent` for user
+    /// messages, `complete` for the rest.
+    pub fn stored(role: &str) -> Self {
+        if role == "user" {
+            MessageStatus::Sent
+        } else {
+            MessageStatus::Complete
+        }
+    }
+}
+
+/// Role of a user within a chat, stored as lowercase text in `chat_members.role`.
+/// Ordered from most to least privileged: owner, admin, member, viewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberRole {
+    Owner,
+    Admin,
+    Member,
+    /// Can read the chat and subscribe to it, but not post.
+    Viewer,
+}
+
+impl MemberRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MemberRole::Owner => "owner",
+            MemberRole::Admin => "admin",
+            MemberRole::Member => "member",
+            MemberRole::Viewer => "viewer",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "owner" => Some(MemberRole::Owner),
+            "admin" => Some(MemberRole::Admin),
+            "member" => Some(MemberRole::Member),
+            "viewer" => Some(MemberRole::Viewer),
+            _ => None,
+        }
+    }
+
+    /// Whether members with this role may post messages.
+    pub fn can_post(self) -> bool {
+        self != MemberRole::Viewer
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, FromRow, ToSchema, Clone)]
 pub struct ChatMember {
     pub id: i64,
@@ -262,17 +448,61 @@ pub struct CreateMessageRequest {
     pub reply_to_id: Option<String>, // public_id
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchMessage {
+    #[serde(flatten)]
+    pub message: CreateMessageRequest,
+    /// RFC3339 creation time to keep from the source conversation; defaults to now.
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchCreateMessagesRequest {
+    /// Messages in conversation order; timestamps must not decrease.
+    pub messages: Vec<BatchMessage>,
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateMessageRequest {
     pub content: String,
+    /// The version the edit is based on; a stale version is rejected with 409.
+    /// The `If-Match` header may be used instead.
+    pub version: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateRetentionRequest {
+    /// Days to keep messages for, or `null` to keep them forever.
+    pub message_retention_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateChatModelRequest {
+    /// Model to reply with when a message names none, or `null` to use the server default.
+    pub default_model: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SaveDraftRequest {
+    pub content: String,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateAttachmentRequest {
     pub file_name: String,
+    /// MIME type. For `source_url` the fetched response's type takes precedence.
     pub file_type: String,
-    pub file_url: String,
-    pub file_size_bytes: i64,
+    /// Externally hosted file, stored as given. Requires `file_size_bytes`.
+    #[serde(default)]
+    pub file_url: Option<String>,
+    #[serde(default)]
+    pub file_size_bytes: Option<i64>,
+    /// Base64-encoded file content to store with the attachment.
+    #[serde(default)]
+    pub content: Option<String>,
+    /// Public http(s) URL the server fetches and stores the content of.
+    #[serde(default)]
+    pub source_url: Option<String>,
 }
 
 // Notification DTOs
@@ -289,6 +519,14 @@ pub struct MarkNotificationReadRequest {
     pub read: bool,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MarkNotificationsReadRequest {
+    /// Only notifications about this chat (public id).
+    pub chat_id: Option<String>,
+    /// Only notifications of this type, such as `chat_invite` or `mention`.
+    pub r#type: Option<String>,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct NotificationsResponse {
     pub notifications: Vec<Notification>,
@@ -318,10 +556,32 @@ pub struct PermissionResponse {
     pub permission: Permission,
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLogResponse {
+    pub entries: Vec<AuditLogEntry>,
+}
+
 // Message edit history
 #[derive(Debug, Serialize, ToSchema)]
 pub struct MessageEditsResponse {
     pub edits: Vec<MessageEdit>,
+    /// Cursor for the next, older page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_before: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DraftResponse {
+    pub draft: MessageDraft,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChatReadState {
+    pub chat_id: String,
+    /// Public id of the newest message the caller has read, if any.
+    pub last_read_message_id: Option<String>,
+    pub read_at: Option<String>,
+    pub unread_count: i64,
 }
 
 // Attachment response structs
@@ -341,22 +601,61 @@ pub struct MessageResponse {
     pub message: Message,
 }
 
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct RegenerateMessageRequest {
+    /// Model to answer with; defaults to the model that wrote the reply being replaced.
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RegenerateMessageResponse {
+    /// Public id the new reply will be saved under.
+    pub message_id: String,
+    /// The reply being regenerated, marked superseded once the new one completes.
+    pub supersedes: String,
+    pub model: String,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct MessagesResponse {
     pub messages: Vec<Message>,
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CountResponse {
+    pub count: i64,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct ListModelsQuery {
+    /// Leave out models that `allowed_models` and `denied_models` rule out.
+    #[serde(default)]
+    pub allowed_only: bool,
+}
+
 #[utoipa::path(
     get,
     path = "/api/models",
     tag = "Models",
+    params(ListModelsQuery),
     responses(
         (status = 200, description = "List available language models", body = ModelsResponse),
+        (status = 429, description = "Model provider rate limit exceeded", body = crate::error::ErrorResponse),
         (status = 503, description = "Model provider unavailable", body = crate::error::ErrorResponse),
         (status = 500, description = "Failed to list models", body = crate::error::ErrorResponse)
     )
 )]
-pub async fn list_models(State(state): State<AppState>) -> Result<Json<ModelsResponse>, ApiError> {
-    let models = state.orchestrator().list_openrouter_models().await?;
-    Ok(Json(ModelsResponse { models }))
+pub async fn list_models(
+    State(state): State<AppState>,
+    Query(query): Query<ListModelsQuery>,
+) -> Result<(HeaderMap, Json<ModelsResponse>), ApiError> {
+    let (mut models, rate_limit) = state
+        .orchestrator()
+        .list_openrouter_models_with_rate_limit()
+        .await?;
+    if query.allowed_only {
+        models.retain(|model| state.orchestrator().check_model_allowed(&model.id).is_ok());
+    }
+    Ok((rate_limit_headers(&rate_limit), Json(ModelsResponse { models })))
 }
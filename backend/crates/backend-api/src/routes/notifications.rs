@@ -4,11 +4,13 @@ use axum::{
     Json,
 };
 use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite};
 use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     routes::models::{
-        MarkNotificationReadRequest, Notification, NotificationResponse, NotificationsResponse,
+        MarkNotificationReadRequest, MarkNotificationsReadRequest, Notification,
+        NotificationResponse, NotificationsResponse,
     },
     util::require_bearer,
     ApiError, AppState,
@@ -237,6 +239,54 @@ pub async fn mark_all_read(
     })))
 }
 
+// Mark the notifications matching a filter as read
+#[utoipa::path(
+    post,
+    path = "/api/notifications/mark-read",
+    tag = "Notifications",
+    security(("bearerAuth" = [])),
+    request_body = MarkNotificationsReadRequest,
+    responses(
+        (status = 200, description = "Matching notifications marked as read", body = BulkUpdateResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 404, description = "Chat not found", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to update notifications", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn mark_read_by_filter(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<MarkNotificationsReadRequest>,
+) -> Result<Json<BulkUpdateResponse>, ApiError> {
+    let token = require_bearer(&headers)?;
+    let (user, _) = state.authenticate(&token).await?;
+
+    let chat_id = match &req.chat_id {
+        Some(public_id) => {
+            let chat_id: Option<i64> =
+                sqlx::query_scalar("SELECT id FROM chats WHERE public_id = ?")
+                    .bind(public_id)
+                    .fetch_optional(state.db_pool())
+                    .await
+                    .map_err(|e| {
+                        tracing::error!("Failed to resolve chat for notifications: {}", e);
+                        ApiError::internal_server_error("Failed to update notifications")
+                    })?;
+            Some(chat_id.ok_or_else(|| ApiError::not_found("Chat not found"))?)
+        }
+        None => None,
+    };
+
+    let filter = NotificationFilter {
+        chat_id,
+        notification_type: req.r#type,
+    };
+    let updated_count =
+        NotificationService::mark_read_by_filter(state.db_pool(), user.id, &filter).await?;
+
+    Ok(Json(BulkUpdateResponse { updated_count }))
+}
+
 // Delete a notification
 #[utoipa::path(
     delete,
@@ -278,6 +328,13 @@ pub async fn delete_notification(
     Ok(())
 }
 
+// Narrows a bulk update to one chat and/or one notification type
+#[derive(Debug, Clone, Default)]
+pub struct NotificationFilter {
+    pub chat_id: Option<i64>,
+    pub notification_type: Option<String>,
+}
+
 // Notification service for creating notifications
 pub struct NotificationService;
 
@@ -286,6 +343,7 @@ impl NotificationService {
     pub async fn create_notification(
         pool: &sqlx::Pool<sqlx::Sqlite>,
         user_id: i64,
+        chat_id: Option<i64>,
         notification_type: &str,
         title: &str,
         body: &str,
@@ -294,11 +352,12 @@ impl NotificationService {
 
         let result = sqlx::query(
             r#"
-            INSERT INTO notifications (user_id, type, title, body, read, created_at)
-            VALUES (?, ?, ?, ?, FALSE, ?)
+            INSERT INTO notifications (user_id, chat_id, type, title, body, read, created_at)
+            VALUES (?, ?, ?, ?, ?, FALSE, ?)
             "#,
         )
         .bind(user_id)
+        .bind(chat_id)
         .bind(notification_type)
         .bind(title)
         .bind(body)
@@ -313,6 +372,31 @@ impl NotificationService {
         Ok(result.last_insert_rowid())
     }
 
+    // Mark a user's unread notifications matching the filter as read
+    pub async fn mark_read_by_filter(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        user_id: i64,
+        filter: &NotificationFilter,
+    ) -> Result<u64, ApiError> {
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "UPDATE notifications SET read = TRUE WHERE read = FALSE AND user_id = ",
+        );
+        query.push_bind(user_id);
+        if let Some(chat_id) = filter.chat_id {
+            query.push(" AND chat_id = ").push_bind(chat_id);
+        }
+        if let Some(notification_type) = &filter.notification_type {
+            query.push(" AND type = ").push_bind(notification_type);
+        }
+
+        let result = query.build().execute(pool).await.map_err(|e| {
+            tracing::error!("Failed to mark filtered notifications as read: {}", e);
+            ApiError::internal_server_error("Failed to update notifications")
+        })?;
+
+        Ok(result.rows_affected())
+    }
+
     // Notify users in a chat about a new message
     pub async fn notify_new_message(
         pool: &sqlx::Pool<sqlx::Sqlite>,
@@ -348,11 +432,12 @@ impl NotificationService {
 
             sqlx::query(
                 r#"
-                INSERT INTO notifications (user_id, type, title, body, read, created_at)
-                VALUES (?, ?, ?, ?, FALSE, ?)
+                INSERT INTO notifications (user_id, chat_id, type, title, body, read, created_at)
+                VALUES (?, ?, ?, ?, ?, FALSE, ?)
                 "#,
             )
             .bind(user_id)
+            .bind(chat_id)
             .bind("new_message")
             .bind(&title)
             .bind(&body)
@@ -371,6 +456,7 @@ impl NotificationService {
     // Notify user about chat invite
     pub async fn notify_chat_invite(
         pool: &sqlx::Pool<sqlx::Sqlite>,
+        chat_id: i64,
         invited_user_id: i64,
         chat_title: &str,
         inviter_name: &str,
@@ -378,7 +464,15 @@ impl NotificationService {
         let title = format!("Chat invite: {}", chat_title);
         let body = format!("{} invited you to join a chat", inviter_name);
 
-        Self::create_notification(pool, invited_user_id, "chat_invite", &title, &body).await?;
+        Self::create_notification(
+            pool,
+            invited_user_id,
+            Some(chat_id),
+            "chat_invite",
+            &title,
+            &body,
+        )
+        .await?;
 
         Ok(())
     }
@@ -386,7 +480,7 @@ impl NotificationService {
     // Notify user about accepted invite
     pub async fn notify_invite_accepted(
         pool: &sqlx::Pool<sqlx::Sqlite>,
-        _chat_id: i64,
+        chat_id: i64,
         inviter_user_id: i64,
         accepted_user_name: &str,
         chat_title: &str,
@@ -394,7 +488,15 @@ impl NotificationService {
         let title = format!("Invite accepted for {}", chat_title);
         let body = format!("{} accepted your chat invite", accepted_user_name);
 
-        Self::create_notification(pool, inviter_user_id, "invite_accepted", &title, &body).await?;
+        Self::create_notification(
+            pool,
+            inviter_user_id,
+            Some(chat_id),
+            "invite_accepted",
+            &title,
+            &body,
+        )
+        .await?;
 
         Ok(())
     }
@@ -402,6 +504,7 @@ impl NotificationService {
     // Notify user about message mention
     pub async fn notify_mention(
         pool: &sqlx::Pool<sqlx::Sqlite>,
+        chat_id: i64,
         mentioned_user_id: i64,
         sender_name: &str,
         chat_title: &str,
@@ -409,7 +512,8 @@ impl NotificationService {
         let title = format!("You were mentioned in {}", chat_title);
         let body = format!("{} mentioned you in a message", sender_name);
 
-        Self::create_notification(pool, mentioned_user_id, "mention", &title, &body).await?;
+        Self::create_notification(pool, mentioned_user_id, Some(chat_id), "mention", &title, &body)
+            .await?;
 
         Ok(())
     }
@@ -5,6 +5,8 @@ use axum::{
 };
 
 use crate::{
+    audit::{self, record_audit, ClientIp},
+    ids::ResourceKind,
     routes::models::{
         CreatePermissionRequest, Permission, PermissionResponse, PermissionsResponse,
     },
@@ -65,6 +67,55 @@ impl PermissionsService {
         }
     }
 
+    // Resolve a chat or folder public id to its row id, rejecting ids that carry
+    // another resource's prefix before querying
+    pub async fn resolve_resource_id(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        resource_type: &str,
+        public_id: &str,
+    ) -> Result<i64, ApiError> {
+        let (kind, query) = match resource_type {
+            "chat" => (ResourceKind::Chat, "SELECT id FROM chats WHERE public_id = ?"),
+            "folder" => (ResourceKind::Folder, "SELECT id FROM folders WHERE public_id = ?"),
+            _ => return Err(ApiError::bad_request("Invalid resource type")),
+        };
+        kind.check_public_id(public_id)?;
+
+        let resource_id: Option<i64> = sqlx::query_scalar(query)
+            .bind(public_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to resolve {} ID: {}", resource_type, e);
+                ApiError::internal_server_error(format!("Failed to resolve {} ID", resource_type))
+            })?;
+
+        resource_id.ok_or_else(|| ApiError::not_found("Resource not found"))
+    }
+
+    // Admin on any workspace unlocks the instance-wide admin endpoints
+    pub async fn is_workspace_admin(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        user_id: i64,
+    ) -> Result<bool, ApiError> {
+        let admin: Option<i64> = sqlx::query_scalar(
+            r#"
+            SELECT 1 FROM permissions
+            WHERE user_id = ? AND resource_type = 'workspace' AND permission_level = 'admin'
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to check workspace admin: {}", e);
+            ApiError::internal_server_error("Failed to check permission")
+        })?;
+
+        Ok(admin.is_some())
+    }
+
     // Grant permission to user for a resource
     pub async fn grant_permission(
         pool: &sqlx::Pool<sqlx::Sqlite>,
@@ -370,27 +421,12 @@ pub async fn get_resource_permissions(
     let (user, _) = state.authenticate(&token).await?;
 
     // Resolve resource ID from public ID
-    let resource_id = match resource_type.as_str() {
-        "chat" => sqlx::query_scalar::<_, i64>("SELECT id FROM chats WHERE public_id = ?")
-            .bind(&resource_public_id)
-            .fetch_optional(state.db_pool())
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to resolve chat ID: {}", e);
-                ApiError::internal_server_error("Failed to resolve chat ID")
-            })?,
-        "folder" => sqlx::query_scalar::<_, i64>("SELECT id FROM folders WHERE public_id = ?")
-            .bind(&resource_public_id)
-            .fetch_optional(state.db_pool())
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to resolve folder ID: {}", e);
-                ApiError::internal_server_error("Failed to resolve folder ID")
-            })?,
-        _ => return Err(ApiError::bad_request("Invalid resource type")),
-    };
-
-    let resource_id = resource_id.ok_or_else(|| ApiError::not_found("Resource not found"))?;
+    let resource_id = PermissionsService::resolve_resource_id(
+        state.db_pool(),
+        &resource_type,
+        &resource_public_id,
+    )
+    .await?;
 
     // Check if user has admin permission for this resource
     if !PermissionsService::check_permission(
@@ -434,6 +470,7 @@ pub async fn get_resource_permissions(
 )]
 pub async fn grant_permission(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     Path((resource_type, resource_public_id)): Path<(String, String)>,
     headers: HeaderMap,
     Json(req): Json<CreatePermissionRequest>,
@@ -442,27 +479,12 @@ pub async fn grant_permission(
     let (user, _) = state.authenticate(&token).await?;
 
     // Resolve resource ID
-    let resource_id = match resource_type.as_str() {
-        "chat" => sqlx::query_scalar::<_, i64>("SELECT id FROM chats WHERE public_id = ?")
-            .bind(&resource_public_id)
-            .fetch_optional(state.db_pool())
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to resolve chat ID: {}", e);
-                ApiError::internal_server_error("Failed to resolve chat ID")
-            })?,
-        "folder" => sqlx::query_scalar::<_, i64>("SELECT id FROM folders WHERE public_id = ?")
-            .bind(&resource_public_id)
-            .fetch_optional(state.db_pool())
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to resolve folder ID: {}", e);
-                ApiError::internal_server_error("Failed to resolve folder ID")
-            })?,
-        _ => return Err(ApiError::bad_request("Invalid resource type")),
-    };
-
-    let resource_id = resource_id.ok_or_else(|| ApiError::not_found("Resource not found"))?;
+    let resource_id = PermissionsService::resolve_resource_id(
+        state.db_pool(),
+        &resource_type,
+        &resource_public_id,
+    )
+    .await?;
 
     // Check if user has admin permission for this resource
     if !PermissionsService::check_permission(
@@ -502,6 +524,19 @@ pub async fn grant_permission(
     )
     .await?;
 
+    let target = format!(
+        "{}:{}:{}:{}",
+        req.resource_type, resource_public_id, req.user_id, req.permission_level
+    );
+    record_audit(
+        &state,
+        Some(user.id),
+        audit::PERMISSION_GRANTED,
+        Some(&target),
+        ip.as_deref(),
+    )
+    .await;
+
     // Fetch the created/updated permission
     let permission = sqlx::query_as::<_, Permission>(
         r#"
@@ -546,6 +581,7 @@ pub async fn grant_permission(
 )]
 pub async fn revoke_permission(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     Path((resource_type, resource_public_id, user_public_id)): Path<(String, String, String)>,
     headers: HeaderMap,
 ) -> Result<(), ApiError> {
@@ -553,27 +589,12 @@ pub async fn revoke_permission(
     let (user, _) = state.authenticate(&token).await?;
 
     // Resolve resource ID
-    let resource_id = match resource_type.as_str() {
-        "chat" => sqlx::query_scalar::<_, i64>("SELECT id FROM chats WHERE public_id = ?")
-            .bind(&resource_public_id)
-            .fetch_optional(state.db_pool())
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to resolve chat ID: {}", e);
-                ApiError::internal_server_error("Failed to resolve chat ID")
-            })?,
-        "folder" => sqlx::query_scalar::<_, i64>("SELECT id FROM folders WHERE public_id = ?")
-            .bind(&resource_public_id)
-            .fetch_optional(state.db_pool())
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to resolve folder ID: {}", e);
-                ApiError::internal_server_error("Failed to resolve folder ID")
-            })?,
-        _ => return Err(ApiError::bad_request("Invalid resource type")),
-    };
-
-    let resource_id = resource_id.ok_or_else(|| ApiError::not_found("Resource not found"))?;
+    let resource_id = PermissionsService::resolve_resource_id(
+        state.db_pool(),
+        &resource_type,
+        &resource_public_id,
+    )
+    .await?;
 
     // Check if user has admin permission for this resource
     if !PermissionsService::check_permission(
@@ -611,5 +632,15 @@ pub async fn revoke_permission(
     )
     .await?;
 
+    let target = format!("{}:{}:{}", resource_type, resource_public_id, user_public_id);
+    record_audit(
+        &state,
+        Some(user.id),
+        audit::PERMISSION_REVOKED,
+        Some(&target),
+        ip.as_deref(),
+    )
+    .await;
+
     Ok(())
 }
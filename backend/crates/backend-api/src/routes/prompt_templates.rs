@@ -0,0 +1,711 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqliteRow, Row};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    ids::ResourceKind,
+    util::{with_transaction, AuthUser},
+    ApiError, AppState, FieldError,
+};
+
+const MAX_TEMPLATE_NAME_CHARS: usize = 100;
+/// Bundle format written by the export endpoint and accepted by import.
+pub const TEMPLATE_BUNDLE_VERSION: u32 = 1;
+/// Upper bound on the number of templates accepted by one import.
+pub const MAX_IMPORT_TEMPLATES: usize = 500;
+pub const IMPORT_CONFLICT_MODES: &[&str] = &["skip", "rename", "overwrite"];
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PromptTemplate {
+    /// Public identifier.
+    pub id: String,
+    pub name: String,
+    /// Prompt text with `{{name}}` placeholders.
+    pub body: String,
+    /// Variables every use of the template must supply.
+    pub variables: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePromptTemplateRequest {
+    pub name: String,
+    pub body: String,
+    /// Defaults to the placeholders found in `body`.
+    pub variables: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePromptTemplateRequest {
+    pub name: Option<String>,
+    pub body: Option<String>,
+    /// Defaults to the placeholders found in the new `body` when that changes.
+    pub variables: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PromptTemplatesResponse {
+    pub templates: Vec<PromptTemplate>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PromptTemplateResponse {
+    pub template: PromptTemplate,
+}
+
+/// A portable set of templates, without ids or timestamps.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TemplateBundle {
+    pub version: u32,
+    pub templates: Vec<BundledTemplate>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BundledTemplate {
+    pub name: String,
+    pub body: String,
+    pub variables: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct ImportTemplatesQuery {
+    /// What to do with a template whose name is already taken: `skip` it (the
+    /// default), `rename` it to "Name (2)", or `overwrite` the existing template.
+    pub on_conflict: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportTemplatesResponse {
+    pub created: usize,
+    pub overwritten: usize,
+    /// Names of the bundled templates left out because the name was taken.
+    pub skipped: Vec<String>,
+    /// The templates written by the import.
+    pub templates: Vec<PromptTemplate>,
+}
+
+/// Names of the `{{name}}` placeholders in `body`, in order of first use.
+///
+/// ```
+/// use switchboard_backend_api::routes::prompt_templates::placeholders;
+///
+/// assert_eq!(placeholders("{{ lang }} to {{target}}, keep {{lang}}"), ["lang", "target"]);
+/// assert!(placeholders("no {{ placeholders").is_empty());
+/// ```
+pub fn placeholders(body: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + end].trim();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+        rest = &rest[start + 2 + end + 2..];
+    }
+    names
+}
+
+/// `body` with every `{{name}}` replaced by its value. Every one of `variables` must
+/// have a value; substituted values are not scanned for placeholders again.
+pub fn render_template(
+    body: &str,
+    variables: &[String],
+    values: &HashMap<String, String>,
+) -> Result<String, ApiError> {
+    let missing: Vec<FieldError> = variables
+        .iter()
+        .filter(|name| !values.contains_key(*name))
+        .map(|name| FieldError::new(format!("variables.{name}"), "is required"))
+        .collect();
+    ApiError::check_fields(missing)?;
+
+    let mut rendered = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + 2 + end + 2];
+        let name = placeholder[2..placeholder.len() - 2].trim();
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(values.get(name).map_or(placeholder, String::as_str));
+        rest = &rest[start + placeholder.len()..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// The caller's template `template_id` rendered with `values`, for the completion
+/// paths that accept a template in place of a prompt.
+pub async fn render_for_user(
+    state: &AppState,
+    user_id: i64,
+    template_id: &str,
+    values: &HashMap<String, String>,
+) -> Result<String, ApiError> {
+    let template = fetch_template(state, user_id, template_id).await?;
+    render_template(&template.body, &template.variables, values)
+}
+
+// Variables are identifiers, so they never contain the comma they are stored joined by
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// The variables to store, or field errors for the name, body and variables
+fn validate_template(
+    name: &str,
+    body: &str,
+    variables: Option<&[String]>,
+) -> Result<Vec<String>, ApiError> {
+    let mut errors = Vec::new();
+    let variables = check_template("", name, body, variables, &mut errors);
+    ApiError::check_fields(errors)?;
+    Ok(variables)
+}
+
+// The variables to store; problems are added to `errors` with paths under `prefix`
+fn check_template(
+    prefix: &str,
+    name: &str,
+    body: &str,
+    variables: Option<&[String]>,
+    errors: &mut Vec<FieldError>,
+) -> Vec<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        errors.push(FieldError::new(format!("{prefix}name"), "must not be empty"));
+    } else if name.chars().count() > MAX_TEMPLATE_NAME_CHARS {
+        errors.push(FieldError::new(
+            format!("{prefix}name"),
+            format!("must be at most {MAX_TEMPLATE_NAME_CHARS} characters"),
+        ));
+    }
+    if body.trim().is_empty() {
+        errors.push(FieldError::new(format!("{prefix}body"), "must not be empty"));
+    }
+
+    let used = placeholders(body);
+    let variables: Vec<String> = match variables {
+        Some(variables) => variables.to_vec(),
+        None => used.iter().map(|name| name.to_string()).collect(),
+    };
+    let mut seen = HashSet::new();
+    for (index, variable) in variables.iter().enumerate() {
+        if !is_variable_name(variable) {
+            errors.push(FieldError::new(
+                format!("{prefix}variables[{index}]"),
+                "must be letters, digits and underscores, not starting with a digit",
+            ));
+        } else if !seen.insert(variable.as_str()) {
+            errors.push(FieldError::new(format!("{prefix}variables[{index}]"), "is listed twice"));
+        }
+    }
+    for name in used {
+        if !seen.contains(name) {
+            errors.push(FieldError::new(
+                format!("{prefix}body"),
+                format!("uses {{{{{name}}}}}, which is not a declared variable"),
+            ));
+        }
+    }
+    variables
+}
+
+// Names are unique per user (see the prompt_templates UNIQUE constraint)
+fn template_write_error(error: sqlx::Error, name: &str, action: &str) -> ApiError {
+    if let sqlx::Error::Database(db_error) = &error {
+        if db_error.is_unique_violation() {
+            return ApiError::conflict(format!("A template named '{}' already exists", name));
+        }
+    }
+    tracing::error!("Failed to {} prompt template: {}", action, error);
+    ApiError::internal_server_error(format!("Failed to {} prompt template", action))
+}
+
+fn template_from_row(row: &SqliteRow) -> PromptTemplate {
+    let variables: String = row.get("variables");
+    PromptTemplate {
+        id: row.get("public_id"),
+        name: row.get("name"),
+        body: row.get("body"),
+        variables: variables
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect(),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+async fn fetch_template(
+    state: &AppState,
+    user_id: i64,
+    template_id: &str,
+) -> Result<PromptTemplate, ApiError> {
+    ResourceKind::PromptTemplate.check_public_id(template_id)?;
+    let row = sqlx::query(
+        r#"
+        SELECT public_id, name, body, variables, created_at, updated_at
+        FROM prompt_templates
+        WHERE public_id = ? AND user_id = ?
+        "#,
+    )
+    .bind(template_id)
+    .bind(user_id)
+    .fetch_optional(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch prompt template: {}", e);
+        ApiError::internal_server_error("Failed to fetch prompt template")
+    })?
+    .ok_or_else(|| ApiError::not_found("Prompt template not found"))?;
+
+    Ok(template_from_row(&row))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/prompt-templates",
+    tag = "Prompt Templates",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "The caller's prompt templates", body = PromptTemplatesResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch prompt templates", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn list_templates(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<PromptTemplatesResponse>, ApiError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT public_id, name, body, variables, created_at, updated_at
+        FROM prompt_templates
+        WHERE user_id = ?
+        ORDER BY name ASC
+        "#,
+    )
+    .bind(user.id)
+    .fetch_all(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch prompt templates: {}", e);
+        ApiError::internal_server_error("Failed to fetch prompt templates")
+    })?;
+
+    let templates = rows.iter().map(template_from_row).collect();
+    Ok(Json(PromptTemplatesResponse { templates }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/prompt-templates",
+    tag = "Prompt Templates",
+    security(("bearerAuth" = [])),
+    request_body = CreatePromptTemplateRequest,
+    responses(
+        (status = 200, description = "Prompt template created", body = PromptTemplateResponse),
+        (status = 400, description = "Invalid template", body = crate::error::ValidationErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 409, description = "A template with this name already exists", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to create prompt template", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn create_template(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Json(req): Json<CreatePromptTemplateRequest>,
+) -> Result<Json<PromptTemplateResponse>, ApiError> {
+    let variables = validate_template(&req.name, &req.body, req.variables.as_deref())?;
+    let name = req.name.trim();
+    let public_id = ResourceKind::PromptTemplate.new_public_id(&state.config().ids);
+    let now = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"
+        INSERT INTO prompt_templates (public_id, user_id, name, body, variables, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&public_id)
+    .bind(user.id)
+    .bind(name)
+    .bind(&req.body)
+    .bind(variables.join(","))
+    .bind(&now)
+    .bind(&now)
+    .execute(state.db_pool())
+    .await
+    .map_err(|e| template_write_error(e, name, "create"))?;
+
+    Ok(Json(PromptTemplateResponse {
+        template: PromptTemplate {
+            id: public_id,
+            name: name.to_string(),
+            body: req.body,
+            variables,
+            created_at: now.clone(),
+            updated_at: now,
+        },
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/prompt-templates/{template_id}",
+    tag = "Prompt Templates",
+    security(("bearerAuth" = [])),
+    params(
+        ("template_id" = String, Path, description = "Prompt template public identifier")
+    ),
+    responses(
+        (status = 200, description = "Prompt template fetched", body = PromptTemplateResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 404, description = "Prompt template not found", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch prompt template", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_template(
+    State(state): State<AppState>,
+    Path(template_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<PromptTemplateResponse>, ApiError> {
+    let template = fetch_template(&state, user.id, &template_id).await?;
+    Ok(Json(PromptTemplateResponse { template }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/prompt-templates/{template_id}",
+    tag = "Prompt Templates",
+    security(("bearerAuth" = [])),
+    params(
+        ("template_id" = String, Path, description = "Prompt template public identifier")
+    ),
+    request_body = UpdatePromptTemplateRequest,
+    responses(
+        (status = 200, description = "Prompt template updated", body = PromptTemplateResponse),
+        (status = 400, description = "Invalid template", body = crate::error::ValidationErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 404, description = "Prompt template not found", body = crate::error::ErrorResponse),
+        (status = 409, description = "A template with this name already exists", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to update prompt template", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn update_template(
+    State(state): State<AppState>,
+    Path(template_id): Path<String>,
+    AuthUser(user): AuthUser,
+    Json(req): Json<UpdatePromptTemplateRequest>,
+) -> Result<Json<PromptTemplateResponse>, ApiError> {
+    let current = fetch_template(&state, user.id, &template_id).await?;
+
+    let name = req.name.as_deref().unwrap_or(&current.name);
+    let body = req.body.as_deref().unwrap_or(&current.body);
+    // An unchanged body keeps its declared variables unless new ones are given
+    let variables = match (&req.variables, &req.body) {
+        (Some(variables), _) => Some(variables.as_slice()),
+        (None, Some(_)) => None,
+        (None, None) => Some(current.variables.as_slice()),
+    };
+    let variables = validate_template(name, body, variables)?;
+    let name = name.trim();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"
+        UPDATE prompt_templates
+        SET name = ?, body = ?, variables = ?, updated_at = ?
+        WHERE public_id = ? AND user_id = ?
+        "#,
+    )
+    .bind(name)
+    .bind(body)
+    .bind(variables.join(","))
+    .bind(&now)
+    .bind(&template_id)
+    .bind(user.id)
+    .execute(state.db_pool())
+    .await
+    .map_err(|e| template_write_error(e, name, "update"))?;
+
+    Ok(Json(PromptTemplateResponse {
+        template: PromptTemplate {
+            id: current.id,
+            name: name.to_string(),
+            body: body.to_string(),
+            variables,
+            created_at: current.created_at,
+            updated_at: now,
+        },
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/prompt-templates/{template_id}",
+    tag = "Prompt Templates",
+    security(("bearerAuth" = [])),
+    params(
+        ("template_id" = String, Path, description = "Prompt template public identifier")
+    ),
+    responses(
+        (status = 200, description = "Prompt template deleted"),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 404, description = "Prompt template not found", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to delete prompt template", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn delete_template(
+    State(state): State<AppState>,
+    Path(template_id): Path<String>,
+    AuthUser(user): AuthUser,
+) -> Result<(), ApiError> {
+    ResourceKind::PromptTemplate.check_public_id(&template_id)?;
+    let result = sqlx::query("DELETE FROM prompt_templates WHERE public_id = ? AND user_id = ?")
+        .bind(&template_id)
+        .bind(user.id)
+        .execute(state.db_pool())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to delete prompt template: {}", e);
+            ApiError::internal_server_error("Failed to delete prompt template")
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Prompt template not found"));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/prompt-templates/export",
+    tag = "Prompt Templates",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "The caller's prompt templates as a bundle", body = TemplateBundle),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to export prompt templates", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn export_templates(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<TemplateBundle>, ApiError> {
+    let rows = sqlx::query(
+        "SELECT name, body, variables FROM prompt_templates WHERE user_id = ? ORDER BY name ASC",
+    )
+    .bind(user.id)
+    .fetch_all(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to export prompt templates: {}", e);
+        ApiError::internal_server_error("Failed to export prompt templates")
+    })?;
+
+    let templates = rows
+        .iter()
+        .map(|row| {
+            let variables: String = row.get("variables");
+            BundledTemplate {
+                name: row.get("name"),
+                body: row.get("body"),
+                variables: variables
+                    .split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            }
+        })
+        .collect();
+    Ok(Json(TemplateBundle { version: TEMPLATE_BUNDLE_VERSION, templates }))
+}
+
+// "Name (2)", "Name (3)", ... whichever is first free
+fn free_template_name(name: &str, taken: &HashMap<String, (String, String)>) -> String {
+    (2..)
+        .map(|n| format!("{name} ({n})"))
+        .find(|candidate| !taken.contains_key(candidate))
+        .expect("an unused name exists")
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/prompt-templates/import",
+    tag = "Prompt Templates",
+    security(("bearerAuth" = [])),
+    params(ImportTemplatesQuery),
+    request_body = TemplateBundle,
+    responses(
+        (status = 200, description = "Prompt templates imported", body = ImportTemplatesResponse),
+        (status = 400, description = "Unsupported bundle version, invalid template or unknown on_conflict mode", body = crate::error::ValidationErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to import prompt templates", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn import_templates(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<ImportTemplatesQuery>,
+    Json(bundle): Json<TemplateBundle>,
+) -> Result<Json<ImportTemplatesResponse>, ApiError> {
+    let mode = query.on_conflict.as_deref().unwrap_or("skip");
+    if !IMPORT_CONFLICT_MODES.contains(&mode) {
+        return Err(ApiError::bad_request(format!(
+            "Invalid on_conflict mode '{}'; expected one of: {}",
+            mode,
+            IMPORT_CONFLICT_MODES.join(", ")
+        )));
+    }
+
+    let mut errors = Vec::new();
+    if bundle.version != TEMPLATE_BUNDLE_VERSION {
+        errors.push(FieldError::new("version", format!("must be {TEMPLATE_BUNDLE_VERSION}")));
+    }
+    if bundle.templates.len() > MAX_IMPORT_TEMPLATES {
+        errors.push(FieldError::new(
+            "templates",
+            format!("must contain at most {MAX_IMPORT_TEMPLATES} templates"),
+        ));
+    }
+    let mut names = HashSet::new();
+    let mut templates = Vec::with_capacity(bundle.templates.len());
+    for (index, template) in bundle.templates.iter().enumerate() {
+        let prefix = format!("templates[{index}].");
+        let variables = check_template(
+            &prefix,
+            &template.name,
+            &template.body,
+            Some(&template.variables),
+            &mut errors,
+        );
+        let name = template.name.trim();
+        if !names.insert(name) {
+            errors.push(FieldError::new(format!("{prefix}name"), "is listed twice"));
+        }
+        templates.push((name, template.body.as_str(), variables));
+    }
+    ApiError::check_fields(errors)?;
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let failure = "Failed to import prompt templates";
+    let response = with_transaction(state.db_pool(), failure, async |tx| {
+        let rows = sqlx::query(
+            "SELECT public_id, name, created_at FROM prompt_templates WHERE user_id = ?",
+        )
+        .bind(user.id)
+        .fetch_all(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch prompt templates: {}", e);
+            ApiError::internal_server_error(failure)
+        })?;
+        // name -> (public id, created_at)
+        let mut taken: HashMap<String, (String, String)> = rows
+            .iter()
+            .map(|row| (row.get("name"), (row.get("public_id"), row.get("created_at"))))
+            .collect();
+
+        let mut response = ImportTemplatesResponse {
+            created: 0,
+            overwritten: 0,
+            skipped: Vec::new(),
+            templates: Vec::new(),
+        };
+        for (name, body, variables) in templates {
+            if let Some((public_id, created_at)) = taken.get(name) {
+                match mode {
+                    "skip" => {
+                        response.skipped.push(name.to_string());
+                        continue;
+                    }
+                    "overwrite" => {
+                        sqlx::query(
+                            r#"
+                            UPDATE prompt_templates
+                            SET body = ?, variables = ?, updated_at = ?
+                            WHERE public_id = ? AND user_id = ?
+                            "#,
+                        )
+                        .bind(body)
+                        .bind(variables.join(","))
+                        .bind(&now)
+                        .bind(public_id)
+                        .bind(user.id)
+                        .execute(&mut **tx)
+                        .await
+                        .map_err(|e| template_write_error(e, name, "overwrite"))?;
+
+                        response.overwritten += 1;
+                        response.templates.push(PromptTemplate {
+                            id: public_id.clone(),
+                            name: name.to_string(),
+                            body: body.to_string(),
+                            variables,
+                            created_at: created_at.clone(),
+                            updated_at: now.clone(),
+                        });
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            let name = if taken.contains_key(name) {
+                free_template_name(name, &taken)
+            } else {
+                name.to_string()
+            };
+            let public_id = ResourceKind::PromptTemplate.new_public_id(&state.config().ids);
+            sqlx::query(
+                r#"
+                INSERT INTO prompt_templates (public_id, user_id, name, body, variables, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&public_id)
+            .bind(user.id)
+            .bind(&name)
+            .bind(body)
+            .bind(variables.join(","))
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| template_write_error(e, &name, "import"))?;
+
+            taken.insert(name.clone(), (public_id.clone(), now.clone()));
+            response.created += 1;
+            response.templates.push(PromptTemplate {
+                id: public_id,
+                name,
+                body: body.to_string(),
+                variables,
+                created_at: now.clone(),
+                updated_at: now.clone(),
+            });
+        }
+        Ok(response)
+    })
+    .await?;
+
+    Ok(Json(response))
+}
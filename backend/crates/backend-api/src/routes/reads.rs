@@ -0,0 +1,201 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use sqlx::FromRow;
+
+use crate::{
+    routes::{drafts::member_chat_db_id, messages::fetch_chat_member_ids, models::ChatReadState},
+    state::ServerEvent,
+    util::AuthUser,
+    ApiError, AppState,
+};
+
+#[derive(Debug, FromRow)]
+pub struct ChatReadMarker {
+    pub last_read_message_id: Option<String>, // public_id
+    pub read_at: String,
+}
+
+// Read markers service
+pub struct ReadMarkersService;
+
+impl ReadMarkersService {
+    // Move the user's marker to the newest message in the chat, which clears its
+    // unread count
+    pub async fn mark_chat_read(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<ChatReadMarker, ApiError> {
+        let now = chrono::Utc::now().to_rfc3339();
+
+        sqlx::query(
+            r#"
+            INSERT INTO chat_read_markers (user_id, chat_id, last_read_message_id, read_at)
+            VALUES (?, ?, (SELECT MAX(id) FROM messages WHERE chat_id = ?), ?)
+            ON CONFLICT (user_id, chat_id)
+            DO UPDATE SET last_read_message_id = excluded.last_read_message_id,
+                          read_at = excluded.read_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(chat_id)
+        .bind(chat_id)
+        .bind(&now)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to mark chat {} read: {}", chat_id, e);
+            ApiError::internal_server_error("Failed to mark chat read")
+        })?;
+
+        Self::get_marker(pool, chat_id, user_id)
+            .await?
+            .ok_or_else(|| ApiError::internal_server_error("Failed to mark chat read"))
+    }
+
+    pub async fn get_marker(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<Option<ChatReadMarker>, ApiError> {
+        sqlx::query_as::<_, ChatReadMarker>(
+            r#"
+            SELECT (
+                       -- The marked message may since have been deleted; report the
+                       -- newest one still at or before it
+                       SELECT m.public_id FROM messages m
+                       WHERE m.chat_id = r.chat_id AND m.id <= r.last_read_message_id
+                       ORDER BY m.id DESC
+                       LIMIT 1
+                   ) AS last_read_message_id,
+                   r.read_at
+            FROM chat_read_markers r
+            WHERE r.user_id = ? AND r.chat_id = ?
+            "#,
+        )
+        .bind(user_id)
+        .bind(chat_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch read marker: {}", e);
+            ApiError::internal_server_error("Failed to fetch read marker")
+        })
+    }
+
+    // Messages from other members newer than the user's marker
+    pub async fn unread_count(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<i64, ApiError> {
+        sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM messages
+            WHERE chat_id = ?
+              AND user_id != ?
+              AND id > COALESCE(
+                  (SELECT last_read_message_id FROM chat_read_markers
+                   WHERE chat_id = ? AND user_id = ?),
+                  0
+              )
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(chat_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to count unread messages: {}", e);
+            ApiError::internal_server_error("Failed to count unread messages")
+        })
+    }
+}
+
+// Get the caller's read position and unread count for a chat
+#[utoipa::path(
+    get,
+    path = "/api/chats/{chat_id}/read",
+    tag = "Messages",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "Read state for the caller", body = ChatReadState),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch read state", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_read_state(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(chat_id): Path<String>,
+) -> Result<Json<ChatReadState>, ApiError> {
+    let chat_db_id = member_chat_db_id(&state, &chat_id, user.id).await?;
+
+    let marker = ReadMarkersService::get_marker(state.db_pool(), chat_db_id, user.id).await?;
+    let unread_count =
+        ReadMarkersService::unread_count(state.db_pool(), chat_db_id, user.id).await?;
+
+    let (last_read_message_id, read_at) = match marker {
+        Some(marker) => (marker.last_read_message_id, Some(marker.read_at)),
+        None => (None, None),
+    };
+
+    Ok(Json(ChatReadState {
+        chat_id,
+        last_read_message_id,
+        read_at,
+        unread_count,
+    }))
+}
+
+// Mark every message in a chat as read for the caller
+#[utoipa::path(
+    post,
+    path = "/api/chats/{chat_id}/read",
+    tag = "Messages",
+    security(("bearerAuth" = [])),
+    params(
+        ("chat_id" = String, Path, description = "Chat public identifier")
+    ),
+    responses(
+        (status = 200, description = "Chat marked read", body = ChatReadState),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 403, description = "Forbidden", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to mark chat read", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn mark_chat_read(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Path(chat_id): Path<String>,
+) -> Result<Json<ChatReadState>, ApiError> {
+    let chat_db_id = member_chat_db_id(&state, &chat_id, user.id).await?;
+
+    let marker = ReadMarkersService::mark_chat_read(state.db_pool(), chat_db_id, user.id).await?;
+    let unread_count =
+        ReadMarkersService::unread_count(state.db_pool(), chat_db_id, user.id).await?;
+
+    let member_ids = fetch_chat_member_ids(&state, chat_db_id).await?;
+    let event = ServerEvent::ReadReceipt {
+        chat_id: chat_id.clone(),
+        user_id: user.id,
+        last_read_message_id: marker.last_read_message_id.clone(),
+        read_at: marker.read_at.clone(),
+    };
+    state.broadcast_to_chat_members(&chat_id, member_ids, &event).await;
+
+    Ok(Json(ChatReadState {
+        chat_id,
+        last_read_message_id: marker.last_read_message_id,
+        read_at: Some(marker.read_at),
+        unread_count,
+    }))
+}
@@ -0,0 +1,278 @@
+use std::collections::HashSet;
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    audit::{self, record_audit, ClientIp},
+    routes::models::{Chat, Message, User},
+    util::AuthUser,
+    ApiError, AppState, FieldError,
+};
+
+/// Fields a user response can be narrowed to with `fields`.
+pub const USER_FIELDS: &[&str] = &["id", "email", "display_name", "created_at"];
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct UserFieldsQuery {
+    /// Comma-separated fields to return, e.g. `display_name`. `id` is always
+    /// included; omit to get every field.
+    pub fields: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserProfileResponse {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+impl UserProfileResponse {
+    /// `user` with only the `selected` fields filled in, or every field for `None`.
+    pub fn select(user: User, selected: Option<&HashSet<&str>>) -> Self {
+        let wants = |field: &str| match selected {
+            Some(selected) => selected.contains(field),
+            None => true,
+        };
+        Self {
+            id: user.public_id,
+            email: user.email.filter(|_| wants("email")),
+            display_name: user.display_name.filter(|_| wants("display_name")),
+            created_at: Some(user.created_at).filter(|_| wants("created_at")),
+        }
+    }
+}
+
+/// One line of a data export: a chat the user belongs to, with its history.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChatExport {
+    pub chat: Chat,
+    pub messages: Vec<Message>,
+    /// Files attached to the chat's messages. File contents are not included.
+    pub attachments: Vec<AttachmentManifestEntry>,
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct AttachmentManifestEntry {
+    /// Public id of the message the file is attached to.
+    pub message_id: String,
+    pub file_name: String,
+    pub file_type: String,
+    pub file_size_bytes: i64,
+    pub created_at: String,
+}
+
+/// Parse a `fields` selection against `known`. Unknown names are ignored unless
+/// `http.reject_unknown_fields` is set, in which case they are a 400.
+pub fn parse_field_selection<'a>(
+    state: &AppState,
+    fields: &'a str,
+    known: &[&str],
+) -> Result<HashSet<&'a str>, ApiError> {
+    let mut selected = HashSet::new();
+    let mut errors = Vec::new();
+    for field in fields.split(',').map(str::trim) {
+        if field.is_empty() {
+            continue;
+        }
+        if known.contains(&field) {
+            selected.insert(field);
+        } else if state.config().http.reject_unknown_fields {
+            errors.push(FieldError::new("fields", format!("unknown field `{field}`")));
+        }
+    }
+    ApiError::check_fields(errors)?;
+    Ok(selected)
+}
+
+// Get the caller's profile, optionally narrowed to some fields
+#[utoipa::path(
+    get,
+    path = "/api/users/me",
+    tag = "Users",
+    security(("bearerAuth" = [])),
+    params(UserFieldsQuery),
+    responses(
+        (status = 200, description = "The caller's profile", body = UserProfileResponse),
+        (status = 400, description = "Unknown field selected", body = crate::error::ValidationErrorResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to fetch profile", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn get_current_user(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<UserFieldsQuery>,
+) -> Result<Json<UserProfileResponse>, ApiError> {
+    let selected = query
+        .fields
+        .as_deref()
+        .map(|fields| parse_field_selection(&state, fields, USER_FIELDS))
+        .transpose()?;
+
+    let profile = sqlx::query_as::<_, User>(
+        "SELECT id, public_id, email, display_name, created_at, updated_at FROM users WHERE id = ?",
+    )
+    .bind(user.id)
+    .fetch_one(state.db_read_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch user profile: {}", e);
+        ApiError::internal_server_error("Failed to fetch profile")
+    })?;
+
+    Ok(Json(UserProfileResponse::select(profile, selected.as_ref())))
+}
+
+// Give the caller a fresh public id, for when the old one has leaked
+#[utoipa::path(
+    post,
+    path = "/api/users/me/public-id",
+    tag = "Users",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "The caller's profile under its new public id", body = UserProfileResponse),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to rotate public id", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn rotate_public_id(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    AuthUser(user): AuthUser,
+) -> Result<Json<UserProfileResponse>, ApiError> {
+    state.authenticator().rotate_public_id(user.id).await?;
+    record_audit(
+        &state,
+        Some(user.id),
+        audit::PUBLIC_ID_ROTATED,
+        Some(&user.public_id),
+        ip.as_deref(),
+    )
+    .await;
+
+    // Read from the primary: a replica may still hold the old id
+    let profile = sqlx::query_as::<_, User>(
+        "SELECT id, public_id, email, display_name, created_at, updated_at FROM users WHERE id = ?",
+    )
+    .bind(user.id)
+    .fetch_one(state.db_pool())
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch user profile after rotation: {}", e);
+        ApiError::internal_server_error("Failed to rotate public id")
+    })?;
+
+    Ok(Json(UserProfileResponse::select(profile, None)))
+}
+
+// Export every chat the caller belongs to as JSON Lines, one `ChatExport` per line
+#[utoipa::path(
+    get,
+    path = "/api/users/me/export",
+    tag = "Users",
+    security(("bearerAuth" = [])),
+    responses(
+        (status = 200, description = "One ChatExport object per line", body = ChatExport, content_type = "application/x-ndjson"),
+        (status = 401, description = "Authentication required", body = crate::error::ErrorResponse),
+        (status = 500, description = "Failed to export chats", body = crate::error::ErrorResponse)
+    )
+)]
+pub async fn export_current_user(
+    State(state): State<AppState>,
+    AuthUser(user): AuthUser,
+) -> Result<Response, ApiError> {
+    let chat_ids: Vec<i64> =
+        sqlx::query_scalar("SELECT chat_id FROM chat_members WHERE user_id = ? ORDER BY chat_id")
+            .bind(user.id)
+            .fetch_all(state.db_read_pool())
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to list chats for export: {}", e);
+                ApiError::internal_server_error("Failed to export chats")
+            })?;
+
+    // Chats are loaded one at a time as the client reads, so a large account is never
+    // held in memory all at once
+    let lines = stream::iter(chat_ids).then(move |chat_db_id| {
+        let state = state.clone();
+        async move {
+            export_chat_line(&state, chat_db_id)
+                .await
+                .inspect_err(|e| tracing::error!("Failed to export chat {}: {}", chat_db_id, e))
+        }
+    });
+
+    let headers = [
+        (CONTENT_TYPE, "application/x-ndjson"),
+        (CONTENT_DISPOSITION, "attachment; filename=\"switchboard-export.jsonl\""),
+    ];
+    Ok((headers, Body::from_stream(lines)).into_response())
+}
+
+// The export line for one chat, or nothing if the chat was deleted mid-export
+async fn export_chat_line(state: &AppState, chat_db_id: i64) -> anyhow::Result<Vec<u8>> {
+    let chat = sqlx::query_as::<_, Chat>(
+        r#"
+        SELECT id, public_id, user_id, folder_id, title, chat_type,
+               message_retention_days, default_model, version, created_at, updated_at
+        FROM chats
+        WHERE id = ?
+        "#,
+    )
+    .bind(chat_db_id)
+    .fetch_optional(state.db_replica_pool())
+    .await?;
+    let Some(chat) = chat else {
+        return Ok(Vec::new());
+    };
+
+    let messages = sqlx::query_as::<_, Message>(
+        r#"
+        SELECT id, public_id, chat_id, user_id, content, role, model, message_type,
+               thread_id, reply_to_id, version, superseded_by, status, created_at, updated_at
+        FROM messages
+        WHERE chat_id = ?
+        ORDER BY created_at ASC, id ASC
+        "#,
+    )
+    .bind(chat_db_id)
+    .fetch_all(state.db_replica_pool())
+    .await?;
+
+    let attachments = sqlx::query_as::<_, AttachmentManifestEntry>(
+        r#"
+        SELECT m.public_id AS message_id, ma.file_name, ma.file_type, ma.file_size_bytes,
+               ma.created_at
+        FROM message_attachments ma
+        JOIN messages m ON m.id = ma.message_id
+        WHERE m.chat_id = ?
+        ORDER BY ma.created_at ASC, ma.id ASC
+        "#,
+    )
+    .bind(chat_db_id)
+    .fetch_all(state.db_replica_pool())
+    .await?;
+
+    let export = ChatExport {
+        chat,
+        messages,
+        attachments,
+    };
+    let mut line = serde_json::to_vec(&export)?;
+    line.push(b'\n');
+    Ok(line)
+}
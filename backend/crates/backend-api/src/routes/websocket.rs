@@ -1,61 +1,98 @@
 use axum::{
     extract::{ws::WebSocketUpgrade, Query, State},
-    http::StatusCode,
+    http::{header::ORIGIN, HeaderMap, StatusCode},
     response::Response,
 };
+use denkwerk::StreamEvent;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
-use tokio::sync::{broadcast, mpsc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+use switchboard_auth::ApiKeyScope;
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+};
 use utoipa::IntoParams;
 
-use crate::state::{AppState, ClientEvent, ServerEvent};
+use crate::{
+    ids::ResourceKind,
+    routes::{
+        chat,
+        drafts::DraftsService,
+        models::{MemberRole, Message, MessageStatus},
+        prompt_templates::render_for_user,
+    },
+    state::{AppState, ClientEvent, ServerEvent, ServerEventEnvelope},
+    streaming::StreamingMessage,
+    titles::spawn_title_untitled_chat,
+};
 
 #[derive(Debug, Deserialize, IntoParams)]
 pub struct WebSocketQuery {
     token: Option<String>,
 }
 
+/// A chat this connection is subscribed to. Dropping it stops forwarding the chat's
+/// broadcasts, so unsubscribing releases the forwarding task as well as the slot.
+struct ChatSubscription {
+    chat_db_id: i64,
+    broadcaster: broadcast::Sender<ServerEventEnvelope>,
+    forwarder: JoinHandle<()>,
+}
+
+impl Drop for ChatSubscription {
+    fn drop(&mut self) {
+        self.forwarder.abort();
+    }
+}
+
+/// Public ids of the chats this connection is subscribed to, shared with the user-channel
+/// forwarder so it can skip events the chat channel already delivers.
+type SubscribedChatIds = Arc<RwLock<HashSet<String>>>;
+
 #[utoipa::path(
     get,
     path = "/ws",
     tag = "WebSocket",
     params(WebSocketQuery),
     responses(
-        (status = 101, description = "WebSocket handshake successful")
+        (status = 101, description = "WebSocket handshake successful"),
+        (status = 401, description = "Missing or invalid token"),
+        (status = 403, description = "Disallowed origin, or an API key without messages:write")
     )
 )]
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     Query(params): Query<WebSocketQuery>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    // Authenticate the user
-    let user = match params.token {
-        Some(token) => {
-            match state.authenticate(&token).await {
-                Ok((user, _session)) => user,
-                Err(_) => {
-                    // For development: create a dummy user if auth fails
-                    tracing::warn!("Auth failed, using dummy user for development");
-                    switchboard_auth::User {
-                        id: 1,
-                        public_id: "dev-user-123".to_string(),
-                        email: Some("dev@example.com".to_string()),
-                        display_name: Some("Dev User".to_string()),
-                    }
-                }
-            }
+    // CORS doesn't apply to websocket upgrades, so check the origin here to stop other
+    // sites opening a socket with the user's credentials
+    if let Some(origin) = headers.get(ORIGIN) {
+        let allowed = origin
+            .to_str()
+            .is_ok_and(|origin| state.config().http.allows_origin(origin));
+        if !allowed {
+            tracing::warn!(?origin, "Rejected websocket upgrade from disallowed origin");
+            return Err(StatusCode::FORBIDDEN);
         }
-        None => {
-            // For development: allow connections without token
-            tracing::warn!("No token provided, using dummy user for development");
-            switchboard_auth::User {
-                id: 1,
-                public_id: "dev-user-123".to_string(),
-                email: Some("dev@example.com".to_string()),
-                display_name: Some("Dev User".to_string()),
-            }
+    }
+
+    let token = params.token.ok_or(StatusCode::UNAUTHORIZED)?;
+    let user = match state.authenticate(&token).await {
+        // Sending messages over the socket writes, so keys need that scope
+        Ok((_, session)) if !session.allows(ApiKeyScope::MessagesWrite) => {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        Ok((user, _session)) => user,
+        Err(e) => {
+            tracing::debug!("Rejected websocket upgrade: {}", e.message);
+            return Err(StatusCode::UNAUTHORIZED);
         }
     };
 
@@ -68,17 +105,29 @@ async fn handle_socket(
     user: switchboard_auth::User,
 ) {
     let (mut ws_sender, mut receiver) = socket.split();
-    let mut subscribed_chats = HashMap::new(); // chat_public_id -> (chat_db_id, broadcaster)
+    let mut subscribed_chats = HashMap::new(); // chat_public_id -> subscription
+    let subscribed_chat_ids = SubscribedChatIds::default();
 
-    let (out_tx, mut out_rx) = mpsc::channel::<ServerEvent>(100);
+    let (out_tx, mut out_rx) = mpsc::channel::<ServerEventEnvelope>(100);
 
     // Forward user-scoped broadcasts into this connection so cross-channel
-    // updates (e.g. folder or chat mutations) reach this socket too.
+    // updates (e.g. folder or chat mutations) reach this socket too. Events for
+    // chats we're subscribed to already arrive through the chat channel.
     let user_forward_tx = out_tx.clone();
     let user_broadcaster = state.get_user_broadcaster(user.id).await;
-    let _user_task = tokio::spawn(async move {
+    let user_subscribed_chat_ids = subscribed_chat_ids.clone();
+    let user_task = tokio::spawn(async move {
         let mut receiver = user_broadcaster.subscribe();
         while let Ok(event) = receiver.recv().await {
+            let delivered_by_chat = event.event.chat_id().is_some_and(|chat_id| {
+                user_subscribed_chat_ids
+                    .read()
+                    .map(|chat_ids| chat_ids.contains(chat_id))
+                    .unwrap_or(false)
+            });
+            if delivered_by_chat {
+                continue;
+            }
             if user_forward_tx.send(event.clone()).await.is_err() {
                 break;
             }
@@ -103,10 +152,26 @@ async fn handle_socket(
         version: "1.0".to_string(),
         user_id: user.id,
     };
-    let _ = out_tx.send(hello_event).await;
+    let _ = out_tx.send(hello_event.into()).await;
 
     while let Some(msg) = receiver.next().await {
         match msg {
+            Ok(axum::extract::ws::Message::Text(text))
+                if text.len() > state.config().websocket.max_message_bytes =>
+            {
+                let limit = state.config().websocket.max_message_bytes;
+                tracing::warn!(
+                    "📦 Closing WebSocket for user {}: {} byte message exceeds {} bytes",
+                    user.id,
+                    text.len(),
+                    limit
+                );
+                let error_event = ServerEvent::Error {
+                    message: format!("Message exceeds the {} byte limit", limit),
+                };
+                let _ = out_tx.send(error_event.into()).await;
+                break;
+            }
             Ok(axum::extract::ws::Message::Text(text)) => {
                 tracing::debug!("Received WebSocket message from user {}: {}", user.id, text);
 
@@ -118,6 +183,7 @@ async fn handle_socket(
                             &state,
                             &user,
                             &mut subscribed_chats,
+                            &subscribed_chat_ids,
                         )
                         .await
                         {
@@ -125,7 +191,7 @@ async fn handle_socket(
                             let error_event = ServerEvent::Error {
                                 message: "Failed to process event".to_string(),
                             };
-                            let _ = out_tx.send(error_event).await;
+                            let _ = out_tx.send(error_event.into()).await;
                         }
                     }
                     Err(e) => {
@@ -133,7 +199,7 @@ async fn handle_socket(
                         let error_event = ServerEvent::Error {
                             message: "Invalid event format".to_string(),
                         };
-                        let _ = out_tx.send(error_event).await;
+                        let _ = out_tx.send(error_event.into()).await;
                     }
                 }
             }
@@ -154,18 +220,36 @@ async fn handle_socket(
         }
     }
 
+    // The user forwarder holds the last outgoing sender once subscriptions drop; with
+    // it gone the sender task flushes what is queued and the socket closes
+    user_task.abort();
     tracing::info!("🔚 WebSocket handler finished for user {}", user.id);
 }
 
 async fn handle_client_event(
     event: ClientEvent,
-    out_tx: &mpsc::Sender<ServerEvent>,
+    out_tx: &mpsc::Sender<ServerEventEnvelope>,
     state: &AppState,
     user: &switchboard_auth::User,
-    subscribed_chats: &mut HashMap<String, (i64, broadcast::Sender<ServerEvent>)>, // chat_public_id -> (chat_db_id, broadcaster)
+    subscribed_chats: &mut HashMap<String, ChatSubscription>, // chat_public_id -> subscription
+    subscribed_chat_ids: &SubscribedChatIds,
 ) -> Result<(), anyhow::Error> {
     match event {
         ClientEvent::Subscribe { chat_id } => {
+            let max_subscriptions = state.config().websocket.max_subscriptions_per_connection;
+            if !subscribed_chats.contains_key(&chat_id)
+                && subscribed_chats.len() >= max_subscriptions
+            {
+                let error = ServerEvent::Error {
+                    message: format!(
+                        "Subscription limit of {} chats reached; unsubscribe from a chat first",
+                        max_subscriptions
+                    ),
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            }
+
             // Find the chat by public_id
             let chat_db_id: Option<i64> =
                 sqlx::query_scalar("SELECT id FROM chats WHERE public_id = ?")
@@ -179,7 +263,7 @@ async fn handle_client_event(
                     let error = ServerEvent::Error {
                         message: "Chat not found".to_string(),
                     };
-                    out_tx.send(error).await?;
+                    out_tx.send(error.into()).await?;
                     return Ok(());
                 }
             };
@@ -196,24 +280,16 @@ async fn handle_client_event(
                 let error = ServerEvent::Error {
                     message: "Not a member of this chat".to_string(),
                 };
-                out_tx.send(error).await?;
+                out_tx.send(error.into()).await?;
                 return Ok(());
             }
 
-            // Get or create broadcaster for this chat
-            let broadcaster = {
-                let mut broadcasters = state.chat_broadcasters.lock().await;
-                broadcasters
-                    .entry(chat_id.clone())
-                    .or_insert_with(|| broadcast::channel(100).0)
-                    .clone()
-            };
+            let broadcaster = state.chat_broadcaster(&chat_id).await;
 
             // Start broadcasting task
             let tx = out_tx.clone();
-            let broadcaster_clone = broadcaster.clone();
-            tokio::spawn(async move {
-                let mut receiver = broadcaster_clone.subscribe();
+            let mut receiver = broadcaster.subscribe();
+            let forwarder = tokio::spawn(async move {
                 while let Ok(event) = receiver.recv().await {
                     if tx.send(event).await.is_err() {
                         break;
@@ -221,19 +297,34 @@ async fn handle_client_event(
                 }
             });
 
-            subscribed_chats.insert(chat_id.clone(), (chat_db_id, broadcaster));
+            subscribed_chats.insert(
+                chat_id.clone(),
+                ChatSubscription {
+                    chat_db_id,
+                    broadcaster,
+                    forwarder,
+                },
+            );
+            if let Ok(mut chat_ids) = subscribed_chat_ids.write() {
+                chat_ids.insert(chat_id.clone());
+            }
             let response = ServerEvent::Subscribed { chat_id };
-            out_tx.send(response).await?;
+            out_tx.send(response.into()).await?;
         }
         ClientEvent::Unsubscribe { chat_id } => {
             subscribed_chats.remove(&chat_id);
+            if let Ok(mut chat_ids) = subscribed_chat_ids.write() {
+                chat_ids.remove(&chat_id);
+            }
             let response = ServerEvent::Unsubscribed { chat_id };
-            out_tx.send(response).await?;
+            out_tx.send(response.into()).await?;
         }
         ClientEvent::Message {
             chat_id,
             content,
             models,
+            template_id,
+            variables,
         } => {
             tracing::info!(
                 "📨 Received chat message from user {} in chat {}: {}",
@@ -256,7 +347,7 @@ async fn handle_client_event(
             }
 
             let (chat_db_id, broadcaster) = match subscribed_chats.get(&chat_id) {
-                Some((id, sender)) => (*id, sender.clone()),
+                Some(subscription) => (subscription.chat_db_id, subscription.broadcaster.clone()),
                 None => {
                     tracing::warn!(
                         "❌ User {} tried to send message to unsubscribed chat {}",
@@ -266,20 +357,52 @@ async fn handle_client_event(
                     let error = ServerEvent::Error {
                         message: "Not subscribed to chat".to_string(),
                     };
-                    out_tx.send(error).await?;
+                    out_tx.send(error.into()).await?;
                     return Ok(());
                 }
             };
 
+            if let Some(message) = posting_denied(state, chat_db_id, user.id).await? {
+                let error = ServerEvent::Error {
+                    message: message.to_string(),
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            }
+
+            if let Err(flood_error) = state.check_message_flood(user.id, chat_db_id) {
+                let error = ServerEvent::Error {
+                    message: flood_error.message,
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            }
+
+            let content = match template_id {
+                Some(template_id) => {
+                    match render_for_user(state, user.id, &template_id, &variables).await {
+                        Ok(rendered) => rendered,
+                        Err(template_error) => {
+                            let error = ServerEvent::Error {
+                                message: template_error.message,
+                            };
+                            out_tx.send(error.into()).await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                None => content,
+            };
+
             tracing::debug!("💾 Saving user message to database...");
             // Save user message to database
-            let message_public_id = cuid2::create_id();
+            let message_public_id = ResourceKind::Message.new_public_id(&state.config().ids);
             let now = chrono::Utc::now().to_rfc3339();
 
             sqlx::query(
                 r#"
-                INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, created_at, updated_at)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, status, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                 "#
             )
             .bind(&message_public_id)
@@ -289,6 +412,7 @@ async fn handle_client_event(
             .bind("text")
             .bind("user")
             .bind(Option::<String>::None)
+            .bind(MessageStatus::Sent.as_str())
             .bind(&now)
             .bind(&now)
             .execute(&state.db_pool)
@@ -299,7 +423,12 @@ async fn handle_client_event(
                 message_public_id
             );
 
-            let message_event = ServerEvent::Message {
+            DraftsService::clear_draft(&state.db_pool, chat_db_id, user.id)
+                .await
+                .map_err(|e| anyhow::anyhow!(e.message))?;
+            spawn_title_untitled_chat(state, chat_db_id, &content);
+
+            let message_event = ServerEventEnvelope::new(ServerEvent::Message {
                 chat_id: chat_id.clone(),
                 message_id: message_public_id,
                 user_id: user.id,
@@ -307,7 +436,7 @@ async fn handle_client_event(
                 model: None,
                 timestamp: now.clone(),
                 message_type: "text".to_string(),
-            };
+            });
             // Send user message to self
             tracing::debug!("📤 Sending user message echo to sender via out_tx");
             if let Err(e) = out_tx.send(message_event.clone()).await {
@@ -334,9 +463,16 @@ async fn handle_client_event(
                 .filter(|m| !m.is_empty())
                 .collect();
 
+            // A model named on the message wins, then the chat's pinned model, then the
+            // server default
             if requested_models.is_empty() {
-                if let Some(active) = state.orchestrator().active_model() {
-                    requested_models.push(active);
+                let pinned: Option<String> =
+                    sqlx::query_scalar("SELECT default_model FROM chats WHERE id = ?")
+                        .bind(chat_db_id)
+                        .fetch_one(&state.db_pool)
+                        .await?;
+                if let Some(model) = pinned.or_else(|| state.orchestrator().active_model()) {
+                    requested_models.push(model);
                 }
             }
 
@@ -351,181 +487,570 @@ async fn handle_client_event(
                 let error_event = ServerEvent::Error {
                     message: "No model configured".to_string(),
                 };
-                out_tx.send(error_event).await?;
+                out_tx.send(error_event.into()).await?;
                 return Ok(());
             }
 
-            for model_to_use in models_to_use {
-                let state_clone = state.clone();
-                let chat_id_clone = chat_id.clone();
-                let content_clone = content.clone();
-                let out_tx_clone = out_tx.clone();
-                let broadcaster_clone = broadcaster.clone();
-                let chat_db_id = chat_db_id;
-                let user_id = user.id;
-
-                tokio::spawn(async move {
-                    tracing::info!("🧠 Using model {} for chat {}", model_to_use, chat_id_clone);
-                    tracing::debug!("🔧 Getting LLM provider for model {}", model_to_use);
-                    let provider =
-                        match state_clone.orchestrator().provider_for_model(&model_to_use) {
-                            Ok(provider) => {
-                                tracing::debug!(
-                                    "✅ LLM provider obtained successfully for {}",
-                                    model_to_use
-                                );
-                                provider
-                            }
-                            Err(e) => {
-                                tracing::error!(
-                                    "❌ Failed to get LLM provider for {}: {}",
-                                    model_to_use,
-                                    e
-                                );
-                                let error_event = ServerEvent::Error {
-                                    message: format!(
-                                        "LLM provider not available for {}: {}",
-                                        model_to_use, e
-                                    ),
-                                };
-                                let _ = out_tx_clone.send(error_event).await;
-                                return;
-                            }
-                        };
-
-                    tracing::debug!("📝 Preparing completion request for model {}", model_to_use);
-                    let message = denkwerk::ChatMessage::user(&content_clone);
-                    let request =
-                        denkwerk::CompletionRequest::new(model_to_use.clone(), vec![message]);
-
-                    tracing::info!("🚀 Sending request to LLM...");
-                    match provider.complete(request).await {
-                        Ok(completion) => {
-                            tracing::info!("✅ LLM response received successfully");
-                            let response_content =
-                                completion.message.text().unwrap_or_default().to_string();
-                            let _reasoning: Option<Vec<String>> = completion
-                                .reasoning
-                                .map(|steps| steps.into_iter().map(|step| step.content).collect());
-
-                            tracing::debug!("💾 Saving assistant response to database...");
-                            // Save assistant response to database
-                            let assistant_message_id = cuid2::create_id();
-                            let assistant_timestamp = chrono::Utc::now().to_rfc3339();
-
-                            if let Err(e) = sqlx::query(
-                                r#"
-                                INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, created_at, updated_at)
-                                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-                                "#
-                            )
-                            .bind(&assistant_message_id)
-                            .bind(chat_db_id)
-                            .bind(user_id) // Use the same user ID for assistant messages in development
-                            .bind(&response_content)
-                            .bind("text")
-                            .bind("assistant")
-                            .bind(Some(model_to_use.clone()))
-                            .bind(&assistant_timestamp)
-                            .bind(&assistant_timestamp)
-                            .execute(&state_clone.db_pool)
-                            .await {
-                                tracing::error!("❌ Failed to save assistant message: {}", e);
-                                return;
-                            }
-
-                            tracing::debug!(
-                                "✅ Assistant response saved to database with ID: {}",
-                                assistant_message_id
-                            );
-                            tracing::info!(
-                                "📤 Broadcasting assistant response to chat {}",
-                                chat_id_clone
-                            );
-
-                            let assistant_event = ServerEvent::Message {
-                                chat_id: chat_id_clone.clone(),
-                                message_id: assistant_message_id,
-                                user_id: user_id, // Use the same user ID for assistant messages in development
-                                content: response_content,
-                                model: Some(model_to_use.clone()),
-                                timestamp: assistant_timestamp,
-                                message_type: "text".to_string(),
-                            };
-
-                            // Send assistant response to self
-                            tracing::debug!(
-                                "📤 Sending assistant response directly to sender via out_tx"
-                            );
-                            // Check if the channel is still open (connection hasn't closed)
-                            match out_tx_clone.send(assistant_event.clone()).await {
-                                Ok(_) => {
-                                    tracing::debug!(
-                                        "✅ Assistant response sent to sender via out_tx"
-                                    );
-                                }
-                                Err(e) => {
-                                    tracing::error!(
-                                        "❌ Failed to send assistant response to sender: {}",
-                                        e
-                                    );
-                                    tracing::warn!(
-                                        "⚠️ WebSocket connection may have closed during LLM processing"
-                                    );
-                                    // Don't try to broadcast if we can't send to the original sender
-                                    return;
-                                }
-                            }
-                            // Broadcast assistant response to others
-                            tracing::debug!(
-                                "📡 Broadcasting assistant response to other subscribers"
-                            );
-                            if let Err(e) = broadcaster_clone.send(assistant_event) {
-                                tracing::error!("❌ Failed to broadcast assistant response: {}", e);
-                            } else {
-                                tracing::debug!("✅ Assistant response broadcasted successfully");
-                            }
-
-                            tracing::info!(
-                                "✅ Message processing completed for chat {}",
-                                chat_id_clone
-                            );
-                        }
-                        Err(e) => {
-                            tracing::error!("❌ LLM completion failed: {}", e);
-                            let error_message = format!("LLM completion failed: {}", e);
-                            let error_event = ServerEvent::Error {
-                                message: error_message,
-                            };
-                            let _ = out_tx_clone.send(error_event).await;
-                        }
-                    }
-                });
+            for model in models_to_use {
+                tokio::spawn(generate_reply(ReplyJob {
+                    state: state.clone(),
+                    chat_id: chat_id.clone(),
+                    chat_db_id,
+                    user_id: user.id,
+                    model,
+                    prompt: content.clone(),
+                    reply_id: ResourceKind::Message.new_public_id(&state.config().ids),
+                    supersedes: None,
+                    requester: Some(out_tx.clone()),
+                    broadcaster: broadcaster.clone(),
+                }));
             }
         }
         ClientEvent::Typing { chat_id, is_typing } => {
             let broadcaster = match subscribed_chats.get(&chat_id) {
-                Some((_, sender)) => sender.clone(),
+                Some(subscription) => {
+                    state.typing().set(subscription.chat_db_id, user.id, is_typing);
+                    subscription.broadcaster.clone()
+                }
                 None => {
                     let error = ServerEvent::Error {
                         message: "Not subscribed to chat".to_string(),
                     };
-                    out_tx.send(error).await?;
+                    out_tx.send(error.into()).await?;
                     return Ok(());
                 }
             };
 
-            let typing_event = ServerEvent::Typing {
+            let typing_event = ServerEventEnvelope::new(ServerEvent::Typing {
                 chat_id: chat_id.clone(),
                 user_id: user.id,
                 is_typing,
-            };
+            });
             // Send to self
             out_tx.send(typing_event.clone()).await?;
             // Broadcast to others
             let _ = broadcaster.send(typing_event);
         }
+        ClientEvent::SaveDraft { chat_id, content } => {
+            let Some(chat_db_id) = subscribed_chats.get(&chat_id).map(|s| s.chat_db_id) else {
+                let error = ServerEvent::Error {
+                    message: "Not subscribed to chat".to_string(),
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            };
+
+            let draft = DraftsService::save_draft(&state.db_pool, chat_db_id, user.id, &content)
+                .await
+                .map_err(|e| anyhow::anyhow!(e.message))?;
+            // Drafts are private: reply to this connection only.
+            let saved = ServerEvent::DraftSaved {
+                chat_id,
+                updated_at: draft.updated_at,
+            };
+            out_tx.send(saved.into()).await?;
+        }
+        ClientEvent::ClearDraft { chat_id } => {
+            let Some(chat_db_id) = subscribed_chats.get(&chat_id).map(|s| s.chat_db_id) else {
+                let error = ServerEvent::Error {
+                    message: "Not subscribed to chat".to_string(),
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            };
+
+            DraftsService::clear_draft(&state.db_pool, chat_db_id, user.id)
+                .await
+                .map_err(|e| anyhow::anyhow!(e.message))?;
+            let cleared = ServerEvent::DraftCleared { chat_id };
+            out_tx.send(cleared.into()).await?;
+        }
+        ClientEvent::GetMessage {
+            chat_id,
+            message_id,
+        } => {
+            let is_member: Option<i64> = sqlx::query_scalar(
+                r#"
+                SELECT 1 FROM chats c
+                JOIN chat_members cm ON c.id = cm.chat_id
+                WHERE c.public_id = ? AND cm.user_id = ?
+                "#,
+            )
+            .bind(&chat_id)
+            .bind(user.id)
+            .fetch_optional(&state.db_pool)
+            .await?;
+            if is_member.is_none() {
+                let error = ServerEvent::Error {
+                    message: "Not a member of this chat".to_string(),
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            }
+
+            // Scoped to the chat so an id from another chat is not found
+            let message = sqlx::query_as::<_, Message>(
+                r#"
+                SELECT m.id, m.public_id, m.chat_id, m.user_id, m.content, m.role, m.model,
+                       m.message_type, m.thread_id, m.reply_to_id, m.version, m.superseded_by,
+                       m.status, m.created_at, m.updated_at
+                FROM messages m
+                JOIN chats c ON c.id = m.chat_id
+                WHERE c.public_id = ? AND m.public_id = ?
+                "#,
+            )
+            .bind(&chat_id)
+            .bind(&message_id)
+            .fetch_optional(&state.db_pool)
+            .await?;
+
+            let response = match message {
+                Some(message) => ServerEvent::MessageResponse { message },
+                None => ServerEvent::Error {
+                    message: "Message not found".to_string(),
+                },
+            };
+            out_tx.send(response.into()).await?;
+        }
+        ClientEvent::RegenerateLast { chat_id, model } => {
+            let Some((chat_db_id, broadcaster)) = subscribed_chats
+                .get(&chat_id)
+                .map(|s| (s.chat_db_id, s.broadcaster.clone()))
+            else {
+                let error = ServerEvent::Error {
+                    message: "Not subscribed to chat".to_string(),
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            };
+
+            if let Some(message) = posting_denied(state, chat_db_id, user.id).await? {
+                let error = ServerEvent::Error {
+                    message: message.to_string(),
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            }
+
+            if let Err(flood_error) = state.check_message_flood(user.id, chat_db_id) {
+                let error = ServerEvent::Error {
+                    message: flood_error.message,
+                };
+                out_tx.send(error.into()).await?;
+                return Ok(());
+            }
+
+            let target = match LastReply::find(&state.db_pool, chat_db_id).await? {
+                Some(last) => last
+                    .regeneration(state, model)
+                    .map(|(prompt, model)| (last.id, prompt, model)),
+                None => Err("No reply to regenerate"),
+            };
+            let (supersedes, prompt, model) = match target {
+                Ok(target) => target,
+                Err(message) => {
+                    let error = ServerEvent::Error {
+                        message: message.to_string(),
+                    };
+                    out_tx.send(error.into()).await?;
+                    return Ok(());
+                }
+            };
+
+            tokio::spawn(generate_reply(ReplyJob {
+                state: state.clone(),
+                chat_id,
+                chat_db_id,
+                user_id: user.id,
+                model,
+                prompt,
+                reply_id: ResourceKind::Message.new_public_id(&state.config().ids),
+                supersedes: Some(supersedes),
+                requester: Some(out_tx.clone()),
+                broadcaster,
+            }));
+        }
     }
 
     Ok(())
 }
+
+// Why the user may not post in the chat right now, if they may not. The role may
+// have changed since subscribing, so this is checked on every send.
+async fn posting_denied(
+    state: &AppState,
+    chat_db_id: i64,
+    user_id: i64,
+) -> Result<Option<&'static str>, sqlx::Error> {
+    let role: Option<String> =
+        sqlx::query_scalar("SELECT role FROM chat_members WHERE chat_id = ? AND user_id = ?")
+            .bind(chat_db_id)
+            .bind(user_id)
+            .fetch_optional(&state.db_pool)
+            .await?;
+    Ok(match role.as_deref().map(MemberRole::parse) {
+        None => Some("Not a member of this chat"),
+        Some(role) if !role.is_some_and(MemberRole::can_post) => {
+            Some("Viewers cannot post messages")
+        }
+        Some(_) => None,
+    })
+}
+
+/// One assistant reply to generate: `model`'s answer to `prompt` in a chat.
+pub(crate) struct ReplyJob {
+    pub state: AppState,
+    pub chat_id: String,
+    pub chat_db_id: i64,
+    pub user_id: i64,
+    pub model: String,
+    pub prompt: String,
+    /// Public id for the new assistant message.
+    pub reply_id: String,
+    /// A reply this one regenerates; it is marked superseded once this one completes.
+    pub supersedes: Option<i64>,
+    /// The connection that asked for the reply, which is told about failures and sent
+    /// the reply directly. Replies requested over REST have none.
+    pub requester: Option<mpsc::Sender<ServerEventEnvelope>>,
+    pub broadcaster: broadcast::Sender<ServerEventEnvelope>,
+}
+
+impl ReplyJob {
+    async fn report_error(&self, message: String) {
+        if let Some(requester) = &self.requester {
+            let _ = requester.send(ServerEvent::Error { message }.into()).await;
+        }
+    }
+}
+
+/// Run `job`'s completion, saving the reply as it goes and broadcasting its progress
+/// to the chat.
+pub(crate) async fn generate_reply(job: ReplyJob) {
+    let state = &job.state;
+    let model = job.model.as_str();
+    let chat_id = job.chat_id.as_str();
+
+    tracing::info!("🧠 Using model {} for chat {}", model, chat_id);
+    if let Err(e) = state.orchestrator().check_model_allowed(model) {
+        tracing::warn!("🚫 Completion for {} refused: {}", model, e);
+        job.report_error(e.to_string()).await;
+        return;
+    }
+    tracing::debug!("🔧 Getting LLM provider for model {}", model);
+    let provider = match state.orchestrator().provider_for_model(model) {
+        Ok(provider) => {
+            tracing::debug!("✅ LLM provider obtained successfully for {}", model);
+            provider
+        }
+        Err(e) => {
+            tracing::error!("❌ Failed to get LLM provider for {}: {}", model, e);
+            job.report_error(format!("LLM provider not available for {}: {}", model, e))
+                .await;
+            return;
+        }
+    };
+
+    tracing::debug!("📝 Preparing completion request for model {}", model);
+    let request = chat::message_request(model, &job.prompt);
+    if let Err(e) = state.orchestrator().check_prompt_length(&request) {
+        tracing::warn!("📏 Completion for {} refused: {}", model, e);
+        job.report_error(format!("{} ({})", e, model)).await;
+        return;
+    }
+
+    let slot = state
+        .orchestrator()
+        .acquire_completion_slot_for(model)
+        .await;
+    let _slot = match slot {
+        Ok(slot) => slot,
+        Err(e) => {
+            tracing::warn!("⏳ Completion for {} rejected: {}", model, e);
+            job.report_error(format!("{} ({})", e, model)).await;
+            return;
+        }
+    };
+
+    // Saved as streaming up front so a restart mid-completion leaves an
+    // interrupted reply rather than nothing
+    let mut reply = match StreamingMessage::start(
+        &state.db_pool,
+        &state.config().chat,
+        job.chat_db_id,
+        job.user_id, // Use the same user ID for assistant messages in development
+        job.reply_id.clone(),
+        Some(model),
+    )
+    .await
+    {
+        Ok(reply) => reply,
+        Err(e) => {
+            tracing::error!("❌ Failed to save assistant message: {}", e);
+            return;
+        }
+    };
+    broadcast_status(
+        &job.broadcaster,
+        chat_id,
+        reply.public_id(),
+        MessageStatus::Streaming,
+    );
+
+    tracing::info!("🚀 Sending request to LLM...");
+    let started = Instant::now();
+    let stream = state
+        .orchestrator()
+        .stream_completion(provider.as_ref(), request)
+        .await;
+    let mut stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::error!("❌ LLM completion failed: {}", e);
+            let reply_id = reply.public_id().to_string();
+            if let Err(e) = reply.fail().await {
+                tracing::error!("❌ Failed to mark reply failed: {}", e);
+            }
+            broadcast_status(&job.broadcaster, chat_id, &reply_id, MessageStatus::Error);
+            job.report_error(format!("LLM completion failed: {}", e)).await;
+            return;
+        }
+    };
+
+    // Each chunk goes through the checkpointing reply, which also keeps `updated_at`
+    // moving so the startup sweep can tell this reply from an abandoned one
+    let mut completion = None;
+    let mut stream_error = None;
+    while let Some(event) = stream.next().await {
+        match event {
+            Ok(StreamEvent::MessageDelta(delta)) => {
+                if let Err(e) = reply.push(&delta).await {
+                    tracing::error!("❌ Failed to checkpoint reply: {}", e);
+                }
+            }
+            Ok(StreamEvent::Completed(response)) => completion = Some(response),
+            Ok(_) => {}
+            Err(e) => {
+                stream_error = Some(e);
+                break;
+            }
+        }
+    }
+    if let Some(e) = stream_error {
+        tracing::error!("❌ LLM completion failed part way: {}", e);
+        let reply_id = reply.public_id().to_string();
+        // Keep whatever text arrived before the failure
+        let (status, saved) = if reply.content().is_empty() {
+            (MessageStatus::Error, reply.fail().await)
+        } else {
+            (MessageStatus::Interrupted, reply.interrupt().await)
+        };
+        if let Err(e) = saved {
+            tracing::error!("❌ Failed to mark reply {}: {}", status.as_str(), e);
+        }
+        broadcast_status(&job.broadcaster, chat_id, &reply_id, status);
+        job.report_error(format!("LLM completion failed: {}", e)).await;
+        return;
+    }
+
+    let latency_ms = started.elapsed().as_millis() as u64;
+    tracing::info!("✅ LLM response received successfully");
+    if reply.content().is_empty() {
+        // Providers that send the whole reply at the end rather than in deltas
+        if let Some(text) = completion.as_ref().and_then(|c| c.message.text()) {
+            reply.append(text);
+        }
+    }
+    let token_counts = completion
+        .as_ref()
+        .and_then(|completion| completion.usage.as_ref())
+        .map(|usage| (usage.prompt_tokens, usage.completion_tokens));
+    let usage = token_counts
+        .map(|(prompt, generated)| (i64::from(prompt), i64::from(generated)));
+    let estimated_cost = token_counts.and_then(|(prompt, generated)| {
+        state
+            .orchestrator()
+            .completion_cost(model, prompt, generated)
+    });
+
+    tracing::debug!("💾 Saving assistant response to database...");
+    // Save assistant response to database
+    let assistant_db_id = reply.id();
+    let assistant_message_id = reply.public_id().to_string();
+    let assistant_timestamp = reply.created_at().to_string();
+    let response_content = reply.content().to_string();
+    if let Err(e) = reply.finish(usage, estimated_cost).await {
+        tracing::error!("❌ Failed to save assistant message: {}", e);
+        return;
+    }
+    broadcast_status(
+        &job.broadcaster,
+        chat_id,
+        &assistant_message_id,
+        MessageStatus::Complete,
+    );
+    if let Some(superseded) = job.supersedes {
+        supersede_reply(&job, superseded, assistant_db_id).await;
+    }
+    let finished = ServerEvent::CompletionFinished {
+        chat_id: chat_id.to_string(),
+        message_id: assistant_message_id.clone(),
+        model: model.to_string(),
+        latency_ms,
+        prompt_tokens: token_counts.map(|(prompt, _)| prompt),
+        completion_tokens: token_counts.map(|(_, generated)| generated),
+        estimated_cost,
+    };
+    if let Err(e) = job.broadcaster.send(finished.into()) {
+        tracing::debug!("No subscribers for completion finished: {}", e);
+    }
+
+    tracing::debug!(
+        "✅ Assistant response saved to database with ID: {}",
+        assistant_message_id
+    );
+    tracing::info!("📤 Broadcasting assistant response to chat {}", chat_id);
+
+    let assistant_event = ServerEventEnvelope::new(ServerEvent::Message {
+        chat_id: chat_id.to_string(),
+        message_id: assistant_message_id,
+        user_id: job.user_id, // Use the same user ID for assistant messages in development
+        content: response_content,
+        model: Some(model.to_string()),
+        timestamp: assistant_timestamp,
+        message_type: "text".to_string(),
+    });
+
+    if let Some(requester) = &job.requester {
+        // Send assistant response to self
+        tracing::debug!("📤 Sending assistant response directly to sender via out_tx");
+        // Check if the channel is still open (connection hasn't closed)
+        match requester.send(assistant_event.clone()).await {
+            Ok(_) => {
+                tracing::debug!("✅ Assistant response sent to sender via out_tx");
+            }
+            Err(e) => {
+                tracing::error!("❌ Failed to send assistant response to sender: {}", e);
+                tracing::warn!("⚠️ WebSocket connection may have closed during LLM processing");
+                // Don't try to broadcast if we can't send to the original sender
+                return;
+            }
+        }
+    }
+    // Broadcast assistant response to others
+    tracing::debug!("📡 Broadcasting assistant response to other subscribers");
+    if let Err(e) = job.broadcaster.send(assistant_event) {
+        tracing::error!("❌ Failed to broadcast assistant response: {}", e);
+    } else {
+        tracing::debug!("✅ Assistant response broadcasted successfully");
+    }
+
+    tracing::info!("✅ Message processing completed for chat {}", chat_id);
+}
+
+// Point the regenerated reply at its replacement and tell the chat it changed
+async fn supersede_reply(job: &ReplyJob, superseded: i64, replacement: i64) {
+    let now = chrono::Utc::now().to_rfc3339();
+    let message = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages SET superseded_by = ?, updated_at = ?
+        WHERE id = ?
+        RETURNING id, public_id, chat_id, user_id, content, role, model, message_type,
+                  thread_id, reply_to_id, version, superseded_by, status, created_at, updated_at
+        "#,
+    )
+    .bind(replacement)
+    .bind(&now)
+    .bind(superseded)
+    .fetch_optional(&job.state.db_pool)
+    .await;
+
+    match message {
+        Ok(Some(message)) => {
+            let event = ServerEvent::MessageUpdated {
+                chat_id: job.chat_id.clone(),
+                message,
+            };
+            if let Err(e) = job.broadcaster.send(event.into()) {
+                tracing::debug!("No subscribers for superseded reply: {}", e);
+            }
+        }
+        // Deleted while the replacement was generated; nothing left to mark
+        Ok(None) => {}
+        Err(e) => tracing::error!("❌ Failed to mark reply {} superseded: {}", superseded, e),
+    }
+}
+
+/// The newest assistant reply in a chat that has not been regenerated, with the user
+/// message it answered.
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct LastReply {
+    pub id: i64,
+    pub public_id: String,
+    pub model: Option<String>,
+    pub status: String,
+    pub prompt: Option<String>,
+}
+
+impl LastReply {
+    pub(crate) async fn find(
+        pool: &sqlx::SqlitePool,
+        chat_db_id: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            r#"
+            SELECT m.id, m.public_id, m.model, m.status,
+                   (SELECT p.content FROM messages p
+                    WHERE p.chat_id = m.chat_id AND p.role = 'user' AND p.id < m.id
+                    ORDER BY p.id DESC LIMIT 1) AS prompt
+            FROM messages m
+            WHERE m.chat_id = ? AND m.role = 'assistant' AND m.superseded_by IS NULL
+            ORDER BY m.id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(chat_db_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// The prompt to answer again and the model to answer it with: `requested` if
+    /// given, else the model that wrote this reply, else the active one. Errors say
+    /// why the reply cannot be regenerated.
+    pub(crate) fn regeneration(
+        &self,
+        state: &AppState,
+        requested: Option<String>,
+    ) -> Result<(String, String), &'static str> {
+        if self.status == MessageStatus::Streaming.as_str() {
+            return Err("The last reply is still being generated");
+        }
+        let prompt = self
+            .prompt
+            .clone()
+            .ok_or("The last reply does not answer a user message")?;
+        let model = requested
+            .map(|model| model.trim().to_string())
+            .filter(|model| !model.is_empty())
+            .or_else(|| self.model.clone())
+            .or_else(|| state.orchestrator().active_model())
+            .ok_or("No model configured")?;
+        Ok((prompt, model))
+    }
+}
+
+// Tell the chat's subscribers that an assistant reply moved to `status`
+fn broadcast_status(
+    broadcaster: &broadcast::Sender<ServerEventEnvelope>,
+    chat_id: &str,
+    message_id: &str,
+    status: MessageStatus,
+) {
+    let event = ServerEvent::MessageStatusChanged {
+        chat_id: chat_id.to_string(),
+        message_id: message_id.to_string(),
+        status,
+    };
+    if let Err(e) = broadcaster.send(event.into()) {
+        tracing::debug!("No subscribers for message status change: {}", e);
+    }
+}
@@ -1,4 +1,13 @@
-use std::{collections::HashMap, sync::Arc, time::Duration as StdDuration, time::Instant};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration as StdDuration,
+    time::Instant,
+};
 
 use chrono::{Duration, Utc};
 use rand::{distributions::Alphanumeric, Rng};
@@ -6,11 +15,16 @@ use redis::aio::ConnectionManager;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use switchboard_auth::{AuthError, AuthSession, Authenticator, User};
+use switchboard_backend_runtime::TaskRegistry;
+use switchboard_config::{AppConfig, AuthConfig, NonMemberStatus};
 use switchboard_orchestrator::Orchestrator;
 use tokio::sync::{broadcast, Mutex};
 
 use crate::{
-    routes::models::{Chat, ChatInvite, ChatMember, Folder, Message},
+    event_bus::{self, EventBus, EventTarget},
+    flood::MessageFloodGuard,
+    routes::models::{Chat, ChatInvite, ChatMember, Folder, Message, MessageStatus},
+    typing::TypingTracker,
     ApiError,
 };
 
@@ -25,14 +39,43 @@ pub enum ClientEvent {
     },
     Message {
         chat_id: String,
+        /// Ignored when `template_id` is set.
+        #[serde(default)]
         content: String,
         #[serde(default, deserialize_with = "deserialize_models")]
         models: Vec<String>,
+        /// One of the sender's prompt templates, rendered with `variables` to become
+        /// the message.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        template_id: Option<String>,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        variables: HashMap<String, String>,
     },
     Typing {
         chat_id: String,
         is_typing: bool,
     },
+    SaveDraft {
+        chat_id: String,
+        content: String,
+    },
+    ClearDraft {
+        chat_id: String,
+    },
+    /// Refetch one message, e.g. after missing its edit. Answered with a
+    /// `message_response` or an `error`.
+    GetMessage {
+        chat_id: String,
+        message_id: String,
+    },
+    /// Answer the prompt of the chat's last assistant reply again, with `model` or the
+    /// model that wrote it. The old reply is kept and marked superseded once the new
+    /// one completes.
+    RegenerateLast {
+        chat_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +109,14 @@ pub enum ServerEvent {
     Error {
         message: String,
     },
+    /// Sent only to the connection that saved the draft; drafts are never broadcast.
+    DraftSaved {
+        chat_id: String,
+        updated_at: String,
+    },
+    DraftCleared {
+        chat_id: String,
+    },
     ChatCreated {
         chat: Chat,
     },
@@ -92,6 +143,30 @@ pub enum ServerEvent {
         chat_id: String,
         message_id: String,
     },
+    /// An assistant reply started streaming, finished or failed.
+    MessageStatusChanged {
+        chat_id: String,
+        message_id: String,
+        status: MessageStatus,
+    },
+    /// An assistant reply finished, with how long the provider took and, when the
+    /// provider reports it, token usage and cost.
+    CompletionFinished {
+        chat_id: String,
+        message_id: String,
+        model: String,
+        latency_ms: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        prompt_tokens: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        completion_tokens: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        estimated_cost: Option<f64>,
+    },
+    /// Sent only to the connection that asked for the message.
+    MessageResponse {
+        message: Message,
+    },
     InviteCreated {
         chat_id: String,
         invite: ChatInvite,
@@ -104,19 +179,164 @@ pub enum ServerEvent {
         chat_id: String,
         user_id: i64,
     },
+    OwnershipTransferred {
+        chat_id: String,
+        previous_owner_id: i64,
+        new_owner: ChatMember,
+    },
+    ReadReceipt {
+        chat_id: String,
+        user_id: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_read_message_id: Option<String>,
+        read_at: String,
+    },
+}
+
+impl ServerEvent {
+    /// The chat an event is scoped to, if any.
+    pub fn chat_id(&self) -> Option<&str> {
+        match self {
+            ServerEvent::Subscribed { chat_id }
+            | ServerEvent::Unsubscribed { chat_id }
+            | ServerEvent::Message { chat_id, .. }
+            | ServerEvent::Typing { chat_id, .. }
+            | ServerEvent::DraftSaved { chat_id, .. }
+            | ServerEvent::DraftCleared { chat_id }
+            | ServerEvent::ChatDeleted { chat_id }
+            | ServerEvent::MessageUpdated { chat_id, .. }
+            | ServerEvent::MessageDeleted { chat_id, .. }
+            | ServerEvent::MessageStatusChanged { chat_id, .. }
+            | ServerEvent::CompletionFinished { chat_id, .. }
+            | ServerEvent::InviteCreated { chat_id, .. }
+            | ServerEvent::MemberUpdated { chat_id, .. }
+            | ServerEvent::MemberRemoved { chat_id, .. }
+            | ServerEvent::OwnershipTransferred { chat_id, .. }
+            | ServerEvent::ReadReceipt { chat_id, .. } => Some(chat_id),
+            ServerEvent::ChatCreated { chat } | ServerEvent::ChatUpdated { chat } => {
+                Some(&chat.public_id)
+            }
+            ServerEvent::Hello { .. }
+            | ServerEvent::Error { .. }
+            | ServerEvent::MessageResponse { .. }
+            | ServerEvent::FolderCreated { .. }
+            | ServerEvent::FolderUpdated { .. }
+            | ServerEvent::FolderDeleted { .. } => None,
+        }
+    }
 }
 
-const DEFAULT_OAUTH_STATE_TTL: StdDuration = StdDuration::from_secs(600);
+/// Wire format for outbound events: the event's own fields plus an `event_id` and a
+/// server `timestamp`. One envelope is shared by every copy of a fanned-out event, so
+/// clients can drop duplicates and correlate deliveries by id.
+#[derive(Debug, Clone)]
+pub struct ServerEventEnvelope {
+    pub event_id: String,
+    pub timestamp: String,
+    pub event: ServerEvent,
+}
+
+impl ServerEventEnvelope {
+    pub fn new(event: ServerEvent) -> Self {
+        // Message events already carry their own timestamp; reuse it so the two agree.
+        let timestamp = match &event {
+            ServerEvent::Message { timestamp, .. } => timestamp.clone(),
+            _ => Utc::now().to_rfc3339(),
+        };
+
+        Self {
+            event_id: cuid2::create_id(),
+            timestamp,
+            event,
+        }
+    }
+}
+
+impl From<ServerEvent> for ServerEventEnvelope {
+    fn from(event: ServerEvent) -> Self {
+        Self::new(event)
+    }
+}
+
+impl Serialize for ServerEventEnvelope {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut value = serde_json::to_value(&self.event).map_err(serde::ser::Error::custom)?;
+        let fields = value
+            .as_object_mut()
+            .ok_or_else(|| serde::ser::Error::custom("server event must be an object"))?;
+        fields.insert("event_id".into(), self.event_id.clone().into());
+        fields.insert("timestamp".into(), self.timestamp.clone().into());
+        value.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ServerEventEnvelope {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        // Event variants ignore the envelope fields, so the same object deserializes as both.
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let field = |name: &'static str| {
+            value
+                .get(name)
+                .and_then(serde_json::Value::as_str)
+                .map(str::to_owned)
+                .ok_or_else(|| <D::Error as serde::de::Error>::missing_field(name))
+        };
+        let event_id = field("event_id")?;
+        let timestamp = field("timestamp")?;
+        let event = serde_json::from_value(value).map_err(serde::de::Error::custom)?;
+
+        Ok(Self {
+            event_id,
+            timestamp,
+            event,
+        })
+    }
+}
+
+/// Read-only replicas of the primary database, handed out round-robin.
+#[derive(Clone, Default)]
+struct ReadReplicas {
+    pools: Arc<Vec<SqlitePool>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl ReadReplicas {
+    fn next(&self) -> Option<&SqlitePool> {
+        if self.pools.is_empty() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.pools.len();
+        self.pools.get(index)
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: SqlitePool,
+    read_pool: Option<SqlitePool>,
+    replicas: ReadReplicas,
     orchestrator: Arc<Orchestrator>,
     authenticator: Authenticator,
     oauth_state: OAuthStateStore,
+    /// Whether `oauth_state` was handed in, and so outlives `with_config`.
+    oauth_state_supplied: bool,
     redis_conn: Option<ConnectionManager>,
-    pub chat_broadcasters: Arc<Mutex<HashMap<String, broadcast::Sender<ServerEvent>>>>,
-    pub user_broadcasters: Arc<Mutex<HashMap<i64, broadcast::Sender<ServerEvent>>>>,
+    event_bus: Option<Arc<dyn EventBus>>,
+    /// Identifies this instance's messages on the event bus.
+    instance_id: Arc<str>,
+    config: Arc<AppConfig>,
+    message_flood: Arc<MessageFloodGuard>,
+    guest_flood: Arc<MessageFloodGuard<String>>,
+    typing: Arc<TypingTracker>,
+    tasks: TaskRegistry,
+    pub chat_broadcasters: Arc<Mutex<HashMap<String, broadcast::Sender<ServerEventEnvelope>>>>,
+    pub user_broadcasters: Arc<Mutex<HashMap<i64, broadcast::Sender<ServerEventEnvelope>>>>,
 }
 
 impl AppState {
@@ -131,7 +351,17 @@ impl AppState {
             orchestrator,
             authenticator,
             oauth_state: OAuthStateStore::default(),
+            oauth_state_supplied: false,
             redis_conn,
+            event_bus: None,
+            instance_id: cuid2::create_id().into(),
+            read_pool: None,
+            replicas: ReadReplicas::default(),
+            config: Arc::new(AppConfig::default()),
+            message_flood: Arc::default(),
+            guest_flood: Arc::default(),
+            typing: Arc::default(),
+            tasks: TaskRegistry::new(),
             chat_broadcasters: Arc::new(Mutex::new(HashMap::new())),
             user_broadcasters: Arc::new(Mutex::new(HashMap::new())),
         }
@@ -149,12 +379,33 @@ impl AppState {
             orchestrator,
             authenticator,
             oauth_state,
+            oauth_state_supplied: true,
             redis_conn,
+            event_bus: None,
+            instance_id: cuid2::create_id().into(),
+            read_pool: None,
+            replicas: ReadReplicas::default(),
+            config: Arc::new(AppConfig::default()),
+            message_flood: Arc::default(),
+            guest_flood: Arc::default(),
+            typing: Arc::default(),
+            tasks: TaskRegistry::new(),
             chat_broadcasters: Arc::new(Mutex::new(HashMap::new())),
             user_broadcasters: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Replace the application configuration consulted by handlers and the router.
+    /// Unless a store was passed to [`AppState::with_oauth_store`], the OAuth state
+    /// store is rebuilt with the configured TTL, forgetting any flow issued beforehand.
+    pub fn with_config(mut self, config: AppConfig) -> Self {
+        if !self.oauth_state_supplied {
+            self.oauth_state = OAuthStateStore::from_config(&config.auth);
+        }
+        self.config = Arc::new(config);
+        self
+    }
+
     pub fn orchestrator(&self) -> &Orchestrator {
         &self.orchestrator
     }
@@ -167,6 +418,34 @@ impl AppState {
         &self.db_pool
     }
 
+    /// Pool for read-only queries against the local database: the separate read pool
+    /// when one was configured, otherwise `db_pool`. It sees every committed write, so
+    /// membership and permission checks belong here.
+    pub fn db_read_pool(&self) -> &SqlitePool {
+        self.read_pool.as_ref().unwrap_or(&self.db_pool)
+    }
+
+    /// Pool for bulk reads that can tolerate replication lag, such as lists and
+    /// exports. Cycles through the read replicas when there are any, otherwise the
+    /// same as [`AppState::db_read_pool`]. Never decide access from it.
+    pub fn db_replica_pool(&self) -> &SqlitePool {
+        self.replicas.next().unwrap_or_else(|| self.db_read_pool())
+    }
+
+    pub fn with_read_pool(mut self, pool: SqlitePool) -> Self {
+        self.read_pool = Some(pool);
+        self
+    }
+
+    /// Route [`AppState::db_replica_pool`] reads across `pools` round-robin.
+    pub fn with_replica_pools(mut self, pools: Vec<SqlitePool>) -> Self {
+        self.replicas = ReadReplicas {
+            pools: Arc::new(pools),
+            next: Arc::new(AtomicUsize::new(0)),
+        };
+        self
+    }
+
     pub fn oauth_state(&self) -> &OAuthStateStore {
         &self.oauth_state
     }
@@ -175,7 +454,95 @@ impl AppState {
         self.redis_conn.as_ref()
     }
 
-    pub async fn get_user_broadcaster(&self, user_id: i64) -> broadcast::Sender<ServerEvent> {
+    /// Also publish broadcast events on `bus` so other instances deliver them to their
+    /// connections. [`event_bus::run_event_bus`] handles the receiving side.
+    pub fn with_event_bus(mut self, bus: Arc<dyn EventBus>) -> Self {
+        self.event_bus = Some(bus);
+        self
+    }
+
+    pub fn event_bus(&self) -> Option<Arc<dyn EventBus>> {
+        self.event_bus.clone()
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    pub fn config(&self) -> &AppConfig {
+        &self.config
+    }
+
+    /// Background tasks of this instance, reported at `/health/tasks`.
+    pub fn tasks(&self) -> &TaskRegistry {
+        &self.tasks
+    }
+
+    /// Await a database call, warning with `tag` and the elapsed time when it takes at
+    /// least `database.slow_query_threshold_ms`. Bound values are never logged.
+    pub async fn timed_query<T>(&self, tag: &str, query: impl Future<Output = T>) -> T {
+        let Some(threshold_ms) = self.config.database.slow_query_threshold_ms else {
+            return query.await;
+        };
+        let started = Instant::now();
+        let result = query.await;
+        let elapsed = started.elapsed();
+        if elapsed >= StdDuration::from_millis(threshold_ms) {
+            tracing::warn!(
+                target: "switchboard::slow_query",
+                query = tag,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "slow database query"
+            );
+        }
+        result
+    }
+
+    /// Spend one message send for `user_id` in `chat_id`, or 429 when they are
+    /// sending faster than `[chat]` allows.
+    pub fn check_message_flood(&self, user_id: i64, chat_id: i64) -> Result<(), ApiError> {
+        let chat = &self.config.chat;
+        self.message_flood
+            .check(chat.message_burst, chat.messages_per_minute, (user_id, chat_id))
+            .map_err(ApiError::from)
+    }
+
+    /// Spend one guest request for `client`, or 429 when it is asking faster than
+    /// `[chat.guest]` allows.
+    pub fn check_guest_flood(&self, client: String) -> Result<(), ApiError> {
+        let guest = &self.config.chat.guest;
+        self.guest_flood
+            .check(guest.burst, guest.requests_per_minute, client)
+            .map_err(ApiError::from)
+    }
+
+    /// Who is typing in each chat on this instance.
+    pub fn typing(&self) -> &TypingTracker {
+        &self.typing
+    }
+
+    /// Error for a chat the caller is not a member of, per `chat.non_member_status`.
+    /// A chat that does not exist is a 404 under either policy.
+    pub async fn chat_access_denied(&self, chat_id: &str) -> ApiError {
+        if self.config.chat.non_member_status == NonMemberStatus::Forbidden {
+            let exists: Result<Option<i64>, _> =
+                sqlx::query_scalar("SELECT id FROM chats WHERE public_id = ?")
+                    .bind(chat_id)
+                    .fetch_optional(self.db_read_pool())
+                    .await;
+            match exists {
+                Ok(Some(_)) => return ApiError::forbidden("Not a member of this chat"),
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::error!("Failed to check chat existence: {}", e);
+                    return ApiError::internal_server_error("Failed to check chat membership");
+                }
+            }
+        }
+        ApiError::not_found("Chat not found")
+    }
+
+    pub async fn get_user_broadcaster(&self, user_id: i64) -> broadcast::Sender<ServerEventEnvelope> {
         let mut broadcasters = self.user_broadcasters.lock().await;
         broadcasters
             .entry(user_id)
@@ -184,15 +551,8 @@ impl AppState {
     }
 
     pub async fn broadcast_to_user(&self, user_id: i64, event: &ServerEvent) {
-        let sender = self.get_user_broadcaster(user_id).await;
-        if let Err(err) = sender.send(event.clone()) {
-            tracing::debug!(
-                "failed to deliver event {:?} to user {}: {}",
-                event,
-                user_id,
-                err
-            );
-        }
+        self.fan_out_to_user(user_id, &ServerEventEnvelope::new(event.clone()))
+            .await;
     }
 
     pub async fn broadcast_to_users(
@@ -200,22 +560,100 @@ impl AppState {
         user_ids: impl IntoIterator<Item = i64>,
         event: &ServerEvent,
     ) {
+        let envelope = ServerEventEnvelope::new(event.clone());
         for user_id in user_ids {
-            self.broadcast_to_user(user_id, event).await;
+            self.fan_out_to_user(user_id, &envelope).await;
         }
     }
 
     pub async fn broadcast_to_chat(&self, chat_public_id: &str, event: &ServerEvent) {
+        self.fan_out_to_chat(chat_public_id, &ServerEventEnvelope::new(event.clone()))
+            .await;
+    }
+
+    /// Deliver a chat-scoped event to the chat's subscribers and to each member's user
+    /// channel. A connection subscribed to the chat gets the chat-channel copy and skips
+    /// the user-channel one, so every chat-scoped user event must go through here.
+    pub async fn broadcast_to_chat_members(
+        &self,
+        chat_public_id: &str,
+        member_ids: impl IntoIterator<Item = i64>,
+        event: &ServerEvent,
+    ) {
+        let envelope = ServerEventEnvelope::new(event.clone());
+        self.fan_out_to_chat(chat_public_id, &envelope).await;
+        for user_id in member_ids {
+            self.fan_out_to_user(user_id, &envelope).await;
+        }
+    }
+
+    async fn fan_out_to_user(&self, user_id: i64, envelope: &ServerEventEnvelope) {
+        self.deliver_to_user(user_id, envelope).await;
+        self.publish(EventTarget::User(user_id), envelope).await;
+    }
+
+    async fn fan_out_to_chat(&self, chat_public_id: &str, envelope: &ServerEventEnvelope) {
+        self.deliver_to_chat(chat_public_id, envelope).await;
+        self.publish(EventTarget::Chat(chat_public_id.to_string()), envelope)
+            .await;
+    }
+
+    async fn publish(&self, target: EventTarget, envelope: &ServerEventEnvelope) {
+        let Some(bus) = &self.event_bus else {
+            return;
+        };
+        let payload = match event_bus::encode(&self.instance_id, envelope) {
+            Ok(payload) => payload,
+            Err(err) => {
+                tracing::error!("failed to encode event {:?} for the event bus: {}", envelope, err);
+                return;
+            }
+        };
+        if let Err(err) = bus.publish(target.channel(), payload).await {
+            tracing::warn!("failed to publish event to {:?}: {}", target, err);
+        }
+    }
+
+    /// Deliver to this instance's connections only.
+    pub(crate) async fn deliver_to_user(&self, user_id: i64, envelope: &ServerEventEnvelope) {
+        let sender = self.get_user_broadcaster(user_id).await;
+        if let Err(err) = sender.send(envelope.clone()) {
+            tracing::debug!(
+                "failed to deliver event {:?} to user {}: {}",
+                envelope,
+                user_id,
+                err
+            );
+        }
+    }
+
+    /// The chat's broadcast channel, created on first use.
+    pub(crate) async fn chat_broadcaster(
+        &self,
+        chat_public_id: &str,
+    ) -> broadcast::Sender<ServerEventEnvelope> {
+        let mut broadcasters = self.chat_broadcasters.lock().await;
+        broadcasters
+            .entry(chat_public_id.to_string())
+            .or_insert_with(|| broadcast::channel(100).0)
+            .clone()
+    }
+
+    pub(crate) async fn deliver_to_chat(
+        &self,
+        chat_public_id: &str,
+        envelope: &ServerEventEnvelope,
+    ) {
         let broadcaster = {
             let broadcasters = self.chat_broadcasters.lock().await;
             broadcasters.get(chat_public_id).cloned()
         };
 
         if let Some(sender) = broadcaster {
-            if let Err(err) = sender.send(event.clone()) {
+            if let Err(err) = sender.send(envelope.clone()) {
                 tracing::debug!(
                     "failed to deliver chat event {:?} for chat {}: {}",
-                    event,
+                    envelope,
                     chat_public_id,
                     err
                 );
@@ -278,6 +716,7 @@ impl AppState {
             token: token.to_string(),
             user_id: 1,
             expires_at,
+            scopes: None,
         };
 
         Ok((user, session))
@@ -346,6 +785,14 @@ impl OAuthStateStore {
         }
     }
 
+    pub fn from_config(config: &AuthConfig) -> Self {
+        Self::new(StdDuration::from_secs(config.oauth_state_ttl_seconds))
+    }
+
+    pub fn ttl(&self) -> StdDuration {
+        self.ttl
+    }
+
     pub async fn issue(&self) -> String {
         let state = Self::random_state();
         self.store(state.clone()).await;
@@ -380,7 +827,7 @@ impl OAuthStateStore {
 
 impl Default for OAuthStateStore {
     fn default() -> Self {
-        Self::new(DEFAULT_OAUTH_STATE_TTL)
+        Self::from_config(&AuthConfig::default())
     }
 }
 
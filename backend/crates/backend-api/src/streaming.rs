@@ -0,0 +1,181 @@
+//! Saving assistant replies while their completion is still being generated, so a
+//! crash or restart loses at most the text since the last checkpoint.
+
+use std::time::{Duration, Instant};
+
+use sqlx::SqlitePool;
+use switchboard_config::ChatConfig;
+
+use crate::routes::models::MessageStatus;
+
+/// An assistant message whose content is still arriving. The row is inserted as
+/// `streaming` and rewritten every `stream_checkpoint_chunks` chunks or
+/// `stream_checkpoint_interval_ms`, whichever comes first.
+pub struct StreamingMessage {
+    pool: SqlitePool,
+    id: i64,
+    public_id: String,
+    created_at: String,
+    content: String,
+    unsaved_chunks: usize,
+    last_checkpoint: Instant,
+    checkpoint_chunks: usize,
+    checkpoint_interval: Duration,
+}
+
+impl StreamingMessage {
+    /// Insert an empty `streaming` assistant message into `chat_id`.
+    pub async fn start(
+        pool: &SqlitePool,
+        config: &ChatConfig,
+        chat_id: i64,
+        user_id: i64,
+        public_id: String,
+        model: Option<&str>,
+    ) -> Result<Self, sqlx::Error> {
+        let created_at = chrono::Utc::now().to_rfc3339();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO messages (public_id, chat_id, user_id, content, message_type, role, model, status, created_at, updated_at)
+            VALUES (?, ?, ?, '', 'text', 'assistant', ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&public_id)
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(model)
+        .bind(MessageStatus::Streaming.as_str())
+        .bind(&created_at)
+        .bind(&created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(Self {
+            pool: pool.clone(),
+            id: result.last_insert_rowid(),
+            public_id,
+            created_at,
+            content: String::new(),
+            unsaved_chunks: 0,
+            last_checkpoint: Instant::now(),
+            checkpoint_chunks: config.stream_checkpoint_chunks,
+            checkpoint_interval: Duration::from_millis(config.stream_checkpoint_interval_ms),
+        })
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn public_id(&self) -> &str {
+        &self.public_id
+    }
+
+    pub fn created_at(&self) -> &str {
+        &self.created_at
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Append a chunk without writing it.
+    pub fn append(&mut self, chunk: &str) {
+        self.content.push_str(chunk);
+        self.unsaved_chunks += 1;
+    }
+
+    /// Append a chunk, checkpointing if enough chunks or time have accumulated.
+    pub async fn push(&mut self, chunk: &str) -> Result<(), sqlx::Error> {
+        self.append(chunk);
+        if self.unsaved_chunks >= self.checkpoint_chunks
+            || self.last_checkpoint.elapsed() >= self.checkpoint_interval
+        {
+            self.checkpoint().await?;
+        }
+        Ok(())
+    }
+
+    /// Write the content received so far without changing the status.
+    pub async fn checkpoint(&mut self) -> Result<(), sqlx::Error> {
+        self.save(MessageStatus::Streaming, None, None).await?;
+        self.unsaved_chunks = 0;
+        self.last_checkpoint = Instant::now();
+        Ok(())
+    }
+
+    /// Save the final content, token usage and cost and mark the message `complete`.
+    pub async fn finish(
+        self,
+        usage: Option<(i64, i64)>,
+        estimated_cost: Option<f64>,
+    ) -> Result<(), sqlx::Error> {
+        self.save(MessageStatus::Complete, usage, estimated_cost).await
+    }
+
+    /// Save what was received and mark the message `interrupted`, for a completion
+    /// that failed part way.
+    pub async fn interrupt(self) -> Result<(), sqlx::Error> {
+        self.save(MessageStatus::Interrupted, None, None).await
+    }
+
+    /// Save what was received and mark the message `error`, for a completion the
+    /// provider refused or failed.
+    pub async fn fail(self) -> Result<(), sqlx::Error> {
+        self.save(MessageStatus::Error, None, None).await
+    }
+
+    async fn save(
+        &self,
+        status: MessageStatus,
+        usage: Option<(i64, i64)>,
+        estimated_cost: Option<f64>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE messages
+            SET content = ?, status = ?,
+                prompt_tokens = COALESCE(?, prompt_tokens),
+                completion_tokens = COALESCE(?, completion_tokens),
+                estimated_cost = COALESCE(?, estimated_cost),
+                updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&self.content)
+        .bind(status.as_str())
+        .bind(usage.map(|(prompt, _)| prompt))
+        .bind(usage.map(|(_, completion)| completion))
+        .bind(estimated_cost)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(self.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Mark messages still `streaming` as `interrupted` once nothing has been saved to
+/// them for `stale_after`. Run at startup: a reply whose process is gone stops being
+/// checkpointed, while replies other instances are still streaming keep moving their
+/// `updated_at` and are left alone. Returns the number of messages marked.
+pub async fn mark_interrupted_messages(
+    pool: &SqlitePool,
+    stale_after: Duration,
+) -> Result<u64, sqlx::Error> {
+    let stale_after_days = stale_after.as_secs_f64() / 86_400.0;
+    let result = sqlx::query(
+        r#"
+        UPDATE messages SET status = ?, updated_at = ?
+        WHERE status = ? AND julianday(updated_at) <= julianday('now') - ?
+        "#,
+    )
+    .bind(MessageStatus::Interrupted.as_str())
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(MessageStatus::Streaming.as_str())
+    .bind(stale_after_days)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
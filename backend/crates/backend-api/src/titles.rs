@@ -0,0 +1,91 @@
+//! Naming untitled chats after their first user message, when
+//! `orchestrator.title_generation` is enabled.
+
+use crate::{routes::models::Chat, AppState, ServerEvent};
+
+/// Give the chat a generated title if it is still untitled and `first_message` is its
+/// only user message, then broadcast `ChatUpdated` to its members. A rename that
+/// lands while the title is being generated wins. Returns the updated chat, or
+/// `None` when the chat was left alone.
+pub async fn title_untitled_chat(
+    state: &AppState,
+    chat_db_id: i64,
+    first_message: &str,
+) -> Result<Option<Chat>, sqlx::Error> {
+    let config = &state.config().orchestrator.title_generation;
+    if !config.enabled || first_message.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let current: Option<String> = sqlx::query_scalar("SELECT title FROM chats WHERE id = ?")
+        .bind(chat_db_id)
+        .fetch_optional(state.db_pool())
+        .await?;
+    let Some(current) = current.filter(|title| config.is_untitled(title)) else {
+        return Ok(None);
+    };
+
+    let user_messages: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE chat_id = ? AND role = 'user'")
+            .bind(chat_db_id)
+            .fetch_one(state.db_pool())
+            .await?;
+    if user_messages != 1 {
+        return Ok(None);
+    }
+
+    let title = state.orchestrator().generate_chat_title(first_message).await;
+    let result = sqlx::query(
+        r#"
+        UPDATE chats
+        SET title = ?, version = version + 1, updated_at = ?
+        WHERE id = ? AND title = ?
+        "#,
+    )
+    .bind(&title)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(chat_db_id)
+    .bind(&current)
+    .execute(state.db_pool())
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    let chat = sqlx::query_as::<_, Chat>(
+        r#"
+        SELECT id, public_id, user_id, folder_id, title, chat_type,
+               message_retention_days, default_model, version, created_at, updated_at
+        FROM chats
+        WHERE id = ?
+        "#,
+    )
+    .bind(chat_db_id)
+    .fetch_one(state.db_pool())
+    .await?;
+
+    let member_ids: Vec<i64> =
+        sqlx::query_scalar("SELECT user_id FROM chat_members WHERE chat_id = ?")
+            .bind(chat_db_id)
+            .fetch_all(state.db_pool())
+            .await?;
+    let event = ServerEvent::ChatUpdated { chat: chat.clone() };
+    state.broadcast_to_chat_members(&chat.public_id, member_ids, &event).await;
+
+    Ok(Some(chat))
+}
+
+/// Run [`title_untitled_chat`] in the background so the message that triggered it
+/// is not held up by the model call.
+pub fn spawn_title_untitled_chat(state: &AppState, chat_db_id: i64, first_message: &str) {
+    if !state.config().orchestrator.title_generation.enabled {
+        return;
+    }
+    let state = state.clone();
+    let first_message = first_message.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = title_untitled_chat(&state, chat_db_id, &first_message).await {
+            tracing::error!("Failed to title chat {}: {}", chat_db_id, e);
+        }
+    });
+}
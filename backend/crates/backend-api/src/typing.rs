@@ -0,0 +1,56 @@
+//! Who is typing in each chat, as last reported over the websocket, so a client that
+//! has just joined can fetch a snapshot instead of waiting for the next event. Only
+//! this instance's connections are tracked.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long a typing report lasts. Clients repeat `is_typing: true` while the user
+/// keeps typing, so a user whose client stops reporting (or disconnects) drops out.
+pub const TYPING_EXPIRY: Duration = Duration::from_secs(6);
+
+/// The users typing in each chat, keyed by chat and user database id, with the
+/// time each was last reported typing.
+#[derive(Debug, Default)]
+pub struct TypingTracker {
+    chats: Mutex<HashMap<i64, HashMap<i64, Instant>>>,
+}
+
+impl TypingTracker {
+    /// Record that `user_id` started or stopped typing in `chat_id`.
+    pub fn set(&self, chat_id: i64, user_id: i64, is_typing: bool) {
+        let mut chats = self.chats.lock().unwrap_or_else(|error| error.into_inner());
+        if is_typing {
+            chats
+                .entry(chat_id)
+                .or_default()
+                .insert(user_id, Instant::now());
+        } else if let Some(users) = chats.get_mut(&chat_id) {
+            users.remove(&user_id);
+            if users.is_empty() {
+                chats.remove(&chat_id);
+            }
+        }
+    }
+
+    /// The users currently typing in `chat_id`, in ascending id order. Expired
+    /// reports are dropped along the way.
+    pub fn typing_users(&self, chat_id: i64) -> Vec<i64> {
+        let mut chats = self.chats.lock().unwrap_or_else(|error| error.into_inner());
+        let Some(users) = chats.get_mut(&chat_id) else {
+            return Vec::new();
+        };
+
+        let now = Instant::now();
+        users.retain(|_, reported_at| now.duration_since(*reported_at) < TYPING_EXPIRY);
+        let mut user_ids: Vec<i64> = users.keys().copied().collect();
+        if user_ids.is_empty() {
+            chats.remove(&chat_id);
+        }
+        user_ids.sort_unstable();
+        user_ids
+    }
+}
@@ -1,7 +1,38 @@
-use axum::http::{header::AUTHORIZATION, HeaderMap};
+use std::{future::Future, time::Duration};
 
-use crate::ApiError;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{
+        header::{AUTHORIZATION, IF_MATCH},
+        request::Parts,
+        HeaderMap,
+    },
+};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use switchboard_auth::User;
 
+use crate::{ApiError, AppState};
+
+/// The authenticated caller. Extracting it checks the bearer token and rejects the
+/// request with 401 before the handler runs.
+#[derive(Debug, Clone)]
+pub struct AuthUser(pub User);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, ApiError> {
+        let token = require_bearer(&parts.headers)?;
+        let (user, _) = state.authenticate(&token).await?;
+        Ok(Self(user))
+    }
+}
+
+/// The bearer token from the `Authorization` header. HTTP routes authenticate from this
+/// header alone: cookies are never read, so a request carrying both a session cookie and
+/// a header is always the header's user, whichever session the cookie names.
 pub fn require_bearer(headers: &HeaderMap) -> Result<String, ApiError> {
     let value = headers
         .get(AUTHORIZATION)
@@ -22,6 +53,116 @@ pub fn require_bearer(headers: &HeaderMap) -> Result<String, ApiError> {
     Ok(token.to_string())
 }
 
+/// The version an update is based on: the body's `version`, or else an `If-Match`
+/// header holding the version (`3`, `"3"` or `W/"3"`). `None` skips the check.
+pub fn expected_version(
+    headers: &HeaderMap,
+    body_version: Option<i64>,
+) -> Result<Option<i64>, ApiError> {
+    if body_version.is_some() {
+        return Ok(body_version);
+    }
+    let Some(value) = headers.get(IF_MATCH) else {
+        return Ok(None);
+    };
+
+    let invalid = || ApiError::bad_request("If-Match must be a version number");
+    let value = value.to_str().map_err(|_| invalid())?.trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    let value = value.trim_start_matches("W/").trim_matches('"');
+    value.parse().map(Some).map_err(|_| invalid())
+}
+
+const SQLITE_BUSY: i32 = 5;
+const SQLITE_LOCKED: i32 = 6;
+const BUSY_RETRY_ATTEMPTS: u32 = 5;
+const BUSY_RETRY_BACKOFF: Duration = Duration::from_millis(20);
+
+/// Errors `retry_on_busy` can recognise as SQLite reporting the database busy or
+/// locked.
+pub trait BusyError: std::fmt::Debug {
+    fn is_busy(&self) -> bool;
+}
+
+impl BusyError for sqlx::Error {
+    fn is_busy(&self) -> bool {
+        is_busy(self)
+    }
+}
+
+impl BusyError for ApiError {
+    fn is_busy(&self) -> bool {
+        self.database_busy
+    }
+}
+
+/// Run a write, retrying with linear backoff while SQLite reports the database busy or
+/// locked. Other errors, and the busy error from the final attempt, are returned as-is.
+/// A whole `with_transaction` call can be retried as long as its busy failures are
+/// marked with [`ApiError::busy_from`].
+pub async fn retry_on_busy<T, E, F, Fut>(mut operation: F) -> Result<T, E>
+where
+    E: BusyError,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(error) if attempt < BUSY_RETRY_ATTEMPTS && error.is_busy() => {
+                tracing::debug!(attempt, "database busy, retrying write: {:?}", error);
+                tokio::time::sleep(BUSY_RETRY_BACKOFF * attempt).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Run `operation` in a transaction, committing when it returns `Ok`. An error rolls
+/// back everything it wrote. `failure` is the message of the 500 returned when the
+/// transaction cannot be started or committed.
+pub async fn with_transaction<T>(
+    pool: &SqlitePool,
+    failure: &str,
+    operation: impl AsyncFnOnce(&mut Transaction<'static, Sqlite>) -> Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let mut tx = pool.begin().await.map_err(|e| {
+        tracing::error!("Failed to begin transaction: {}", e);
+        ApiError::internal_server_error(failure).busy_from(&e)
+    })?;
+
+    match operation(&mut tx).await {
+        Ok(value) => {
+            tx.commit().await.map_err(|e| {
+                tracing::error!("Failed to commit transaction: {}", e);
+                ApiError::internal_server_error(failure).busy_from(&e)
+            })?;
+            Ok(value)
+        }
+        Err(error) => {
+            if let Err(e) = tx.rollback().await {
+                tracing::error!("Failed to roll back transaction: {}", e);
+            }
+            Err(error)
+        }
+    }
+}
+
+pub(crate) fn is_busy(error: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(error) = error else {
+        return false;
+    };
+    // Extended result codes (e.g. SQLITE_BUSY_SNAPSHOT) keep the primary code in the low byte.
+    let primary = error
+        .code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .map(|code| code & 0xff);
+    matches!(primary, Some(SQLITE_BUSY | SQLITE_LOCKED))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
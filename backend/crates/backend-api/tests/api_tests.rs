@@ -14,7 +14,7 @@ use axum::{
             ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_HEADERS,
             ACCESS_CONTROL_REQUEST_METHOD, AUTHORIZATION, CONTENT_TYPE, ORIGIN,
         },
-        Method, Request, StatusCode,
+        HeaderMap, HeaderValue, Method, Request, StatusCode,
     },
     response::IntoResponse,
     Router,
@@ -28,8 +28,9 @@ use sqlx::{
 use switchboard_auth::Authenticator;
 use switchboard_backend_api::{
     build_router, routes, ApiError, AppState, ClientEvent, OAuthStateStore, ServerEvent,
+    ServerEventEnvelope,
 };
-use switchboard_config::AppConfig;
+use switchboard_config::{AppConfig, NonMemberStatus};
 use switchboard_orchestrator::Orchestrator;
 use tempfile::TempDir;
 use tokio::sync::broadcast;
@@ -52,6 +53,11 @@ impl TestContext {
     }
 
     async fn with_config(config: AppConfig) -> TestResult<Self> {
+        let orchestrator = Orchestrator::new(&config);
+        Self::with_orchestrator(config, orchestrator).await
+    }
+
+    async fn with_orchestrator(config: AppConfig, orchestrator: Orchestrator) -> TestResult<Self> {
         let temp_dir = TempDir::new()?;
         let db_path = temp_dir.path().join("backend_api.sqlite");
         let db_url = format!("sqlite://{}", db_path.display());
@@ -67,15 +73,10 @@ impl TestContext {
 
         MIGRATOR.run(&pool).await?;
 
-        let orchestrator = Arc::new(Orchestrator::new(&config));
+        let orchestrator = Arc::new(orchestrator);
         let authenticator = Authenticator::new(pool.clone(), config.auth.clone());
-        let state = AppState::with_oauth_store(
-            pool.clone(),
-            orchestrator,
-            authenticator,
-            OAuthStateStore::default(),
-            None,
-        );
+        let state =
+            AppState::new(pool.clone(), orchestrator, authenticator, None).with_config(config);
 
         Ok(Self {
             _temp_dir: temp_dir,
@@ -88,6 +89,10 @@ impl TestContext {
         let mut config = AppConfig::default();
         config.auth.github.client_id = Some("test-client-id".into());
         config.auth.github.client_secret = Some("test-client-secret".into());
+        config.auth.allowed_redirect_uris = vec![
+            "https://example.com/callback".into(),
+            "https://preview.example.com/auth/*".into(),
+        ];
         Self::with_config(config).await
     }
 
@@ -237,6 +242,15 @@ impl TestContext {
     }
 }
 
+fn bearer_headers(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {token}")).expect("valid bearer header"),
+    );
+    headers
+}
+
 mod router_tests {
     use super::*;
 
@@ -342,13 +356,37 @@ mod router_tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn oversized_request_body_is_rejected_with_413_envelope() -> TestResult {
+        let mut config = AppConfig::default();
+        config.http.max_body_bytes = 64;
+        let ctx = TestContext::with_config(config).await?;
+
+        let payload = serde_json::json!({ "name": "x".repeat(256) });
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/folders")
+            .header(AUTHORIZATION, "Bearer test-token")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&payload)?))?;
+
+        let response = ctx.router().oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["error"], "request body too large");
+
+        Ok(())
+    }
 }
 
 mod error_handling_tests {
     use super::*;
     use anyhow::anyhow;
     use switchboard_auth::AuthError;
-    use switchboard_orchestrator::OrchestratorError;
+    use switchboard_orchestrator::{OrchestratorError, RateLimitInfo};
 
     #[tokio::test]
     async fn api_error_into_response_sets_status_and_body() -> TestResult {
@@ -372,6 +410,17 @@ mod error_handling_tests {
             (AuthError::SessionExpired, StatusCode::UNAUTHORIZED),
             (AuthError::InvalidCredentials, StatusCode::UNAUTHORIZED),
             (AuthError::UserExists, StatusCode::BAD_REQUEST),
+            (
+                AuthError::IdentityAlreadyLinked("github"),
+                StatusCode::CONFLICT,
+            ),
+            (AuthError::LastLoginMethod, StatusCode::CONFLICT),
+            (AuthError::InvalidApiKey, StatusCode::UNAUTHORIZED),
+            (AuthError::ApiKeyNotFound, StatusCode::NOT_FOUND),
+            (
+                AuthError::IdentityNotLinked("github".into()),
+                StatusCode::NOT_FOUND,
+            ),
             (
                 AuthError::GithubOauth(anyhow!("oauth failed")),
                 StatusCode::BAD_GATEWAY,
@@ -400,6 +449,66 @@ mod error_handling_tests {
         let other: ApiError = OrchestratorError::ProviderIndexMissing.into();
         assert_eq!(other.status, StatusCode::INTERNAL_SERVER_ERROR);
     }
+
+    #[test]
+    fn api_error_forwards_upstream_rate_limit_headers() {
+        let rate_limited: ApiError = OrchestratorError::ProviderRateLimited(RateLimitInfo {
+            limit: Some(20),
+            remaining: Some(0),
+            reset: None,
+            retry_after_seconds: Some(12),
+        })
+        .into();
+        assert_eq!(rate_limited.status, StatusCode::TOO_MANY_REQUESTS);
+
+        let response = rate_limited.into_response();
+        let headers = response.headers();
+        assert_eq!(headers["x-ratelimit-limit"], "20");
+        assert_eq!(headers["x-ratelimit-remaining"], "0");
+        assert_eq!(headers["retry-after"], "12");
+        assert!(!headers.contains_key("x-ratelimit-reset"));
+    }
+
+    #[tokio::test]
+    async fn api_error_reports_classified_provider_failures() -> TestResult {
+        let cases = [
+            (
+                OrchestratorError::ContextLengthExceeded("too long".into()),
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "context_length_exceeded",
+            ),
+            (
+                OrchestratorError::ModelNotFound("openai/gpt-9".into()),
+                StatusCode::BAD_REQUEST,
+                "model_not_found",
+            ),
+            (
+                OrchestratorError::ProviderAuthFailed("invalid key".into()),
+                StatusCode::BAD_GATEWAY,
+                "auth_failed",
+            ),
+            (
+                OrchestratorError::ProviderServerError("upstream down".into()),
+                StatusCode::BAD_GATEWAY,
+                "server_error",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            let response = ApiError::from(error).into_response();
+            assert_eq!(response.status(), status);
+            let body = response.into_body().collect().await?.to_bytes();
+            let payload: Value = serde_json::from_slice(&body)?;
+            assert_eq!(payload["code"], code);
+        }
+
+        let response = ApiError::bad_request("missing payload").into_response();
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert!(payload.get("code").is_none());
+
+        Ok(())
+    }
 }
 
 mod app_state_tests {
@@ -427,6 +536,74 @@ mod app_state_tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn read_queries_cycle_through_replicas() -> TestResult {
+        let ctx = TestContext::new().await?;
+        let mut replicas = Vec::new();
+        for version in [1, 2] {
+            // Each in-memory pool is its own database; tag it to tell them apart
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await?;
+            sqlx::query(&format!("PRAGMA user_version = {version}"))
+                .execute(&pool)
+                .await?;
+            replicas.push(pool);
+        }
+        let state = ctx.state().with_replica_pools(replicas);
+
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+                .fetch_one(state.db_replica_pool())
+                .await?;
+            seen.push(version);
+        }
+        assert_eq!(seen, vec![1, 2, 1, 2]);
+
+        // Writes and lag-sensitive reads stay on the primary
+        for pool in [state.db_pool(), state.db_read_pool()] {
+            let primary: i64 = sqlx::query_scalar("PRAGMA user_version")
+                .fetch_one(pool)
+                .await?;
+            assert_eq!(primary, 0);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn membership_is_checked_on_the_primary_not_a_lagging_replica() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        // A replica that has not caught up with the chat or its membership yet
+        let replica = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        MIGRATOR.run(&replica).await?;
+        let state = ctx.state().with_replica_pools(vec![replica]);
+
+        let chat_id = ctx.create_chat("chat-fresh", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let response = build_router(state)
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chats/chat-fresh/messages")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["messages"], serde_json::json!([]));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn broadcast_to_chat_delivers_events_to_existing_subscribers() -> TestResult {
         let ctx = TestContext::new().await?;
@@ -443,7 +620,7 @@ mod app_state_tests {
         state.broadcast_to_chat("chat-1", &event).await;
 
         let received = receiver.recv().await?;
-        match received {
+        match received.event {
             ServerEvent::ChatDeleted { chat_id } => assert_eq!(chat_id, "chat-1"),
             other => panic!("unexpected event: {:?}", other),
         }
@@ -471,8 +648,9 @@ mod app_state_tests {
 
         let first = rx_a.recv().await?;
         let second = rx_b.recv().await?;
-        assert!(matches!(first, ServerEvent::FolderDeleted { .. }));
-        assert!(matches!(second, ServerEvent::FolderDeleted { .. }));
+        assert!(matches!(first.event, ServerEvent::FolderDeleted { .. }));
+        assert!(matches!(second.event, ServerEvent::FolderDeleted { .. }));
+        assert_eq!(first.event_id, second.event_id, "fan-out shares one envelope");
 
         Ok(())
     }
@@ -502,6 +680,50 @@ mod app_state_tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn oauth_state_ttl_follows_auth_config() -> TestResult {
+        let mut config = AppConfig::default();
+        config.auth.oauth_state_ttl_seconds = 1;
+        let ctx = TestContext::with_config(config).await?;
+        let state = ctx.state();
+        assert_eq!(state.oauth_state().ttl(), Duration::from_secs(1));
+
+        let fresh = state.oauth_state().issue().await;
+        let stale = state.oauth_state().issue().await;
+        assert!(state.oauth_state().consume(&fresh).await);
+
+        sleep(Duration::from_millis(1_100)).await;
+        assert!(
+            !state.oauth_state().consume(&stale).await,
+            "state should expire after the configured TTL"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn with_config_keeps_a_supplied_oauth_store() -> TestResult {
+        let ctx = TestContext::new().await?;
+        let store = OAuthStateStore::new(Duration::from_secs(42));
+        let issued = store.issue().await;
+
+        let mut config = AppConfig::default();
+        config.auth.oauth_state_ttl_seconds = 1;
+        let state = AppState::with_oauth_store(
+            ctx.pool().clone(),
+            Arc::new(Orchestrator::new(&config)),
+            Authenticator::new(ctx.pool().clone(), config.auth.clone()),
+            store,
+            None,
+        )
+        .with_config(config);
+
+        assert_eq!(state.oauth_state().ttl(), Duration::from_secs(42));
+        assert!(state.oauth_state().consume(&issued).await);
+
+        Ok(())
+    }
+
     #[test]
     fn deserialize_models_accepts_scalar_and_array_payloads() {
         let single = serde_json::json!({
@@ -532,35 +754,109 @@ mod app_state_tests {
             other => panic!("unexpected event: {:?}", other),
         }
     }
+
+    #[test]
+    fn server_event_envelope_serializes_flat_with_metadata() -> TestResult {
+        let envelope = ServerEventEnvelope::new(ServerEvent::ChatDeleted {
+            chat_id: "chat-1".into(),
+        });
+
+        let json = serde_json::to_value(&envelope)?;
+        assert_eq!(json["type"], "chat_deleted");
+        assert_eq!(json["chat_id"], "chat-1");
+        assert_eq!(json["event_id"], envelope.event_id.as_str());
+        assert_eq!(json["timestamp"], envelope.timestamp.as_str());
+
+        let parsed: ServerEventEnvelope = serde_json::from_value(json)?;
+        assert_eq!(parsed.event_id, envelope.event_id);
+        assert_eq!(parsed.timestamp, envelope.timestamp);
+        assert!(matches!(
+            parsed.event,
+            ServerEvent::ChatDeleted { chat_id } if chat_id == "chat-1"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn completion_finished_event_omits_missing_usage() -> TestResult {
+        let event = ServerEvent::CompletionFinished {
+            chat_id: "chat-1".into(),
+            message_id: "msg-1".into(),
+            model: "gpt-4o".into(),
+            latency_ms: 840,
+            prompt_tokens: Some(120),
+            completion_tokens: None,
+            estimated_cost: None,
+        };
+        assert_eq!(event.chat_id(), Some("chat-1"));
+
+        let json = serde_json::to_value(ServerEventEnvelope::new(event))?;
+        assert_eq!(json["type"], "completion_finished");
+        assert_eq!(json["message_id"], "msg-1");
+        assert_eq!(json["model"], "gpt-4o");
+        assert_eq!(json["latency_ms"], 840);
+        assert_eq!(json["prompt_tokens"], 120);
+        assert!(json.get("completion_tokens").is_none());
+        assert!(json.get("estimated_cost").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn server_event_envelope_reuses_message_timestamp() -> TestResult {
+        let envelope = ServerEventEnvelope::new(ServerEvent::Message {
+            chat_id: "chat-1".into(),
+            message_id: "msg-1".into(),
+            user_id: 1,
+            content: "hello".into(),
+            model: None,
+            timestamp: "2024-01-01T00:00:00+00:00".into(),
+            message_type: "text".into(),
+        });
+        assert_eq!(envelope.timestamp, "2024-01-01T00:00:00+00:00");
+
+        let parsed: ServerEventEnvelope = serde_json::from_value(serde_json::to_value(&envelope)?)?;
+        match parsed.event {
+            ServerEvent::Message { timestamp, .. } => {
+                assert_eq!(timestamp, "2024-01-01T00:00:00+00:00")
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn server_event_envelope_requires_event_id() {
+        let payload = serde_json::json!({
+            "type": "chat_deleted",
+            "chat_id": "chat-1",
+            "timestamp": "2024-01-01T00:00:00+00:00"
+        });
+        assert!(serde_json::from_value::<ServerEventEnvelope>(payload).is_err());
+    }
 }
 
 mod message_route_tests {
     use super::*;
     use axum::{
         extract::{Path, State},
-        http::{header::AUTHORIZATION, HeaderMap, HeaderValue, StatusCode},
+        http::{header::AUTHORIZATION, StatusCode},
         Json,
     };
     use switchboard_backend_api::routes::{
         messages::{
             create_message, delete_message, get_message_edits, get_messages, update_message,
+            GetMessageEditsQuery, GetMessagesQuery,
         },
-        models::{CreateMessageRequest, MessageResponse, UpdateMessageRequest},
+        models::{CreateMessageRequest, DiffSegment, MessageResponse, UpdateMessageRequest},
     };
     use tokio::{
         sync::broadcast,
         time::{timeout, Duration},
     };
 
-    fn bearer_headers(token: &str) -> HeaderMap {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {token}")).expect("valid bearer header"),
-        );
-        headers
-    }
-
     fn expect_ok<T>(result: Result<T, ApiError>, context: &str) -> TestResult<T> {
         result.map_err(|err| anyhow!("{context}: {} ({})", err.message, err.status))
     }
@@ -581,6 +877,7 @@ mod message_route_tests {
                 State(ctx.state()),
                 Path(chat_public_id.to_string()),
                 bearer_headers("test-token"),
+                Query(GetMessagesQuery::default()),
             )
             .await,
             "get_messages for member",
@@ -605,6 +902,59 @@ mod message_route_tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn get_messages_includes_assistant_usage_on_request() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_public_id = "chat-usage";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-question", "question").await?;
+        ctx.insert_message(chat_id, 1, "msg-answer", "answer").await?;
+        sqlx::query(
+            r#"
+            UPDATE messages
+            SET role = 'assistant', prompt_tokens = 120, completion_tokens = 30,
+                estimated_cost = 0.0015
+            WHERE public_id = 'msg-answer'
+            "#,
+        )
+        .execute(ctx.pool())
+        .await?;
+
+        let list = |include_usage| {
+            get_messages(
+                State(ctx.state()),
+                Path(chat_public_id.to_string()),
+                bearer_headers("test-token"),
+                Query(GetMessagesQuery {
+                    include_usage,
+                    ..GetMessagesQuery::default()
+                }),
+            )
+        };
+
+        let Json(response) = expect_ok(list(true).await, "get_messages with usage")?;
+        let usage: Vec<_> = response
+            .messages
+            .iter()
+            .map(|message| message.usage.clone().expect("usage requested"))
+            .collect();
+        assert_eq!(usage[0].prompt_tokens, None);
+        assert_eq!(usage[0].estimated_cost, None);
+        assert_eq!(usage[1].prompt_tokens, Some(120));
+        assert_eq!(usage[1].completion_tokens, Some(30));
+        assert_eq!(usage[1].estimated_cost, Some(0.0015));
+
+        let Json(response) = expect_ok(list(false).await, "get_messages without usage")?;
+        assert!(response.messages.iter().all(|message| message.usage.is_none()));
+        let json = serde_json::to_value(&response.messages[1])?;
+        assert!(json.get("usage").is_none());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn get_messages_rejects_non_members() -> TestResult {
         let ctx = TestContext::new().await?;
@@ -619,11 +969,74 @@ mod message_route_tests {
             State(ctx.state()),
             Path(chat_public_id.to_string()),
             bearer_headers("test-token"),
+            Query(GetMessagesQuery::default()),
         )
         .await
         .expect_err("non-members should not see chat messages");
 
-        assert_eq!(error.status, StatusCode::FORBIDDEN);
+        assert_eq!(error.status, StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_messages_filters_by_role() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_public_id = "chat-roles";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-user-1", "question").await?;
+        ctx.insert_message(chat_id, 1, "msg-assistant-1", "answer").await?;
+        ctx.insert_message(chat_id, 1, "msg-user-2", "follow-up").await?;
+        ctx.insert_message(chat_id, 1, "msg-assistant-2", "second answer")
+            .await?;
+        sqlx::query(
+            "UPDATE messages SET role = 'assistant' WHERE public_id LIKE 'msg-assistant-%'",
+        )
+        .execute(ctx.pool())
+        .await?;
+
+        let Json(response) = expect_ok(
+            get_messages(
+                State(ctx.state()),
+                Path(chat_public_id.to_string()),
+                bearer_headers("test-token"),
+                Query(GetMessagesQuery {
+                    role: Some("assistant".to_string()),
+                    message_type: None,
+                    include_usage: false,
+                }),
+            )
+            .await,
+            "get_messages filtered by role",
+        )?;
+
+        let contents: Vec<_> = response
+            .messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["answer", "second answer"]);
+        assert!(response
+            .messages
+            .iter()
+            .all(|message| message.role == "assistant"));
+
+        let error = get_messages(
+            State(ctx.state()),
+            Path(chat_public_id.to_string()),
+            bearer_headers("test-token"),
+            Query(GetMessagesQuery {
+                role: Some("robot".to_string()),
+                message_type: None,
+                include_usage: false,
+            }),
+        )
+        .await
+        .expect_err("unknown role filter should be rejected");
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+
         Ok(())
     }
 
@@ -690,79 +1103,339 @@ mod message_route_tests {
             .await
             .expect("chat broadcast timed out")?;
         assert!(
-            matches!(chat_event, ServerEvent::Message { ref message_id, .. } if message_id == &message.public_id),
+            matches!(chat_event.event, ServerEvent::Message { ref message_id, .. } if message_id == &message.public_id),
             "expected chat broadcast with message event"
         );
 
         let user_event = timeout(Duration::from_millis(200), user_rx.recv())
             .await
             .expect("user broadcast timed out")?;
-        assert!(matches!(user_event, ServerEvent::Message { .. }));
+        assert!(matches!(user_event.event, ServerEvent::Message { .. }));
+        assert_eq!(user_event.event_id, chat_event.event_id);
 
         let other_event = timeout(Duration::from_millis(200), other_rx.recv())
             .await
             .expect("secondary user broadcast timed out")?;
-        assert!(matches!(other_event, ServerEvent::Message { .. }));
+        assert!(matches!(other_event.event, ServerEvent::Message { .. }));
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn update_message_records_edit_and_notifies() -> TestResult {
+    async fn create_message_rejects_unknown_reply_to_id() -> TestResult {
         let ctx = TestContext::new().await?;
         ctx.ensure_dev_session("test-token").await?;
 
-        let chat_public_id = "chat-update";
+        let chat_public_id = "chat-bad-reply";
         let chat_id = ctx.create_chat(chat_public_id, 1).await?;
         ctx.add_chat_member(chat_id, 1, "owner").await?;
 
-        let message_id = ctx
-            .insert_message(chat_id, 1, "msg-edit", "original content")
-            .await?;
-
-        let state = ctx.state();
-        let (chat_sender, mut chat_rx) = broadcast::channel(4);
-        let (user_sender, mut user_rx) = broadcast::channel(4);
-        {
-            let mut guard = state.chat_broadcasters.lock().await;
-            guard.insert(chat_public_id.to_string(), chat_sender.clone());
-        }
-        {
-            let mut guard = state.user_broadcasters.lock().await;
-            guard.insert(1, user_sender.clone());
-        }
-
-        let request = UpdateMessageRequest {
-            content: "updated content".to_string(),
+        let request = CreateMessageRequest {
+            content: "replying to nothing".to_string(),
+            role: "user".to_string(),
+            model: None,
+            message_type: None,
+            thread_id: None,
+            reply_to_id: Some("does-not-exist".to_string()),
         };
 
-        let Json(MessageResponse { message }) = expect_ok(
-            update_message(
-                State(state.clone()),
-                Path((chat_public_id.to_string(), "msg-edit".to_string())),
-                bearer_headers("test-token"),
-                Json(request),
-            )
-            .await,
-            "update_message",
-        )?;
+        let err = create_message(
+            State(ctx.state()),
+            Path(chat_public_id.to_string()),
+            bearer_headers("test-token"),
+            Json(request),
+        )
+        .await
+        .expect_err("expected unknown reply_to_id to be rejected");
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(err.message.contains("reply_to_id"));
 
-        assert_eq!(message.content, "updated content");
+        let stored_count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE chat_id = ?")
+                .bind(chat_id)
+                .fetch_one(ctx.pool())
+                .await?;
+        assert_eq!(stored_count, 0);
 
-        let audit_row: (String, String) = sqlx::query_as(
-            "SELECT old_content, new_content FROM message_edits WHERE message_id = ?",
-        )
-        .bind(message_id)
-        .fetch_one(ctx.pool())
-        .await?;
-        assert_eq!(audit_row.0, "original content");
-        assert_eq!(audit_row.1, "updated content");
+        Ok(())
+    }
 
-        let chat_event = timeout(Duration::from_millis(200), chat_rx.recv())
+    #[tokio::test]
+    async fn stored_messages_are_sent_or_complete_by_role() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_public_id = "chat-status";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        for (role, status) in [("user", "sent"), ("assistant", "complete")] {
+            let request = CreateMessageRequest {
+                content: format!("from the {role}"),
+                role: role.to_string(),
+                model: None,
+                message_type: None,
+                thread_id: None,
+                reply_to_id: None,
+            };
+            let Json(MessageResponse { message }) = expect_ok(
+                create_message(
+                    State(ctx.state()),
+                    Path(chat_public_id.to_string()),
+                    bearer_headers("test-token"),
+                    Json(request),
+                )
+                .await,
+                "create_message",
+            )?;
+            assert_eq!(message.status, status, "{role}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn viewer_can_read_but_not_post() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "viewer-chat-owner").await?;
+
+        let chat_public_id = "chat-viewer";
+        let chat_id = ctx.create_chat(chat_public_id, 2).await?;
+        ctx.add_chat_member(chat_id, 2, "owner").await?;
+        ctx.add_chat_member(chat_id, 1, "viewer").await?;
+        ctx.insert_message(chat_id, 2, "msg-viewer", "read me").await?;
+
+        let Json(response) = expect_ok(
+            get_messages(
+                State(ctx.state()),
+                Path(chat_public_id.to_string()),
+                bearer_headers("test-token"),
+                Query(GetMessagesQuery::default()),
+            )
+            .await,
+            "get_messages for viewer",
+        )?;
+        assert_eq!(response.messages.len(), 1);
+
+        let request = CreateMessageRequest {
+            content: "let me in".to_string(),
+            role: "user".to_string(),
+            model: None,
+            message_type: None,
+            thread_id: None,
+            reply_to_id: None,
+        };
+        let err = create_message(
+            State(ctx.state()),
+            Path(chat_public_id.to_string()),
+            bearer_headers("test-token"),
+            Json(request),
+        )
+        .await
+        .expect_err("expected a viewer's message to be rejected");
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+
+        let stored_count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE chat_id = ?")
+                .bind(chat_id)
+                .fetch_one(ctx.pool())
+                .await?;
+        assert_eq!(stored_count, 1);
+
+        // Nor can they change the model every later reply is generated with
+        sqlx::query("UPDATE chats SET default_model = 'mock/pinned' WHERE id = ?")
+            .bind(chat_id)
+            .execute(ctx.pool())
+            .await?;
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri(format!("/api/chats/{chat_public_id}/model"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"default_model":null}"#))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let default_model: Option<String> =
+            sqlx::query_scalar("SELECT default_model FROM chats WHERE id = ?")
+                .bind(chat_id)
+                .fetch_one(ctx.pool())
+                .await?;
+        assert_eq!(default_model.as_deref(), Some("mock/pinned"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_message_rejects_rapid_sends_beyond_the_flood_limit() -> TestResult {
+        let mut config = AppConfig::default();
+        config.chat.message_burst = 2;
+        config.chat.messages_per_minute = 1;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_id = ctx.create_chat("chat-flooded", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let other_chat_id = ctx.create_chat("chat-quiet", 1).await?;
+        ctx.add_chat_member(other_chat_id, 1, "owner").await?;
+
+        let send = |chat: &str, role: &str| {
+            create_message(
+                State(ctx.state()),
+                Path(chat.to_string()),
+                bearer_headers("test-token"),
+                Json(CreateMessageRequest {
+                    content: "again".to_string(),
+                    role: role.to_string(),
+                    model: None,
+                    message_type: None,
+                    thread_id: None,
+                    reply_to_id: None,
+                }),
+            )
+        };
+
+        send("chat-flooded", "user")
+            .await
+            .expect("first send within burst");
+        send("chat-flooded", "user")
+            .await
+            .expect("second send within burst");
+        let err = send("chat-flooded", "user")
+            .await
+            .expect_err("third rapid send should be limited");
+        assert_eq!(err.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(err.code, Some("message_rate_limited"));
+        let retry_after = err
+            .headers
+            .get(axum::http::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .expect("retry-after header");
+        assert!((1..=60).contains(&retry_after), "{retry_after}");
+
+        // Labelling a post as an assistant reply does not get around the limit
+        let err = send("chat-flooded", "assistant")
+            .await
+            .expect_err("client-sent assistant messages are limited too");
+        assert_eq!(err.status, StatusCode::TOO_MANY_REQUESTS);
+
+        // Other chats have their own allowance
+        send("chat-quiet", "user")
+            .await
+            .expect("other chats are limited separately");
+
+        let stored_count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE chat_id = ?")
+                .bind(chat_id)
+                .fetch_one(ctx.pool())
+                .await?;
+        assert_eq!(stored_count, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_message_rejects_cross_chat_references() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_id = ctx.create_chat("chat-target", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let other_chat_id = ctx.create_chat("chat-elsewhere", 1).await?;
+        ctx.add_chat_member(other_chat_id, 1, "owner").await?;
+        ctx.insert_message(other_chat_id, 1, "msg-elsewhere", "other chat")
+            .await?;
+
+        for (thread_id, reply_to_id, field) in [
+            (None, Some("msg-elsewhere"), "reply_to_id"),
+            (Some("msg-elsewhere"), None, "thread_id"),
+        ] {
+            let request = CreateMessageRequest {
+                content: "cross-chat reference".to_string(),
+                role: "user".to_string(),
+                model: None,
+                message_type: None,
+                thread_id: thread_id.map(str::to_string),
+                reply_to_id: reply_to_id.map(str::to_string),
+            };
+
+            let err = create_message(
+                State(ctx.state()),
+                Path("chat-target".to_string()),
+                bearer_headers("test-token"),
+                Json(request),
+            )
+            .await
+            .expect_err("expected cross-chat reference to be rejected");
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+            assert!(err.message.contains(field));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_message_records_edit_and_notifies() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_public_id = "chat-update";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let message_id = ctx
+            .insert_message(chat_id, 1, "msg-edit", "original content")
+            .await?;
+
+        let state = ctx.state();
+        let (chat_sender, mut chat_rx) = broadcast::channel(4);
+        let (user_sender, mut user_rx) = broadcast::channel(4);
+        {
+            let mut guard = state.chat_broadcasters.lock().await;
+            guard.insert(chat_public_id.to_string(), chat_sender.clone());
+        }
+        {
+            let mut guard = state.user_broadcasters.lock().await;
+            guard.insert(1, user_sender.clone());
+        }
+
+        let request = UpdateMessageRequest {
+            content: "updated content".to_string(),
+            version: None,
+        };
+
+        let Json(MessageResponse { message }) = expect_ok(
+            update_message(
+                State(state.clone()),
+                Path((chat_public_id.to_string(), "msg-edit".to_string())),
+                bearer_headers("test-token"),
+                Json(request),
+            )
+            .await,
+            "update_message",
+        )?;
+
+        assert_eq!(message.content, "updated content");
+
+        let audit_row: (String, String) = sqlx::query_as(
+            "SELECT old_content, new_content FROM message_edits WHERE message_id = ?",
+        )
+        .bind(message_id)
+        .fetch_one(ctx.pool())
+        .await?;
+        assert_eq!(audit_row.0, "original content");
+        assert_eq!(audit_row.1, "updated content");
+
+        let chat_event = timeout(Duration::from_millis(200), chat_rx.recv())
             .await
             .expect("chat broadcast timed out")?;
         assert!(matches!(
-            chat_event,
+            chat_event.event,
             ServerEvent::MessageUpdated { message: ref updated, .. }
             if updated.public_id == "msg-edit" && updated.content == "updated content"
         ));
@@ -770,7 +1443,54 @@ mod message_route_tests {
         let user_event = timeout(Duration::from_millis(200), user_rx.recv())
             .await
             .expect("user broadcast timed out")?;
-        assert!(matches!(user_event, ServerEvent::MessageUpdated { .. }));
+        assert!(matches!(user_event.event, ServerEvent::MessageUpdated { .. }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_message_checks_expected_version() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_id = ctx.create_chat("chat-versions", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let message_id = ctx
+            .insert_message(chat_id, 1, "msg-versioned", "first draft")
+            .await?;
+
+        let edit = |content: &str, version: i64| {
+            update_message(
+                State(ctx.state()),
+                Path(("chat-versions".to_string(), "msg-versioned".to_string())),
+                bearer_headers("test-token"),
+                Json(UpdateMessageRequest {
+                    content: content.to_string(),
+                    version: Some(version),
+                }),
+            )
+        };
+
+        let Json(MessageResponse { message }) =
+            expect_ok(edit("second draft", 1).await, "versioned update")?;
+        assert_eq!(message.content, "second draft");
+        assert_eq!(message.version, 2);
+
+        let err = edit("conflicting draft", 1)
+            .await
+            .expect_err("stale version should be rejected");
+        assert_eq!(err.status, StatusCode::CONFLICT);
+
+        let (content, edits): (String, i64) = sqlx::query_as(
+            "SELECT content, (SELECT COUNT(*) FROM message_edits WHERE message_id = ?) \
+             FROM messages WHERE id = ?",
+        )
+        .bind(message_id)
+        .bind(message_id)
+        .fetch_one(ctx.pool())
+        .await?;
+        assert_eq!(content, "second draft");
+        assert_eq!(edits, 1, "a rejected edit must not be audited");
 
         Ok(())
     }
@@ -821,14 +1541,14 @@ mod message_route_tests {
             .await
             .expect("chat broadcast timed out")?;
         assert!(matches!(
-            chat_event,
+            chat_event.event,
             ServerEvent::MessageDeleted { ref message_id, .. } if message_id == "msg-delete"
         ));
 
         let user_event = timeout(Duration::from_millis(200), user_rx.recv())
             .await
             .expect("user broadcast timed out")?;
-        assert!(matches!(user_event, ServerEvent::MessageDeleted { .. }));
+        assert!(matches!(user_event.event, ServerEvent::MessageDeleted { .. }));
 
         Ok(())
     }
@@ -868,6 +1588,7 @@ mod message_route_tests {
                 State(ctx.state()),
                 Path((chat_public_id.to_string(), "msg-history".to_string())),
                 bearer_headers("test-token"),
+                Query(GetMessageEditsQuery::default()),
             )
             .await,
             "get_message_edits",
@@ -876,125 +1597,4635 @@ mod message_route_tests {
         assert_eq!(response.edits.len(), 2);
         assert_eq!(response.edits[0].new_content, "v3");
         assert_eq!(response.edits[1].new_content, "v2");
+        assert!(response.edits.iter().all(|edit| edit.diff.is_none()));
 
         Ok(())
     }
-}
-
-mod util_tests {
-    use super::*;
-    use axum::http::{HeaderMap, HeaderValue};
 
-    #[test]
-    fn require_bearer_rejects_wrong_scheme() {
-        let mut headers = HeaderMap::new();
-        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic abc123"));
+    #[tokio::test]
+    async fn get_message_edits_caps_the_page_and_paginates_older_edits() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
 
-        let err = switchboard_backend_api::require_bearer(&headers)
-            .expect_err("expected non-bearer scheme to be rejected");
-        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
-        assert!(err.message.contains("invalid authorization scheme"));
-    }
+        let chat_public_id = "chat-edit-pages";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let message_id = ctx.insert_message(chat_id, 1, "msg-paged", "v0").await?;
+        // Every edit shares a timestamp, so only the id orders them
+        for version in 1..=55 {
+            let old = format!("v{}", version - 1);
+            let new = format!("v{version}");
+            ctx.insert_message_edit(message_id, 1, &old, &new, Some("2024-05-01T00:00:00Z"))
+                .await?;
+        }
 
-    #[test]
-    fn require_bearer_rejects_missing_header() {
-        let headers = HeaderMap::new();
-        let err = switchboard_backend_api::require_bearer(&headers)
-            .expect_err("expected missing header to be rejected");
-        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
-        assert!(err.message.contains("missing authorization header"));
-    }
-}
+        let fetch = |before: Option<i64>| {
+            get_message_edits(
+                State(ctx.state()),
+                Path((chat_public_id.to_string(), "msg-paged".to_string())),
+                bearer_headers("test-token"),
+                Query(GetMessageEditsQuery {
+                    before,
+                    ..GetMessageEditsQuery::default()
+                }),
+            )
+        };
 
-mod health_route_tests {
-    use super::*;
+        let Json(first) = expect_ok(fetch(None).await, "first page")?;
+        assert_eq!(first.edits.len(), 50);
+        assert_eq!(first.edits[0].new_content, "v55");
+        assert_eq!(first.edits[49].new_content, "v6");
+        assert_eq!(first.next_before, Some(first.edits[49].id));
+
+        let Json(second) = expect_ok(fetch(first.next_before).await, "second page")?;
+        let contents: Vec<&str> = second
+            .edits
+            .iter()
+            .map(|edit| edit.new_content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["v5", "v4", "v3", "v2", "v1"]);
+        assert_eq!(second.next_before, None);
 
-    #[tokio::test]
-    async fn health_check_returns_service_version() -> TestResult {
-        let Json(response) = routes::health::health_check().await;
-        assert_eq!(response.status, "ok");
-        chrono::DateTime::parse_from_rfc3339(&response.timestamp).expect("valid timestamp");
         Ok(())
     }
-}
-
-mod auth_route_tests {
-    use super::*;
 
     #[tokio::test]
-    async fn github_login_requires_oauth_configuration() -> TestResult {
+    async fn get_message_edits_includes_requested_diff() -> TestResult {
         let ctx = TestContext::new().await?;
-        let result = routes::auth::github_login(
-            State(ctx.state()),
-            Query(routes::auth::GithubLoginQuery {
-                redirect_uri: "https://example.com/callback".into(),
-            }),
-        )
-        .await;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_public_id = "chat-edit-diff";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let before = "intro\nthe quick fox\noutro";
+        let after = "intro\nthe slow fox\noutro";
+        let message_id = ctx.insert_message(chat_id, 1, "msg-diffed", before).await?;
+        ctx.insert_message_edit(message_id, 1, before, after, None).await?;
+
+        let fetch = |mode: &str| {
+            get_message_edits(
+                State(ctx.state()),
+                Path((chat_public_id.to_string(), "msg-diffed".to_string())),
+                bearer_headers("test-token"),
+                Query(GetMessageEditsQuery {
+                    diff: Some(mode.to_string()),
+                    ..GetMessageEditsQuery::default()
+                }),
+            )
+        };
+        let segment = |op: &str, text: &str| DiffSegment {
+            op: op.to_string(),
+            text: text.to_string(),
+        };
+
+        let Json(response) = expect_ok(fetch("word").await, "word diff")?;
+        assert_eq!(
+            response.edits[0].diff,
+            Some(vec![
+                segment("equal", "intro\nthe "),
+                segment("delete", "quick"),
+                segment("insert", "slow"),
+                segment("equal", " fox\noutro"),
+            ])
+        );
+
+        let Json(response) = expect_ok(fetch("line").await, "line diff")?;
+        assert_eq!(
+            response.edits[0].diff,
+            Some(vec![
+                segment("equal", "intro\n"),
+                segment("delete", "the quick fox\n"),
+                segment("insert", "the slow fox\n"),
+                segment("equal", "outro"),
+            ])
+        );
+
+        let err = fetch("char").await.expect_err("unknown diff mode should be rejected");
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
 
-        let err = result.expect_err("expected login without configuration to fail");
-        assert_eq!(err.status, StatusCode::SERVICE_UNAVAILABLE);
         Ok(())
     }
 
-    #[tokio::test]
-    async fn github_login_issues_state_and_authorization_url() -> TestResult {
-        let ctx = TestContext::with_github().await?;
-        let state = ctx.state();
-        let redirect_uri = "https://example.com/callback";
+    async fn draft_request(
+        ctx: &TestContext,
+        method: Method,
+        chat_public_id: &str,
+        content: Option<&str>,
+    ) -> TestResult<(StatusCode, Value)> {
+        let builder = Request::builder()
+            .method(method)
+            .uri(format!("/api/chats/{chat_public_id}/draft"))
+            .header(AUTHORIZATION, "Bearer test-token");
+        let request = match content {
+            Some(content) => builder
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::json!({ "content": content }).to_string()))?,
+            None => builder.body(Body::empty())?,
+        };
 
-        let response = routes::auth::github_login(
-            State(state.clone()),
+        let response = ctx.router().oneshot(request).await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        Ok((status, payload))
+    }
+
+    #[tokio::test]
+    async fn drafts_upsert_and_clear_on_send() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_public_id = "chat-drafts";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let (status, _) = draft_request(&ctx, Method::GET, chat_public_id, None).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, payload) =
+            draft_request(&ctx, Method::PUT, chat_public_id, Some("first take")).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload["draft"]["content"], "first take");
+
+        let (status, _) =
+            draft_request(&ctx, Method::PUT, chat_public_id, Some("second take")).await?;
+        assert_eq!(status, StatusCode::OK);
+        let (status, payload) = draft_request(&ctx, Method::GET, chat_public_id, None).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload["draft"]["content"], "second take");
+
+        let drafts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM message_drafts")
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(drafts, 1, "saving again should replace the draft");
+
+        let (status, _) = draft_request(&ctx, Method::DELETE, chat_public_id, None).await?;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = draft_request(&ctx, Method::GET, chat_public_id, None).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        draft_request(&ctx, Method::PUT, chat_public_id, Some("about to send")).await?;
+        let request = CreateMessageRequest {
+            content: "about to send".to_string(),
+            role: "user".to_string(),
+            model: None,
+            message_type: None,
+            thread_id: None,
+            reply_to_id: None,
+        };
+        expect_ok(
+            create_message(
+                State(ctx.state()),
+                Path(chat_public_id.to_string()),
+                bearer_headers("test-token"),
+                Json(request),
+            )
+            .await,
+            "create_message",
+        )?;
+
+        let (status, _) = draft_request(&ctx, Method::GET, chat_public_id, None).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND, "sending should clear the draft");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drafts_require_chat_membership() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-two").await?;
+        ctx.create_chat("chat-private", 2).await?;
+
+        let (status, _) = draft_request(&ctx, Method::PUT, "chat-private", Some("sneaky")).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    async fn read_request(
+        ctx: &TestContext,
+        method: Method,
+        chat_public_id: &str,
+    ) -> TestResult<(StatusCode, Value)> {
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(format!("/api/chats/{chat_public_id}/read"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    #[tokio::test]
+    async fn mark_chat_read_clears_unread_count() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "chatty").await?;
+
+        let chat_public_id = "chat-unread";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.add_chat_member(chat_id, 2, "member").await?;
+        ctx.insert_message(chat_id, 2, "msg-unread-1", "one").await?;
+        ctx.insert_message(chat_id, 1, "msg-unread-own", "mine").await?;
+        ctx.insert_message(chat_id, 2, "msg-unread-2", "two").await?;
+
+        let (status, payload) = read_request(&ctx, Method::GET, chat_public_id).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload["unread_count"], 2);
+        assert!(payload["last_read_message_id"].is_null());
+
+        let state = ctx.state();
+        let (chat_sender, mut chat_rx) = broadcast::channel(8);
+        state
+            .chat_broadcasters
+            .lock()
+            .await
+            .insert(chat_public_id.to_string(), chat_sender);
+
+        let (status, payload) = read_request(&ctx, Method::POST, chat_public_id).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload["unread_count"], 0);
+        assert_eq!(payload["last_read_message_id"], "msg-unread-2");
+
+        let receipt = chat_rx.try_recv()?;
+        assert!(matches!(receipt.event, ServerEvent::ReadReceipt { user_id: 1, .. }));
+
+        let (_, payload) = read_request(&ctx, Method::GET, chat_public_id).await?;
+        assert_eq!(payload["unread_count"], 0);
+
+        // New messages after the marker count again
+        ctx.insert_message(chat_id, 2, "msg-unread-3", "three").await?;
+        let (_, payload) = read_request(&ctx, Method::GET, chat_public_id).await?;
+        assert_eq!(payload["unread_count"], 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deleting_the_last_read_message_keeps_the_read_position() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "chatty").await?;
+
+        let chat_public_id = "chat-read-delete";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.add_chat_member(chat_id, 2, "member").await?;
+        ctx.insert_message(chat_id, 2, "msg-kept", "one").await?;
+        ctx.insert_message(chat_id, 2, "msg-gone", "two").await?;
+
+        let (_, payload) = read_request(&ctx, Method::POST, chat_public_id).await?;
+        assert_eq!(payload["last_read_message_id"], "msg-gone");
+
+        sqlx::query("DELETE FROM messages WHERE public_id = 'msg-gone'")
+            .execute(ctx.pool())
+            .await?;
+
+        let (status, payload) = read_request(&ctx, Method::GET, chat_public_id).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload["unread_count"], 0);
+        assert_eq!(payload["last_read_message_id"], "msg-kept");
+
+        Ok(())
+    }
+
+    async fn post_batch(
+        ctx: &TestContext,
+        chat_public_id: &str,
+        body: Value,
+    ) -> TestResult<(StatusCode, Value)> {
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri(format!("/api/chats/{chat_public_id}/messages/batch"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))?,
+            )
+            .await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    #[tokio::test]
+    async fn batch_create_preserves_order_and_timestamps() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-import", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        // The first two share a timestamp; the third is given in another offset
+        let opened_at = "2024-01-01T10:00:00Z";
+        let followed_up_at = "2024-01-01T12:30:00+02:00";
+        let (status, payload) = post_batch(
+            &ctx,
+            "chat-import",
+            serde_json::json!({
+                "messages": [
+                    { "role": "user", "content": "first", "created_at": opened_at },
+                    { "role": "assistant", "content": "second", "created_at": opened_at },
+                    { "role": "user", "content": "third", "created_at": followed_up_at },
+                ]
+            }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+        let contents: Vec<&str> = payload["messages"]
+            .as_array()
+            .ok_or_else(|| anyhow!("messages missing"))?
+            .iter()
+            .filter_map(|message| message["content"].as_str())
+            .collect();
+        assert_eq!(contents, ["first", "second", "third"]);
+        assert_eq!(payload["messages"][2]["created_at"], "2024-01-01T10:30:00+00:00");
+
+        let Json(response) = expect_ok(
+            get_messages(
+                State(ctx.state()),
+                Path("chat-import".to_string()),
+                bearer_headers("test-token"),
+                Query(GetMessagesQuery::default()),
+            )
+            .await,
+            "get_messages after batch import",
+        )?;
+        let stored: Vec<&str> = response
+            .messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect();
+        assert_eq!(stored, ["first", "second", "third"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_create_rolls_back_on_invalid_item() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-import-bad", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let (status, payload) = post_batch(
+            &ctx,
+            "chat-import-bad",
+            serde_json::json!({
+                "messages": [
+                    { "role": "user", "content": "kept?" },
+                    { "role": "user", "content": "orphan", "reply_to_id": "missing" },
+                ]
+            }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(payload["error"]
+            .as_str()
+            .unwrap_or_default()
+            .starts_with("messages[1]:"));
+
+        let (status, _) = post_batch(
+            &ctx,
+            "chat-import-bad",
+            serde_json::json!({
+                "messages": [
+                    { "role": "user", "content": "later", "created_at": "2024-01-02T00:00:00Z" },
+                    { "role": "user", "content": "earlier", "created_at": "2024-01-01T00:00:00Z" },
+                ]
+            }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(count, 0);
+
+        Ok(())
+    }
+}
+
+mod chat_route_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn create_chat_registers_creator_as_owner() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let router = ctx.router();
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/api/chats")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"title":"Fresh chat"}"#))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        let chat_public_id = payload["chat"]["public_id"]
+            .as_str()
+            .ok_or_else(|| anyhow!("chat public_id missing"))?
+            .to_owned();
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/chats/{chat_public_id}/members"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        let members = payload["members"]
+            .as_array()
+            .ok_or_else(|| anyhow!("members missing"))?;
+
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0]["user_id"], 1);
+        assert_eq!(members[0]["role"], "owner");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_chat_falls_back_to_configured_type_and_folder() -> TestResult {
+        let mut config = AppConfig::default();
+        config.chat.default_chat_type = "group".to_string();
+        config.chat.default_folder = Some("Inbox".to_string());
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let now = chrono::Utc::now().to_rfc3339();
+        let inbox_id = sqlx::query(
+            "INSERT INTO folders (public_id, user_id, name, created_at, updated_at) VALUES ('inbox', 1, 'Inbox', ?, ?)",
+        )
+        .bind(&now)
+        .bind(&now)
+        .execute(ctx.pool())
+        .await?
+        .last_insert_rowid();
+
+        let create = |body: &'static str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/chats")
+                .header(AUTHORIZATION, "Bearer test-token")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+        };
+
+        let response = ctx.router().oneshot(create(r#"{"title":"Defaults"}"#)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["chat"]["chat_type"], "group");
+        assert_eq!(payload["chat"]["folder_id"], inbox_id);
+
+        let response = ctx
+            .router()
+            .oneshot(create(r#"{"title":"Explicit","chat_type":"direct"}"#)?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["chat"]["chat_type"], "direct");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_chats_reports_member_counts() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-2").await?;
+        ctx.insert_user(3, "user-3").await?;
+
+        let solo = ctx.create_chat("solo", 1).await?;
+        ctx.add_chat_member(solo, 1, "owner").await?;
+        let shared = ctx.create_chat("shared", 1).await?;
+        ctx.add_chat_member(shared, 1, "owner").await?;
+        ctx.add_chat_member(shared, 2, "member").await?;
+        ctx.add_chat_member(shared, 3, "member").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chats")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        let chats = payload["chats"]
+            .as_array()
+            .ok_or_else(|| anyhow!("chats missing"))?;
+        let count_for = |public_id: &str| {
+            chats
+                .iter()
+                .find(|chat| chat["public_id"] == public_id)
+                .map(|chat| chat["member_count"].clone())
+        };
+
+        assert_eq!(chats.len(), 2);
+        assert_eq!(count_for("solo"), Some(Value::from(1)));
+        assert_eq!(count_for("shared"), Some(Value::from(3)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn typing_snapshot_lists_members_currently_typing() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-2").await?;
+        ctx.insert_user(3, "user-3").await?;
+        let chat = ctx.create_chat("typing-chat", 1).await?;
+        for user_id in 1..=3 {
+            ctx.add_chat_member(chat, user_id, "member").await?;
+        }
+
+        let state = ctx.state();
+        state.typing().set(chat, 2, true);
+        state.typing().set(chat, 3, true);
+        state.typing().set(chat, 3, false);
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chats/typing-chat/typing")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+
+        assert_eq!(payload["chat_id"], "typing-chat");
+        assert_eq!(payload["user_ids"], serde_json::json!([2]));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_chat_reports_every_invalid_field() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let body = serde_json::json!({
+            "title": "  ",
+            "chat_type": "channel",
+            "messages": [
+                { "role": "user", "content": "fine" },
+                { "role": "robot", "content": "not fine" },
+            ],
+        });
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/api/chats")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+
+        assert_eq!(payload["error"]["code"], "validation");
+        let fields: Vec<&str> = payload["error"]["fields"]
+            .as_array()
+            .ok_or_else(|| anyhow!("fields missing"))?
+            .iter()
+            .filter_map(|field| field["field"].as_str())
+            .collect();
+        assert_eq!(fields, ["title", "chat_type", "messages[1].role"]);
+
+        let chats: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chats")
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(chats, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn count_endpoints_match_seeded_data() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "stranger").await?;
+
+        let busy = ctx.create_chat("chat-busy", 1).await?;
+        ctx.add_chat_member(busy, 1, "owner").await?;
+        let quiet = ctx.create_chat("chat-quiet", 2).await?;
+        ctx.add_chat_member(quiet, 2, "owner").await?;
+        ctx.add_chat_member(quiet, 1, "member").await?;
+        let private = ctx.create_chat("chat-private", 2).await?;
+        ctx.add_chat_member(private, 2, "owner").await?;
+
+        for index in 0..3 {
+            ctx.insert_message(busy, 1, &format!("msg-busy-{index}"), "hello")
+                .await?;
+        }
+        ctx.insert_message(private, 2, "msg-private", "secret")
+            .await?;
+
+        let get_count = |uri: &'static str| {
+            let router = ctx.router();
+            async move {
+                let response = router
+                    .oneshot(
+                        Request::builder()
+                            .uri(uri)
+                            .header(AUTHORIZATION, "Bearer test-token")
+                            .body(Body::empty())?,
+                    )
+                    .await?;
+                let status = response.status();
+                let body = response.into_body().collect().await?.to_bytes();
+                let payload: Value = serde_json::from_slice(&body)?;
+                TestResult::Ok((status, payload["count"].as_i64()))
+            }
+        };
+
+        assert_eq!(get_count("/api/chats/count").await?, (StatusCode::OK, Some(2)));
+        assert_eq!(
+            get_count("/api/chats/chat-busy/messages/count").await?,
+            (StatusCode::OK, Some(3))
+        );
+        assert_eq!(
+            get_count("/api/chats/chat-quiet/messages/count").await?,
+            (StatusCode::OK, Some(0))
+        );
+        let (status, _) = get_count("/api/chats/chat-private/messages/count").await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    async fn put_chat_type(
+        ctx: &TestContext,
+        chat_public_id: &str,
+        chat_type: &str,
+    ) -> TestResult<(StatusCode, Value)> {
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri(format!("/api/chats/{chat_public_id}"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(format!(r#"{{"chat_type":"{chat_type}"}}"#)))?,
+            )
+            .await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    #[tokio::test]
+    async fn update_chat_converts_between_direct_and_group() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "partner").await?;
+
+        let chat_id = ctx.create_chat("chat-convert", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.add_chat_member(chat_id, 2, "member").await?;
+
+        let (status, payload) = put_chat_type(&ctx, "chat-convert", "group").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload["chat"]["chat_type"], "group");
+        let is_group: bool = sqlx::query_scalar("SELECT is_group FROM chats WHERE id = ?")
+            .bind(chat_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        assert!(is_group);
+
+        let (status, payload) = put_chat_type(&ctx, "chat-convert", "direct").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload["chat"]["chat_type"], "direct");
+        let is_group: bool = sqlx::query_scalar("SELECT is_group FROM chats WHERE id = ?")
+            .bind(chat_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        assert!(!is_group);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_chat_rejects_invalid_chat_type_conversions() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "first-guest").await?;
+        ctx.insert_user(3, "second-guest").await?;
+
+        let chat_id = ctx.create_chat("chat-crowded", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.add_chat_member(chat_id, 2, "member").await?;
+        ctx.add_chat_member(chat_id, 3, "member").await?;
+
+        let (status, _) = put_chat_type(&ctx, "chat-crowded", "group").await?;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, payload) = put_chat_type(&ctx, "chat-crowded", "direct").await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(payload["error"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("exactly two members"));
+
+        let (status, _) = put_chat_type(&ctx, "chat-crowded", "system").await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, payload) = put_chat_type(&ctx, "chat-crowded", "channel").await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(payload["error"]["code"], "validation");
+        assert_eq!(payload["error"]["fields"][0]["field"], "chat_type");
+
+        let chat_type: String = sqlx::query_scalar("SELECT chat_type FROM chats WHERE id = ?")
+            .bind(chat_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(chat_type, "group");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_chat_rejects_stale_if_match_version() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-versioned", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let rename = |title: &'static str, if_match: &'static str| {
+            ctx.router().oneshot(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri("/api/chats/chat-versioned")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .header("If-Match", if_match)
+                    .body(Body::from(format!(r#"{{"title":"{title}"}}"#)))
+                    .expect("valid request"),
+            )
+        };
+
+        let response = rename("First rename", "\"1\"").await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["chat"]["title"], "First rename");
+        assert_eq!(payload["chat"]["version"], 2);
+
+        // A second client still holding version 1 must not clobber the rename
+        let response = rename("Stale rename", "1").await?;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let title: String = sqlx::query_scalar("SELECT title FROM chats WHERE id = ?")
+            .bind(chat_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(title, "First rename");
+
+        let response = rename("Bad header", "latest").await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn last_owner_leaving_promotes_longest_tenured_admin() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "senior-admin").await?;
+        ctx.insert_user(3, "junior-admin").await?;
+        ctx.insert_user(4, "plain-member").await?;
+
+        let chat_public_id = "chat-succession";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 4, "member").await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.add_chat_member(chat_id, 2, "admin").await?;
+        ctx.add_chat_member(chat_id, 3, "admin").await?;
+
+        let state = ctx.state();
+        let (chat_sender, mut chat_rx) = broadcast::channel(8);
+        state
+            .chat_broadcasters
+            .lock()
+            .await
+            .insert(chat_public_id.to_string(), chat_sender);
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::DELETE)
+                    .uri(format!("/api/chats/{chat_public_id}/members/1"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let owners: Vec<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM chat_members WHERE chat_id = ? AND role = 'owner'",
+        )
+        .bind(chat_id)
+        .fetch_all(ctx.pool())
+        .await?;
+        assert_eq!(owners, vec![2]);
+
+        let mut transferred = None;
+        while let Ok(event) = chat_rx.try_recv() {
+            if let ServerEvent::OwnershipTransferred {
+                previous_owner_id,
+                new_owner,
+                ..
+            } = event.event
+            {
+                transferred = Some((previous_owner_id, new_owner.user_id, new_owner.role));
+            }
+        }
+        assert_eq!(transferred, Some((1, 2, "owner".to_string())));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn last_owner_leaving_promotes_a_member_over_an_older_viewer() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "early-viewer").await?;
+        ctx.insert_user(3, "late-member").await?;
+
+        let chat_id = ctx.create_chat("chat-viewer-succession", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.add_chat_member(chat_id, 2, "viewer").await?;
+        ctx.add_chat_member(chat_id, 3, "member").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::DELETE)
+                    .uri("/api/chats/chat-viewer-succession/members/1")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let roles: Vec<(i64, String)> =
+            sqlx::query_as("SELECT user_id, role FROM chat_members WHERE chat_id = ? ORDER BY user_id")
+                .bind(chat_id)
+                .fetch_all(ctx.pool())
+                .await?;
+        assert_eq!(
+            roles,
+            vec![(2, "viewer".to_string()), (3, "owner".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_owner_handover_keeps_the_leaving_owner() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "would-be-owner").await?;
+
+        let chat_public_id = "chat-handover-fails";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.add_chat_member(chat_id, 2, "admin").await?;
+
+        // Make the promotion fail after the owner's membership has been deleted
+        sqlx::query(
+            r#"
+            CREATE TRIGGER refuse_promotion BEFORE UPDATE OF role ON chat_members
+            BEGIN SELECT RAISE(ABORT, 'promotion refused'); END
+            "#,
+        )
+        .execute(ctx.pool())
+        .await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::DELETE)
+                    .uri(format!("/api/chats/{chat_public_id}/members/1"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let owners: Vec<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM chat_members WHERE chat_id = ? AND role = 'owner'",
+        )
+        .bind(chat_id)
+        .fetch_all(ctx.pool())
+        .await?;
+        assert_eq!(owners, vec![1], "the removal should have been rolled back");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn admin_can_assign_the_viewer_role() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "future-viewer").await?;
+
+        let chat_public_id = "chat-assign-viewer";
+        let chat_id = ctx.create_chat(chat_public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "admin").await?;
+        ctx.add_chat_member(chat_id, 2, "member").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri(format!("/api/chats/{chat_public_id}/members/2"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"role":"viewer"}"#))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let role: String =
+            sqlx::query_scalar("SELECT role FROM chat_members WHERE chat_id = ? AND user_id = 2")
+                .bind(chat_id)
+                .fetch_one(ctx.pool())
+                .await?;
+        assert_eq!(role, "viewer");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn members_me_returns_the_callers_role() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "chat-owner").await?;
+
+        let chat_id = ctx.create_chat("chat-members-me", 2).await?;
+        ctx.add_chat_member(chat_id, 2, "owner").await?;
+        ctx.add_chat_member(chat_id, 1, "admin").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chats/chat-members-me/members/me")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["member"]["user_id"], 1);
+        assert_eq!(payload["member"]["role"], "admin");
+        assert!(payload["member"]["joined_at"].is_string());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn members_me_is_forbidden_for_non_members() -> TestResult {
+        let mut config = AppConfig::default();
+        config.chat.non_member_status = NonMemberStatus::Forbidden;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "chat-owner").await?;
+
+        let chat_id = ctx.create_chat("chat-members-me-outsider", 2).await?;
+        ctx.add_chat_member(chat_id, 2, "owner").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chats/chat-members-me-outsider/members/me")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_stats_aggregate_messages_members_and_tokens() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "stats-partner").await?;
+
+        let chat_id = ctx.create_chat("chat-stats", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.add_chat_member(chat_id, 2, "member").await?;
+        ctx.insert_message(chat_id, 1, "msg-stats-1", "question").await?;
+        let answer = ctx.insert_message(chat_id, 2, "msg-stats-2", "answer").await?;
+        let follow_up = ctx.insert_message(chat_id, 1, "msg-stats-3", "thanks").await?;
+
+        sqlx::query("UPDATE messages SET prompt_tokens = 12, completion_tokens = 30 WHERE id = ?")
+            .bind(answer)
+            .execute(ctx.pool())
+            .await?;
+        sqlx::query("UPDATE messages SET completion_tokens = 8, updated_at = ? WHERE id = ?")
+            .bind("2999-01-01T00:00:00+00:00")
+            .bind(follow_up)
+            .execute(ctx.pool())
+            .await?;
+
+        let stats_request = |chat: &str| {
+            Request::builder()
+                .uri(format!("/api/chats/{chat}/stats"))
+                .header(AUTHORIZATION, "Bearer test-token")
+                .body(Body::empty())
+        };
+
+        let response = ctx.router().oneshot(stats_request("chat-stats")?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["stats"]["message_count"], 3);
+        assert_eq!(payload["stats"]["member_count"], 2);
+        assert_eq!(payload["stats"]["total_tokens"], 50);
+        assert_eq!(payload["stats"]["last_activity_at"], "2999-01-01T00:00:00+00:00");
+
+        let other_chat = ctx.create_chat("chat-stats-private", 2).await?;
+        ctx.add_chat_member(other_chat, 2, "owner").await?;
+        let response = ctx.router().oneshot(stats_request("chat-stats-private")?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prefixed_public_ids_tag_new_chats_and_messages() -> TestResult {
+        let mut config = AppConfig::default();
+        config.ids.prefixed_public_ids = true;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/api/chats")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"title":"Tagged","messages":[{"role":"user","content":"hi"}]}"#,
+                    ))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        let chat_public_id = payload["chat"]["public_id"]
+            .as_str()
+            .ok_or_else(|| anyhow!("chat public_id missing"))?;
+        assert!(chat_public_id.starts_with("chat_"));
+
+        let message_ids: Vec<String> = sqlx::query_scalar("SELECT public_id FROM messages")
+            .fetch_all(ctx.pool())
+            .await?;
+        assert!(!message_ids.is_empty());
+        assert!(message_ids.iter().all(|id| id.starts_with("msg_")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mismatched_id_prefix_is_rejected() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        for uri in ["/api/chats/msg_abc/messages", "/api/permissions/chat/fld_abc"] {
+            let response = ctx
+                .router()
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .header(AUTHORIZATION, "Bearer test-token")
+                        .body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{uri}");
+        }
+
+        // Unprefixed ids minted before the switch still resolve normally
+        let chat_id = ctx.create_chat("legacy-chat", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chats/legacy-chat/messages")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn non_member_chat_access_follows_configured_status() -> TestResult {
+        let policies = [
+            (NonMemberStatus::NotFound, StatusCode::NOT_FOUND),
+            (NonMemberStatus::Forbidden, StatusCode::FORBIDDEN),
+        ];
+        for (policy, non_member_status) in policies {
+            let mut config = AppConfig::default();
+            config.chat.non_member_status = policy;
+            let ctx = TestContext::with_config(config).await?;
+            ctx.ensure_dev_session("test-token").await?;
+            ctx.insert_user(2, "user-two").await?;
+            let mine = ctx.create_chat("chat-mine", 1).await?;
+            ctx.add_chat_member(mine, 1, "owner").await?;
+            let theirs = ctx.create_chat("chat-theirs", 2).await?;
+            ctx.add_chat_member(theirs, 2, "owner").await?;
+
+            let cases = [
+                ("chat-mine", StatusCode::OK),
+                ("chat-theirs", non_member_status),
+                ("chat-missing", StatusCode::NOT_FOUND),
+            ];
+            for (chat_id, expected) in cases {
+                let response = ctx
+                    .router()
+                    .oneshot(
+                        Request::builder()
+                            .uri(format!("/api/chats/{chat_id}/messages"))
+                            .header(AUTHORIZATION, "Bearer test-token")
+                            .body(Body::empty())?,
+                    )
+                    .await?;
+                assert_eq!(response.status(), expected, "{policy:?} {chat_id}");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn completion_estimate_counts_prompt_tokens() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-two").await?;
+        let chat_id = ctx.create_chat("chat-estimate", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-estimate-1", "Plan a week in Lisbon").await?;
+        ctx.insert_message(chat_id, 1, "msg-estimate-2", "Add a day trip to Sintra").await?;
+        let other_chat = ctx.create_chat("chat-elsewhere", 2).await?;
+        ctx.add_chat_member(other_chat, 2, "owner").await?;
+
+        let estimate = |chat: &str, content: &str| {
+            let payload = serde_json::json!({ "content": content, "model": "openai/gpt-4.1" });
+            Request::builder()
+                .method(Method::POST)
+                .uri(format!("/api/chats/{chat}/completions/estimate"))
+                .header(AUTHORIZATION, "Bearer test-token")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(payload.to_string()))
+        };
+
+        let response = ctx
+            .router()
+            .oneshot(estimate("chat-estimate", "What should I pack?")?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["model"], "openai/gpt-4.1");
+        assert!(payload["prompt_tokens"].as_u64().unwrap_or_default() > 0);
+        // No model list to price against in tests
+        assert!(payload["estimated_cost"].is_null());
+
+        let longer = "What should I pack for a week of hiking and city walks?";
+        let response = ctx.router().oneshot(estimate("chat-estimate", longer)?).await?;
+        let body = response.into_body().collect().await?.to_bytes();
+        let longer_payload: Value = serde_json::from_slice(&body)?;
+        assert!(longer_payload["prompt_tokens"].as_u64() > payload["prompt_tokens"].as_u64());
+
+        let response = ctx.router().oneshot(estimate("chat-estimate", "  ")?).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = ctx.router().oneshot(estimate("chat-elsewhere", "Hi")?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invites_are_addressed_by_public_id() -> TestResult {
+        let mut config = AppConfig::default();
+        config.ids.prefixed_public_ids = true;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("invite-chat", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        sqlx::query("UPDATE chats SET chat_type = 'group', is_group = 1 WHERE id = ?")
+            .bind(chat_id)
+            .execute(ctx.pool())
+            .await?;
+        let email: String = sqlx::query_scalar("SELECT email FROM users WHERE id = 1")
+            .fetch_one(ctx.pool())
+            .await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/api/chats/invite-chat/invites")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(serde_json::json!({ "email": email }).to_string()))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        let invite_public_id = payload["invite"]["public_id"]
+            .as_str()
+            .ok_or_else(|| anyhow!("invite public_id missing"))?
+            .to_string();
+        assert!(invite_public_id.starts_with("inv_"));
+        let invite_db_id = payload["invite"]["id"]
+            .as_i64()
+            .ok_or_else(|| anyhow!("invite id missing"))?;
+
+        let cases = [
+            (format!("/api/invites/{invite_db_id}/reject"), StatusCode::NOT_FOUND),
+            ("/api/invites/chat_abc/reject".to_string(), StatusCode::BAD_REQUEST),
+            (format!("/api/invites/{invite_public_id}/reject"), StatusCode::OK),
+        ];
+        for (uri, expected) in cases {
+            let response = ctx
+                .router()
+                .oneshot(
+                    Request::builder()
+                        .method(Method::POST)
+                        .uri(uri.as_str())
+                        .header(AUTHORIZATION, "Bearer test-token")
+                        .body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), expected, "{uri}");
+        }
+
+        let status: String =
+            sqlx::query_scalar("SELECT status FROM chat_invites WHERE public_id = ?")
+                .bind(&invite_public_id)
+                .fetch_one(ctx.pool())
+                .await?;
+        assert_eq!(status, "rejected");
+
+        Ok(())
+    }
+}
+
+mod attachment_route_tests {
+    use super::*;
+    use axum::extract::Path;
+    use switchboard_backend_api::routes::{
+        attachments::{create_message_attachment, get_attachment_metadata},
+        models::CreateAttachmentRequest,
+    };
+
+    async fn insert_attachment(ctx: &TestContext, message_id: i64) -> TestResult<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO message_attachments (message_id, file_name, file_type, file_url, file_size_bytes, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(message_id)
+        .bind("report.pdf")
+        .bind("application/pdf")
+        .bind("https://files.example.com/report.pdf")
+        .bind(4096_i64)
+        .bind(Utc::now().to_rfc3339())
+        .execute(ctx.pool())
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    #[tokio::test]
+    async fn attachment_metadata_is_returned_for_members() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_id = ctx.create_chat("chat-files", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let message_id = ctx.insert_message(chat_id, 1, "msg-file", "see attached").await?;
+        let attachment_id = insert_attachment(&ctx, message_id).await?;
+
+        let Json(response) = get_attachment_metadata(
+            State(ctx.state()),
+            Path(("chat-files".to_string(), "msg-file".to_string(), attachment_id)),
+            bearer_headers("test-token"),
+        )
+        .await
+        .map_err(|err| anyhow!("get_attachment_metadata: {}", err.message))?;
+
+        assert_eq!(response.attachment.id, attachment_id);
+        assert_eq!(response.attachment.file_name, "report.pdf");
+        assert_eq!(response.attachment.file_type, "application/pdf");
+        assert_eq!(response.attachment.file_size_bytes, 4096);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_attachment_rejects_beyond_configured_limit() -> TestResult {
+        let mut config = AppConfig::default();
+        config.chat.max_attachments_per_message = 2;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_id = ctx.create_chat("chat-attachment-limit", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-many-files", "lots of files")
+            .await?;
+
+        let upload = |index: usize| {
+            create_message_attachment(
+                State(ctx.state()),
+                Path((
+                    "chat-attachment-limit".to_string(),
+                    "msg-many-files".to_string(),
+                )),
+                bearer_headers("test-token"),
+                Json(CreateAttachmentRequest {
+                    file_name: format!("file-{index}.txt"),
+                    file_type: "text/plain".to_string(),
+                    file_url: Some(format!("https://files.example.com/file-{index}.txt")),
+                    file_size_bytes: Some(16),
+                    content: None,
+                    source_url: None,
+                }),
+            )
+        };
+
+        for index in 0..2 {
+            upload(index)
+                .await
+                .map_err(|err| anyhow!("attachment {index} within limit: {}", err.message))?;
+        }
+
+        let err = upload(2)
+            .await
+            .expect_err("expected attachment beyond the limit to be rejected");
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(err.message.contains("maximum of 2 attachments"));
+
+        // Concurrent uploads to a message with room for one more cannot overshoot
+        sqlx::query("DELETE FROM message_attachments WHERE file_name = 'file-1.txt'")
+            .execute(ctx.pool())
+            .await?;
+        let results = tokio::join!(upload(3), upload(4), upload(5), upload(6));
+        let accepted = [results.0.is_ok(), results.1.is_ok(), results.2.is_ok(), results.3.is_ok()]
+            .into_iter()
+            .filter(|accepted| *accepted)
+            .count();
+        assert_eq!(accepted, 1);
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM message_attachments")
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(stored, 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn linked_attachments_are_held_to_the_type_and_size_limits() -> TestResult {
+        let mut config = AppConfig::default();
+        config.chat.max_attachment_bytes = 1024;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_id = ctx.create_chat("chat-linked-file", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-linked-file", "see link").await?;
+
+        let link = |file_type: &str, file_size_bytes: i64| {
+            create_message_attachment(
+                State(ctx.state()),
+                Path(("chat-linked-file".to_string(), "msg-linked-file".to_string())),
+                bearer_headers("test-token"),
+                Json(CreateAttachmentRequest {
+                    file_name: "setup.exe".to_string(),
+                    file_type: file_type.to_string(),
+                    file_url: Some("https://files.example.com/setup.exe".to_string()),
+                    file_size_bytes: Some(file_size_bytes),
+                    content: None,
+                    source_url: None,
+                }),
+            )
+        };
+
+        let err = link("application/x-msdownload", 16)
+            .await
+            .expect_err("disallowed type should be rejected");
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(err.message.contains("is not allowed"), "{}", err.message);
+
+        let err = link("text/plain", 4096)
+            .await
+            .expect_err("oversized attachment should be rejected");
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM message_attachments")
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(stored, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_attachment_stores_inline_content() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_id = ctx.create_chat("chat-inline-file", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-inline-file", "notes").await?;
+
+        let Json(response) = create_message_attachment(
+            State(ctx.state()),
+            Path(("chat-inline-file".to_string(), "msg-inline-file".to_string())),
+            bearer_headers("test-token"),
+            Json(CreateAttachmentRequest {
+                file_name: "notes.txt".to_string(),
+                file_type: "Text/Plain".to_string(),
+                file_url: None,
+                file_size_bytes: None,
+                content: Some("aGVsbG8gd29ybGQ=".to_string()),
+                source_url: None,
+            }),
+        )
+        .await
+        .map_err(|err| anyhow!("create_message_attachment: {}", err.message))?;
+
+        assert_eq!(response.attachment.file_type, "text/plain");
+        assert_eq!(response.attachment.file_size_bytes, 11);
+        assert_eq!(response.attachment.file_url, "data:text/plain;base64,aGVsbG8gd29ybGQ=");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_attachment_rejects_internal_source_urls() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let chat_id = ctx.create_chat("chat-remote-file", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-remote-file", "look").await?;
+
+        let blocked = [
+            "http://127.0.0.1:7070/api/admin",
+            "http://localhost/secret.png",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.0.0.8/image.png",
+            "https://192.168.1.1/",
+            "http://100.64.0.1/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "file:///etc/passwd",
+            "ftp://files.example.com/image.png",
+        ];
+        for source_url in blocked {
+            let err = create_message_attachment(
+                State(ctx.state()),
+                Path(("chat-remote-file".to_string(), "msg-remote-file".to_string())),
+                bearer_headers("test-token"),
+                Json(CreateAttachmentRequest {
+                    file_name: "image.png".to_string(),
+                    file_type: "image/png".to_string(),
+                    file_url: None,
+                    file_size_bytes: None,
+                    content: None,
+                    source_url: Some(source_url.to_string()),
+                }),
+            )
+            .await
+            .expect_err("internal source_url should be rejected");
+            assert_eq!(err.status, StatusCode::BAD_REQUEST, "{source_url}");
+        }
+
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM message_attachments")
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(stored, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn attachment_metadata_rejects_non_members() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "uploader").await?;
+
+        let chat_id = ctx.create_chat("chat-private-files", 2).await?;
+        ctx.add_chat_member(chat_id, 2, "owner").await?;
+        let message_id = ctx
+            .insert_message(chat_id, 2, "msg-private-file", "secret")
+            .await?;
+        let attachment_id = insert_attachment(&ctx, message_id).await?;
+
+        let err = get_attachment_metadata(
+            State(ctx.state()),
+            Path((
+                "chat-private-files".to_string(),
+                "msg-private-file".to_string(),
+                attachment_id,
+            )),
+            bearer_headers("test-token"),
+        )
+        .await
+        .expect_err("expected non-member to be rejected");
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+}
+
+mod websocket_route_tests {
+    use super::*;
+    use axum::{async_trait, http::HeaderValue};
+    use denkwerk::{
+        CompletionRequest, CompletionResponse, CompletionStream, ImageUploadRequest,
+        ImageUploadResponse, LLMError, LLMProvider, ProviderCapabilities, StreamEvent,
+    };
+    use futures_util::{SinkExt, StreamExt};
+    use std::net::SocketAddr;
+    use switchboard_backend_api::streaming::mark_interrupted_messages;
+    use switchboard_orchestrator::{test_support::OrchestratorTestBuilder, ProviderMetadata};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::{
+        connect_async,
+        tungstenite::{client::IntoClientRequest, Error as WsError, Message as WsMessage},
+        MaybeTlsStream, WebSocketStream,
+    };
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn serve(ctx: &TestContext) -> TestResult<SocketAddr> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let router = ctx.router();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        });
+        Ok(address)
+    }
+
+    async fn connect(ctx: &TestContext) -> TestResult<Socket> {
+        let address = serve(ctx).await?;
+        let (socket, _) = connect_async(format!("ws://{address}/ws?token=test-token")).await?;
+        Ok(socket)
+    }
+
+    async fn send(socket: &mut Socket, event: Value) -> TestResult {
+        socket.send(WsMessage::Text(event.to_string())).await?;
+        Ok(())
+    }
+
+    /// Wait for the next event of the given type, skipping unrelated frames.
+    async fn expect_event(socket: &mut Socket, event_type: &str) -> TestResult<Value> {
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(2), socket.next())
+                .await
+                .map_err(|_| anyhow!("timed out waiting for {event_type} event"))?
+                .ok_or_else(|| anyhow!("socket closed waiting for {event_type} event"))??;
+            if let WsMessage::Text(text) = frame {
+                let event: Value = serde_json::from_str(&text)?;
+                if event["type"] == event_type {
+                    return Ok(event);
+                }
+            }
+        }
+    }
+
+    /// Count events of the given type that arrive within `window`.
+    async fn count_events(
+        socket: &mut Socket,
+        event_type: &str,
+        window: Duration,
+    ) -> TestResult<usize> {
+        let mut count = 0;
+        let deadline = tokio::time::Instant::now() + window;
+        while let Ok(frame) = tokio::time::timeout_at(deadline, socket.next()).await {
+            let Some(frame) = frame else { break };
+            if let WsMessage::Text(text) = frame? {
+                let event: Value = serde_json::from_str(&text)?;
+                if event["type"] == event_type {
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    async fn post_message(ctx: &TestContext, chat_public_id: &str, content: &str) -> TestResult {
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri(format!("/api/chats/{chat_public_id}/messages"))
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        serde_json::json!({ "content": content, "role": "user" }).to_string(),
+                    ))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_events_reach_a_subscribed_member_once() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-ws-dedupe", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-ws-dedupe" }),
+        )
+        .await?;
+        expect_event(&mut socket, "subscribed").await?;
+
+        post_message(&ctx, "chat-ws-dedupe", "subscribed").await?;
+        let delivered = count_events(&mut socket, "message", Duration::from_millis(300)).await?;
+        assert_eq!(delivered, 1, "subscribed member should see the message once");
+
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "unsubscribe", "chat_id": "chat-ws-dedupe" }),
+        )
+        .await?;
+        expect_event(&mut socket, "unsubscribed").await?;
+
+        post_message(&ctx, "chat-ws-dedupe", "unsubscribed").await?;
+        let delivered = count_events(&mut socket, "message", Duration::from_millis(300)).await?;
+        assert_eq!(delivered, 1, "user channel should still deliver after unsubscribing");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subscriptions_are_capped_per_connection() -> TestResult {
+        let mut config = AppConfig::default();
+        config.websocket.max_subscriptions_per_connection = 2;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        for chat in ["chat-ws-1", "chat-ws-2", "chat-ws-3"] {
+            let chat_id = ctx.create_chat(chat, 1).await?;
+            ctx.add_chat_member(chat_id, 1, "owner").await?;
+        }
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+
+        for chat in ["chat-ws-1", "chat-ws-2"] {
+            send(&mut socket, serde_json::json!({ "type": "subscribe", "chat_id": chat })).await?;
+            let event = expect_event(&mut socket, "subscribed").await?;
+            assert_eq!(event["chat_id"], chat);
+        }
+
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-ws-3" }),
+        )
+        .await?;
+        let error = expect_event(&mut socket, "error").await?;
+        assert!(error["message"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("Subscription limit"));
+
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "unsubscribe", "chat_id": "chat-ws-1" }),
+        )
+        .await?;
+        expect_event(&mut socket, "unsubscribed").await?;
+
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-ws-3" }),
+        )
+        .await?;
+        let event = expect_event(&mut socket, "subscribed").await?;
+        assert_eq!(event["chat_id"], "chat-ws-3");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_message_returns_one_message_from_the_chat() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-ws-get", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-ws-get", "fetch me").await?;
+        let other_chat = ctx.create_chat("chat-ws-other", 1).await?;
+        ctx.add_chat_member(other_chat, 1, "owner").await?;
+        ctx.insert_message(other_chat, 1, "msg-ws-other", "elsewhere").await?;
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+
+        let get_message = |message_id: &str| {
+            serde_json::json!({
+                "type": "get_message",
+                "chat_id": "chat-ws-get",
+                "message_id": message_id,
+            })
+        };
+
+        send(&mut socket, get_message("msg-ws-get")).await?;
+        let event = expect_event(&mut socket, "message_response").await?;
+        assert_eq!(event["message"]["public_id"], "msg-ws-get");
+        assert_eq!(event["message"]["content"], "fetch me");
+
+        for missing in ["msg-ws-missing", "msg-ws-other"] {
+            send(&mut socket, get_message(missing)).await?;
+            let error = expect_event(&mut socket, "error").await?;
+            assert_eq!(error["message"], "Message not found", "{missing}");
+        }
+
+        Ok(())
+    }
+
+    /// Fails every completion, so assistant replies end in `error`.
+    struct FailingProvider;
+
+    #[async_trait]
+    impl LLMProvider for FailingProvider {
+        async fn complete(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionResponse, LLMError> {
+            Err(LLMError::Unsupported("complete"))
+        }
+
+        async fn stream_completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionStream, LLMError> {
+            Err(LLMError::Unsupported("stream"))
+        }
+
+        async fn upload_image(
+            &self,
+            _request: ImageUploadRequest,
+        ) -> Result<ImageUploadResponse, LLMError> {
+            Err(LLMError::Unsupported("upload"))
+        }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities::default()
+        }
+
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+    }
+
+    async fn failing_provider_context() -> TestResult<TestContext> {
+        failing_provider_context_with(AppConfig::default()).await
+    }
+
+    async fn failing_provider_context_with(mut config: AppConfig) -> TestResult<TestContext> {
+        config.orchestrator.default_model = "mock/model".into();
+        let metadata = ProviderMetadata {
+            identifier: "mock".into(),
+            family: "mock".into(),
+            capabilities: vec!["chat-completions".into()],
+        };
+        let orchestrator = OrchestratorTestBuilder::new(config.orchestrator.clone())
+            .with_provider(metadata, Arc::new(FailingProvider))
+            .build();
+        let ctx = TestContext::with_orchestrator(config, orchestrator).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        Ok(ctx)
+    }
+
+    /// Streams `chunks` one every `gap`, like a slow model.
+    struct SlowStreamProvider {
+        chunks: Vec<&'static str>,
+        gap: Duration,
+    }
+
+    #[async_trait]
+    impl LLMProvider for SlowStreamProvider {
+        async fn complete(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionResponse, LLMError> {
+            Err(LLMError::Unsupported("complete"))
+        }
+
+        async fn stream_completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionStream, LLMError> {
+            let gap = self.gap;
+            let events = futures_util::stream::iter(self.chunks.clone()).then(move |chunk| {
+                async move {
+                    sleep(gap).await;
+                    Ok(StreamEvent::MessageDelta(chunk.to_string()))
+                }
+            });
+            Ok(Box::pin(events))
+        }
+
+        async fn upload_image(
+            &self,
+            _request: ImageUploadRequest,
+        ) -> Result<ImageUploadResponse, LLMError> {
+            Err(LLMError::Unsupported("upload"))
+        }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities::default()
+        }
+
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_streams_are_checkpointed_and_not_swept() -> TestResult {
+        let mut config = AppConfig::default();
+        config.orchestrator.default_model = "mock/model".into();
+        config.chat.stream_checkpoint_interval_ms = 20;
+        let metadata = ProviderMetadata {
+            identifier: "mock".into(),
+            family: "mock".into(),
+            capabilities: vec!["chat-completions".into()],
+        };
+        let provider = SlowStreamProvider {
+            chunks: vec!["one ", "two ", "three ", "four ", "five ", "six ", "seven ", "eight"],
+            gap: Duration::from_millis(50),
+        };
+        let orchestrator = OrchestratorTestBuilder::new(config.orchestrator.clone())
+            .with_provider(metadata, Arc::new(provider))
+            .build();
+        let ctx = TestContext::with_orchestrator(config, orchestrator).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-ws-slow", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-ws-slow" }),
+        )
+        .await?;
+        expect_event(&mut socket, "subscribed").await?;
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "message", "chat_id": "chat-ws-slow", "content": "hi" }),
+        )
+        .await?;
+        let streaming = expect_event(&mut socket, "message_status_changed").await?;
+        assert_eq!(streaming["status"], "streaming");
+        let reply_id = streaming["message_id"].as_str().unwrap_or_default().to_string();
+
+        // Past the stale window for a reply that was never checkpointed, but the
+        // stream is still running and saving
+        sleep(Duration::from_millis(250)).await;
+        let swept = mark_interrupted_messages(ctx.pool(), Duration::from_millis(150)).await?;
+        assert_eq!(swept, 0);
+        let (status, content): (String, String) =
+            sqlx::query_as("SELECT status, content FROM messages WHERE public_id = ?")
+                .bind(&reply_id)
+                .fetch_one(ctx.pool())
+                .await?;
+        assert_eq!(status, "streaming");
+        assert!(content.starts_with("one two "), "{content}");
+
+        let complete = expect_event(&mut socket, "message_status_changed").await?;
+        assert_eq!(complete["message_id"], reply_id.as_str());
+        assert_eq!(complete["status"], "complete");
+        let content: String = sqlx::query_scalar("SELECT content FROM messages WHERE public_id = ?")
+            .bind(&reply_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(content, "one two three four five six seven eight");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_completion_moves_the_reply_from_streaming_to_error() -> TestResult {
+        let ctx = failing_provider_context().await?;
+        let chat_id = ctx.create_chat("chat-ws-status", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-ws-status" }),
+        )
+        .await?;
+        expect_event(&mut socket, "subscribed").await?;
+
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "message", "chat_id": "chat-ws-status", "content": "hi" }),
+        )
+        .await?;
+        let streaming = expect_event(&mut socket, "message_status_changed").await?;
+        assert_eq!(streaming["status"], "streaming");
+        let failed = expect_event(&mut socket, "message_status_changed").await?;
+        assert_eq!(failed["message_id"], streaming["message_id"]);
+        assert_eq!(failed["status"], "error");
+
+        let statuses: Vec<(String, String)> =
+            sqlx::query_as("SELECT role, status FROM messages WHERE chat_id = ? ORDER BY id")
+                .bind(chat_id)
+                .fetch_all(ctx.pool())
+                .await?;
+        assert_eq!(
+            statuses,
+            vec![
+                ("user".to_string(), "sent".to_string()),
+                ("assistant".to_string(), "error".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pinned_chat_model_answers_messages_without_models() -> TestResult {
+        let ctx = failing_provider_context().await?;
+        let chat_id = ctx.create_chat("chat-ws-pinned", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri("/api/chats/chat-ws-pinned/model")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        serde_json::json!({ "default_model": "mock/pinned" }).to_string(),
+                    ))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["chat"]["default_model"], "mock/pinned");
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-ws-pinned" }),
+        )
+        .await?;
+        expect_event(&mut socket, "subscribed").await?;
+
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "message", "chat_id": "chat-ws-pinned", "content": "hi" }),
+        )
+        .await?;
+        expect_event(&mut socket, "message_status_changed").await?;
+        expect_event(&mut socket, "message_status_changed").await?;
+
+        let models: Vec<Option<String>> = sqlx::query_scalar(
+            "SELECT model FROM messages WHERE chat_id = ? AND role = 'assistant'",
+        )
+        .bind(chat_id)
+        .fetch_all(ctx.pool())
+        .await?;
+        assert_eq!(models, vec![Some("mock/pinned".to_string())]);
+
+        Ok(())
+    }
+
+    /// A user prompt followed by an assistant reply in `status`; returns the reply's id.
+    async fn insert_exchange(ctx: &TestContext, chat_id: i64, status: &str) -> TestResult<i64> {
+        ctx.insert_message(chat_id, 1, "msg-prompt", "What is 2 + 2?")
+            .await?;
+        let reply = ctx.insert_message(chat_id, 1, "msg-reply", "5").await?;
+        sqlx::query("UPDATE messages SET role = 'assistant', model = ?, status = ? WHERE id = ?")
+            .bind("mock/model")
+            .bind(status)
+            .bind(reply)
+            .execute(ctx.pool())
+            .await?;
+        Ok(reply)
+    }
+
+    async fn assistant_replies(
+        ctx: &TestContext,
+        chat_id: i64,
+    ) -> TestResult<Vec<(String, Option<String>, String, Option<i64>)>> {
+        Ok(sqlx::query_as(
+            r#"
+            SELECT public_id, model, status, superseded_by FROM messages
+            WHERE chat_id = ? AND role = 'assistant'
+            ORDER BY id
+            "#,
+        )
+        .bind(chat_id)
+        .fetch_all(ctx.pool())
+        .await?)
+    }
+
+    #[tokio::test]
+    async fn regenerate_last_starts_a_new_reply_and_keeps_the_old_one() -> TestResult {
+        let ctx = failing_provider_context().await?;
+        let chat_id = ctx.create_chat("chat-ws-regenerate", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let reply = insert_exchange(&ctx, chat_id, "streaming").await?;
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-ws-regenerate" }),
+        )
+        .await?;
+        expect_event(&mut socket, "subscribed").await?;
+
+        let regenerate = serde_json::json!({
+            "type": "regenerate_last",
+            "chat_id": "chat-ws-regenerate",
+        });
+        send(&mut socket, regenerate.clone()).await?;
+        let error = expect_event(&mut socket, "error").await?;
+        assert_eq!(error["message"], "The last reply is still being generated");
+
+        sqlx::query("UPDATE messages SET status = 'complete' WHERE id = ?")
+            .bind(reply)
+            .execute(ctx.pool())
+            .await?;
+        send(&mut socket, regenerate).await?;
+        let streaming = expect_event(&mut socket, "message_status_changed").await?;
+        assert_eq!(streaming["status"], "streaming");
+        assert_ne!(streaming["message_id"], "msg-reply");
+        let failed = expect_event(&mut socket, "message_status_changed").await?;
+        assert_eq!(failed["message_id"], streaming["message_id"]);
+        assert_eq!(failed["status"], "error");
+
+        // The new reply failed, so the old one is not superseded
+        let new_id = streaming["message_id"].as_str().unwrap_or_default().to_string();
+        let model = Some("mock/model".to_string());
+        assert_eq!(
+            assistant_replies(&ctx, chat_id).await?,
+            vec![
+                ("msg-reply".to_string(), model.clone(), "complete".to_string(), None),
+                (new_id, model, "error".to_string(), None),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn regenerate_endpoint_broadcasts_the_new_reply() -> TestResult {
+        let ctx = failing_provider_context().await?;
+        let chat_id = ctx.create_chat("chat-rest-regenerate", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        let uri = "/api/chats/chat-rest-regenerate/messages/regenerate";
+        let regenerate = |body: Value| -> TestResult<Request<Body>> {
+            Ok(Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(AUTHORIZATION, "Bearer test-token")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))?)
+        };
+
+        let response = ctx
+            .router()
+            .oneshot(regenerate(serde_json::json!({}))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "nothing to regenerate yet");
+
+        insert_exchange(&ctx, chat_id, "complete").await?;
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-rest-regenerate" }),
+        )
+        .await?;
+        expect_event(&mut socket, "subscribed").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(regenerate(serde_json::json!({ "model": "mock/model" }))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["supersedes"], "msg-reply");
+        assert_eq!(payload["model"], "mock/model");
+
+        let streaming = expect_event(&mut socket, "message_status_changed").await?;
+        assert_eq!(streaming["message_id"], payload["message_id"]);
+        assert_eq!(streaming["status"], "streaming");
+        let failed = expect_event(&mut socket, "message_status_changed").await?;
+        assert_eq!(failed["status"], "error");
+        assert_eq!(assistant_replies(&ctx, chat_id).await?.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn regenerating_spends_the_message_flood_allowance() -> TestResult {
+        let mut config = AppConfig::default();
+        config.chat.message_burst = 1;
+        config.chat.messages_per_minute = 1;
+        let ctx = failing_provider_context_with(config).await?;
+        let chat_id = ctx.create_chat("chat-regenerate-flood", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        insert_exchange(&ctx, chat_id, "complete").await?;
+        let regenerate = || -> TestResult<Request<Body>> {
+            Ok(Request::builder()
+                .method(Method::POST)
+                .uri("/api/chats/chat-regenerate-flood/messages/regenerate")
+                .header(AUTHORIZATION, "Bearer test-token")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from("{}"))?)
+        };
+
+        let response = ctx.router().oneshot(regenerate()?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = ctx.router().oneshot(regenerate()?).await?;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "subscribe", "chat_id": "chat-regenerate-flood" }),
+        )
+        .await?;
+        expect_event(&mut socket, "subscribed").await?;
+        send(
+            &mut socket,
+            serde_json::json!({ "type": "regenerate_last", "chat_id": "chat-regenerate-flood" }),
+        )
+        .await?;
+        let error = expect_event(&mut socket, "error").await?;
+        assert!(
+            error["message"]
+                .as_str()
+                .is_some_and(|message| message.starts_with("sending messages too quickly")),
+            "{error}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upgrade_checks_origin_against_allowed_origins() -> TestResult {
+        let mut config = AppConfig::default();
+        config.http.allowed_origins = vec!["https://app.example.com".to_string()];
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let address = serve(&ctx).await?;
+
+        let upgrade = |origin: &'static str| -> TestResult<_> {
+            let mut request = format!("ws://{address}/ws?token=test-token").into_client_request()?;
+            request
+                .headers_mut()
+                .insert(ORIGIN, HeaderValue::from_static(origin));
+            Ok(request)
+        };
+
+        match connect_async(upgrade("https://evil.example")?).await {
+            Err(WsError::Http(response)) => assert_eq!(response.status(), StatusCode::FORBIDDEN),
+            Err(other) => return Err(anyhow!("unexpected upgrade error: {other}")),
+            Ok(_) => return Err(anyhow!("upgrade from a disallowed origin succeeded")),
+        }
+
+        let (mut socket, _) = connect_async(upgrade("https://app.example.com")?).await?;
+        expect_event(&mut socket, "hello").await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upgrade_requires_a_valid_token() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let address = serve(&ctx).await?;
+
+        for url in [format!("ws://{address}/ws"), format!("ws://{address}/ws?token=bogus")] {
+            match connect_async(url.as_str()).await {
+                Err(WsError::Http(response)) => {
+                    assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{url}")
+                }
+                Err(other) => return Err(anyhow!("unexpected upgrade error for {url}: {other}")),
+                Ok(_) => return Err(anyhow!("upgrade without a valid token succeeded: {url}")),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oversize_messages_close_the_connection() -> TestResult {
+        let mut config = AppConfig::default();
+        config.websocket.max_message_bytes = 1024;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let mut socket = connect(&ctx).await?;
+        expect_event(&mut socket, "hello").await?;
+        let padding = "x".repeat(2048);
+        send(&mut socket, serde_json::json!({ "type": "subscribe", "chat_id": padding })).await?;
+
+        let error = expect_event(&mut socket, "error").await?;
+        assert_eq!(error["message"], "Message exceeds the 1024 byte limit");
+        let closed = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                match socket.next().await {
+                    None | Some(Err(_)) | Some(Ok(WsMessage::Close(_))) => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        })
+        .await;
+        assert!(closed.is_ok(), "connection should close after an oversize message");
+
+        Ok(())
+    }
+}
+
+mod util_tests {
+    use super::*;
+    use axum::{
+        extract::FromRequestParts,
+        http::{HeaderMap, HeaderValue},
+    };
+    use switchboard_backend_api::{with_transaction, AuthUser};
+
+    #[test]
+    fn require_bearer_rejects_wrong_scheme() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic abc123"));
+
+        let err = switchboard_backend_api::require_bearer(&headers)
+            .expect_err("expected non-bearer scheme to be rejected");
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        assert!(err.message.contains("invalid authorization scheme"));
+    }
+
+    #[test]
+    fn require_bearer_rejects_missing_header() {
+        let headers = HeaderMap::new();
+        let err = switchboard_backend_api::require_bearer(&headers)
+            .expect_err("expected missing header to be rejected");
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        assert!(err.message.contains("missing authorization header"));
+    }
+
+    async fn contended_pool(temp_dir: &TempDir) -> TestResult<SqlitePool> {
+        // No busy_timeout, so contention surfaces as SQLITE_BUSY immediately.
+        let options = SqliteConnectOptions::new()
+            .filename(temp_dir.path().join("busy.sqlite"))
+            .create_if_missing(true)
+            .busy_timeout(Duration::ZERO);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(2)
+            .connect_with(options)
+            .await?;
+        sqlx::query("CREATE TABLE notes (body TEXT NOT NULL)")
+            .execute(&pool)
+            .await?;
+        Ok(pool)
+    }
+
+    #[tokio::test]
+    async fn retry_on_busy_succeeds_once_the_lock_is_released() -> TestResult {
+        let temp_dir = TempDir::new()?;
+        let pool = contended_pool(&temp_dir).await?;
+
+        let mut holder = pool.acquire().await?;
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut *holder).await?;
+        let release = tokio::spawn(async move {
+            sleep(Duration::from_millis(60)).await;
+            sqlx::query("COMMIT").execute(&mut *holder).await
+        });
+
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        switchboard_backend_api::retry_on_busy(|| {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            sqlx::query("INSERT INTO notes (body) VALUES ('hello')").execute(&pool)
+        })
+        .await?;
+        release.await??;
+
+        assert!(
+            attempts.load(std::sync::atomic::Ordering::SeqCst) > 1,
+            "the first attempt should have hit the held lock"
+        );
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM notes")
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(count, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_on_busy_retries_a_whole_transaction() -> TestResult {
+        let temp_dir = TempDir::new()?;
+        let pool = contended_pool(&temp_dir).await?;
+
+        let mut holder = pool.acquire().await?;
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut *holder).await?;
+        let release = tokio::spawn(async move {
+            sleep(Duration::from_millis(60)).await;
+            sqlx::query("COMMIT").execute(&mut *holder).await
+        });
+
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result = switchboard_backend_api::retry_on_busy(|| {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            switchboard_backend_api::with_transaction(&pool, "Failed to write", async |tx| {
+                sqlx::query("INSERT INTO notes (body) VALUES ('hello')")
+                    .execute(&mut **tx)
+                    .await
+                    .map_err(|e| {
+                        ApiError::internal_server_error("Failed to write").busy_from(&e)
+                    })?;
+                Ok(())
+            })
+        })
+        .await;
+        release.await??;
+
+        assert!(result.is_ok(), "{result:?}");
+        assert!(
+            attempts.load(std::sync::atomic::Ordering::SeqCst) > 1,
+            "the first attempt should have hit the held lock"
+        );
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM notes")
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(count, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_on_busy_returns_other_errors_immediately() -> TestResult {
+        let temp_dir = TempDir::new()?;
+        let pool = contended_pool(&temp_dir).await?;
+
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result = switchboard_backend_api::retry_on_busy(|| {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            sqlx::query("INSERT INTO notes (body) VALUES (NULL)").execute(&pool)
+        })
+        .await;
+
+        assert!(result.is_err(), "NOT NULL violation should be returned");
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    /// Everything a fmt subscriber writes, for asserting on log output.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn queries_over_the_slow_threshold_are_logged() -> TestResult {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let slow = "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < 1000000) SELECT COUNT(*) FROM n";
+        for threshold_ms in [60_000, 5] {
+            let mut config = AppConfig::default();
+            config.database.slow_query_threshold_ms = Some(threshold_ms);
+            let ctx = TestContext::with_config(config).await?;
+            let query = sqlx::query_scalar::<_, i64>(slow).fetch_one(ctx.pool());
+            assert_eq!(ctx.state().timed_query("test.slow", query).await?, 1_000_000);
+        }
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone())?;
+        let warnings: Vec<_> =
+            output.lines().filter(|line| line.contains("slow database query")).collect();
+        assert_eq!(warnings.len(), 1, "only the 5ms threshold should warn: {output}");
+        assert!(warnings[0].contains("WARN"));
+        assert!(warnings[0].contains("test.slow"));
+        assert!(warnings[0].contains("elapsed_ms="));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn with_transaction_rolls_back_when_the_operation_fails() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-rollback", 1).await?;
+
+        let result: Result<(), ApiError> = with_transaction(ctx.pool(), "Failed", async |tx| {
+            let now = Utc::now().to_rfc3339();
+            sqlx::query(
+                "INSERT INTO messages (public_id, chat_id, user_id, content, role, created_at, updated_at) VALUES ('msg-rolled-back', ?, 1, 'partial', 'user', ?, ?)",
+            )
+            .bind(chat_id)
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut **tx)
+            .await
+            .map_err(|_| ApiError::internal_server_error("insert failed"))?;
+            Err(ApiError::bad_request("second step failed"))
+        })
+        .await;
+
+        let error = result.expect_err("the operation's error should be returned");
+        assert_eq!(error.message, "second step failed");
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(count, 0);
+
+        let committed = with_transaction(ctx.pool(), "Failed", async |tx| {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM chats WHERE id = ?")
+                .bind(chat_id)
+                .fetch_one(&mut **tx)
+                .await
+                .map_err(|_| ApiError::internal_server_error("select failed"))
+        })
+        .await
+        .map_err(|e| anyhow!(e.message))?;
+        assert_eq!(committed, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn auth_user_extractor_resolves_bearer_token() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let (mut parts, _) = Request::builder()
+            .header(AUTHORIZATION, "Bearer test-token")
+            .body(())?
+            .into_parts();
+        let AuthUser(user) = AuthUser::from_request_parts(&mut parts, &ctx.state())
+            .await
+            .map_err(|err| anyhow!("{}", err.message))?;
+        assert_eq!(user.id, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn auth_user_routes_reject_unauthenticated_requests() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(Request::builder().uri("/api/folders").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let bytes = response.into_body().collect().await?.to_bytes();
+        let body: Value = serde_json::from_slice(&bytes)?;
+        assert_eq!(body["error"], "missing authorization header");
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/folders")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+}
+
+mod health_route_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn health_check_returns_service_version() -> TestResult {
+        let Json(response) = routes::health::health_check().await;
+        assert_eq!(response.status, "ok");
+        chrono::DateTime::parse_from_rfc3339(&response.timestamp).expect("valid timestamp");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn readiness_reports_healthy_database() -> TestResult {
+        let ctx = TestContext::new().await?;
+        let (status, Json(response)) = routes::health::readiness_check(State(ctx.state())).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(response.status, "down");
+        let database = response
+            .dependencies
+            .iter()
+            .find(|dependency| dependency.name == "database")
+            .expect("database dependency reported");
+        assert_eq!(database.status, "ok");
+        assert!(database.critical);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn readiness_returns_503_when_database_is_down() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.pool().close().await;
+
+        let response = ctx
+            .router()
+            .oneshot(Request::builder().uri("/health/ready").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["status"], "down");
+        let database = payload["dependencies"]
+            .as_array()
+            .and_then(|deps| deps.iter().find(|dep| dep["name"] == "database"))
+            .ok_or_else(|| anyhow!("database dependency missing"))?;
+        assert_eq!(database["status"], "down");
+        // The driver's error stays in the logs
+        assert_eq!(database["detail"], "unavailable");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn liveness_does_not_depend_on_database() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.pool().close().await;
+
+        let response = ctx
+            .router()
+            .oneshot(Request::builder().uri("/health/live").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+}
+
+mod auth_route_tests {
+    use super::*;
+    use axum::http::header::COOKIE;
+    use switchboard_auth::ApiKeyScope;
+    use switchboard_backend_api::audit::ClientIp;
+
+    #[tokio::test]
+    async fn github_login_requires_oauth_configuration() -> TestResult {
+        let ctx = TestContext::new().await?;
+        let result = routes::auth::github_login(
+            State(ctx.state()),
+            Query(routes::auth::GithubLoginQuery {
+                redirect_uri: "https://example.com/callback".into(),
+            }),
+        )
+        .await;
+
+        let err = result.expect_err("expected login without configuration to fail");
+        assert_eq!(err.status, StatusCode::SERVICE_UNAVAILABLE);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn github_login_issues_state_and_authorization_url() -> TestResult {
+        let ctx = TestContext::with_github().await?;
+        let state = ctx.state();
+        let redirect_uri = "https://example.com/callback";
+
+        let response = routes::auth::github_login(
+            State(state.clone()),
             Query(routes::auth::GithubLoginQuery {
                 redirect_uri: redirect_uri.into(),
             }),
         )
         .await
-        .expect("github login should succeed");
+        .expect("github login should succeed");
+
+        let authorize_url = &response.0.authorize_url;
+        let parsed = Url::parse(authorize_url)?;
+
+        let state_param = parsed
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, value)| value.to_string())
+            .expect("state parameter to be present");
+        assert_eq!(state_param.len(), 32);
+        assert!(
+            state.oauth_state().consume(&state_param).await,
+            "state should be cached for consumption"
+        );
+
+        let redirect_param = parsed
+            .query_pairs()
+            .find(|(key, _)| key == "redirect_uri")
+            .map(|(_, value)| value.to_string())
+            .expect("redirect_uri parameter to be present");
+        assert_eq!(redirect_param, redirect_uri);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn github_login_accepts_wildcard_subpath_redirect() -> TestResult {
+        let ctx = TestContext::with_github().await?;
+        let response = routes::auth::github_login(
+            State(ctx.state()),
+            Query(routes::auth::GithubLoginQuery {
+                redirect_uri: "https://preview.example.com/auth/pr-42/callback".into(),
+            }),
+        )
+        .await
+        .expect("wildcard subpath should be allowed");
+
+        assert!(response.0.authorize_url.contains("preview.example.com"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn github_login_rejects_unlisted_redirect() -> TestResult {
+        let ctx = TestContext::with_github().await?;
+
+        for redirect_uri in [
+            "https://evil.example.net/callback",
+            "https://example.com/callback/extra",
+            "https://preview.example.com/other",
+            "https://preview.example.com/auth/../other",
+        ] {
+            let result = routes::auth::github_login(
+                State(ctx.state()),
+                Query(routes::auth::GithubLoginQuery {
+                    redirect_uri: redirect_uri.into(),
+                }),
+            )
+            .await;
+
+            let err = result.expect_err("unlisted redirect should be rejected");
+            assert_eq!(err.status, StatusCode::BAD_REQUEST, "{redirect_uri}");
+            assert!(err.message.contains("redirect uri is not allowed"));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn github_callback_rejects_unknown_state() -> TestResult {
+        let ctx = TestContext::with_github().await?;
+        let result = routes::auth::github_callback(
+            State(ctx.state()),
+            ClientIp(None),
+            Json(routes::auth::GithubCallbackRequest {
+                code: "dummy".into(),
+                state: "missing-state".into(),
+                redirect_uri: "https://example.com/callback".into(),
+            }),
+        )
+        .await;
+
+        let err = result.expect_err("expected callback to reject unknown state");
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(
+            err.message.contains("invalid or expired"),
+            "error message should reference invalid or expired state"
+        );
+        Ok(())
+    }
+
+    async fn send(
+        ctx: &TestContext,
+        method: Method,
+        uri: &str,
+        token: &str,
+    ) -> TestResult<StatusCode> {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"title":"From a key"}"#))?;
+        Ok(ctx.router().oneshot(request).await?.status())
+    }
+
+    #[tokio::test]
+    async fn authorization_header_takes_precedence_over_session_cookie() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-2").await?;
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO sessions (token, user_id, expires_at, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind("cookie-token")
+        .bind(2i64)
+        .bind((now + chrono::Duration::hours(1)).to_rfc3339())
+        .bind(now.to_rfc3339())
+        .execute(ctx.pool())
+        .await?;
+
+        let get_me = |authorization: Option<&str>| {
+            let mut request = Request::builder()
+                .uri("/api/users/me")
+                .header(COOKIE, "session=cookie-token; token=cookie-token");
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            request.body(Body::empty())
+        };
+
+        let response = ctx.router().oneshot(get_me(Some("Bearer test-token"))?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let user: Value = serde_json::from_slice(&body)?;
+        assert_eq!(user["id"], "dev-user-123");
+
+        let response = ctx.router().oneshot(get_me(None)?).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn api_key_routes_create_list_and_revoke_keys() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let headers = bearer_headers("test-token");
+
+        let created = routes::auth::create_api_key(
+            State(ctx.state()),
+            ClientIp(None),
+            headers.clone(),
+            Json(routes::auth::CreateApiKeyRequest {
+                name: "deploy bot".into(),
+                scopes: vec!["chats:read".into()],
+            }),
+        )
+        .await
+        .expect("api key should be created")
+        .0;
+        assert!(created.key.starts_with(&created.api_key.prefix));
+        assert_eq!(created.api_key.scopes, vec!["chats:read"]);
+
+        let listed = routes::auth::list_api_keys(State(ctx.state()), headers.clone())
+            .await
+            .expect("api keys should be listed")
+            .0;
+        assert_eq!(listed.api_keys.len(), 1);
+        assert_eq!(listed.api_keys[0].id, created.api_key.id);
+        let listed_json = serde_json::to_string(&listed)?;
+        assert!(!listed_json.contains(&created.key), "key must not be listed");
+
+        routes::auth::revoke_api_key(
+            State(ctx.state()),
+            ClientIp(None),
+            headers.clone(),
+            axum::extract::Path(created.api_key.id.clone()),
+        )
+        .await
+        .expect("api key should be revoked");
+        let listed = routes::auth::list_api_keys(State(ctx.state()), headers.clone())
+            .await
+            .expect("api keys should be listed")
+            .0;
+        assert!(listed.api_keys.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_api_key_rejects_unknown_scopes() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let err = routes::auth::create_api_key(
+            State(ctx.state()),
+            ClientIp(None),
+            bearer_headers("test-token"),
+            Json(routes::auth::CreateApiKeyRequest {
+                name: " ".into(),
+                scopes: vec!["chats:read".into(), "chats:admin".into()],
+            }),
+        )
+        .await
+        .expect_err("invalid payload should be rejected");
+
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        let fields: Vec<&str> = err.fields.iter().map(|field| field.field.as_str()).collect();
+        assert_eq!(fields, vec!["name", "scopes[1]"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_only_api_key_is_rejected_on_write_routes() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let (_, read_key) = ctx
+            .state()
+            .authenticator()
+            .create_api_key(1, "reader", &[ApiKeyScope::ChatsRead])
+            .await?;
+
+        assert_eq!(send(&ctx, Method::GET, "/api/chats", &read_key).await?, StatusCode::OK);
+        assert_eq!(
+            send(&ctx, Method::POST, "/api/chats", &read_key).await?,
+            StatusCode::FORBIDDEN
+        );
+        // Reading chats does not extend to other resources
+        assert_eq!(
+            send(&ctx, Method::GET, "/api/folders", &read_key).await?,
+            StatusCode::FORBIDDEN
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn api_key_scopes_limit_reachable_routes() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let authenticator = ctx.state().authenticator().clone();
+        let (write_summary, write_key) = authenticator
+            .create_api_key(1, "writer", &[ApiKeyScope::ChatsRead, ApiKeyScope::ChatsWrite])
+            .await?;
+
+        assert_eq!(send(&ctx, Method::POST, "/api/chats", &write_key).await?, StatusCode::OK);
+        assert_eq!(send(&ctx, Method::GET, "/health", &write_key).await?, StatusCode::OK);
+        // Session tokens hold every scope
+        assert_eq!(send(&ctx, Method::GET, "/api/folders", "test-token").await?, StatusCode::OK);
+
+        // Keys cannot manage keys or reach admin routes, whatever their scopes
+        assert_eq!(
+            send(&ctx, Method::GET, "/api/auth/api-keys", &write_key).await?,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&ctx, Method::GET, "/api/admin/audit", &write_key).await?,
+            StatusCode::FORBIDDEN
+        );
+
+        authenticator
+            .revoke_api_key(1, &write_summary.public_id)
+            .await?;
+        assert_eq!(
+            send(&ctx, Method::GET, "/api/chats", &write_key).await?,
+            StatusCode::UNAUTHORIZED
+        );
+
+        Ok(())
+    }
+}
+
+mod invite_tests {
+    use super::*;
+    use axum::extract::{Path, Query};
+    use switchboard_backend_api::{
+        routes::{chats::ListInvitesQuery, models::CreateInviteRequest},
+        AuthUser,
+    };
+
+    async fn group_chat(ctx: &TestContext, public_id: &str) -> TestResult<i64> {
+        let chat_id = ctx.create_chat(public_id, 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        sqlx::query("UPDATE chats SET chat_type = 'group', is_group = TRUE WHERE id = ?")
+            .bind(chat_id)
+            .execute(ctx.pool())
+            .await?;
+        Ok(chat_id)
+    }
+
+    async fn invite_user(
+        ctx: &TestContext,
+        chat: &str,
+        username: &str,
+    ) -> Result<routes::models::ChatInvite, ApiError> {
+        routes::chats::create_invite(
+            State(ctx.state()),
+            Path(chat.to_string()),
+            bearer_headers("test-token"),
+            Json(CreateInviteRequest {
+                email: None,
+                username: Some(username.to_string()),
+            }),
+        )
+        .await
+        .map(|response| response.0.invite)
+    }
+
+    #[tokio::test]
+    async fn invite_by_username_reaches_the_named_user() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = group_chat(&ctx, "chat-invites").await?;
+        ctx.insert_user(2, "user-ada").await?;
+        sqlx::query("UPDATE users SET display_name = 'Ada', email = NULL WHERE id = 2")
+            .execute(ctx.pool())
+            .await?;
+
+        let invite = invite_user(&ctx, "chat-invites", "@ada")
+            .await
+            .expect("invite by username");
+        assert_eq!(invite.invitee_user_id, Some(2));
+        assert_eq!(invite.invitee_email, None);
+
+        let notifications: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM notifications WHERE user_id = 2 AND type = 'chat_invite'",
+        )
+        .fetch_one(ctx.pool())
+        .await?;
+        assert_eq!(notifications, 1);
+
+        // Ada has no email, so only the user id can match the invite
+        let expires_at = (Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+        sqlx::query(
+            "INSERT INTO sessions (token, user_id, expires_at, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind("ada-token")
+        .bind(2i64)
+        .bind(&expires_at)
+        .bind(Utc::now().to_rfc3339())
+        .execute(ctx.pool())
+        .await?;
+        routes::chats::accept_invite(
+            State(ctx.state()),
+            Path(invite.public_id.clone()),
+            bearer_headers("ada-token"),
+        )
+        .await
+        .expect("named user accepts the invite");
+
+        let role: String =
+            sqlx::query_scalar("SELECT role FROM chat_members WHERE chat_id = ? AND user_id = 2")
+                .bind(chat_id)
+                .fetch_one(ctx.pool())
+                .await?;
+        assert_eq!(role, "member");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invite_by_unknown_or_shared_username_is_rejected() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        group_chat(&ctx, "chat-invites").await?;
+        ctx.insert_user(2, "user-sam-a").await?;
+        ctx.insert_user(3, "user-sam-b").await?;
+        sqlx::query("UPDATE users SET display_name = 'Sam' WHERE id IN (2, 3)")
+            .execute(ctx.pool())
+            .await?;
+
+        let err = invite_user(&ctx, "chat-invites", "@nobody")
+            .await
+            .expect_err("unknown handle");
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+        assert!(err.message.contains("@nobody"), "{}", err.message);
+
+        let err = invite_user(&ctx, "chat-invites", "sam")
+            .await
+            .expect_err("ambiguous handle");
+        assert_eq!(err.status, StatusCode::CONFLICT);
+
+        let invites: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chat_invites")
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(invites, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invite_needs_exactly_one_of_email_and_username() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        group_chat(&ctx, "chat-invites").await?;
+
+        for (email, username) in [(None, None), (Some("a@example.com"), Some("ada"))] {
+            let err = routes::chats::create_invite(
+                State(ctx.state()),
+                Path("chat-invites".to_string()),
+                bearer_headers("test-token"),
+                Json(CreateInviteRequest {
+                    email: email.map(str::to_string),
+                    username: username.map(str::to_string),
+                }),
+            )
+            .await
+            .expect_err("ambiguous invitee");
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        }
+
+        Ok(())
+    }
+
+    fn invite_ids(response: Json<routes::models::InvitesResponse>) -> Vec<String> {
+        response.0.invites.into_iter().map(|invite| invite.public_id).collect()
+    }
+
+    #[tokio::test]
+    async fn invite_listings_filter_by_status_and_page() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "invite-owner").await?;
+        let chat_id = ctx.create_chat("chat-invite-list", 2).await?;
+        ctx.add_chat_member(chat_id, 2, "owner").await?;
+        ctx.add_chat_member(chat_id, 1, "member").await?;
+
+        // Oldest first; user 1 is the invitee of every invite without an email
+        let seeded = [
+            ("inv-pending-1", "pending", None),
+            ("inv-accepted", "accepted", None),
+            ("inv-pending-2", "pending", None),
+            ("inv-rejected", "rejected", None),
+            ("inv-pending-3", "pending", None),
+            ("inv-email", "pending", Some("someone@example.com")),
+        ];
+        for (index, (public_id, status, email)) in seeded.into_iter().enumerate() {
+            let created_at = format!("2026-01-0{}T00:00:00+00:00", index + 1);
+            sqlx::query(
+                "INSERT INTO chat_invites (public_id, chat_id, inviter_id, invitee_email, invitee_user_id, status, created_at, updated_at) VALUES (?, ?, 2, ?, ?, ?, ?, ?)",
+            )
+            .bind(public_id)
+            .bind(chat_id)
+            .bind(email)
+            .bind(email.is_none().then_some(1_i64))
+            .bind(status)
+            .bind(&created_at)
+            .bind(&created_at)
+            .execute(ctx.pool())
+            .await?;
+        }
+
+        let list_chat = |query: ListInvitesQuery| {
+            routes::chats::list_invites(
+                State(ctx.state()),
+                Path("chat-invite-list".to_string()),
+                bearer_headers("test-token"),
+                Query(query),
+            )
+        };
+        let pending = || ListInvitesQuery {
+            status: Some("pending".into()),
+            ..Default::default()
+        };
+
+        let page = list_chat(pending()).await.expect("pending chat invites");
+        assert_eq!(
+            invite_ids(page),
+            ["inv-email", "inv-pending-3", "inv-pending-2", "inv-pending-1"]
+        );
+
+        let page = list_chat(ListInvitesQuery {
+            order: Some("asc".into()),
+            limit: Some(2),
+            offset: Some(1),
+            ..pending()
+        })
+        .await
+        .expect("second page of pending chat invites");
+        assert_eq!(invite_ids(page), ["inv-pending-2", "inv-pending-3"]);
+
+        let (user, _) = ctx
+            .state()
+            .authenticate("test-token")
+            .await
+            .map_err(|e| anyhow!(e.message))?;
+        let list_mine = |query: ListInvitesQuery| {
+            routes::chats::list_user_invites(
+                State(ctx.state()),
+                AuthUser(user.clone()),
+                Query(query),
+            )
+        };
+        let page = list_mine(pending()).await.expect("pending invites for user 1");
+        assert_eq!(invite_ids(page), ["inv-pending-3", "inv-pending-2", "inv-pending-1"]);
+
+        let err = list_mine(ListInvitesQuery {
+            status: Some("lost".into()),
+            ..Default::default()
+        })
+        .await
+        .expect_err("unknown status");
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+}
+
+mod user_route_tests {
+    use super::*;
+
+    async fn get_me(ctx: &TestContext, query: &str) -> TestResult<(StatusCode, Value)> {
+        let request = Request::builder()
+            .uri(format!("/api/users/me{query}"))
+            .header(AUTHORIZATION, "Bearer test-token")
+            .body(Body::empty())?;
+        let response = ctx.router().oneshot(request).await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    #[tokio::test]
+    async fn current_user_returns_every_field_by_default() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let (status, body) = get_me(&ctx, "").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], "dev-user-123");
+        assert_eq!(body["email"], "dev@example.com");
+        assert_eq!(body["display_name"], "Dev User");
+        assert!(body["created_at"].is_string());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn current_user_returns_only_selected_fields() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let (status, body) = get_me(&ctx, "?fields=display_name,avatar_url").await?;
+        assert_eq!(status, StatusCode::OK);
+        // `avatar_url` is not a user field and is ignored
+        assert_eq!(
+            body,
+            serde_json::json!({ "id": "dev-user-123", "display_name": "Dev User" })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unknown_selected_fields_can_be_rejected() -> TestResult {
+        let mut config = AppConfig::default();
+        config.http.reject_unknown_fields = true;
+        let ctx = TestContext::with_config(config).await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let (status, body) = get_me(&ctx, "?fields=display_name,avatar_url").await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["fields"][0]["field"], "fields");
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("avatar_url"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn export_has_one_entry_per_chat_of_the_user() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "export-stranger").await?;
+
+        let first = ctx.create_chat("chat-export-one", 1).await?;
+        ctx.add_chat_member(first, 1, "owner").await?;
+        let message_id = ctx.insert_message(first, 1, "msg-export-one", "hello").await?;
+        sqlx::query(
+            r#"
+            INSERT INTO message_attachments (message_id, file_name, file_type, file_url, file_size_bytes, created_at)
+            VALUES (?, 'notes.txt', 'text/plain', 'https://files.example.com/notes.txt', 12, ?)
+            "#,
+        )
+        .bind(message_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(ctx.pool())
+        .await?;
+        let second = ctx.create_chat("chat-export-two", 2).await?;
+        ctx.add_chat_member(second, 2, "owner").await?;
+        ctx.add_chat_member(second, 1, "member").await?;
+        let foreign = ctx.create_chat("chat-export-foreign", 2).await?;
+        ctx.add_chat_member(foreign, 2, "owner").await?;
+
+        let request = Request::builder()
+            .uri("/api/users/me/export")
+            .header(AUTHORIZATION, "Bearer test-token")
+            .body(Body::empty())?;
+        let response = ctx.router().oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/x-ndjson");
+
+        let body = response.into_body().collect().await?.to_bytes();
+        let entries = std::str::from_utf8(&body)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()?;
+        let chat_ids: Vec<&str> = entries
+            .iter()
+            .filter_map(|entry| entry["chat"]["public_id"].as_str())
+            .collect();
+        assert_eq!(chat_ids, ["chat-export-one", "chat-export-two"]);
+
+        assert_eq!(entries[0]["messages"][0]["content"], "hello");
+        assert_eq!(entries[0]["attachments"][0]["message_id"], "msg-export-one");
+        assert_eq!(entries[0]["attachments"][0]["file_name"], "notes.txt");
+        assert_eq!(entries[1]["messages"], serde_json::json!([]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rotating_the_public_id_retires_the_old_one() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/users/me/public-id")
+            .header(AUTHORIZATION, "Bearer test-token")
+            .body(Body::empty())?;
+        let response = ctx.router().oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let rotated: Value = serde_json::from_slice(&body)?;
+        let new_id = rotated["id"].as_str().ok_or_else(|| anyhow!("missing id"))?;
+        assert_ne!(new_id, "dev-user-123");
+
+        let resolve = |public_id: &str| {
+            sqlx::query_scalar::<_, i64>("SELECT id FROM users WHERE public_id = ?")
+                .bind(public_id.to_string())
+                .fetch_optional(ctx.pool())
+        };
+        assert_eq!(resolve("dev-user-123").await?, None);
+        assert_eq!(resolve(new_id).await?, Some(1));
+
+        // The session keys on the internal id, so it carries over
+        let (status, me) = get_me(&ctx, "").await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(me["id"], new_id);
+        Ok(())
+    }
+}
+
+mod admin_route_tests {
+    use super::*;
+    use axum::extract::ConnectInfo;
+    use std::net::SocketAddr;
+    use switchboard_backend_api::audit::{self, record_audit, ClientIp};
+
+    type AuditRow = (Option<i64>, Option<String>, Option<String>);
+
+    async fn audit_rows(ctx: &TestContext, action: &str) -> TestResult<Vec<AuditRow>> {
+        let rows = sqlx::query_as(
+            "SELECT actor_user_id, target, ip FROM audit_log WHERE action = ? ORDER BY id",
+        )
+        .bind(action)
+        .fetch_all(ctx.pool())
+        .await?;
+        Ok(rows)
+    }
+
+    async fn make_workspace_admin(ctx: &TestContext, user_id: i64) -> TestResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO permissions (user_id, resource_type, resource_id, permission_level, granted_at)
+            VALUES (?, 'workspace', 1, 'admin', ?)
+            "#,
+        )
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(ctx.pool())
+        .await?;
+        Ok(())
+    }
+
+    fn get_audit(uri: &str) -> TestResult<Request<Body>> {
+        Ok(Request::builder()
+            .uri(uri)
+            .header(AUTHORIZATION, "Bearer test-token")
+            .body(Body::empty())?)
+    }
+
+    fn dev_login_from(peer: &str, forwarded_for: &str) -> TestResult<Request<Body>> {
+        Ok(Request::builder()
+            .uri("/api/auth/dev/token")
+            .header("x-forwarded-for", forwarded_for)
+            .extension(ConnectInfo(SocketAddr::new(peer.parse()?, 40_000)))
+            .body(Body::empty())?)
+    }
+
+    #[tokio::test]
+    async fn dev_login_is_audited_with_client_ip() -> TestResult {
+        let mut config = AppConfig::default();
+        config.http.trusted_proxies = vec!["127.0.0.1".parse()?, "10.0.0.1".parse()?];
+        let ctx = TestContext::with_config(config).await?;
+        let response = ctx
+            .router()
+            .oneshot(dev_login_from("127.0.0.1", "198.51.100.9, 203.0.113.7, 10.0.0.1")?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let rows = audit_rows(&ctx, "login_succeeded").await?;
+        assert_eq!(
+            rows,
+            vec![(Some(1), Some("dev".to_string()), Some("203.0.113.7".to_string()))]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forwarded_for_from_untrusted_peer_is_ignored() -> TestResult {
+        let mut config = AppConfig::default();
+        config.http.trusted_proxies = vec!["127.0.0.1".parse()?];
+        let ctx = TestContext::with_config(config).await?;
+        let response = ctx
+            .router()
+            .oneshot(dev_login_from("198.51.100.4", "203.0.113.7")?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let rows = audit_rows(&ctx, "login_succeeded").await?;
+        assert_eq!(
+            rows,
+            vec![(Some(1), Some("dev".to_string()), Some("198.51.100.4".to_string()))]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_github_login_is_audited() -> TestResult {
+        let ctx = TestContext::with_github().await?;
+        ctx.state()
+            .oauth_state()
+            .store("known-state".into())
+            .await;
+
+        let result = routes::auth::github_callback(
+            State(ctx.state()),
+            ClientIp(None),
+            Json(routes::auth::GithubCallbackRequest {
+                code: "dummy".into(),
+                state: "known-state".into(),
+                redirect_uri: "https://evil.example.net/callback".into(),
+            }),
+        )
+        .await;
+        assert!(result.is_err());
+
+        let rows = audit_rows(&ctx, "login_failed").await?;
+        assert_eq!(rows, vec![(None, Some("github".to_string()), None)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn permission_grant_and_revoke_are_audited() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-two").await?;
+        let chat_id = ctx.create_chat("chat-audit", 1).await?;
+        sqlx::query(
+            r#"
+            INSERT INTO permissions (user_id, resource_type, resource_id, permission_level, granted_at)
+            VALUES (1, 'chat', ?, 'admin', ?)
+            "#,
+        )
+        .bind(chat_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(ctx.pool())
+        .await?;
+
+        let grant = Request::builder()
+            .method(Method::POST)
+            .uri("/api/permissions/chat/chat-audit")
+            .header(AUTHORIZATION, "Bearer test-token")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "user_id": "user-two",
+                    "resource_type": "chat",
+                    "resource_id": "chat-audit",
+                    "permission_level": "write"
+                })
+                .to_string(),
+            ))?;
+        let response = ctx.router().oneshot(grant).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let revoke = Request::builder()
+            .method(Method::DELETE)
+            .uri("/api/permissions/chat/chat-audit/user-two")
+            .header(AUTHORIZATION, "Bearer test-token")
+            .body(Body::empty())?;
+        let response = ctx.router().oneshot(revoke).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let granted = audit_rows(&ctx, "permission_granted").await?;
+        assert_eq!(
+            granted,
+            vec![(Some(1), Some("chat:chat-audit:user-two:write".to_string()), None)]
+        );
+        let revoked = audit_rows(&ctx, "permission_revoked").await?;
+        assert_eq!(
+            revoked,
+            vec![(Some(1), Some("chat:chat-audit:user-two".to_string()), None)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn audit_log_requires_workspace_admin() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let response = ctx.router().oneshot(get_audit("/api/admin/audit")?).await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn audit_log_filters_entries_for_admins() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        make_workspace_admin(&ctx, 1).await?;
+        ctx.insert_user(2, "user-two").await?;
+
+        let state = ctx.state();
+        record_audit(&state, Some(1), audit::LOGIN_SUCCEEDED, None, None).await;
+        record_audit(&state, Some(2), audit::LOGIN_SUCCEEDED, None, None).await;
+        record_audit(&state, None, audit::LOGIN_FAILED, None, None).await;
+
+        let response = ctx
+            .router()
+            .oneshot(get_audit("/api/admin/audit?action=login_succeeded&actor=user-two")?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        let entries = payload["entries"].as_array().expect("entries array");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["actor_user_id"], 2);
+
+        let response = ctx
+            .router()
+            .oneshot(get_audit("/api/admin/audit?limit=2")?)
+            .await?;
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        let entries = payload["entries"].as_array().expect("entries array");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["action"], "login_failed");
+
+        let response = ctx
+            .router()
+            .oneshot(get_audit("/api/admin/audit?since=2020-01-01T00:00:00Z")?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for bad in [
+            "/api/admin/audit?action=dropped_tables",
+            "/api/admin/audit?until=yesterday",
+        ] {
+            let response = ctx.router().oneshot(get_audit(bad)?).await?;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{bad}");
+        }
+        Ok(())
+    }
+
+    async fn insert_session(ctx: &TestContext, token: &str, user_id: i64) -> TestResult<()> {
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO sessions (token, user_id, expires_at, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(token)
+        .bind(user_id)
+        .bind((now + chrono::Duration::hours(1)).to_rfc3339())
+        .bind(now.to_rfc3339())
+        .execute(ctx.pool())
+        .await?;
+        Ok(())
+    }
+
+    fn sessions_request(method: Method, user_id: &str) -> TestResult<Request<Body>> {
+        Ok(Request::builder()
+            .method(method)
+            .uri(format!("/api/admin/users/{user_id}/sessions"))
+            .header(AUTHORIZATION, "Bearer test-token")
+            .body(Body::empty())?)
+    }
+
+    #[tokio::test]
+    async fn admins_can_list_and_revoke_another_users_sessions() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        make_workspace_admin(&ctx, 1).await?;
+        ctx.insert_user(2, "user-two").await?;
+        insert_session(&ctx, "user-two-laptop-token", 2).await?;
+        insert_session(&ctx, "user-two-phone-token", 2).await?;
+
+        let response = ctx
+            .router()
+            .oneshot(sessions_request(Method::GET, "user-two")?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let text = std::str::from_utf8(&body)?;
+        assert!(!text.contains("laptop-token") && !text.contains("phone-token"));
+        let payload: Value = serde_json::from_str(text)?;
+        assert_eq!(payload["sessions"].as_array().map(Vec::len), Some(2));
+
+        let response = ctx
+            .router()
+            .oneshot(sessions_request(Method::DELETE, "user-two")?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["revoked"], 2);
+
+        assert!(ctx.state().authenticate("user-two-laptop-token").await.is_err());
+        assert_eq!(
+            audit_rows(&ctx, audit::SESSIONS_REVOKED).await?,
+            vec![(Some(1), Some("user-two".to_string()), None)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn non_admins_cannot_revoke_sessions() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-two").await?;
+        insert_session(&ctx, "user-two-token", 2).await?;
+
+        for method in [Method::GET, Method::DELETE] {
+            let response = ctx
+                .router()
+                .oneshot(sessions_request(method, "user-two")?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+
+        assert!(ctx.state().authenticate("user-two-token").await.is_ok());
+        assert!(audit_rows(&ctx, audit::SESSIONS_REVOKED).await?.is_empty());
+        Ok(())
+    }
+}
+
+mod retention_tests {
+    use super::*;
+    use switchboard_backend_api::retention::{purge_expired_messages, run_retention_sweeper};
+
+    fn put_retention(chat_public_id: &str, days: Option<i64>) -> TestResult<Request<Body>> {
+        Ok(Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/api/chats/{chat_public_id}/retention"))
+            .header(AUTHORIZATION, "Bearer test-token")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::json!({ "message_retention_days": days }).to_string()))?)
+    }
+
+    async fn backdate_message(ctx: &TestContext, message_id: i64, days: i64) -> TestResult<()> {
+        let created_at = (Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+        sqlx::query("UPDATE messages SET created_at = ? WHERE id = ?")
+            .bind(created_at)
+            .bind(message_id)
+            .execute(ctx.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn message_ids(ctx: &TestContext) -> TestResult<Vec<String>> {
+        let ids = sqlx::query_scalar("SELECT public_id FROM messages ORDER BY id")
+            .fetch_all(ctx.pool())
+            .await?;
+        Ok(ids)
+    }
+
+    #[tokio::test]
+    async fn messages_past_retention_are_purged() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let ephemeral = ctx.create_chat("chat-ephemeral", 1).await?;
+        ctx.add_chat_member(ephemeral, 1, "owner").await?;
+        let stale = ctx.insert_message(ephemeral, 1, "msg-stale", "old news").await?;
+        backdate_message(&ctx, stale, 10).await?;
+        let recent = ctx.insert_message(ephemeral, 1, "msg-recent", "still fresh").await?;
+        backdate_message(&ctx, recent, 3).await?;
+
+        let archive = ctx.create_chat("chat-archive", 1).await?;
+        ctx.add_chat_member(archive, 1, "owner").await?;
+        let kept = ctx.insert_message(archive, 1, "msg-archived", "keep forever").await?;
+        backdate_message(&ctx, kept, 400).await?;
+
+        let response = ctx
+            .router()
+            .oneshot(put_retention("chat-ephemeral", Some(7))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["chat"]["message_retention_days"], 7);
+
+        let purged = purge_expired_messages(ctx.pool()).await?;
+        assert_eq!(purged, 1);
+        assert_eq!(message_ids(&ctx).await?, vec!["msg-recent", "msg-archived"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retention_requires_owner_or_admin() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-two").await?;
+
+        let chat_id = ctx.create_chat("chat-shared", 2).await?;
+        ctx.add_chat_member(chat_id, 2, "owner").await?;
+        ctx.add_chat_member(chat_id, 1, "member").await?;
+
+        let response = ctx
+            .router()
+            .oneshot(put_retention("chat-shared", Some(1))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        sqlx::query("UPDATE chat_members SET role = 'admin' WHERE chat_id = ? AND user_id = 1")
+            .bind(chat_id)
+            .execute(ctx.pool())
+            .await?;
+        let response = ctx
+            .router()
+            .oneshot(put_retention("chat-shared", Some(0))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = ctx
+            .router()
+            .oneshot(put_retention("chat-shared", None)?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sweeper_stops_on_shutdown() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-swept", 1).await?;
+        sqlx::query("UPDATE chats SET message_retention_days = 1 WHERE id = ?")
+            .bind(chat_id)
+            .execute(ctx.pool())
+            .await?;
+        let stale = ctx.insert_message(chat_id, 1, "msg-stale", "old news").await?;
+        backdate_message(&ctx, stale, 2).await?;
+
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let sweeper = tokio::spawn(run_retention_sweeper(
+            ctx.state(),
+            Duration::from_millis(20),
+            async move {
+                let _ = stop_rx.await;
+            },
+        ));
+
+        sleep(Duration::from_millis(100)).await;
+        assert!(message_ids(&ctx).await?.is_empty(), "sweeper should purge on its first tick");
+
+        stop_tx.send(()).ok();
+        tokio::time::timeout(Duration::from_secs(1), sweeper).await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sweeper_runs_are_reported_at_health_tasks() -> TestResult {
+        let ctx = TestContext::new().await?;
+        let tasks = || async {
+            let request = Request::builder().uri("/health/tasks").body(Body::empty())?;
+            let response = ctx.router().oneshot(request).await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().collect().await?.to_bytes();
+            TestResult::<Value>::Ok(serde_json::from_slice(&body)?)
+        };
+        assert_eq!(tasks().await?["tasks"], serde_json::json!([]));
+
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let sweeper = tokio::spawn(run_retention_sweeper(
+            ctx.state(),
+            Duration::from_secs(60),
+            async move {
+                let _ = stop_rx.await;
+            },
+        ));
+        sleep(Duration::from_millis(100)).await;
+
+        let payload = tasks().await?;
+        assert_eq!(payload["status"], "ok");
+        let task = &payload["tasks"][0];
+        assert_eq!(task["name"], "message_retention");
+        assert_eq!(task["interval_secs"], 60);
+        assert!(task["last_run"].is_string(), "first tick should have run: {task}");
+        assert_eq!(task["last_result"], "ok");
+        assert_eq!(task["detail"], "purged 0 messages");
+        assert_eq!(task["stale"], false);
+
+        stop_tx.send(()).ok();
+        tokio::time::timeout(Duration::from_secs(1), sweeper).await??;
+        Ok(())
+    }
+}
+
+mod event_bus_tests {
+    use super::*;
+    use axum::async_trait;
+    use futures_util::stream::{self, BoxStream, StreamExt};
+    use switchboard_backend_api::event_bus::{run_event_bus, EventBus, EventTarget};
+    use tokio::{sync::mpsc, time::timeout};
+
+    /// In-process stand-in for Redis pub/sub, shared by several app states.
+    struct MockBus {
+        messages: broadcast::Sender<(String, String)>,
+        subscribed: mpsc::UnboundedSender<()>,
+    }
+
+    #[async_trait]
+    impl EventBus for MockBus {
+        async fn publish(&self, channel: String, payload: String) -> anyhow::Result<()> {
+            let _ = self.messages.send((channel, payload));
+            Ok(())
+        }
+
+        async fn subscribe(&self) -> anyhow::Result<BoxStream<'static, (String, String)>> {
+            let receiver = self.messages.subscribe();
+            let _ = self.subscribed.send(());
+            let messages = stream::unfold(receiver, |mut receiver| async move {
+                let message = receiver.recv().await.ok()?;
+                Some((message, receiver))
+            });
+            Ok(messages.boxed())
+        }
+    }
+
+    #[test]
+    fn event_targets_round_trip_through_channel_names() {
+        for target in [EventTarget::Chat("chat_abc".into()), EventTarget::User(42)] {
+            assert_eq!(EventTarget::from_channel(&target.channel()), Some(target));
+        }
+        assert_eq!(EventTarget::from_channel("other:user:1"), None);
+    }
+
+    #[tokio::test]
+    async fn events_reach_connections_on_other_instances() -> TestResult {
+        let (subscribed, mut subscriptions) = mpsc::unbounded_channel();
+        let bus = Arc::new(MockBus {
+            messages: broadcast::channel(16).0,
+            subscribed,
+        });
+        let ctx_a = TestContext::new().await?;
+        let ctx_b = TestContext::new().await?;
+        let node_a = ctx_a.state().with_event_bus(bus.clone());
+        let node_b = ctx_b.state().with_event_bus(bus.clone());
+
+        let listeners = [node_a.clone(), node_b.clone()]
+            .map(|state| tokio::spawn(run_event_bus(state, std::future::pending())));
+        for _ in 0..2 {
+            timeout(Duration::from_secs(1), subscriptions.recv()).await?;
+        }
+
+        let mut user_on_a = node_a.get_user_broadcaster(7).await.subscribe();
+        let mut user_on_b = node_b.get_user_broadcaster(7).await.subscribe();
+        let mut chat_on_b = node_b
+            .chat_broadcasters
+            .lock()
+            .await
+            .entry("chat-1".to_string())
+            .or_insert_with(|| broadcast::channel(16).0)
+            .subscribe();
+
+        let event = ServerEvent::Typing {
+            chat_id: "chat-1".into(),
+            user_id: 7,
+            is_typing: true,
+        };
+        node_a.broadcast_to_user(7, &event).await;
+
+        let local = timeout(Duration::from_secs(1), user_on_a.recv()).await??;
+        let remote = timeout(Duration::from_secs(1), user_on_b.recv()).await??;
+        assert_eq!(remote.event_id, local.event_id);
+        assert!(matches!(remote.event, ServerEvent::Typing { user_id: 7, .. }));
+
+        node_a.broadcast_to_chat("chat-1", &event).await;
+        let remote = timeout(Duration::from_secs(1), chat_on_b.recv()).await??;
+        assert_eq!(remote.event.chat_id(), Some("chat-1"));
+
+        // The publishing instance ignores its own events when they come back
+        sleep(Duration::from_millis(50)).await;
+        assert!(matches!(user_on_a.try_recv(), Err(broadcast::error::TryRecvError::Empty)));
+
+        for listener in listeners {
+            listener.abort();
+        }
+        Ok(())
+    }
+}
+
+mod streaming_tests {
+    use super::*;
+    use switchboard_backend_api::streaming::{mark_interrupted_messages, StreamingMessage};
+
+    async fn message_row(ctx: &TestContext, public_id: &str) -> TestResult<(String, String)> {
+        let row = sqlx::query_as("SELECT content, status FROM messages WHERE public_id = ?")
+            .bind(public_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        Ok(row)
+    }
+
+    #[tokio::test]
+    async fn interrupted_stream_keeps_last_checkpoint() -> TestResult {
+        let mut config = AppConfig::default();
+        config.chat.stream_checkpoint_chunks = 2;
+        config.chat.stream_checkpoint_interval_ms = 60_000;
+        let ctx = TestContext::with_config(config.clone()).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-stream", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+
+        let mut reply = StreamingMessage::start(
+            ctx.pool(),
+            &config.chat,
+            chat_id,
+            1,
+            "msg-stream".into(),
+            Some("test-model"),
+        )
+        .await?;
+        assert_eq!(
+            message_row(&ctx, "msg-stream").await?,
+            (String::new(), "streaming".to_string())
+        );
+
+        reply.push("Hel").await?;
+        reply.push("lo").await?;
+        reply.push(" wor").await?;
+        // The server dies before the stream ends; only the checkpoint survives
+        drop(reply);
+        assert_eq!(
+            message_row(&ctx, "msg-stream").await?,
+            ("Hello".to_string(), "streaming".to_string())
+        );
+
+        // Recently checkpointed, so it may belong to an instance that is still running
+        let stale_after = Duration::from_secs(60);
+        assert_eq!(mark_interrupted_messages(ctx.pool(), stale_after).await?, 0);
+
+        let last_checkpoint = (Utc::now() - chrono::Duration::minutes(2)).to_rfc3339();
+        sqlx::query("UPDATE messages SET updated_at = ? WHERE public_id = 'msg-stream'")
+            .bind(&last_checkpoint)
+            .execute(ctx.pool())
+            .await?;
+        assert_eq!(mark_interrupted_messages(ctx.pool(), stale_after).await?, 1);
+        assert_eq!(
+            message_row(&ctx, "msg-stream").await?,
+            ("Hello".to_string(), "interrupted".to_string())
+        );
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chats/chat-stream/messages")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["messages"][0]["status"], "interrupted");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finished_stream_is_complete_with_usage() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.insert_user(2, "user-two").await?;
+        let chat_id = ctx.create_chat("chat-done", 2).await?;
+
+        let mut reply = StreamingMessage::start(
+            ctx.pool(),
+            &ctx.state().config().chat,
+            chat_id,
+            2,
+            "msg-done".into(),
+            None,
+        )
+        .await?;
+        reply.push("all ").await?;
+        reply.push("done").await?;
+        reply.finish(Some((12, 4)), None).await?;
+
+        assert_eq!(
+            message_row(&ctx, "msg-done").await?,
+            ("all done".to_string(), "complete".to_string())
+        );
+        let tokens: (i64, i64) = sqlx::query_as(
+            "SELECT prompt_tokens, completion_tokens FROM messages WHERE public_id = ?",
+        )
+        .bind("msg-done")
+        .fetch_one(ctx.pool())
+        .await?;
+        assert_eq!(tokens, (12, 4));
+
+        // Nothing left streaming, so a restart has nothing to mark
+        assert_eq!(mark_interrupted_messages(ctx.pool(), Duration::ZERO).await?, 0);
+
+        Ok(())
+    }
+}
+
+mod title_tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::time::timeout;
+
+    use axum::async_trait;
+    use denkwerk::{
+        CompletionRequest, CompletionResponse, CompletionStream, ImageUploadRequest,
+        ImageUploadResponse, LLMError, LLMProvider, ProviderCapabilities,
+    };
+    use switchboard_backend_api::titles::title_untitled_chat;
+    use switchboard_orchestrator::{
+        test_support::OrchestratorTestBuilder, titles::fallback_title, ProviderMetadata,
+    };
+
+    /// Records the model of every completion and fails it, so titles fall back to the
+    /// first message.
+    #[derive(Clone, Default)]
+    struct FailingTitleProvider {
+        models: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl LLMProvider for FailingTitleProvider {
+        async fn complete(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse, LLMError> {
+            self.models.lock().unwrap().push(request.model);
+            Err(LLMError::Unsupported("complete"))
+        }
+
+        async fn stream_completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionStream, LLMError> {
+            Err(LLMError::Unsupported("stream"))
+        }
+
+        async fn upload_image(
+            &self,
+            _request: ImageUploadRequest,
+        ) -> Result<ImageUploadResponse, LLMError> {
+            Err(LLMError::Unsupported("upload"))
+        }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities::default()
+        }
+
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+    }
+
+    async fn titled_context(enabled: bool) -> TestResult<(TestContext, FailingTitleProvider)> {
+        let mut config = AppConfig::default();
+        config.orchestrator.title_generation.enabled = enabled;
+        config.orchestrator.title_generation.model = Some("mock/title-model".into());
+        config.orchestrator.title_generation.max_chars = 24;
+
+        let provider = FailingTitleProvider::default();
+        let metadata = ProviderMetadata {
+            identifier: "mock".into(),
+            family: "mock".into(),
+            capabilities: vec!["chat-completions".into()],
+        };
+        let orchestrator = OrchestratorTestBuilder::new(config.orchestrator.clone())
+            .with_provider(metadata, Arc::new(provider.clone()))
+            .build();
+        let ctx = TestContext::with_orchestrator(config, orchestrator).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        Ok((ctx, provider))
+    }
+
+    async fn chat_title(ctx: &TestContext, chat_id: i64) -> TestResult<(String, i64)> {
+        let row = sqlx::query_as("SELECT title, version FROM chats WHERE id = ?")
+            .bind(chat_id)
+            .fetch_one(ctx.pool())
+            .await?;
+        Ok(row)
+    }
+
+    #[tokio::test]
+    async fn untitled_chat_is_named_after_its_first_message() -> TestResult {
+        let (ctx, provider) = titled_context(true).await?;
+        let chat_id = ctx.create_chat("chat-title", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        sqlx::query("UPDATE chats SET title = 'New Chat' WHERE id = ?")
+            .bind(chat_id)
+            .execute(ctx.pool())
+            .await?;
+        let (_, version) = chat_title(&ctx, chat_id).await?;
+
+        let first = "How do I bake sourdough bread at home?";
+        ctx.insert_message(chat_id, 1, "msg-first", first).await?;
+        let mut events = ctx.state().get_user_broadcaster(1).await.subscribe();
+
+        let chat = title_untitled_chat(&ctx.state(), chat_id, first)
+            .await?
+            .ok_or_else(|| anyhow!("chat was not titled"))?;
+
+        let expected = fallback_title(first, 24);
+        assert_eq!(expected, "How do I bake sourdough…");
+        assert_eq!(chat.title, expected);
+        assert_eq!(chat_title(&ctx, chat_id).await?, (expected.clone(), version + 1));
+        assert_eq!(*provider.models.lock().unwrap(), vec!["mock/title-model".to_string()]);
+
+        let envelope = timeout(Duration::from_secs(1), events.recv()).await??;
+        match envelope.event {
+            ServerEvent::ChatUpdated { chat } => assert_eq!(chat.title, expected),
+            other => panic!("expected ChatUpdated, got {other:?}"),
+        }
+
+        // Once named, later messages leave the title alone
+        ctx.insert_message(chat_id, 1, "msg-second", "And rye?").await?;
+        assert!(title_untitled_chat(&ctx.state(), chat_id, "And rye?").await?.is_none());
+        assert_eq!(provider.models.lock().unwrap().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn titles_are_left_alone_unless_enabled_and_untitled() -> TestResult {
+        let (ctx, provider) = titled_context(false).await?;
+        let chat_id = ctx.create_chat("chat-off", 1).await?;
+        sqlx::query("UPDATE chats SET title = 'New Chat' WHERE id = ?")
+            .bind(chat_id)
+            .execute(ctx.pool())
+            .await?;
+        ctx.insert_message(chat_id, 1, "msg-off", "Hello").await?;
+        assert!(title_untitled_chat(&ctx.state(), chat_id, "Hello").await?.is_none());
+
+        assert!(provider.models.lock().unwrap().is_empty());
+
+        let (ctx, provider) = titled_context(true).await?;
+        let chat_id = ctx.create_chat("chat-named", 1).await?;
+        ctx.insert_message(chat_id, 1, "msg-named", "Hello").await?;
+        assert!(title_untitled_chat(&ctx.state(), chat_id, "Hello").await?.is_none());
+        assert_eq!(chat_title(&ctx, chat_id).await?.0, "Chat chat-named");
+        assert!(provider.models.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+}
+
+mod suggestion_tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use axum::async_trait;
+    use denkwerk::{
+        CompletionRequest, CompletionResponse, CompletionStream, ImageUploadRequest,
+        ImageUploadResponse, LLMError, LLMProvider, ProviderCapabilities,
+    };
+    use switchboard_orchestrator::{test_support::OrchestratorTestBuilder, ProviderMetadata};
+
+    /// Records the model of every completion and fails it.
+    #[derive(Clone, Default)]
+    struct FailingSuggestionProvider {
+        models: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl LLMProvider for FailingSuggestionProvider {
+        async fn complete(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse, LLMError> {
+            self.models.lock().unwrap().push(request.model);
+            Err(LLMError::Unsupported("complete"))
+        }
+
+        async fn stream_completion(
+            &self,
+            _request: CompletionRequest,
+        ) -> Result<CompletionStream, LLMError> {
+            Err(LLMError::Unsupported("stream"))
+        }
+
+        async fn upload_image(
+            &self,
+            _request: ImageUploadRequest,
+        ) -> Result<ImageUploadResponse, LLMError> {
+            Err(LLMError::Unsupported("upload"))
+        }
+
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities::default()
+        }
+
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+    }
+
+    async fn suggestion_context(
+        enabled: bool,
+    ) -> TestResult<(TestContext, FailingSuggestionProvider)> {
+        let mut config = AppConfig::default();
+        config.orchestrator.reply_suggestions.enabled = enabled;
+        config.orchestrator.reply_suggestions.model = Some("mock/suggest-model".into());
+
+        let provider = FailingSuggestionProvider::default();
+        let metadata = ProviderMetadata {
+            identifier: "mock".into(),
+            family: "mock".into(),
+            capabilities: vec!["chat-completions".into()],
+        };
+        let orchestrator = OrchestratorTestBuilder::new(config.orchestrator.clone())
+            .with_provider(metadata, Arc::new(provider.clone()))
+            .build();
+        let ctx = TestContext::with_orchestrator(config, orchestrator).await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let chat_id = ctx.create_chat("chat-suggest", 1).await?;
+        ctx.add_chat_member(chat_id, 1, "owner").await?;
+        ctx.insert_message(chat_id, 1, "msg-suggest", "Shall we meet tomorrow?").await?;
+        Ok((ctx, provider))
+    }
+
+    async fn get_suggestions(ctx: &TestContext) -> TestResult<(StatusCode, Value)> {
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/chats/chat-suggest/suggestions")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        Ok((status, serde_json::from_slice(&body)?))
+    }
+
+    #[tokio::test]
+    async fn failed_suggestions_come_back_empty() -> TestResult {
+        let (ctx, provider) = suggestion_context(true).await?;
+
+        let (status, payload) = get_suggestions(&ctx).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(payload["suggestions"], serde_json::json!([]));
+        assert_eq!(*provider.models.lock().unwrap(), vec!["mock/suggest-model".to_string()]);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages")
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(count, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn suggestions_are_not_found_unless_enabled() -> TestResult {
+        let (ctx, provider) = suggestion_context(false).await?;
+
+        let (status, _) = get_suggestions(&ctx).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(provider.models.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+}
+
+mod notification_tests {
+    use super::*;
+    use switchboard_backend_api::routes::notifications::NotificationService;
+
+    async fn unread_notifications(ctx: &TestContext) -> TestResult<Vec<(Option<i64>, String)>> {
+        Ok(sqlx::query_as(
+            "SELECT chat_id, type FROM notifications WHERE user_id = 1 AND read = FALSE ORDER BY id",
+        )
+        .fetch_all(ctx.pool())
+        .await?)
+    }
+
+    #[tokio::test]
+    async fn mark_read_by_filter_only_touches_the_chosen_chat() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let first = ctx.create_chat("chat-first", 1).await?;
+        let second = ctx.create_chat("chat-second", 1).await?;
+        for (chat_id, kind) in [(first, "mention"), (first, "chat_invite"), (second, "mention")] {
+            NotificationService::create_notification(
+                ctx.pool(),
+                1,
+                Some(chat_id),
+                kind,
+                "title",
+                "body",
+            )
+            .await
+            .map_err(|e| anyhow!(e.message))?;
+        }
+
+        let response = ctx
+            .router()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/api/notifications/mark-read")
+                    .header(AUTHORIZATION, "Bearer test-token")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"chat_id":"chat-first"}"#))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&body)?;
+        assert_eq!(payload["updated_count"], 2);
+        assert_eq!(unread_notifications(&ctx).await?, vec![(Some(second), "mention".to_string())]);
+
+        let updated = NotificationService::mark_read_by_filter(
+            ctx.pool(),
+            1,
+            &routes::notifications::NotificationFilter {
+                chat_id: None,
+                notification_type: Some("chat_invite".into()),
+            },
+        )
+        .await
+        .map_err(|e| anyhow!(e.message))?;
+        assert_eq!(updated, 0, "already read notifications are not counted");
 
-        let authorize_url = &response.0.authorize_url;
-        let parsed = Url::parse(authorize_url)?;
+        Ok(())
+    }
+}
 
-        let state_param = parsed
-            .query_pairs()
-            .find(|(key, _)| key == "state")
-            .map(|(_, value)| value.to_string())
-            .expect("state parameter to be present");
-        assert_eq!(state_param.len(), 32);
-        assert!(
-            state.oauth_state().consume(&state_param).await,
-            "state should be cached for consumption"
-        );
+mod prompt_template_tests {
+    use super::*;
+    use std::collections::HashMap;
+    use switchboard_backend_api::routes::prompt_templates::render_template;
+
+    async fn send_json(
+        ctx: &TestContext,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> TestResult<(StatusCode, Value)> {
+        send_json_as(ctx, "test-token", method, uri, body).await
+    }
 
-        let redirect_param = parsed
-            .query_pairs()
-            .find(|(key, _)| key == "redirect_uri")
-            .map(|(_, value)| value.to_string())
-            .expect("redirect_uri parameter to be present");
-        assert_eq!(redirect_param, redirect_uri);
+    async fn send_json_as(
+        ctx: &TestContext,
+        token: &str,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> TestResult<(StatusCode, Value)> {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))?;
+        let response = ctx.router().oneshot(request).await?;
+        let status = response.status();
+        let bytes = response.into_body().collect().await?.to_bytes();
+        let payload = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes)?
+        };
+        Ok((status, payload))
+    }
+
+    #[tokio::test]
+    async fn prompt_templates_can_be_created_listed_updated_and_deleted() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+
+        let (status, created) = send_json(
+            &ctx,
+            Method::POST,
+            "/api/prompt-templates",
+            Some(serde_json::json!({
+                "name": "Translate",
+                "body": "Translate {{ text }} into {{lang}}",
+            })),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+        let template = &created["template"];
+        assert_eq!(template["variables"], serde_json::json!(["text", "lang"]));
+        let uri = format!("/api/prompt-templates/{}", template["id"].as_str().unwrap_or(""));
+
+        let (status, _) = send_json(
+            &ctx,
+            Method::POST,
+            "/api/prompt-templates",
+            Some(serde_json::json!({ "name": "Translate", "body": "Again" })),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, _) = send_json(
+            &ctx,
+            Method::POST,
+            "/api/prompt-templates",
+            Some(serde_json::json!({
+                "name": "Undeclared",
+                "body": "Hello {{name}}",
+                "variables": [],
+            })),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, updated) = send_json(
+            &ctx,
+            Method::PUT,
+            &uri,
+            Some(serde_json::json!({ "name": "Translator" })),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["template"]["name"], "Translator");
+        assert_eq!(updated["template"]["variables"], serde_json::json!(["text", "lang"]));
+
+        let (status, listed) = send_json(&ctx, Method::GET, "/api/prompt-templates", None).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(listed["templates"].as_array().map(Vec::len), Some(1));
+        assert_eq!(listed["templates"][0]["name"], "Translator");
+
+        let (status, _) = send_json(&ctx, Method::DELETE, &uri, None).await?;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(&ctx, Method::GET, &uri, None).await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn github_callback_rejects_unknown_state() -> TestResult {
-        let ctx = TestContext::with_github().await?;
-        let result = routes::auth::github_callback(
-            State(ctx.state()),
-            Json(routes::auth::GithubCallbackRequest {
-                code: "dummy".into(),
-                state: "missing-state".into(),
-                redirect_uri: "https://example.com/callback".into(),
-            }),
+    async fn completion_from_a_template_requires_every_variable() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        let (_, created) = send_json(
+            &ctx,
+            Method::POST,
+            "/api/prompt-templates",
+            Some(serde_json::json!({
+                "name": "Translate",
+                "body": "Translate {{text}} into {{lang}}",
+            })),
         )
-        .await;
+        .await?;
+        let template_id = created["template"]["id"].as_str().unwrap_or("").to_string();
 
-        let err = result.expect_err("expected callback to reject unknown state");
-        assert_eq!(err.status, StatusCode::BAD_REQUEST);
-        assert!(
-            err.message.contains("invalid or expired"),
-            "error message should reference invalid or expired state"
+        let boundary = "template-boundary";
+        let body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"template_id\"\r\n\r\n\
+             {template_id}\r\n--{boundary}\r\nContent-Disposition: form-data; \
+             name=\"variables\"\r\n\r\n{{\"text\":\"hello\"}}\r\n--{boundary}--\r\n"
+        );
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/chat")
+            .header(AUTHORIZATION, "Bearer test-token")
+            .header(CONTENT_TYPE, format!("multipart/form-data; boundary={boundary}"))
+            .body(Body::from(body))?;
+        let response = ctx.router().oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let bytes = response.into_body().collect().await?.to_bytes();
+        let payload: Value = serde_json::from_slice(&bytes)?;
+        assert_eq!(payload["error"]["fields"][0]["field"], "variables.lang");
+
+        let values = HashMap::from([
+            ("text".to_string(), "{{lang}}".to_string()),
+            ("lang".to_string(), "French".to_string()),
+        ]);
+        let variables = ["text".to_string(), "lang".to_string()];
+        let rendered = render_template("Translate {{text}} into {{ lang }}", &variables, &values)
+            .map_err(|e| anyhow!(e.message))?;
+        assert_eq!(rendered, "Translate {{lang}} into French");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exported_templates_import_into_another_account() -> TestResult {
+        let ctx = TestContext::new().await?;
+        ctx.ensure_dev_session("test-token").await?;
+        ctx.insert_user(2, "user-2").await?;
+        let now = Utc::now();
+        sqlx::query(
+            "INSERT INTO sessions (token, user_id, expires_at, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind("other-token")
+        .bind(2i64)
+        .bind((now + chrono::Duration::hours(1)).to_rfc3339())
+        .bind(now.to_rfc3339())
+        .execute(ctx.pool())
+        .await?;
+
+        for (name, body) in [("Summarize", "Summarize {{text}}"), ("Greet", "Say hello")] {
+            let template = serde_json::json!({ "name": name, "body": body });
+            let (status, _) =
+                send_json(&ctx, Method::POST, "/api/prompt-templates", Some(template)).await?;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let (export, import) = ("/api/prompt-templates/export", "/api/prompt-templates/import");
+        let (status, bundle) = send_json(&ctx, Method::GET, export, None).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            bundle,
+            serde_json::json!({
+                "version": 1,
+                "templates": [
+                    { "name": "Greet", "body": "Say hello", "variables": [] },
+                    { "name": "Summarize", "body": "Summarize {{text}}", "variables": ["text"] },
+                ],
+            })
+        );
+
+        let (status, imported) =
+            send_json_as(&ctx, "other-token", Method::POST, import, Some(bundle.clone())).await?;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(imported["created"], 2);
+        let (_, exported) = send_json_as(&ctx, "other-token", Method::GET, export, None).await?;
+        assert_eq!(exported, bundle);
+
+        let (_, skipped) =
+            send_json_as(&ctx, "other-token", Method::POST, import, Some(bundle.clone())).await?;
+        assert_eq!(skipped["skipped"], serde_json::json!(["Greet", "Summarize"]));
+        let rename = "/api/prompt-templates/import?on_conflict=rename";
+        let (_, renamed) =
+            send_json_as(&ctx, "other-token", Method::POST, rename, Some(bundle.clone())).await?;
+        assert_eq!(renamed["templates"][0]["name"], "Greet (2)");
+
+        let mut invalid = bundle;
+        invalid["version"] = serde_json::json!(2);
+        invalid["templates"][0]["body"] = serde_json::json!("Say {{ who }}");
+        let (status, payload) =
+            send_json_as(&ctx, "other-token", Method::POST, import, Some(invalid)).await?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let fields: Vec<&str> = payload["error"]["fields"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|field| field["field"].as_str())
+            .collect();
+        assert_eq!(fields, ["version", "templates[0].body"]);
+
+        Ok(())
+    }
+}
+
+mod guest_chat_tests {
+    use super::*;
+    use axum::extract::ConnectInfo;
+    use std::net::SocketAddr;
+
+    async fn guest_chat(ctx: &TestContext, client: &str) -> TestResult<StatusCode> {
+        let peer: SocketAddr = format!("{client}:40000").parse()?;
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/guest/chat")
+            .header(CONTENT_TYPE, "application/json")
+            .extension(ConnectInfo(peer))
+            .body(Body::from(serde_json::json!({ "prompt": "hello" }).to_string()))?;
+        Ok(ctx.router().oneshot(request).await?.status())
+    }
+
+    #[tokio::test]
+    async fn guest_chat_is_not_found_when_disabled() -> TestResult {
+        let ctx = TestContext::new().await?;
+        assert_eq!(guest_chat(&ctx, "203.0.113.7").await?, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn guest_chat_is_rate_limited_per_client_ip() -> TestResult {
+        let mut config = AppConfig::default();
+        config.chat.guest.enabled = true;
+        config.chat.guest.burst = 2;
+        config.chat.guest.requests_per_minute = 1;
+        let ctx = TestContext::with_config(config).await?;
+
+        // No provider is configured, so allowed requests fail after spending the limit
+        for _ in 0..2 {
+            let status = guest_chat(&ctx, "203.0.113.7").await?;
+            assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+            assert_ne!(status, StatusCode::NOT_FOUND);
+        }
+        assert_eq!(
+            guest_chat(&ctx, "203.0.113.7").await?,
+            StatusCode::TOO_MANY_REQUESTS
         );
+        assert_ne!(
+            guest_chat(&ctx, "198.51.100.2").await?,
+            StatusCode::TOO_MANY_REQUESTS,
+            "another address has its own limit"
+        );
+
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages")
+            .fetch_one(ctx.pool())
+            .await?;
+        assert_eq!(stored, 0, "guest prompts are never stored");
+
         Ok(())
     }
 }
@@ -1,6 +1,6 @@
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{net::IpAddr, path::PathBuf};
 use tracing::debug;
 
 const DEFAULT_CONFIG_FILES: &[&str] = &[
@@ -15,29 +15,117 @@ const DEFAULT_CONFIG_FILES: &[&str] = &[
     "backend/crates/config/switchboard.toml",
 ];
 
+/// `User-Agent` sent with every outbound request the backend makes.
+pub const USER_AGENT: &str = concat!("switchboard-ngx/", env!("CARGO_PKG_VERSION"));
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub http: HttpConfig,
+    #[serde(default)]
+    pub outbound: OutboundHttpConfig,
     pub orchestrator: OrchestratorConfig,
     pub database: DatabaseConfig,
     pub auth: AuthConfig,
+    #[serde(default)]
+    pub chat: ChatConfig,
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+    #[serde(default)]
+    pub ids: IdsConfig,
+    #[serde(default)]
+    pub folders: FolderConfig,
+    #[serde(default)]
+    pub redis: RedisConfig,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             http: HttpConfig::default(),
+            outbound: OutboundHttpConfig::default(),
             orchestrator: OrchestratorConfig::default(),
             database: DatabaseConfig::default(),
             auth: AuthConfig::default(),
+            chat: ChatConfig::default(),
+            websocket: WebSocketConfig::default(),
+            ids: IdsConfig::default(),
+            folders: FolderConfig::default(),
+            redis: RedisConfig::default(),
         }
     }
 }
 
+/// HTTP listener settings, including request body limits and shutdown behaviour.
+///
+/// ```
+/// use switchboard_config::HttpConfig;
+///
+/// let http = HttpConfig::default();
+/// assert_eq!(http.max_body_bytes, 2 * 1024 * 1024);
+/// assert!(http.max_upload_body_bytes > http.max_body_bytes);
+/// assert_eq!(http.shutdown_timeout_secs, 30);
+/// assert!(!http.reject_unknown_fields);
+/// assert!(http.allows_origin("https://anywhere.example"));
+/// assert!(!http.trusts_proxy("127.0.0.1".parse().unwrap()));
+///
+/// let http = HttpConfig {
+///     allowed_origins: vec!["https://app.example.com".to_string()],
+///     ..HttpConfig::default()
+/// };
+/// assert!(http.allows_origin("https://app.example.com"));
+/// assert!(!http.allows_origin("https://evil.example"));
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpConfig {
     pub address: String,
     pub port: u16,
+    /// Maximum accepted request body size for regular API routes.
+    #[serde(default = "HttpConfig::default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Maximum accepted request body size for upload routes (attachments, multipart chat).
+    #[serde(default = "HttpConfig::default_max_upload_body_bytes")]
+    pub max_upload_body_bytes: usize,
+    /// Seconds to wait for in-flight requests and background tasks after a shutdown
+    /// signal before remaining connections are closed forcibly.
+    #[serde(default = "HttpConfig::default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Answer a `fields` selection naming an unknown field with 400 instead of
+    /// ignoring the name.
+    #[serde(default)]
+    pub reject_unknown_fields: bool,
+    /// Origins browsers may call the API and open websockets from, such as
+    /// `https://app.example.com`. An empty list allows any origin.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Proxies whose `X-Forwarded-For` and `X-Real-IP` headers are believed. Requests
+    /// from any other peer are attributed to the socket address; empty trusts no one.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl HttpConfig {
+    const fn default_max_body_bytes() -> usize {
+        2 * 1024 * 1024
+    }
+
+    const fn default_max_upload_body_bytes() -> usize {
+        25 * 1024 * 1024
+    }
+
+    const fn default_shutdown_timeout_secs() -> u64 {
+        30
+    }
+
+    /// Whether a request's `Origin` header value is allowed by `allowed_origins`.
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins.is_empty()
+            || self.allowed_origins.iter().any(|allowed| allowed == origin)
+    }
+
+    /// Whether forwarding headers set by `peer` may be believed.
+    pub fn trusts_proxy(&self, peer: IpAddr) -> bool {
+        self.trusted_proxies.contains(&peer)
+    }
 }
 
 impl Default for HttpConfig {
@@ -45,6 +133,53 @@ impl Default for HttpConfig {
         Self {
             address: "127.0.0.1".to_string(),
             port: 7070,
+            max_body_bytes: Self::default_max_body_bytes(),
+            max_upload_body_bytes: Self::default_max_upload_body_bytes(),
+            shutdown_timeout_secs: Self::default_shutdown_timeout_secs(),
+            reject_unknown_fields: false,
+            allowed_origins: Vec::new(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+/// Timeouts for requests the backend makes to third parties such as GitHub.
+///
+/// OpenRouter completions use `orchestrator.openrouter.request_timeout_seconds` as
+/// their total timeout instead, since generations legitimately run long.
+///
+/// ```
+/// use switchboard_config::OutboundHttpConfig;
+///
+/// let outbound = OutboundHttpConfig::default();
+/// assert_eq!(outbound.connect_timeout_seconds, 5);
+/// assert_eq!(outbound.request_timeout_seconds, 15);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundHttpConfig {
+    /// Seconds allowed to establish a connection, TLS handshake included.
+    #[serde(default = "OutboundHttpConfig::default_connect_timeout_seconds")]
+    pub connect_timeout_seconds: u64,
+    /// Seconds allowed for a whole request, from connecting to reading the body.
+    #[serde(default = "OutboundHttpConfig::default_request_timeout_seconds")]
+    pub request_timeout_seconds: u64,
+}
+
+impl OutboundHttpConfig {
+    const fn default_connect_timeout_seconds() -> u64 {
+        5
+    }
+
+    const fn default_request_timeout_seconds() -> u64 {
+        15
+    }
+}
+
+impl Default for OutboundHttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_seconds: Self::default_connect_timeout_seconds(),
+            request_timeout_seconds: Self::default_request_timeout_seconds(),
         }
     }
 }
@@ -55,6 +190,172 @@ pub struct OrchestratorConfig {
     pub provider_search_path: Vec<String>,
     #[serde(default)]
     pub openrouter: OpenRouterProviderConfig,
+    /// Upper bound on completions in flight across all users and connections.
+    #[serde(default = "OrchestratorConfig::default_max_concurrent_completions")]
+    pub max_concurrent_completions: usize,
+    /// Milliseconds a completion may wait for a free slot before it is rejected.
+    /// `0` rejects immediately when every slot is taken.
+    #[serde(default = "OrchestratorConfig::default_completion_queue_timeout_ms")]
+    pub completion_queue_timeout_ms: u64,
+    #[serde(default)]
+    pub provider_logging: ProviderLoggingConfig,
+    /// Completion parameters applied per model or provider when a request leaves
+    /// them unset.
+    #[serde(default)]
+    pub completion_defaults: Vec<CompletionDefaults>,
+    /// Prompt tokens allowed for models without their own `max_prompt_tokens` in
+    /// `completion_defaults`. Unset falls back to the model's context length when the
+    /// OpenRouter model list reports one.
+    #[serde(default)]
+    pub max_prompt_tokens: Option<u32>,
+    /// Caps on in-flight completions per provider, within `max_concurrent_completions`.
+    /// Providers without an entry are only limited by the global cap.
+    #[serde(default)]
+    pub provider_concurrency: Vec<ProviderConcurrency>,
+    /// Models users may request, as exact ids or `*` globs such as `openai/*`. Empty
+    /// allows every model that `denied_models` does not name.
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// Models users may not request, as exact ids or `*` globs. A model matching
+    /// both lists is denied.
+    #[serde(default)]
+    pub denied_models: Vec<String>,
+    #[serde(default)]
+    pub title_generation: TitleGenerationConfig,
+    #[serde(default)]
+    pub reply_suggestions: ReplySuggestionsConfig,
+}
+
+impl OrchestratorConfig {
+    const fn default_max_concurrent_completions() -> usize {
+        16
+    }
+
+    const fn default_completion_queue_timeout_ms() -> u64 {
+        2_000
+    }
+
+    /// The defaults that apply to `model`. An entry naming the model exactly wins over
+    /// one naming its provider (the part before the first `/`), field by field.
+    ///
+    /// ```
+    /// use switchboard_config::{CompletionDefaults, OrchestratorConfig};
+    ///
+    /// let mut config = OrchestratorConfig::default();
+    /// config.completion_defaults = vec![
+    ///     CompletionDefaults {
+    ///         model: "openai".into(),
+    ///         temperature: Some(0.7),
+    ///         max_tokens: Some(1_024),
+    ///         max_prompt_tokens: Some(100_000),
+    ///     },
+    ///     CompletionDefaults {
+    ///         model: "openai/o3".into(),
+    ///         temperature: Some(1.0),
+    ///         max_tokens: None,
+    ///         max_prompt_tokens: None,
+    ///     },
+    /// ];
+    ///
+    /// let defaults = config.completion_defaults_for("openai/o3");
+    /// assert_eq!(defaults.temperature, Some(1.0));
+    /// assert_eq!(defaults.max_tokens, Some(1_024));
+    /// assert_eq!(defaults.max_prompt_tokens, Some(100_000));
+    /// assert_eq!(config.completion_defaults_for("anthropic/claude").temperature, None);
+    /// ```
+    pub fn completion_defaults_for(&self, model: &str) -> CompletionDefaults {
+        let provider = model.split('/').next().unwrap_or(model);
+        let find = |name: &str| self.completion_defaults.iter().find(|entry| entry.model == name);
+        let exact = find(model);
+        let by_provider = find(provider).filter(|_| provider != model);
+
+        CompletionDefaults {
+            model: model.to_string(),
+            temperature: exact
+                .and_then(|entry| entry.temperature)
+                .or_else(|| by_provider.and_then(|entry| entry.temperature)),
+            max_tokens: exact
+                .and_then(|entry| entry.max_tokens)
+                .or_else(|| by_provider.and_then(|entry| entry.max_tokens)),
+            max_prompt_tokens: exact
+                .and_then(|entry| entry.max_prompt_tokens)
+                .or_else(|| by_provider.and_then(|entry| entry.max_prompt_tokens)),
+        }
+    }
+
+    /// Whether users may request `model` under `allowed_models` and `denied_models`.
+    ///
+    /// ```
+    /// use switchboard_config::OrchestratorConfig;
+    ///
+    /// let mut config = OrchestratorConfig::default();
+    /// assert!(config.allows_model("openai/o3"));
+    ///
+    /// config.allowed_models = vec!["openai/*".into(), "anthropic/claude-sonnet-4".into()];
+    /// config.denied_models = vec!["openai/o*-pro".into()];
+    /// assert!(config.allows_model("openai/gpt-4.1"));
+    /// assert!(config.allows_model("anthropic/claude-sonnet-4"));
+    /// assert!(!config.allows_model("anthropic/claude-opus-4"));
+    /// assert!(!config.allows_model("openai/o3-pro"));
+    /// ```
+    pub fn allows_model(&self, model: &str) -> bool {
+        let matches =
+            |patterns: &[String]| patterns.iter().any(|pattern| glob_matches(pattern, model));
+        !matches(&self.denied_models)
+            && (self.allowed_models.is_empty() || matches(&self.allowed_models))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for entry in &self.completion_defaults {
+            if entry.model.trim().is_empty() {
+                anyhow::bail!("orchestrator.completion_defaults entries need a model");
+            }
+            let temperature = entry.temperature.filter(|value| !(0.0..=2.0).contains(value));
+            if let Some(temperature) = temperature {
+                anyhow::bail!(
+                    "completion_defaults for {}: temperature {temperature} is outside 0..=2",
+                    entry.model
+                );
+            }
+            if entry.max_tokens == Some(0) {
+                anyhow::bail!(
+                    "completion_defaults for {}: max_tokens must be positive",
+                    entry.model
+                );
+            }
+            if entry.max_prompt_tokens == Some(0) {
+                anyhow::bail!(
+                    "completion_defaults for {}: max_prompt_tokens must be positive",
+                    entry.model
+                );
+            }
+        }
+        if self.max_prompt_tokens == Some(0) {
+            anyhow::bail!("orchestrator.max_prompt_tokens must be positive");
+        }
+        for entry in &self.provider_concurrency {
+            if entry.provider.trim().is_empty() {
+                anyhow::bail!("orchestrator.provider_concurrency entries need a provider");
+            }
+            if entry.max_concurrent == 0 {
+                anyhow::bail!(
+                    "provider_concurrency for {}: max_concurrent must be positive",
+                    entry.provider
+                );
+            }
+        }
+        let mut patterns = self.allowed_models.iter().chain(&self.denied_models);
+        if patterns.any(|pattern| pattern.trim().is_empty()) {
+            anyhow::bail!("orchestrator model allow and deny list entries must not be empty");
+        }
+        if self.title_generation.max_chars == 0 {
+            anyhow::bail!("orchestrator.title_generation.max_chars must be positive");
+        }
+        if self.reply_suggestions.context_messages == 0 {
+            anyhow::bail!("orchestrator.reply_suggestions.context_messages must be positive");
+        }
+        Ok(())
+    }
 }
 
 impl Default for OrchestratorConfig {
@@ -63,8 +364,218 @@ impl Default for OrchestratorConfig {
             default_model: "gpt-4.1".to_string(),
             provider_search_path: vec!["providers".to_string()],
             openrouter: OpenRouterProviderConfig::default(),
+            max_concurrent_completions: Self::default_max_concurrent_completions(),
+            completion_queue_timeout_ms: Self::default_completion_queue_timeout_ms(),
+            provider_logging: ProviderLoggingConfig::default(),
+            completion_defaults: Vec::new(),
+            max_prompt_tokens: None,
+            provider_concurrency: Vec::new(),
+            allowed_models: Vec::new(),
+            denied_models: Vec::new(),
+            title_generation: TitleGenerationConfig::default(),
+            reply_suggestions: ReplySuggestionsConfig::default(),
+        }
+    }
+}
+
+// `*` stands for any run of characters, `/` included; everything else is literal
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = text.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
         }
     }
+    rest.ends_with(last)
+}
+
+/// Default completion parameters for a model, or for every model of a provider.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompletionDefaults {
+    /// A full model id such as `openai/o3`, or a provider such as `openai`.
+    pub model: String,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Longest prompt, in estimated tokens, sent to the model. Longer prompts are
+    /// refused before reaching the provider.
+    #[serde(default)]
+    pub max_prompt_tokens: Option<u32>,
+}
+
+/// How many completions may run against one provider at once, and how many more may
+/// wait for a slot. Requests beyond both fail immediately.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderConcurrency {
+    /// A provider identifier such as `openrouter`, the part of a model id before the
+    /// first `/`.
+    pub provider: String,
+    pub max_concurrent: usize,
+    /// Requests allowed to queue for a slot, each waiting up to
+    /// `completion_queue_timeout_ms`.
+    #[serde(default)]
+    pub max_queued: usize,
+}
+
+/// Opt-in logging of provider completions for debugging.
+///
+/// Each completion logs its model, latency and token counts along with a prompt
+/// preview that is redacted and truncated to `preview_chars`. Full prompts are only
+/// logged when `verbose` is also set.
+///
+/// ```
+/// use switchboard_config::{ProviderLogLevel, ProviderLoggingConfig};
+///
+/// let logging = ProviderLoggingConfig::default();
+/// assert!(!logging.enabled);
+/// assert!(!logging.verbose);
+/// assert_eq!(logging.level, ProviderLogLevel::Info);
+/// assert_eq!(logging.preview_chars, 200);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderLoggingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub level: ProviderLogLevel,
+    /// Log prompts in full, unredacted. Never enable this where prompts may hold
+    /// user data you are not allowed to keep in logs.
+    #[serde(default)]
+    pub verbose: bool,
+    #[serde(default = "ProviderLoggingConfig::default_preview_chars")]
+    pub preview_chars: usize,
+}
+
+impl ProviderLoggingConfig {
+    const fn default_preview_chars() -> usize {
+        200
+    }
+}
+
+impl Default for ProviderLoggingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            level: ProviderLogLevel::default(),
+            verbose: false,
+            preview_chars: Self::default_preview_chars(),
+        }
+    }
+}
+
+/// Opt-in naming of untitled chats after their first user message.
+///
+/// A chat counts as untitled when its title is empty or one of `untitled_titles`.
+/// When the model call fails the first message itself, truncated to `max_chars`,
+/// becomes the title.
+///
+/// ```
+/// use switchboard_config::TitleGenerationConfig;
+///
+/// let titles = TitleGenerationConfig::default();
+/// assert!(!titles.enabled);
+/// assert!(titles.model.is_none());
+/// assert_eq!(titles.max_chars, 60);
+/// assert!(titles.is_untitled("New Chat"));
+/// assert!(titles.is_untitled("  "));
+/// assert!(!titles.is_untitled("Trip planning"));
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TitleGenerationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model asked for titles; a small, cheap model is enough. Falls back to
+    /// `default_model` when unset.
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default = "TitleGenerationConfig::default_max_chars")]
+    pub max_chars: usize,
+    /// Placeholder titles clients give new chats.
+    #[serde(default = "TitleGenerationConfig::default_untitled_titles")]
+    pub untitled_titles: Vec<String>,
+}
+
+impl TitleGenerationConfig {
+    const fn default_max_chars() -> usize {
+        60
+    }
+
+    fn default_untitled_titles() -> Vec<String> {
+        vec!["New Chat".to_string(), "New Group Chat".to_string()]
+    }
+
+    pub fn is_untitled(&self, title: &str) -> bool {
+        let title = title.trim();
+        title.is_empty() || self.untitled_titles.iter().any(|untitled| untitled == title)
+    }
+}
+
+impl Default for TitleGenerationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            max_chars: Self::default_max_chars(),
+            untitled_titles: Self::default_untitled_titles(),
+        }
+    }
+}
+
+/// Opt-in suggested replies for a chat, generated from its latest messages and never
+/// stored.
+///
+/// ```
+/// use switchboard_config::ReplySuggestionsConfig;
+///
+/// let suggestions = ReplySuggestionsConfig::default();
+/// assert!(!suggestions.enabled);
+/// assert!(suggestions.model.is_none());
+/// assert_eq!(suggestions.context_messages, 10);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplySuggestionsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model asked for suggestions; a small, cheap model is enough. Falls back to
+    /// `default_model` when unset.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Latest messages of the chat given to the model as context.
+    #[serde(default = "ReplySuggestionsConfig::default_context_messages")]
+    pub context_messages: usize,
+}
+
+impl ReplySuggestionsConfig {
+    const fn default_context_messages() -> usize {
+        10
+    }
+}
+
+impl Default for ReplySuggestionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            context_messages: Self::default_context_messages(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderLogLevel {
+    Debug,
+    #[default]
+    Info,
 }
 
 /// Configuration options for the built-in OpenRouter provider integration.
@@ -117,10 +628,368 @@ impl Default for OpenRouterProviderConfig {
     }
 }
 
+/// Limits and defaults applied to chats, messages and their attachments.
+///
+/// ```
+/// use switchboard_config::{ChatConfig, NonMemberStatus};
+///
+/// let chat = ChatConfig::default();
+/// assert_eq!(chat.max_attachments_per_message, 32);
+/// assert_eq!(chat.retention_sweep_interval_secs, 3_600);
+/// assert_eq!(chat.max_attachment_bytes, 10 * 1024 * 1024);
+/// assert_eq!(chat.non_member_status, NonMemberStatus::NotFound);
+/// assert_eq!(chat.stream_checkpoint_chunks, 32);
+/// assert_eq!(chat.stream_checkpoint_interval_ms, 1_000);
+/// assert_eq!(chat.stream_stale_after_secs, 300);
+/// assert_eq!(chat.message_burst, 10);
+/// assert_eq!(chat.messages_per_minute, 30);
+/// assert_eq!(chat.default_chat_type, "direct");
+/// assert_eq!(chat.default_folder, None);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatConfig {
+    /// Attachments a single message may carry; further uploads are rejected.
+    #[serde(default = "ChatConfig::default_max_attachments_per_message")]
+    pub max_attachments_per_message: usize,
+    /// Seconds between passes that purge messages past their chat's retention.
+    /// `0` disables the sweeper.
+    #[serde(default = "ChatConfig::default_retention_sweep_interval_secs")]
+    pub retention_sweep_interval_secs: u64,
+    /// Largest attachment the server will accept. A `file_url` is checked against the
+    /// size the client claims for it.
+    #[serde(default = "ChatConfig::default_max_attachment_bytes")]
+    pub max_attachment_bytes: usize,
+    /// MIME types accepted for stored attachments. Entries match exactly, or end in
+    /// `/*` to allow a whole top-level type.
+    #[serde(default = "ChatConfig::default_allowed_attachment_types")]
+    pub allowed_attachment_types: Vec<String>,
+    /// How requests for a chat the caller is not a member of are answered.
+    #[serde(default)]
+    pub non_member_status: NonMemberStatus,
+    /// Chunks a streaming assistant message receives between writes to the database.
+    #[serde(default = "ChatConfig::default_stream_checkpoint_chunks")]
+    pub stream_checkpoint_chunks: usize,
+    /// Longest time, in milliseconds, a streaming assistant message goes unsaved while
+    /// chunks keep arriving.
+    #[serde(default = "ChatConfig::default_stream_checkpoint_interval_ms")]
+    pub stream_checkpoint_interval_ms: u64,
+    /// Seconds a `streaming` message may go without a checkpoint before startup marks
+    /// it interrupted. Replies other instances are still generating stay younger.
+    #[serde(default = "ChatConfig::default_stream_stale_after_secs")]
+    pub stream_stale_after_secs: u64,
+    /// Messages a user can send to one chat in a quick burst before being limited.
+    /// Replies the server generates are not counted. `0` disables the limit.
+    #[serde(default = "ChatConfig::default_message_burst")]
+    pub message_burst: u32,
+    /// Rate, per user and chat, at which sends beyond the burst are allowed again.
+    /// `0` disables the limit.
+    #[serde(default = "ChatConfig::default_messages_per_minute")]
+    pub messages_per_minute: u32,
+    /// `chat_type` given to chats created without one.
+    #[serde(default = "ChatConfig::default_default_chat_type")]
+    pub default_chat_type: String,
+    /// Name of a top-level folder that chats created without a `folder_id` are filed
+    /// into. Users without a folder of that name get an unfiled chat.
+    #[serde(default)]
+    pub default_folder: Option<String>,
+    #[serde(default)]
+    pub guest: GuestChatConfig,
+}
+
+impl ChatConfig {
+    const fn default_max_attachments_per_message() -> usize {
+        32
+    }
+
+    const fn default_retention_sweep_interval_secs() -> u64 {
+        3_600
+    }
+
+    const fn default_max_attachment_bytes() -> usize {
+        10 * 1024 * 1024
+    }
+
+    const fn default_stream_checkpoint_chunks() -> usize {
+        32
+    }
+
+    const fn default_stream_checkpoint_interval_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_stream_stale_after_secs() -> u64 {
+        300
+    }
+
+    const fn default_message_burst() -> u32 {
+        10
+    }
+
+    const fn default_messages_per_minute() -> u32 {
+        30
+    }
+
+    fn default_default_chat_type() -> String {
+        "direct".to_string()
+    }
+
+    fn default_allowed_attachment_types() -> Vec<String> {
+        ["image/*", "text/*", "application/pdf", "application/json"]
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            max_attachments_per_message: Self::default_max_attachments_per_message(),
+            retention_sweep_interval_secs: Self::default_retention_sweep_interval_secs(),
+            max_attachment_bytes: Self::default_max_attachment_bytes(),
+            allowed_attachment_types: Self::default_allowed_attachment_types(),
+            non_member_status: NonMemberStatus::default(),
+            stream_checkpoint_chunks: Self::default_stream_checkpoint_chunks(),
+            stream_checkpoint_interval_ms: Self::default_stream_checkpoint_interval_ms(),
+            stream_stale_after_secs: Self::default_stream_stale_after_secs(),
+            message_burst: Self::default_message_burst(),
+            messages_per_minute: Self::default_messages_per_minute(),
+            default_chat_type: Self::default_default_chat_type(),
+            default_folder: None,
+            guest: GuestChatConfig::default(),
+        }
+    }
+}
+
+/// Opt-in completions for visitors without an account, e.g. on a demo instance.
+/// Guest prompts and replies are never stored, and every client IP is held to a
+/// strict rate limit.
+///
+/// ```
+/// use switchboard_config::GuestChatConfig;
+///
+/// let guest = GuestChatConfig::default();
+/// assert!(!guest.enabled);
+/// assert!(guest.model.is_none());
+/// assert_eq!(guest.burst, 3);
+/// assert_eq!(guest.requests_per_minute, 5);
+/// assert_eq!(guest.max_prompt_chars, 2_000);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuestChatConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model guests are answered by. Falls back to `default_model` when unset.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Requests one client IP can make in a quick burst before being limited.
+    #[serde(default = "GuestChatConfig::default_burst")]
+    pub burst: u32,
+    /// Rate, per client IP, at which requests beyond the burst are allowed again.
+    #[serde(default = "GuestChatConfig::default_requests_per_minute")]
+    pub requests_per_minute: u32,
+    /// Longest prompt a guest may send, in characters.
+    #[serde(default = "GuestChatConfig::default_max_prompt_chars")]
+    pub max_prompt_chars: usize,
+}
+
+impl GuestChatConfig {
+    const fn default_burst() -> u32 {
+        3
+    }
+
+    const fn default_requests_per_minute() -> u32 {
+        5
+    }
+
+    const fn default_max_prompt_chars() -> usize {
+        2_000
+    }
+
+    // Unlike the member flood limits, the guest limit cannot be switched off
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.enabled && (self.burst == 0 || self.requests_per_minute == 0) {
+            anyhow::bail!("chat.guest.burst and requests_per_minute must be positive");
+        }
+        if self.max_prompt_chars == 0 {
+            anyhow::bail!("chat.guest.max_prompt_chars must be positive");
+        }
+        Ok(())
+    }
+}
+
+impl Default for GuestChatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            burst: Self::default_burst(),
+            requests_per_minute: Self::default_requests_per_minute(),
+            max_prompt_chars: Self::default_max_prompt_chars(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NonMemberStatus {
+    /// 404, indistinguishable from a chat that does not exist.
+    #[default]
+    NotFound,
+    /// 403, which confirms the chat exists.
+    Forbidden,
+}
+
+/// What happens to a folder's contents when the folder is deleted.
+///
+/// ```
+/// use switchboard_config::{ChatsOnFolderDelete, FolderConfig, SubfoldersOnFolderDelete};
+///
+/// let folders = FolderConfig::default();
+/// assert_eq!(folders.chats_on_delete, ChatsOnFolderDelete::Detach);
+/// assert_eq!(folders.subfolders_on_delete, SubfoldersOnFolderDelete::Delete);
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FolderConfig {
+    #[serde(default)]
+    pub chats_on_delete: ChatsOnFolderDelete,
+    #[serde(default)]
+    pub subfolders_on_delete: SubfoldersOnFolderDelete,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatsOnFolderDelete {
+    /// Chats stay but no longer belong to any folder.
+    #[default]
+    Detach,
+    /// Chats move to the deleted folder's parent, or to the top level.
+    MoveToParent,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubfoldersOnFolderDelete {
+    /// Subfolders are deleted along with the folder; their chats are handled per
+    /// `chats_on_delete`.
+    #[default]
+    Delete,
+    /// Subfolders move up to the deleted folder's parent.
+    MoveToParent,
+}
+
+/// Per-connection limits for the realtime WebSocket endpoint.
+///
+/// ```
+/// use switchboard_config::WebSocketConfig;
+///
+/// let websocket = WebSocketConfig::default();
+/// assert_eq!(websocket.max_subscriptions_per_connection, 64);
+/// assert_eq!(websocket.max_message_bytes, 2 * 1024 * 1024);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSocketConfig {
+    /// Chats a single connection may be subscribed to at once.
+    #[serde(default = "WebSocketConfig::default_max_subscriptions_per_connection")]
+    pub max_subscriptions_per_connection: usize,
+    /// Largest client message accepted, the WebSocket counterpart of
+    /// `http.max_body_bytes`. A connection sending a larger one is closed.
+    #[serde(default = "WebSocketConfig::default_max_message_bytes")]
+    pub max_message_bytes: usize,
+}
+
+impl WebSocketConfig {
+    const fn default_max_subscriptions_per_connection() -> usize {
+        64
+    }
+
+    const fn default_max_message_bytes() -> usize {
+        2 * 1024 * 1024
+    }
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            max_subscriptions_per_connection: Self::default_max_subscriptions_per_connection(),
+            max_message_bytes: Self::default_max_message_bytes(),
+        }
+    }
+}
+
+/// Optional Redis server. The backend runs without it when it is unreachable.
+///
+/// ```
+/// use switchboard_config::RedisConfig;
+///
+/// let redis = RedisConfig::default();
+/// assert_eq!(redis.url, "redis://127.0.0.1:6379");
+/// assert!(!redis.pubsub_events);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedisConfig {
+    #[serde(default = "RedisConfig::default_url")]
+    pub url: String,
+    /// Fan WebSocket events out through Redis pub/sub so clients connected to other
+    /// instances receive them. Without it events only reach this instance's clients.
+    #[serde(default)]
+    pub pubsub_events: bool,
+}
+
+impl RedisConfig {
+    fn default_url() -> String {
+        "redis://127.0.0.1:6379".to_string()
+    }
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            url: Self::default_url(),
+            pubsub_events: false,
+        }
+    }
+}
+
+/// SQLite connection settings.
+///
+/// ```
+/// use switchboard_config::DatabaseConfig;
+///
+/// let database = DatabaseConfig::default();
+/// assert!(!database.split_read_write);
+/// assert_eq!(database.read_max_connections, 8);
+/// assert!(database.replica_urls.is_empty());
+/// assert_eq!(database.slow_query_threshold_ms, None);
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
     pub url: String,
     pub max_connections: u32,
+    /// Open a single-connection write pool and a separate read-only pool in WAL mode,
+    /// so long reads don't queue behind writes. `max_connections` is ignored when set.
+    #[serde(default)]
+    pub split_read_write: bool,
+    /// Size of the read-only pool when `split_read_write` is enabled, and of each
+    /// replica pool.
+    #[serde(default = "DatabaseConfig::default_read_max_connections")]
+    pub read_max_connections: u32,
+    /// Read-only copies of the primary, e.g. kept up to date by litestream. Reads
+    /// that tolerate lag are spread across them round-robin; writes always go to
+    /// `url`. Each must be reachable at startup.
+    #[serde(default)]
+    pub replica_urls: Vec<String>,
+    /// Warn about instrumented queries that take at least this many milliseconds,
+    /// naming the query and its duration but never its bound values. Unset disables
+    /// the check.
+    #[serde(default)]
+    pub slow_query_threshold_ms: Option<u64>,
+}
+
+impl DatabaseConfig {
+    const fn default_read_max_connections() -> u32 {
+        8
+    }
 }
 
 impl Default for DatabaseConfig {
@@ -128,23 +997,74 @@ impl Default for DatabaseConfig {
         Self {
             url: "sqlite://switchboard.db".to_string(),
             max_connections: 10,
+            split_read_write: false,
+            read_max_connections: Self::default_read_max_connections(),
+            replica_urls: Vec::new(),
+            slow_query_threshold_ms: None,
         }
     }
 }
 
+/// How public identifiers for new resources are generated.
+///
+/// ```
+/// use switchboard_config::IdsConfig;
+///
+/// assert!(!IdsConfig::default().prefixed_public_ids);
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IdsConfig {
+    /// Give new chats, messages, folders, invites and prompt templates a type prefix
+    /// (`chat_`, `msg_`, `fld_`, `inv_`, `tpl_`).
+    /// Existing unprefixed ids keep working either way.
+    #[serde(default)]
+    pub prefixed_public_ids: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthConfig {
     #[serde(default = "AuthConfig::default_session_ttl")]
     pub session_ttl_seconds: u64,
+    /// Log a session out after this many seconds without an authenticated request,
+    /// even if it is still within `session_ttl_seconds`. `0` disables the idle check.
+    #[serde(default)]
+    pub idle_timeout_seconds: u64,
+    /// Push a session's expiry back to a full `session_ttl_seconds` when it is used
+    /// with less than half of that left, so active users stay logged in.
+    #[serde(default)]
+    pub sliding_expiration: bool,
+    /// How long a pending OAuth flow may sit between login and callback.
+    #[serde(default = "AuthConfig::default_oauth_state_ttl")]
+    pub oauth_state_ttl_seconds: u64,
+    /// OAuth redirect URIs the backend will hand to a provider. Entries match exactly,
+    /// or end in `/*` to allow any path below that prefix. An empty list allows none.
+    #[serde(default)]
+    pub allowed_redirect_uris: Vec<String>,
+    /// Application-wide secret mixed into password hashes alongside the per-hash salt.
+    /// Changing or removing it invalidates every stored password, so rotating it means
+    /// users have to reset (re-hash) their passwords.
+    #[serde(default)]
+    pub password_pepper: Option<String>,
+    #[serde(default)]
+    pub argon2: Argon2Config,
     #[serde(default)]
     pub github: GithubAuthConfig,
+    #[serde(default)]
+    pub google: GoogleAuthConfig,
 }
 
 impl Default for AuthConfig {
     fn default() -> Self {
         Self {
             session_ttl_seconds: 86_400,
+            idle_timeout_seconds: 0,
+            sliding_expiration: false,
+            oauth_state_ttl_seconds: Self::default_oauth_state_ttl(),
+            allowed_redirect_uris: Vec::new(),
+            password_pepper: None,
+            argon2: Argon2Config::default(),
             github: GithubAuthConfig::default(),
+            google: GoogleAuthConfig::default(),
         }
     }
 }
@@ -153,6 +1073,66 @@ impl AuthConfig {
     fn default_session_ttl() -> u64 {
         86_400
     }
+
+    const fn default_oauth_state_ttl() -> u64 {
+        600
+    }
+}
+
+/// Cost parameters for new password hashes. Existing hashes keep verifying under the
+/// parameters they were created with and are rehashed on login when weaker.
+///
+/// ```
+/// use switchboard_config::Argon2Config;
+///
+/// let argon2 = Argon2Config::default();
+/// assert_eq!(argon2.memory_kib, 19_456);
+/// assert_eq!(argon2.iterations, 2);
+/// assert_eq!(argon2.parallelism, 1);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Argon2Config {
+    /// Memory cost in KiB; must be at least eight times `parallelism`.
+    #[serde(default = "Argon2Config::default_memory_kib")]
+    pub memory_kib: u32,
+    #[serde(default = "Argon2Config::default_iterations")]
+    pub iterations: u32,
+    #[serde(default = "Argon2Config::default_parallelism")]
+    pub parallelism: u32,
+}
+
+impl Argon2Config {
+    const fn default_memory_kib() -> u32 {
+        19_456
+    }
+
+    const fn default_iterations() -> u32 {
+        2
+    }
+
+    const fn default_parallelism() -> u32 {
+        1
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.iterations == 0 || self.parallelism == 0 {
+            anyhow::bail!("auth.argon2.iterations and parallelism must be positive");
+        }
+        if u64::from(self.memory_kib) < 8 * u64::from(self.parallelism) {
+            anyhow::bail!("auth.argon2.memory_kib must be at least 8 * parallelism");
+        }
+        Ok(())
+    }
+}
+
+impl Default for Argon2Config {
+    fn default() -> Self {
+        Self {
+            memory_kib: Self::default_memory_kib(),
+            iterations: Self::default_iterations(),
+            parallelism: Self::default_parallelism(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -161,6 +1141,12 @@ pub struct GithubAuthConfig {
     pub client_secret: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct GoogleAuthConfig {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
 /// Load the application configuration by combining defaults, files, and environment overrides.
 ///
 /// ```
@@ -262,6 +1248,10 @@ pub fn load() -> anyhow::Result<AppConfig> {
         config.auth.session_ttl_seconds = i64::MAX as u64;
     }
 
+    config.orchestrator.validate()?;
+    config.chat.guest.validate()?;
+    config.auth.argon2.validate()?;
+
     debug!(?config, "loaded backend configuration");
     Ok(config)
 }
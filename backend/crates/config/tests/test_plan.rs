@@ -16,6 +16,8 @@ const ENV_VARS_TO_RESET: &[&str] = &[
     "SWITCHBOARD_CONFIG",
     "SWITCHBOARD__AUTH__GITHUB__CLIENT_ID",
     "SWITCHBOARD__AUTH__GITHUB__CLIENT_SECRET",
+    "SWITCHBOARD__AUTH__GOOGLE__CLIENT_ID",
+    "SWITCHBOARD__AUTH__GOOGLE__CLIENT_SECRET",
     "SWITCHBOARD__AUTH__SESSION_TTL_SECONDS",
     "SWITCHBOARD__DATABASE__MAX_CONNECTIONS",
     "SWITCHBOARD__DATABASE__URL",
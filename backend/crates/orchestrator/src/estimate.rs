@@ -0,0 +1,79 @@
+//! Prompt token counts and costs for a completion, worked out without calling the
+//! provider.
+
+use denkwerk::CompletionRequest;
+use serde::{Deserialize, Serialize};
+
+use crate::ModelPricing;
+
+// Chat formats wrap every message in a few tokens of role markup, and prime the reply
+const TOKENS_PER_MESSAGE: u32 = 4;
+const TOKENS_PER_REPLY: u32 = 3;
+
+/// What a completion request would cost to send.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompletionEstimate {
+    pub model: String,
+    pub prompt_tokens: u32,
+    /// The cap on generated tokens the request carries, if any.
+    pub max_tokens: Option<u32>,
+    /// USD for the prompt alone; `None` when the model's pricing is unknown.
+    pub estimated_cost: Option<f64>,
+}
+
+/// Tokens in the request's prompt. None of the configured providers expose their
+/// tokenizer, so this uses the common approximation of four characters per token,
+/// plus each message's role markup.
+///
+/// ```
+/// use denkwerk::{ChatMessage, CompletionRequest};
+/// use switchboard_orchestrator::estimate::count_prompt_tokens;
+///
+/// let request = CompletionRequest::new("model".into(), vec![ChatMessage::user("Hi there")]);
+/// assert_eq!(count_prompt_tokens(&request), 2 + 4 + 3);
+/// ```
+pub fn count_prompt_tokens(request: &CompletionRequest) -> u32 {
+    let text_tokens: usize = request
+        .messages
+        .iter()
+        .filter_map(|message| message.text())
+        .map(|text| text.chars().count().div_ceil(4))
+        .sum();
+    let markup = request.messages.len() as u32 * TOKENS_PER_MESSAGE + TOKENS_PER_REPLY;
+    u32::try_from(text_tokens)
+        .unwrap_or(u32::MAX)
+        .saturating_add(markup)
+}
+
+/// USD for `prompt_tokens` of input at the model's per-token input price.
+///
+/// ```
+/// use switchboard_orchestrator::{estimate::estimate_cost, ModelPricing};
+///
+/// let pricing = ModelPricing { input: Some(0.000002), output: Some(0.000008) };
+/// assert_eq!(estimate_cost(&pricing, 1_000), Some(0.002));
+/// assert_eq!(estimate_cost(&ModelPricing { input: None, output: None }, 1_000), None);
+/// ```
+pub fn estimate_cost(pricing: &ModelPricing, prompt_tokens: u32) -> Option<f64> {
+    pricing.input.map(|price| price * f64::from(prompt_tokens))
+}
+
+/// USD for a finished completion at the model's per-token input and output prices.
+/// `None` unless both prices are known.
+///
+/// ```
+/// use switchboard_orchestrator::{estimate::completion_cost, ModelPricing};
+///
+/// let pricing = ModelPricing { input: Some(0.000002), output: Some(0.000008) };
+/// assert_eq!(completion_cost(&pricing, 1_000, 500), Some(0.006));
+/// assert_eq!(completion_cost(&ModelPricing { input: Some(0.1), output: None }, 1, 1), None);
+/// ```
+pub fn completion_cost(
+    pricing: &ModelPricing,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+) -> Option<f64> {
+    let input = pricing.input? * f64::from(prompt_tokens);
+    let output = pricing.output? * f64::from(completion_tokens);
+    Some(input + output)
+}
@@ -1,4 +1,12 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 use denkwerk::{
@@ -8,15 +16,29 @@ use denkwerk::{
         },
         LLMProvider,
     },
-    LLMError, ProviderCapabilities,
+    CompletionRequest, CompletionResponse, CompletionStream, LLMError, ProviderCapabilities,
 };
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tracing::{debug, error, info, warn};
 
-use switchboard_config::{AppConfig, OpenRouterProviderConfig, OrchestratorConfig};
+use switchboard_config::{
+    AppConfig, OpenRouterProviderConfig, OrchestratorConfig, OutboundHttpConfig,
+    ProviderConcurrency, USER_AGENT,
+};
+
+pub mod estimate;
+pub mod logging;
+pub mod provider_error;
+pub mod rate_limit;
+pub mod suggestions;
+pub mod titles;
+
+pub use provider_error::ProviderErrorKind;
+pub use rate_limit::RateLimitInfo;
 
 #[derive(Debug, Error)]
 pub enum OrchestratorError {
@@ -40,6 +62,69 @@ pub enum OrchestratorError {
     ProviderResponse(#[from] serde_json::Error),
     #[error("openrouter provider is not available")]
     OpenRouterUnavailable,
+    #[error("completion capacity exceeded, try again shortly")]
+    CompletionCapacityExceeded,
+    #[error("provider rate limit exceeded, try again later")]
+    ProviderRateLimited(RateLimitInfo),
+    #[error("provider rejected the credentials: {0}")]
+    ProviderAuthFailed(String),
+    #[error("model not found: {0}")]
+    ModelNotFound(String),
+    #[error("model {0} is not allowed on this server")]
+    ModelNotAllowed(String),
+    #[error("prompt exceeds the model's context length: {0}")]
+    ContextLengthExceeded(String),
+    #[error("provider failed: {0}")]
+    ProviderServerError(String),
+    #[error("provider rejected the request ({status}): {message}")]
+    ProviderRejected { status: u16, message: String },
+    #[error("completion failed: {0}")]
+    Completion(#[source] LLMError),
+}
+
+impl OrchestratorError {
+    /// Classify a failed provider response by its status and body.
+    pub fn from_provider_response(status: u16, body: &str, rate_limit: RateLimitInfo) -> Self {
+        let message = provider_error::error_message(body);
+        match provider_error::classify(Some(status), body) {
+            ProviderErrorKind::RateLimited => Self::ProviderRateLimited(rate_limit),
+            ProviderErrorKind::AuthFailed => Self::ProviderAuthFailed(message),
+            ProviderErrorKind::ModelNotFound => Self::ModelNotFound(message),
+            ProviderErrorKind::ContextLengthExceeded => Self::ContextLengthExceeded(message),
+            ProviderErrorKind::ServerError => Self::ProviderServerError(message),
+            ProviderErrorKind::Other => Self::ProviderRejected { status, message },
+        }
+    }
+
+    /// The provider failure class, for errors that came from a provider's answer.
+    pub fn kind(&self) -> Option<ProviderErrorKind> {
+        match self {
+            Self::ProviderRateLimited(_) => Some(ProviderErrorKind::RateLimited),
+            Self::ProviderAuthFailed(_) => Some(ProviderErrorKind::AuthFailed),
+            Self::ModelNotFound(_) => Some(ProviderErrorKind::ModelNotFound),
+            Self::ContextLengthExceeded(_) => Some(ProviderErrorKind::ContextLengthExceeded),
+            Self::ProviderServerError(_) => Some(ProviderErrorKind::ServerError),
+            Self::ProviderRejected { .. } | Self::Completion(_) => Some(ProviderErrorKind::Other),
+            _ => None,
+        }
+    }
+}
+
+/// Completion errors only carry the provider's answer as text, so they are
+/// classified from their message.
+impl From<LLMError> for OrchestratorError {
+    fn from(error: LLMError) -> Self {
+        let text = error.to_string();
+        let message = provider_error::error_message(&text);
+        match provider_error::classify(None, &text) {
+            ProviderErrorKind::RateLimited => Self::ProviderRateLimited(RateLimitInfo::default()),
+            ProviderErrorKind::AuthFailed => Self::ProviderAuthFailed(message),
+            ProviderErrorKind::ModelNotFound => Self::ModelNotFound(message),
+            ProviderErrorKind::ContextLengthExceeded => Self::ContextLengthExceeded(message),
+            ProviderErrorKind::ServerError => Self::ProviderServerError(message),
+            ProviderErrorKind::Other => Self::Completion(error),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,14 +188,96 @@ struct ResolvedOpenRouterConfig {
 
 pub struct Orchestrator {
     config: OrchestratorConfig,
+    outbound: OutboundHttpConfig,
     providers: Option<ProviderIndex>,
+    completion_slots: Arc<Semaphore>,
+    /// Slots of the providers with a cap in `provider_concurrency`, by identifier.
+    provider_slots: HashMap<String, ProviderSlots>,
+    /// Models by id, as of the last OpenRouter model listing.
+    catalogue: RwLock<HashMap<String, OpenRouterModelSummary>>,
+}
+
+/// Holds one of the orchestrator's global completion slots, and a slot of the
+/// provider's own cap when it has one, until dropped.
+#[derive(Debug)]
+pub struct CompletionPermit {
+    _global: OwnedSemaphorePermit,
+    _provider: Option<OwnedSemaphorePermit>,
+}
+
+/// The slots of one provider listed in `provider_concurrency`.
+struct ProviderSlots {
+    limit: ProviderConcurrency,
+    slots: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+impl ProviderSlots {
+    fn new(limit: &ProviderConcurrency) -> Self {
+        Self {
+            limit: limit.clone(),
+            slots: Arc::new(Semaphore::new(limit.max_concurrent.max(1))),
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take a free slot, or wait up to `wait` for one if the queue has room.
+    async fn acquire(&self, wait: Duration) -> Result<OwnedSemaphorePermit, OrchestratorError> {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let permit = match QueuePlace::take(&self.queued, self.limit.max_queued) {
+            Some(_place) if !wait.is_zero() => {
+                tokio::time::timeout(wait, self.slots.clone().acquire_owned())
+                    .await
+                    .ok()
+                    .and_then(Result::ok)
+            }
+            _ => None,
+        };
+
+        permit.ok_or_else(|| {
+            warn!(
+                provider = %self.limit.provider,
+                limit = self.limit.max_concurrent,
+                max_queued = self.limit.max_queued,
+                "rejecting completion, provider slots in use"
+            );
+            OrchestratorError::CompletionCapacityExceeded
+        })
+    }
+}
+
+/// A place in a provider's queue, given back when dropped.
+struct QueuePlace<'a>(&'a AtomicUsize);
+
+impl<'a> QueuePlace<'a> {
+    fn take(queued: &'a AtomicUsize, max_queued: usize) -> Option<Self> {
+        queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < max_queued).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Self(queued))
+    }
+}
+
+impl Drop for QueuePlace<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl Orchestrator {
     pub fn new(config: &AppConfig) -> Self {
         Self {
             config: config.orchestrator.clone(),
+            outbound: config.outbound.clone(),
             providers: None,
+            completion_slots: completion_slots(&config.orchestrator),
+            provider_slots: provider_slots(&config.orchestrator),
+            catalogue: RwLock::default(),
         }
     }
 
@@ -136,10 +303,287 @@ impl Orchestrator {
             .ok_or_else(|| OrchestratorError::ProviderNotFound(identifier.to_string()))
     }
 
+    /// Reserve a slot for an outbound completion. Callers hold the permit for the
+    /// duration of the provider call; when every slot is taken the request waits up to
+    /// `completion_queue_timeout_ms` before failing with `CompletionCapacityExceeded`.
+    pub async fn acquire_completion_slot(&self) -> Result<CompletionPermit, OrchestratorError> {
+        Ok(CompletionPermit {
+            _global: self.acquire_global_slot().await?,
+            _provider: None,
+        })
+    }
+
+    /// Like [`Orchestrator::acquire_completion_slot`], but also takes a slot of the
+    /// `provider_concurrency` cap of the provider serving `model`, if it has one. That
+    /// slot is taken first, so requests queued for a busy provider don't hold global
+    /// slots that other providers could use.
+    pub async fn acquire_completion_slot_for(
+        &self,
+        model: &str,
+    ) -> Result<CompletionPermit, OrchestratorError> {
+        let provider = match self.provider_slots.get(&self.provider_key(model)) {
+            Some(slots) => Some(slots.acquire(self.completion_queue_timeout()).await?),
+            None => None,
+        };
+        Ok(CompletionPermit {
+            _global: self.acquire_global_slot().await?,
+            _provider: provider,
+        })
+    }
+
+    fn completion_queue_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.completion_queue_timeout_ms)
+    }
+
+    async fn acquire_global_slot(&self) -> Result<OwnedSemaphorePermit, OrchestratorError> {
+        let slots = self.completion_slots.clone();
+        let wait = self.completion_queue_timeout();
+
+        let permit = if wait.is_zero() {
+            slots.try_acquire_owned().ok()
+        } else {
+            tokio::time::timeout(wait, slots.acquire_owned())
+                .await
+                .ok()
+                .and_then(Result::ok)
+        };
+
+        permit.ok_or_else(|| {
+            warn!(
+                limit = self.config.max_concurrent_completions,
+                "rejecting completion, all slots in use"
+            );
+            OrchestratorError::CompletionCapacityExceeded
+        })
+    }
+
+    /// Fill in parameters the request leaves unset from `completion_defaults` for its
+    /// model. Values already on the request are kept.
+    pub fn apply_completion_defaults(&self, request: &mut CompletionRequest) {
+        let defaults = self.config.completion_defaults_for(&request.model);
+        if request.temperature.is_none() {
+            request.temperature = defaults.temperature;
+        }
+        if request.max_tokens.is_none() {
+            request.max_tokens = defaults.max_tokens;
+        }
+    }
+
+    /// Refuse `model` with `ModelNotAllowed` when `allowed_models` or `denied_models`
+    /// rule it out, before any provider is picked for it.
+    pub fn check_model_allowed(&self, model: &str) -> Result<(), OrchestratorError> {
+        if self.config.allows_model(model) {
+            return Ok(());
+        }
+        debug!(model = %model, "refusing disallowed model");
+        Err(OrchestratorError::ModelNotAllowed(model.to_string()))
+    }
+
+    /// Refuse `request` with `ContextLengthExceeded` when its prompt is longer than
+    /// the model allows, so it never reaches the provider. The limit is the model's
+    /// `max_prompt_tokens` in `completion_defaults`, else the global
+    /// `max_prompt_tokens`, else the context length from the last OpenRouter model
+    /// listing. Returns the prompt's token count.
+    pub fn check_prompt_length(
+        &self,
+        request: &CompletionRequest,
+    ) -> Result<u32, OrchestratorError> {
+        let prompt_tokens = estimate::count_prompt_tokens(request);
+        let Some(allowed) = self.max_prompt_tokens(&request.model) else {
+            return Ok(prompt_tokens);
+        };
+
+        if prompt_tokens > allowed {
+            debug!(model = %request.model, prompt_tokens, allowed, "refusing oversize prompt");
+            return Err(OrchestratorError::ContextLengthExceeded(format!(
+                "{prompt_tokens} prompt tokens, {} allows {allowed}",
+                request.model
+            )));
+        }
+        Ok(prompt_tokens)
+    }
+
+    fn max_prompt_tokens(&self, model: &str) -> Option<u32> {
+        self.config
+            .completion_defaults_for(model)
+            .max_prompt_tokens
+            .or(self.config.max_prompt_tokens)
+            .or_else(|| self.catalogued(model)?.context_length)
+    }
+
+    /// USD for a completion of `model` that used the given tokens, priced from the
+    /// last OpenRouter model listing. `None` when the model or its prices are unknown.
+    pub fn completion_cost(
+        &self,
+        model: &str,
+        prompt_tokens: u32,
+        completion_tokens: u32,
+    ) -> Option<f64> {
+        let pricing = self.catalogued(model)?.pricing?;
+        estimate::completion_cost(&pricing, prompt_tokens, completion_tokens)
+    }
+
+    fn catalogued(&self, model: &str) -> Option<OpenRouterModelSummary> {
+        let catalogue = self
+            .catalogue
+            .read()
+            .unwrap_or_else(|error| error.into_inner());
+        catalogue.get(model).cloned()
+    }
+
+    /// Count the prompt tokens of `request`, with the configured defaults applied as
+    /// [`Orchestrator::complete`] would, and price them from the OpenRouter model
+    /// list. The provider is not called; the cost is `None` when the list is
+    /// unavailable or has no input price for the model.
+    pub async fn estimate_completion(
+        &self,
+        mut request: CompletionRequest,
+    ) -> estimate::CompletionEstimate {
+        self.apply_completion_defaults(&mut request);
+        let prompt_tokens = estimate::count_prompt_tokens(&request);
+
+        let pricing = match self.list_openrouter_models().await {
+            Ok(models) => models
+                .into_iter()
+                .find(|model| model.id == request.model)
+                .and_then(|model| model.pricing),
+            Err(e) => {
+                debug!(error = %e, "model pricing unavailable for estimate");
+                None
+            }
+        };
+
+        estimate::CompletionEstimate {
+            estimated_cost: pricing
+                .as_ref()
+                .and_then(|pricing| estimate::estimate_cost(pricing, prompt_tokens)),
+            model: request.model,
+            prompt_tokens,
+            max_tokens: request.max_tokens,
+        }
+    }
+
+    /// Run a completion on `provider` with the configured defaults applied, logging it
+    /// when `provider_logging` is enabled.
+    pub async fn complete(
+        &self,
+        provider: &dyn LLMProvider,
+        mut request: CompletionRequest,
+    ) -> Result<CompletionResponse, LLMError> {
+        self.apply_completion_defaults(&mut request);
+        let config = &self.config.provider_logging;
+        let logged = config
+            .enabled
+            .then(|| (request.model.clone(), logging::request_prompt(&request)));
+
+        let started = Instant::now();
+        let result = provider.complete(request).await;
+        if let Some((model, prompt)) = logged {
+            let latency = started.elapsed();
+            logging::log_completion(config, provider.name(), &model, &prompt, latency, &result);
+        }
+        result
+    }
+
+    /// Open a streaming completion on `provider`, with defaults and logging handled the
+    /// same way as [`Orchestrator::complete`]. The latency covers opening the stream.
+    pub async fn stream_completion(
+        &self,
+        provider: &dyn LLMProvider,
+        mut request: CompletionRequest,
+    ) -> Result<CompletionStream, LLMError> {
+        self.apply_completion_defaults(&mut request);
+        let config = &self.config.provider_logging;
+        let logged = config
+            .enabled
+            .then(|| (request.model.clone(), logging::request_prompt(&request)));
+
+        let started = Instant::now();
+        let result = provider.stream_completion(request).await;
+        if let Some((model, prompt)) = logged {
+            let latency = started.elapsed();
+            let error = result.as_ref().err();
+            logging::log_stream_opened(config, provider.name(), &model, &prompt, latency, error);
+        }
+        result
+    }
+
+    /// A short title for a chat that starts with `first_message`, asked of the
+    /// `title_generation` model. Falls back to the message itself, truncated, when the
+    /// model is unavailable, fails or replies with nothing usable.
+    pub async fn generate_chat_title(&self, first_message: &str) -> String {
+        let config = &self.config.title_generation;
+        let model = config.model.as_deref().unwrap_or(&self.config.default_model);
+
+        match self.request_chat_title(model, first_message).await {
+            Ok(Some(title)) => return title,
+            Ok(None) => debug!(model = %model, "title model replied without a usable title"),
+            Err(e) => warn!(model = %model, error = %e, "chat title generation failed"),
+        }
+        titles::fallback_title(first_message, config.max_chars)
+    }
+
+    async fn request_chat_title(
+        &self,
+        model: &str,
+        first_message: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let provider = self.provider_for_model(model)?;
+        let _slot = self.acquire_completion_slot_for(model).await?;
+        let request = titles::title_request(model, first_message);
+        let completion = self.complete(provider.as_ref(), request).await?;
+        let reply = completion.message.text().unwrap_or_default();
+        Ok(titles::clean_title(reply, self.config.title_generation.max_chars))
+    }
+
+    /// Up to [`suggestions::SUGGESTION_COUNT`] short replies the user might send next,
+    /// asked of the `reply_suggestions` model with `transcript` (role and content,
+    /// oldest first) as context. Empty when the model is unavailable or fails.
+    pub async fn suggest_replies(&self, transcript: &[(String, String)]) -> Vec<String> {
+        let config = &self.config.reply_suggestions;
+        let model = config.model.as_deref().unwrap_or(&self.config.default_model);
+
+        match self.request_reply_suggestions(model, transcript).await {
+            Ok(suggestions) => suggestions,
+            Err(e) => {
+                warn!(model = %model, error = %e, "reply suggestion generation failed");
+                Vec::new()
+            }
+        }
+    }
+
+    async fn request_reply_suggestions(
+        &self,
+        model: &str,
+        transcript: &[(String, String)],
+    ) -> anyhow::Result<Vec<String>> {
+        let provider = self.provider_for_model(model)?;
+        let _slot = self.acquire_completion_slot_for(model).await?;
+        let request = suggestions::suggestions_request(model, transcript);
+        let completion = self.complete(provider.as_ref(), request).await?;
+        let reply = completion.message.text().unwrap_or_default();
+        Ok(suggestions::parse_suggestions(reply))
+    }
+
     pub fn default_provider(&self) -> Result<Arc<dyn LLMProvider>, OrchestratorError> {
         self.provider_for_model(&self.config.default_model)
     }
 
+    // The identifier of the provider serving `model`, matching `provider_for_model`:
+    // its own provider when registered, otherwise OpenRouter
+    fn provider_key(&self, model: &str) -> String {
+        let identifier = provider_identifier_from_model(model);
+        let registered = |identifier: &str| {
+            self.providers
+                .as_ref()
+                .is_some_and(|providers| providers.get(identifier).is_some())
+        };
+        if !registered(&identifier) && registered("openrouter") {
+            return "openrouter".to_string();
+        }
+        identifier
+    }
+
     pub fn provider_for_model(
         &self,
         model: &str,
@@ -165,6 +609,14 @@ impl Orchestrator {
     pub async fn list_openrouter_models(
         &self,
     ) -> Result<Vec<OpenRouterModelSummary>, OrchestratorError> {
+        self.list_openrouter_models_with_rate_limit().await.map(|(models, _)| models)
+    }
+
+    /// Like [`Orchestrator::list_openrouter_models`], also returning the rate-limit
+    /// headers OpenRouter sent. A 429 fails with `ProviderRateLimited`.
+    pub async fn list_openrouter_models_with_rate_limit(
+        &self,
+    ) -> Result<(Vec<OpenRouterModelSummary>, RateLimitInfo), OrchestratorError> {
         let providers = self
             .providers
             .as_ref()
@@ -175,6 +627,8 @@ impl Orchestrator {
             .ok_or(OrchestratorError::OpenRouterUnavailable)?;
 
         let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(Duration::from_secs(self.outbound.connect_timeout_seconds))
             .timeout(openrouter.request_timeout)
             .build()?;
 
@@ -188,7 +642,18 @@ impl Orchestrator {
             request = request.header("X-Title", title);
         }
 
-        let response = request.send().await?.error_for_status()?;
+        let response = request.send().await?;
+        let rate_limit = RateLimitInfo::from_headers(response.headers());
+        let status = response.status();
+        if !status.is_success() {
+            warn!(%status, ?rate_limit, "openrouter model listing failed");
+            let body = response.text().await.unwrap_or_default();
+            return Err(OrchestratorError::from_provider_response(
+                status.as_u16(),
+                &body,
+                rate_limit,
+            ));
+        }
 
         // Debug: log the raw response text first
         let response_text = response.text().await?;
@@ -263,6 +728,7 @@ impl Orchestrator {
                     label: model.name.unwrap_or_else(|| model.id.clone()),
                     description: model.description,
                     pricing,
+                    context_length: model.context_length,
                     supports_reasoning: supported_params.contains(&"reasoning".to_string()),
                     supports_images: modalities.contains(&"image".to_string()),
                     supports_tools: supported_params.contains(&"tools".to_string())
@@ -278,12 +744,30 @@ impl Orchestrator {
                     supports_streaming: supported_params.contains(&"streaming".to_string()) || true, // Default true for modern models
                 }
             })
-            .collect();
+            .collect::<Vec<_>>();
 
-        Ok(models)
+        // Kept for prompt length checks and pricing, which cannot wait on a listing
+        self.catalogue
+            .write()
+            .unwrap_or_else(|error| error.into_inner())
+            .extend(models.iter().map(|model| (model.id.clone(), model.clone())));
+
+        Ok((models, rate_limit))
     }
 }
 
+fn completion_slots(config: &OrchestratorConfig) -> Arc<Semaphore> {
+    Arc::new(Semaphore::new(config.max_concurrent_completions.max(1)))
+}
+
+fn provider_slots(config: &OrchestratorConfig) -> HashMap<String, ProviderSlots> {
+    config
+        .provider_concurrency
+        .iter()
+        .map(|limit| (limit.provider.clone(), ProviderSlots::new(limit)))
+        .collect()
+}
+
 fn load_providers(config: &OrchestratorConfig) -> Result<ProviderIndex, OrchestratorError> {
     let mut metadata = Vec::new();
 
@@ -411,6 +895,9 @@ pub struct OpenRouterModelSummary {
     pub description: Option<String>,
     #[serde(default)]
     pub pricing: Option<ModelPricing>,
+    /// Tokens the model accepts across prompt and reply, when OpenRouter reports it.
+    #[serde(default)]
+    pub context_length: Option<u32>,
     #[serde(default)]
     pub supports_reasoning: bool,
     #[serde(default)]
@@ -446,6 +933,8 @@ struct OpenRouterModelEntry {
     #[serde(default)]
     pricing: Option<OpenRouterPricing>,
     #[serde(default)]
+    context_length: Option<u32>,
+    #[serde(default)]
     capabilities: Option<Vec<String>>,
     #[serde(default)]
     architecture: Option<OpenRouterArchitecture>,
@@ -567,8 +1056,12 @@ pub mod test_support {
             }
 
             Orchestrator {
+                completion_slots: completion_slots(&self.config),
+                provider_slots: provider_slots(&self.config),
                 config: self.config,
+                outbound: OutboundHttpConfig::default(),
                 providers: Some(index),
+                catalogue: RwLock::default(),
             }
         }
     }
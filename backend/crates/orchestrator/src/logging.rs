@@ -0,0 +1,137 @@
+//! Provider request logging, enabled through `orchestrator.provider_logging`.
+
+use std::time::Duration;
+
+use denkwerk::{CompletionRequest, CompletionResponse, LLMError};
+use switchboard_config::{ProviderLogLevel, ProviderLoggingConfig};
+use tracing::{debug, info};
+
+const REDACTED: &str = "[redacted]";
+
+// Secret-looking prefixes for API keys and access tokens
+const SECRET_PREFIXES: &[&str] = &["sk-", "pk-", "ghp_", "gho_", "github_pat_", "xox", "AKIA"];
+
+// The prompt text of a request, one line per message
+pub(crate) fn request_prompt(request: &CompletionRequest) -> String {
+    request
+        .messages
+        .iter()
+        .filter_map(|message| message.text())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What gets logged for a prompt: the full text when `verbose` is set, otherwise a
+/// redacted preview of at most `preview_chars` characters.
+pub fn prompt_preview(prompt: &str, config: &ProviderLoggingConfig) -> String {
+    if config.verbose {
+        return prompt.to_string();
+    }
+
+    let redacted = redact(prompt);
+    let total = redacted.chars().count();
+    if total <= config.preview_chars {
+        return redacted;
+    }
+
+    let mut preview: String = redacted.chars().take(config.preview_chars).collect();
+    preview.push_str(&format!("… ({} more chars)", total - config.preview_chars));
+    preview
+}
+
+/// Replace words that look like emails, long numbers or credentials with a marker.
+/// Whitespace is collapsed, which is fine for a log preview.
+pub fn redact(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| if is_sensitive(word) { REDACTED } else { word })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_sensitive(word: &str) -> bool {
+    let word = word.trim_matches(|c: char| matches!(c, '"' | '\'' | ',' | ';' | '(' | ')'));
+
+    let is_email = word
+        .split_once('@')
+        .is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'));
+    // Card, phone and account numbers
+    let digits = word.chars().filter(char::is_ascii_digit).count();
+    let has_secret_prefix = SECRET_PREFIXES.iter().any(|prefix| word.starts_with(prefix));
+    // Opaque tokens: long runs mixing letters and digits
+    let is_token = word.len() >= 24
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && word.chars().any(|c| c.is_ascii_alphabetic())
+        && digits > 0;
+
+    is_email || digits >= 8 || (has_secret_prefix && word.len() > 8) || is_token
+}
+
+pub(crate) fn log_completion(
+    config: &ProviderLoggingConfig,
+    provider: &str,
+    model: &str,
+    prompt: &str,
+    latency: Duration,
+    result: &Result<CompletionResponse, LLMError>,
+) {
+    let preview = prompt_preview(prompt, config);
+    let latency_ms = latency.as_millis() as u64;
+    let usage = result.as_ref().ok().and_then(|response| response.usage.as_ref());
+    let prompt_tokens = usage.map(|usage| usage.prompt_tokens);
+    let completion_tokens = usage.map(|usage| usage.completion_tokens);
+    let error = result.as_ref().err().map(|error| error.to_string());
+
+    macro_rules! emit {
+        ($level:ident) => {
+            $level!(
+                target: "switchboard::provider",
+                provider,
+                model,
+                latency_ms,
+                streaming = false,
+                ?prompt_tokens,
+                ?completion_tokens,
+                ?error,
+                prompt = %preview,
+                "provider completion"
+            )
+        };
+    }
+    match config.level {
+        ProviderLogLevel::Debug => emit!(debug),
+        ProviderLogLevel::Info => emit!(info),
+    }
+}
+
+pub(crate) fn log_stream_opened(
+    config: &ProviderLoggingConfig,
+    provider: &str,
+    model: &str,
+    prompt: &str,
+    latency: Duration,
+    error: Option<&LLMError>,
+) {
+    let preview = prompt_preview(prompt, config);
+    let latency_ms = latency.as_millis() as u64;
+    let error = error.map(|error| error.to_string());
+
+    // Token counts are only known once the stream has been drained by the caller
+    macro_rules! emit {
+        ($level:ident) => {
+            $level!(
+                target: "switchboard::provider",
+                provider,
+                model,
+                latency_ms,
+                streaming = true,
+                ?error,
+                prompt = %preview,
+                "provider stream opened"
+            )
+        };
+    }
+    match config.level {
+        ProviderLogLevel::Debug => emit!(debug),
+        ProviderLogLevel::Info => emit!(info),
+    }
+}
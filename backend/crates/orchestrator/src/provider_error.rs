@@ -0,0 +1,119 @@
+//! Classifying provider failures from their HTTP status and error body, so callers
+//! can tell a bad key from an oversized prompt.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What a provider's error response means for the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderErrorKind {
+    /// Too many requests; retry later.
+    RateLimited,
+    /// The provider rejected our credentials, or the account cannot pay.
+    AuthFailed,
+    /// The requested model does not exist or has no available endpoint.
+    ModelNotFound,
+    /// The prompt plus requested output does not fit the model's context window.
+    ContextLengthExceeded,
+    /// The provider, or the upstream it routes to, failed.
+    ServerError,
+    /// Anything else, e.g. a malformed request.
+    Other,
+}
+
+impl ProviderErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProviderErrorKind::RateLimited => "rate_limited",
+            ProviderErrorKind::AuthFailed => "auth_failed",
+            ProviderErrorKind::ModelNotFound => "model_not_found",
+            ProviderErrorKind::ContextLengthExceeded => "context_length_exceeded",
+            ProviderErrorKind::ServerError => "server_error",
+            ProviderErrorKind::Other => "other",
+        }
+    }
+}
+
+// Phrases OpenRouter and the providers behind it use when a prompt is too long
+const CONTEXT_LENGTH_PHRASES: &[&str] = &[
+    "context length",
+    "context_length",
+    "context window",
+    "maximum context",
+    "too many tokens",
+    "prompt is too long",
+    "reduce the length",
+];
+
+const MODEL_NOT_FOUND_PHRASES: &[&str] = &[
+    "not a valid model",
+    "model not found",
+    "model_not_found",
+    "no endpoints found",
+    "does not exist",
+    "unknown model",
+];
+
+/// Classify a failed provider response. `status` is the HTTP status when known;
+/// otherwise the `error.code` of an OpenRouter-style JSON body, or a 4xx/5xx code
+/// mentioned in the text, is used. The message is checked first for context-length
+/// and unknown-model errors, which providers report under several statuses.
+///
+/// ```
+/// use switchboard_orchestrator::provider_error::{classify, ProviderErrorKind};
+///
+/// let body = r#"{"error":{"code":400,"message":"maximum context length is 8192 tokens"}}"#;
+/// assert_eq!(classify(Some(400), body), ProviderErrorKind::ContextLengthExceeded);
+/// assert_eq!(classify(Some(429), "slow down"), ProviderErrorKind::RateLimited);
+/// assert_eq!(classify(None, "provider returned status 503"), ProviderErrorKind::ServerError);
+/// ```
+pub fn classify(status: Option<u16>, body: &str) -> ProviderErrorKind {
+    let message = error_message(body).to_lowercase();
+    if CONTEXT_LENGTH_PHRASES.iter().any(|phrase| message.contains(phrase)) {
+        return ProviderErrorKind::ContextLengthExceeded;
+    }
+    if MODEL_NOT_FOUND_PHRASES.iter().any(|phrase| message.contains(phrase)) {
+        return ProviderErrorKind::ModelNotFound;
+    }
+
+    let status = status
+        .or_else(|| body_code(body))
+        .or_else(|| status_in_text(body));
+    match status {
+        Some(429) => ProviderErrorKind::RateLimited,
+        Some(401 | 402 | 403) => ProviderErrorKind::AuthFailed,
+        Some(404) => ProviderErrorKind::ModelNotFound,
+        Some(413) => ProviderErrorKind::ContextLengthExceeded,
+        Some(500..=599) => ProviderErrorKind::ServerError,
+        _ => ProviderErrorKind::Other,
+    }
+}
+
+/// The human-readable message of an error body: `error.message` of an
+/// OpenRouter-style JSON body, otherwise the trimmed body itself.
+pub fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|value| match &value["error"] {
+            Value::String(message) => Some(message.clone()),
+            error => error["message"].as_str().map(str::to_string),
+        })
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+fn body_code(body: &str) -> Option<u16> {
+    let value = serde_json::from_str::<Value>(body).ok()?;
+    let code = &value["error"]["code"];
+    code.as_u64()
+        .or_else(|| code.as_str()?.parse().ok())
+        .and_then(|code| u16::try_from(code).ok())
+}
+
+// A standalone 4xx or 5xx number in an error's text, e.g. "status 502"
+fn status_in_text(text: &str) -> Option<u16> {
+    text.split(|c: char| !c.is_ascii_digit())
+        .filter(|digits| digits.len() == 3)
+        .filter_map(|digits| digits.parse::<u16>().ok())
+        .find(|code| (400..=599).contains(code))
+}
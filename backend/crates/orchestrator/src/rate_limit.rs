@@ -0,0 +1,61 @@
+//! Upstream rate-limit state reported by OpenRouter through response headers.
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+const LIMIT_HEADER: &str = "x-ratelimit-limit";
+const REMAINING_HEADER: &str = "x-ratelimit-remaining";
+const RESET_HEADER: &str = "x-ratelimit-reset";
+const RETRY_AFTER_HEADER: &str = "retry-after";
+
+/// Rate-limit headers from an upstream response. Every field is optional because
+/// OpenRouter only sends them for some keys and some endpoints.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitInfo {
+    /// Requests allowed in the current window.
+    pub limit: Option<u64>,
+    /// Requests left in the current window.
+    pub remaining: Option<u64>,
+    /// When the window resets, as sent upstream (a Unix timestamp in milliseconds).
+    pub reset: Option<u64>,
+    /// Seconds to wait before retrying, from the `Retry-After` of a 429.
+    pub retry_after_seconds: Option<u64>,
+}
+
+impl RateLimitInfo {
+    /// Read the rate-limit headers, ignoring any that are missing or malformed.
+    /// `Retry-After` is only understood in its delay-seconds form.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let number = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+
+        Self {
+            limit: number(LIMIT_HEADER),
+            remaining: number(REMAINING_HEADER),
+            reset: number(RESET_HEADER),
+            retry_after_seconds: number(RETRY_AFTER_HEADER),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The `X-RateLimit-*` and `Retry-After` headers to forward to clients, with
+    /// names in lowercase as HTTP/2 requires.
+    pub fn response_headers(&self) -> Vec<(&'static str, String)> {
+        [
+            (LIMIT_HEADER, self.limit),
+            (REMAINING_HEADER, self.remaining),
+            (RESET_HEADER, self.reset),
+            (RETRY_AFTER_HEADER, self.retry_after_seconds),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value.to_string())))
+        .collect()
+    }
+}
@@ -0,0 +1,72 @@
+//! Short replies a user might send next in a chat, enabled through
+//! `orchestrator.reply_suggestions`.
+
+use denkwerk::{ChatMessage, CompletionRequest};
+
+/// Suggestions offered per request.
+pub const SUGGESTION_COUNT: usize = 3;
+
+// Three one-line replies, with room for numbering
+const SUGGESTIONS_MAX_TOKENS: u32 = 120;
+
+// Longer lines are the model rambling rather than a quick reply
+const SUGGESTION_MAX_CHARS: usize = 120;
+
+/// `transcript` is `(role, content)` pairs, oldest first.
+pub(crate) fn suggestions_request(
+    model: &str,
+    transcript: &[(String, String)],
+) -> CompletionRequest {
+    let conversation = transcript
+        .iter()
+        .map(|(role, content)| format!("{role}: {content}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let prompt = format!(
+        "Suggest {SUGGESTION_COUNT} short replies the user could send next in the \
+         conversation below. Reply with one suggestion per line and nothing else.\n\n\
+         {conversation}"
+    );
+    let mut request = CompletionRequest::new(model.to_string(), vec![ChatMessage::user(prompt)]);
+    request.max_tokens = Some(SUGGESTIONS_MAX_TOKENS);
+    request
+}
+
+/// Up to [`SUGGESTION_COUNT`] suggestions from a model's reply: one per non-empty
+/// line, without list markers or surrounding quotes.
+///
+/// ```
+/// use switchboard_orchestrator::suggestions::parse_suggestions;
+///
+/// let reply = "1. Sounds good!\n2) \"Can you explain more?\"\n\n- Thanks\n4. Extra";
+/// assert_eq!(parse_suggestions(reply), ["Sounds good!", "Can you explain more?", "Thanks"]);
+/// assert_eq!(parse_suggestions("3.5 hours works"), ["3.5 hours works"]);
+/// assert!(parse_suggestions("  \n").is_empty());
+/// ```
+pub fn parse_suggestions(reply: &str) -> Vec<String> {
+    reply
+        .lines()
+        .filter_map(|line| {
+            let line = strip_list_marker(line.trim())
+                .trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '“' | '”'))
+                .trim();
+            (!line.is_empty() && line.chars().count() <= SUGGESTION_MAX_CHARS)
+                .then(|| line.to_string())
+        })
+        .take(SUGGESTION_COUNT)
+        .collect()
+}
+
+// "1. ", "2) ", "- ", "* " or "• "; a line merely starting with a number keeps it
+fn strip_list_marker(line: &str) -> &str {
+    if let Some(rest) = line.strip_prefix(['-', '*', '•']) {
+        return rest.trim_start();
+    }
+    let unnumbered = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    match unnumbered.strip_prefix(['.', ')']) {
+        Some(rest) if unnumbered.len() < line.len() && rest.starts_with(char::is_whitespace) => {
+            rest.trim_start()
+        }
+        _ => line,
+    }
+}
@@ -0,0 +1,58 @@
+//! Short chat titles derived from a chat's first message, enabled through
+//! `orchestrator.title_generation`.
+
+use denkwerk::{ChatMessage, CompletionRequest};
+
+// Generous for a title of a few words, and keeps a chatty model from running on
+const TITLE_MAX_TOKENS: u32 = 32;
+
+pub(crate) fn title_request(model: &str, first_message: &str) -> CompletionRequest {
+    let prompt = format!(
+        "Write a short title, at most six words, for a conversation that starts with \
+         the message below. Reply with the title only, without quotes.\n\n{first_message}"
+    );
+    let mut request = CompletionRequest::new(model.to_string(), vec![ChatMessage::user(prompt)]);
+    request.max_tokens = Some(TITLE_MAX_TOKENS);
+    request
+}
+
+/// The first line of a model's reply with surrounding quotes and trailing punctuation
+/// removed, truncated to `max_chars`. `None` when nothing usable is left.
+pub fn clean_title(reply: &str, max_chars: usize) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line.trim_start_matches(|c: char| c == '#' || c.is_whitespace());
+    let line = line.strip_prefix("Title:").unwrap_or(line);
+    let line = line
+        .trim()
+        .trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '*' | '“' | '”'))
+        .trim_end_matches(|c: char| matches!(c, '.' | ':' | ';'))
+        .trim();
+    if line.is_empty() {
+        return None;
+    }
+    Some(truncate(line, max_chars))
+}
+
+/// The title used when none could be generated: the message on one line,
+/// truncated to `max_chars` with an ellipsis.
+///
+/// ```
+/// use switchboard_orchestrator::titles::fallback_title;
+///
+/// assert_eq!(fallback_title("How do I\nbake bread?", 60), "How do I bake bread?");
+/// assert_eq!(fallback_title("Plan a week in Lisbon", 10), "Plan a we…");
+/// ```
+pub fn fallback_title(first_message: &str, max_chars: usize) -> String {
+    let message = first_message.split_whitespace().collect::<Vec<_>>().join(" ");
+    truncate(&message, max_chars)
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.truncate(truncated.trim_end().len());
+    truncated.push('…');
+    truncated
+}
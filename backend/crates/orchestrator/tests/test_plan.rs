@@ -1,17 +1,29 @@
 //! Integration tests for the orchestrator crate.
 
-use std::{fs, path::Path, sync::Arc, time::Duration};
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use denkwerk::{
-    CompletionRequest, CompletionResponse, CompletionStream, ImageUploadRequest,
+    ChatMessage, CompletionRequest, CompletionResponse, CompletionStream, ImageUploadRequest,
     ImageUploadResponse, LLMError, LLMProvider, ProviderCapabilities,
 };
 use httpmock::prelude::*;
-use switchboard_config::{AppConfig, OpenRouterProviderConfig, OrchestratorConfig};
+use switchboard_config::{
+    AppConfig, CompletionDefaults, OpenRouterProviderConfig, OrchestratorConfig,
+    ProviderConcurrency, ProviderLoggingConfig, USER_AGENT,
+};
 use switchboard_orchestrator::{
+    logging, provider_error,
     test_support::{self, OrchestratorTestBuilder, TestOpenRouterSettings},
-    Orchestrator, OrchestratorError, ProviderMetadata,
+    Orchestrator, OrchestratorError, ProviderErrorKind, ProviderMetadata, RateLimitInfo,
 };
 use tempfile::tempdir;
 
@@ -59,6 +71,48 @@ impl LLMProvider for DummyProvider {
     }
 }
 
+/// Records how many completions are running at once; every call fails after a short delay.
+#[derive(Clone, Default)]
+struct ConcurrencyTrackingProvider {
+    in_flight: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl LLMProvider for ConcurrencyTrackingProvider {
+    async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse, LLMError> {
+        let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(current, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(LLMError::Unsupported("complete"))
+    }
+
+    async fn stream_completion(
+        &self,
+        _request: CompletionRequest,
+    ) -> Result<CompletionStream, LLMError> {
+        Err(LLMError::Unsupported("stream"))
+    }
+
+    async fn upload_image(
+        &self,
+        _request: ImageUploadRequest,
+    ) -> Result<ImageUploadResponse, LLMError> {
+        Err(LLMError::Unsupported("upload"))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::default()
+    }
+
+    fn name(&self) -> &'static str {
+        "tracking"
+    }
+}
+
 fn provider_descriptor(identifier: &str, family: &str) -> ProviderMetadata {
     ProviderMetadata {
         identifier: identifier.to_string(),
@@ -329,7 +383,253 @@ async fn list_openrouter_models_handles_http_errors() {
         .await
         .expect_err("http error expected");
 
-    assert!(matches!(err, OrchestratorError::ProviderHttp(_)));
+    assert!(matches!(err, OrchestratorError::ProviderServerError(_)));
+}
+
+#[tokio::test]
+async fn list_openrouter_models_classifies_rejected_credentials() {
+    let server = MockServer::start_async().await;
+
+    let _mock = server
+        .mock_async(|when, then| {
+            when.method(GET).path("/models");
+            then.status(401)
+                .body(r#"{"error":{"code":401,"message":"No auth credentials found"}}"#);
+        })
+        .await;
+
+    let mut config = OrchestratorConfig::default();
+    config.provider_search_path.clear();
+
+    let openrouter_provider: Arc<dyn LLMProvider> = Arc::new(DummyProvider::new("openrouter"));
+    let orchestrator = OrchestratorTestBuilder::new(config)
+        .with_provider(openrouter_metadata(), openrouter_provider)
+        .with_openrouter(TestOpenRouterSettings::new("bad-key", server.base_url()))
+        .build();
+
+    let err = orchestrator
+        .list_openrouter_models()
+        .await
+        .expect_err("rejected key expected");
+
+    match err {
+        OrchestratorError::ProviderAuthFailed(message) => {
+            assert_eq!(message, "No auth credentials found")
+        }
+        other => panic!("expected ProviderAuthFailed, got {other:?}"),
+    }
+}
+
+#[test]
+fn provider_errors_are_classified_from_openrouter_responses() {
+    let cases = [
+        (
+            400,
+            r#"{"error":{"code":400,"message":"This endpoint's maximum context length is 8192 tokens. However, you requested about 9100 tokens."}}"#,
+            ProviderErrorKind::ContextLengthExceeded,
+        ),
+        (
+            400,
+            r#"{"error":{"code":400,"message":"openai/gpt-9 is not a valid model ID"}}"#,
+            ProviderErrorKind::ModelNotFound,
+        ),
+        (
+            404,
+            r#"{"error":{"code":404,"message":"No endpoints found for mistral/unknown."}}"#,
+            ProviderErrorKind::ModelNotFound,
+        ),
+        (
+            401,
+            r#"{"error":{"code":401,"message":"User not found."}}"#,
+            ProviderErrorKind::AuthFailed,
+        ),
+        (
+            402,
+            r#"{"error":{"code":402,"message":"Insufficient credits"}}"#,
+            ProviderErrorKind::AuthFailed,
+        ),
+        (
+            429,
+            r#"{"error":{"code":429,"message":"Rate limit exceeded: free-models-per-min"}}"#,
+            ProviderErrorKind::RateLimited,
+        ),
+        (
+            502,
+            r#"{"error":{"code":502,"message":"Provider returned error"}}"#,
+            ProviderErrorKind::ServerError,
+        ),
+        (503, "Service Unavailable", ProviderErrorKind::ServerError),
+        (
+            400,
+            r#"{"error":{"code":400,"message":"temperature must be a number"}}"#,
+            ProviderErrorKind::Other,
+        ),
+    ];
+
+    for (status, body, expected) in cases {
+        assert_eq!(provider_error::classify(Some(status), body), expected, "{body}");
+    }
+
+    // Without a status, the code in the body or the text decides
+    let body = r#"{"error":{"code":"429","message":"slow down"}}"#;
+    assert_eq!(provider_error::classify(None, body), ProviderErrorKind::RateLimited);
+    assert_eq!(
+        provider_error::classify(None, "request failed with status 500"),
+        ProviderErrorKind::ServerError
+    );
+
+    let error = OrchestratorError::from_provider_response(
+        400,
+        r#"{"error":{"message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#,
+        RateLimitInfo::default(),
+    );
+    assert!(matches!(error, OrchestratorError::ContextLengthExceeded(_)));
+    assert_eq!(error.kind(), Some(ProviderErrorKind::ContextLengthExceeded));
+}
+
+#[test]
+fn rate_limit_info_parses_openrouter_headers() {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("X-RateLimit-Limit", "200".parse().unwrap());
+    headers.insert("X-RateLimit-Remaining", " 17 ".parse().unwrap());
+    headers.insert("X-RateLimit-Reset", "1741305600000".parse().unwrap());
+    headers.insert("Retry-After", "30".parse().unwrap());
+
+    let info = RateLimitInfo::from_headers(&headers);
+    assert_eq!(
+        info,
+        RateLimitInfo {
+            limit: Some(200),
+            remaining: Some(17),
+            reset: Some(1_741_305_600_000),
+            retry_after_seconds: Some(30),
+        }
+    );
+    assert_eq!(
+        info.response_headers(),
+        vec![
+            ("x-ratelimit-limit", "200".to_string()),
+            ("x-ratelimit-remaining", "17".to_string()),
+            ("x-ratelimit-reset", "1741305600000".to_string()),
+            ("retry-after", "30".to_string()),
+        ]
+    );
+
+    // HTTP-date Retry-After values and garbage are skipped rather than failing
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+    headers.insert("X-RateLimit-Remaining", "lots".parse().unwrap());
+    assert!(RateLimitInfo::from_headers(&headers).is_empty());
+}
+
+#[tokio::test]
+async fn list_openrouter_models_reports_upstream_rate_limits() {
+    let server = MockServer::start_async().await;
+
+    let _mock = server
+        .mock_async(|when, then| {
+            when.method(GET).path("/models");
+            then.status(429)
+                .header("X-RateLimit-Limit", "20")
+                .header("X-RateLimit-Remaining", "0")
+                .header("Retry-After", "12");
+        })
+        .await;
+
+    let mut config = OrchestratorConfig::default();
+    config.provider_search_path.clear();
+
+    let openrouter_provider: Arc<dyn LLMProvider> = Arc::new(DummyProvider::new("openrouter"));
+    let orchestrator = OrchestratorTestBuilder::new(config)
+        .with_provider(openrouter_metadata(), openrouter_provider)
+        .with_openrouter(
+            TestOpenRouterSettings::new("test-key", server.base_url())
+                .with_timeout(Duration::from_secs(1)),
+        )
+        .build();
+
+    let err = orchestrator
+        .list_openrouter_models()
+        .await
+        .expect_err("rate limit expected");
+
+    let OrchestratorError::ProviderRateLimited(info) = err else {
+        panic!("expected a rate limit error, got {err:?}");
+    };
+    assert_eq!(info.limit, Some(20));
+    assert_eq!(info.remaining, Some(0));
+    assert_eq!(info.retry_after_seconds, Some(12));
+}
+
+#[tokio::test]
+async fn list_openrouter_models_times_out_on_slow_upstream() {
+    let server = MockServer::start_async().await;
+
+    let _mock = server
+        .mock_async(|when, then| {
+            when.method(GET).path("/models");
+            then.status(200)
+                .delay(Duration::from_secs(5))
+                .header("Content-Type", "application/json")
+                .body(r#"{"data":[]}"#);
+        })
+        .await;
+
+    let mut config = OrchestratorConfig::default();
+    config.provider_search_path.clear();
+
+    let openrouter_provider: Arc<dyn LLMProvider> = Arc::new(DummyProvider::new("openrouter"));
+    let orchestrator = OrchestratorTestBuilder::new(config)
+        .with_provider(openrouter_metadata(), openrouter_provider)
+        .with_openrouter(
+            TestOpenRouterSettings::new("test-key", server.base_url())
+                .with_timeout(Duration::from_millis(200)),
+        )
+        .build();
+
+    let started = std::time::Instant::now();
+    let err = orchestrator
+        .list_openrouter_models()
+        .await
+        .expect_err("slow upstream should time out");
+
+    assert!(started.elapsed() < Duration::from_secs(2));
+    match err {
+        OrchestratorError::ProviderHttp(source) => assert!(source.is_timeout()),
+        other => panic!("expected timeout, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn list_openrouter_models_sends_user_agent() {
+    let server = MockServer::start_async().await;
+
+    let mock = server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/models")
+                .header("user-agent", USER_AGENT);
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .body(r#"{"data":[]}"#);
+        })
+        .await;
+
+    let mut config = OrchestratorConfig::default();
+    config.provider_search_path.clear();
+
+    let openrouter_provider: Arc<dyn LLMProvider> = Arc::new(DummyProvider::new("openrouter"));
+    let orchestrator = OrchestratorTestBuilder::new(config)
+        .with_provider(openrouter_metadata(), openrouter_provider)
+        .with_openrouter(TestOpenRouterSettings::new("test-key", server.base_url()))
+        .build();
+
+    orchestrator
+        .list_openrouter_models()
+        .await
+        .expect("request should carry the user agent");
+    mock.assert_async().await;
+    assert!(USER_AGENT.starts_with("switchboard-ngx/"));
 }
 
 #[tokio::test]
@@ -451,3 +751,292 @@ fn active_model_reports_configured_default_model() {
     let orchestrator = Orchestrator::new(&config);
     assert_eq!(orchestrator.active_model().as_deref(), Some("custom-model"));
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn completion_slots_cap_concurrent_provider_calls() {
+    let mut config = OrchestratorConfig::default();
+    config.default_model = "tracking/model".to_string();
+    config.max_concurrent_completions = 2;
+    config.completion_queue_timeout_ms = 5_000;
+
+    let provider = ConcurrencyTrackingProvider::default();
+    let orchestrator = Arc::new(
+        OrchestratorTestBuilder::new(config)
+            .with_provider(
+                provider_descriptor("tracking", "test"),
+                Arc::new(provider.clone()),
+            )
+            .build(),
+    );
+
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            let orchestrator = orchestrator.clone();
+            tokio::spawn(async move {
+                let _slot = orchestrator
+                    .acquire_completion_slot()
+                    .await
+                    .expect("slot acquired within queue timeout");
+                let provider = orchestrator.default_provider().expect("provider registered");
+                let request = CompletionRequest::new("tracking/model".to_string(), Vec::new());
+                let _ = provider.complete(request).await;
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        task.await.expect("completion task panicked");
+    }
+
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 8);
+    assert!(provider.peak.load(Ordering::SeqCst) <= 2);
+}
+
+#[tokio::test]
+async fn completion_slots_fail_fast_without_queue_timeout() {
+    let mut config = OrchestratorConfig::default();
+    config.max_concurrent_completions = 1;
+    config.completion_queue_timeout_ms = 0;
+
+    let orchestrator = OrchestratorTestBuilder::new(config).build();
+    let held = orchestrator
+        .acquire_completion_slot()
+        .await
+        .expect("first slot is free");
+
+    let err = orchestrator
+        .acquire_completion_slot()
+        .await
+        .expect_err("second slot should be rejected");
+    assert!(matches!(err, OrchestratorError::CompletionCapacityExceeded));
+
+    drop(held);
+    assert!(orchestrator.acquire_completion_slot().await.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn provider_concurrency_caps_one_provider_without_limiting_others() {
+    let mut config = OrchestratorConfig::default();
+    config.max_concurrent_completions = 16;
+    config.completion_queue_timeout_ms = 5_000;
+    config.provider_concurrency = vec![ProviderConcurrency {
+        provider: "slow".to_string(),
+        max_concurrent: 2,
+        max_queued: 8,
+    }];
+
+    let slow = ConcurrencyTrackingProvider::default();
+    let fast = ConcurrencyTrackingProvider::default();
+    let orchestrator = Arc::new(
+        OrchestratorTestBuilder::new(config)
+            .with_provider(provider_descriptor("slow", "test"), Arc::new(slow.clone()))
+            .with_provider(provider_descriptor("fast", "test"), Arc::new(fast.clone()))
+            .build(),
+    );
+
+    let tasks: Vec<_> = ["slow/model"; 6]
+        .into_iter()
+        .chain(["fast/model"; 6])
+        .map(|model| {
+            let orchestrator = orchestrator.clone();
+            tokio::spawn(async move {
+                let _slot = orchestrator
+                    .acquire_completion_slot_for(model)
+                    .await
+                    .expect("slot acquired within queue timeout");
+                let provider = orchestrator.provider_for_model(model).expect("provider registered");
+                let request = CompletionRequest::new(model.to_string(), Vec::new());
+                let _ = provider.complete(request).await;
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        task.await.expect("completion task panicked");
+    }
+
+    assert_eq!(slow.calls.load(Ordering::SeqCst), 6);
+    assert_eq!(fast.calls.load(Ordering::SeqCst), 6);
+    assert!(slow.peak.load(Ordering::SeqCst) <= 2);
+    assert!(fast.peak.load(Ordering::SeqCst) > 2);
+}
+
+#[tokio::test]
+async fn provider_concurrency_rejects_requests_beyond_the_queue() {
+    let mut config = OrchestratorConfig::default();
+    config.completion_queue_timeout_ms = 5_000;
+    config.provider_concurrency = vec![ProviderConcurrency {
+        provider: "slow".to_string(),
+        max_concurrent: 1,
+        max_queued: 0,
+    }];
+
+    let orchestrator = OrchestratorTestBuilder::new(config)
+        .with_provider(
+            provider_descriptor("slow", "test"),
+            Arc::new(ConcurrencyTrackingProvider::default()),
+        )
+        .build();
+    let held = orchestrator
+        .acquire_completion_slot_for("slow/model")
+        .await
+        .expect("first slot is free");
+
+    let err = orchestrator
+        .acquire_completion_slot_for("slow/model")
+        .await
+        .expect_err("no queue room, so the request fails without waiting");
+    assert!(matches!(err, OrchestratorError::CompletionCapacityExceeded));
+    assert!(orchestrator.acquire_completion_slot_for("other/model").await.is_ok());
+
+    drop(held);
+    assert!(orchestrator.acquire_completion_slot_for("slow/model").await.is_ok());
+}
+
+#[test]
+fn provider_log_preview_redacts_unless_verbose() {
+    let prompt = "Email jane.doe@example.com about card 4111111111111111, \
+                  key sk-live-abcdef123456 and the weekly report";
+    let mut config = ProviderLoggingConfig {
+        enabled: true,
+        ..ProviderLoggingConfig::default()
+    };
+
+    let preview = logging::prompt_preview(prompt, &config);
+    assert_eq!(
+        preview,
+        "Email [redacted] about card [redacted] key [redacted] and the weekly report"
+    );
+
+    config.preview_chars = 16;
+    let preview = logging::prompt_preview(prompt, &config);
+    assert!(preview.starts_with("Email [redacted]…"));
+    assert!(!preview.contains("jane"));
+
+    config.verbose = true;
+    assert_eq!(logging::prompt_preview(prompt, &config), prompt);
+}
+
+#[tokio::test]
+async fn logged_completion_returns_provider_result() {
+    let mut config = OrchestratorConfig::default();
+    config.provider_logging.enabled = true;
+    let orchestrator = OrchestratorTestBuilder::new(config).build();
+
+    let provider = DummyProvider::new("dummy");
+    let request = CompletionRequest::new("dummy/model".to_string(), Vec::new());
+    let err = orchestrator
+        .complete(&provider, request)
+        .await
+        .expect_err("dummy provider never completes");
+    assert!(matches!(err, LLMError::Unsupported("complete")));
+}
+
+#[test]
+fn completion_defaults_fill_unset_request_parameters() {
+    let mut config = OrchestratorConfig::default();
+    config.completion_defaults = vec![
+        CompletionDefaults {
+            model: "openrouter".to_string(),
+            temperature: Some(0.2),
+            max_tokens: Some(512),
+            max_prompt_tokens: None,
+        },
+        CompletionDefaults {
+            model: "openrouter/anthropic/claude".to_string(),
+            temperature: None,
+            max_tokens: Some(2048),
+            max_prompt_tokens: None,
+        },
+    ];
+    let orchestrator = OrchestratorTestBuilder::new(config).build();
+
+    let mut request = CompletionRequest::new("openrouter/anthropic/claude".to_string(), Vec::new());
+    orchestrator.apply_completion_defaults(&mut request);
+    assert_eq!(request.temperature, Some(0.2));
+    assert_eq!(request.max_tokens, Some(2048));
+
+    let mut request = CompletionRequest::new("openrouter/other".to_string(), Vec::new());
+    request.temperature = Some(1.0);
+    orchestrator.apply_completion_defaults(&mut request);
+    assert_eq!(request.temperature, Some(1.0));
+    assert_eq!(request.max_tokens, Some(512));
+}
+
+#[test]
+fn prompt_length_guard_rejects_oversize_prompts() {
+    let mut config = OrchestratorConfig::default();
+    config.max_prompt_tokens = Some(1_000);
+    config.completion_defaults = vec![CompletionDefaults {
+        model: "openrouter/small".to_string(),
+        temperature: None,
+        max_tokens: None,
+        max_prompt_tokens: Some(50),
+    }];
+    let orchestrator = OrchestratorTestBuilder::new(config).build();
+
+    // 400 characters is 100 tokens, plus 7 of message markup
+    let request = |model: &str| {
+        CompletionRequest::new(model.to_string(), vec![ChatMessage::user("x".repeat(400))])
+    };
+    let small = request("openrouter/small");
+    let err = orchestrator
+        .check_prompt_length(&small)
+        .expect_err("prompt is over the model's limit");
+    assert!(matches!(err, OrchestratorError::ContextLengthExceeded(_)));
+    assert_eq!(
+        err.to_string(),
+        "prompt exceeds the model's context length: 107 prompt tokens, openrouter/small allows 50"
+    );
+
+    let tokens = orchestrator
+        .check_prompt_length(&request("openrouter/other"))
+        .expect("prompt is within the global cap");
+    assert_eq!(tokens, 107);
+}
+
+#[test]
+fn model_allow_list_admits_matching_models() {
+    let mut config = OrchestratorConfig::default();
+    config.allowed_models = vec!["openrouter/openai/gpt-4.1".to_string()];
+    let orchestrator = OrchestratorTestBuilder::new(config).build();
+
+    orchestrator
+        .check_model_allowed("openrouter/openai/gpt-4.1")
+        .expect("model is on the allow list");
+    let err = orchestrator
+        .check_model_allowed("openrouter/openai/gpt-4.1-mini")
+        .expect_err("only exact ids match without a glob");
+    assert!(matches!(err, OrchestratorError::ModelNotAllowed(_)));
+}
+
+#[test]
+fn model_deny_list_wins_over_allow_list() {
+    let mut config = OrchestratorConfig::default();
+    config.allowed_models = vec!["openrouter/*".to_string()];
+    config.denied_models = vec!["openrouter/openai/o3-pro".to_string()];
+    let orchestrator = OrchestratorTestBuilder::new(config).build();
+
+    let err = orchestrator
+        .check_model_allowed("openrouter/openai/o3-pro")
+        .expect_err("model is denied");
+    assert_eq!(err.to_string(), "model openrouter/openai/o3-pro is not allowed on this server");
+    assert_eq!(err.kind(), None);
+    orchestrator
+        .check_model_allowed("openrouter/openai/o3")
+        .expect("model is only on the allow list");
+}
+
+#[test]
+fn model_lists_match_globs() {
+    let mut config = OrchestratorConfig::default();
+    config.denied_models = vec!["*/anthropic/claude-*-opus*".to_string(), "openai/*".to_string()];
+    let orchestrator = OrchestratorTestBuilder::new(config).build();
+
+    for denied in ["openrouter/anthropic/claude-3-opus", "openai/o3", "openai/"] {
+        assert!(orchestrator.check_model_allowed(denied).is_err(), "{denied} is denied");
+    }
+    for allowed in ["openrouter/anthropic/claude-3.5-sonnet", "openrouter/openai/o3", "openai"] {
+        assert!(orchestrator.check_model_allowed(allowed).is_ok(), "{allowed} is allowed");
+    }
+}
@@ -1,13 +1,27 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    future::Future,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
-use redis::aio::ConnectionManager;
-use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    SqlitePool,
+};
 use switchboard_auth::Authenticator;
 use switchboard_config::{AppConfig, DatabaseConfig};
 use switchboard_orchestrator::Orchestrator;
-use tokio::fs;
-use tracing::{error, info};
+use tokio::{fs, sync::watch, task::JoinSet, time::Instant};
+use tracing::{error, info, warn};
+
+mod redis_handle;
+pub mod tasks;
+
+pub use redis_handle::RedisHandle;
+pub use tasks::{TaskRegistry, TaskStatus};
 
 mod migrations {
     pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("../../migrations");
@@ -34,63 +48,77 @@ pub mod telemetry {
 
 #[derive(Clone)]
 pub struct BackendServices {
+    /// Pool for writes, and for reads that must observe them.
     pub db_pool: SqlitePool,
+    /// Read-only pool when `database.split_read_write` is set; otherwise `db_pool`.
+    pub db_read_pool: SqlitePool,
+    /// One read-only pool per `database.replica_urls` entry, in order.
+    pub db_replica_pools: Vec<SqlitePool>,
     pub authenticator: Authenticator,
     pub orchestrator: Arc<Orchestrator>,
-    pub redis_conn: Option<ConnectionManager>,
+    pub redis: RedisHandle,
 }
 
 impl BackendServices {
     pub async fn initialise(config: &AppConfig) -> Result<Self> {
         let db_pool = prepare_database(&config.database).await?;
         run_migrations(&db_pool).await?;
+        let db_read_pool = prepare_read_pool(&config.database, &db_pool).await?;
+        let db_replica_pools = prepare_replica_pools(&config.database).await?;
 
-        let authenticator = Authenticator::new(db_pool.clone(), config.auth.clone());
+        let authenticator = Authenticator::new(db_pool.clone(), config.auth.clone())
+            .with_outbound_http(&config.outbound);
         let orchestrator = Arc::new(
             Orchestrator::new(config)
                 .bootstrap()
                 .context("failed to bootstrap orchestrator")?,
         );
 
-        // Initialize Redis connection (optional for development)
-        let redis_conn = match redis::Client::open("redis://127.0.0.1:6379") {
-            Ok(client) => match ConnectionManager::new(client).await {
-                Ok(conn) => {
-                    info!("redis connection established");
-                    Some(conn)
-                }
-                Err(e) => {
-                    tracing::warn!(
-                        "failed to connect to redis, proceeding without redis: {}",
-                        e
-                    );
-                    None
-                }
-            },
-            Err(e) => {
-                tracing::warn!(
-                    "failed to create redis client, proceeding without redis: {}",
-                    e
-                );
-                None
-            }
-        };
+        // Redis is optional; features that use it fall back while it is unavailable
+        let redis = RedisHandle::connect(&config.redis.url).await;
 
         info!(model = ?orchestrator.active_model(), "orchestrator ready");
-        info!("redis connection established");
 
         Ok(Self {
             db_pool,
+            db_read_pool,
+            db_replica_pools,
             authenticator,
             orchestrator,
-            redis_conn,
+            redis,
         })
     }
+
+    /// Whether Redis is currently reachable. Callers should fall back to in-memory
+    /// behaviour when it is not.
+    pub fn redis_available(&self) -> bool {
+        self.redis.is_available()
+    }
 }
 
 async fn prepare_database(config: &DatabaseConfig) -> Result<SqlitePool> {
     ensure_sqlite_path(&config.url).await?;
 
+    if config.split_read_write && !split_read_write(config) {
+        warn!("database.split_read_write has no effect for in-memory databases");
+    }
+
+    if split_read_write(config) {
+        // SQLite serialises writers anyway; one connection avoids SQLITE_BUSY between them.
+        let options = connect_options(&config.url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .foreign_keys(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("failed to connect to database {}", config.url))?;
+
+        info!(url = %config.url, "database write pool established");
+        return Ok(pool);
+    }
+
     let pool = SqlitePoolOptions::new()
         .max_connections(config.max_connections as u32)
         .connect(&config.url)
@@ -106,6 +134,69 @@ async fn prepare_database(config: &DatabaseConfig) -> Result<SqlitePool> {
     Ok(pool)
 }
 
+/// Opened after migrations so the read-only connections see the final schema.
+async fn prepare_read_pool(
+    config: &DatabaseConfig,
+    write_pool: &SqlitePool,
+) -> Result<SqlitePool> {
+    if !split_read_write(config) {
+        return Ok(write_pool.clone());
+    }
+
+    let options = connect_options(&config.url)?
+        .read_only(true)
+        .journal_mode(SqliteJournalMode::Wal);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.read_max_connections)
+        .connect_with(options)
+        .await
+        .with_context(|| format!("failed to open read pool for database {}", config.url))?;
+
+    info!(
+        url = %config.url,
+        connections = config.read_max_connections,
+        "database read pool established"
+    );
+    Ok(pool)
+}
+
+/// Opens every replica read-only and checks it answers a query, so a missing or
+/// corrupt copy fails startup instead of the first read routed to it.
+async fn prepare_replica_pools(config: &DatabaseConfig) -> Result<Vec<SqlitePool>> {
+    let mut pools = Vec::with_capacity(config.replica_urls.len());
+    for url in &config.replica_urls {
+        if url.contains(":memory:") {
+            anyhow::bail!("database replica {url} is in-memory and cannot mirror the primary");
+        }
+
+        let options = connect_options(url)?.read_only(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(config.read_max_connections)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("failed to open database replica {url}"))?;
+        // Reading the schema fails for a file that is not a SQLite database
+        sqlx::query("SELECT COUNT(*) FROM sqlite_master")
+            .execute(&pool)
+            .await
+            .with_context(|| format!("database replica {url} is not reachable"))?;
+
+        info!(url = %url, connections = config.read_max_connections, "database replica ready");
+        pools.push(pool);
+    }
+    Ok(pools)
+}
+
+/// Every connection to an in-memory database gets its own private database, so those
+/// always use a single pool.
+fn split_read_write(config: &DatabaseConfig) -> bool {
+    config.split_read_write && !config.url.contains(":memory:")
+}
+
+fn connect_options(url: &str) -> Result<SqliteConnectOptions> {
+    SqliteConnectOptions::from_str(url).with_context(|| format!("invalid database url {url}"))
+}
+
 async fn ensure_sqlite_path(url: &str) -> Result<()> {
     let Some(sqlite_path) = url.strip_prefix("sqlite://") else {
         return Ok(());
@@ -151,3 +242,102 @@ pub async fn shutdown_signal() {
     }
     info!("shutdown signal received");
 }
+
+/// Fans a shutdown request out to the HTTP server and background tasks, and bounds
+/// how long they may take to drain once it fires.
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+    tasks: Arc<Mutex<JoinSet<()>>>,
+    timeout: Duration,
+}
+
+impl Shutdown {
+    pub fn new(timeout: Duration) -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+            tasks: Arc::new(Mutex::new(JoinSet::new())),
+            timeout,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Request shutdown. Idempotent.
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Resolves once shutdown has been requested. Background tasks should select on
+    /// this and return promptly.
+    pub fn requested(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut receiver = self.sender.subscribe();
+        async move {
+            let _ = receiver.wait_for(|requested| *requested).await;
+        }
+    }
+
+    /// Spawn a background task that is joined, or aborted once the timeout elapses,
+    /// when the server shuts down.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks
+            .lock()
+            .expect("shutdown task set poisoned")
+            .spawn(task);
+    }
+
+    /// Drive `server` until it exits. Once shutdown is requested the server and all
+    /// background tasks share a single `timeout` budget; whatever is still running
+    /// when it elapses is dropped and logged as a forced shutdown.
+    pub async fn run<F, E>(&self, server: F) -> Result<(), E>
+    where
+        F: Future<Output = Result<(), E>>,
+    {
+        tokio::pin!(server);
+
+        let (result, deadline) = tokio::select! {
+            result = &mut server => (result, Instant::now() + self.timeout),
+            _ = self.requested() => {
+                let deadline = Instant::now() + self.timeout;
+                match tokio::time::timeout_at(deadline, &mut server).await {
+                    Ok(result) => (result, deadline),
+                    Err(_) => {
+                        warn!(
+                            timeout_secs = self.timeout.as_secs(),
+                            "graceful shutdown timed out, forcing remaining connections closed"
+                        );
+                        (Ok(()), deadline)
+                    }
+                }
+            }
+        };
+
+        self.trigger();
+        self.join_tasks(deadline).await;
+        result
+    }
+
+    async fn join_tasks(&self, deadline: Instant) {
+        let mut tasks =
+            std::mem::take(&mut *self.tasks.lock().expect("shutdown task set poisoned"));
+
+        let drained = tokio::time::timeout_at(deadline, async {
+            while tasks.join_next().await.is_some() {}
+        })
+        .await;
+
+        if drained.is_err() {
+            warn!(
+                remaining = tasks.len(),
+                "background tasks did not stop before shutdown timeout, aborting"
+            );
+            tasks.abort_all();
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Optional Redis connection that degrades instead of failing when the server goes away.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use redis::{aio::ConnectionManager, Client, RedisError};
+use tokio::{sync::Mutex, time::Instant};
+use tracing::{info, warn};
+
+/// Upper bound on one connection attempt, including the connection manager's own retries.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Shared handle to the Redis server. While Redis is unreachable `connection` returns
+/// `None`, so callers can fall back to in-memory behaviour. Reconnection is attempted
+/// on use, waiting exponentially longer after each failed attempt.
+#[derive(Clone)]
+pub struct RedisHandle {
+    client: Option<Client>,
+    state: Arc<Mutex<ConnectionState>>,
+    available: Arc<AtomicBool>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+struct ConnectionState {
+    connection: Option<ConnectionManager>,
+    failures: u32,
+    next_attempt: Instant,
+}
+
+impl RedisHandle {
+    pub async fn connect(url: &str) -> Self {
+        Self::connect_with_backoff(url, DEFAULT_INITIAL_BACKOFF, DEFAULT_MAX_BACKOFF).await
+    }
+
+    /// Like [`RedisHandle::connect`], with explicit bounds on the delay between
+    /// reconnection attempts.
+    pub async fn connect_with_backoff(
+        url: &str,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        let client = match Client::open(url) {
+            Ok(client) => Some(client),
+            Err(e) => {
+                warn!("failed to create redis client, proceeding without redis: {}", e);
+                None
+            }
+        };
+
+        let handle = Self {
+            client,
+            initial_backoff,
+            max_backoff,
+            ..Self::disabled()
+        };
+        handle.connection().await;
+        handle
+    }
+
+    /// A handle with no server behind it, for setups that run without Redis.
+    pub fn disabled() -> Self {
+        Self {
+            client: None,
+            state: Arc::new(Mutex::new(ConnectionState {
+                connection: None,
+                failures: 0,
+                next_attempt: Instant::now(),
+            })),
+            available: Arc::new(AtomicBool::new(false)),
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Whether the last connection attempt succeeded and no connection error has
+    /// been reported since. Does not block or touch the network.
+    pub fn is_available(&self) -> bool {
+        self.available.load(Ordering::Acquire)
+    }
+
+    /// The current connection, reconnecting first if the backoff delay has passed.
+    /// `None` while Redis is unavailable.
+    pub async fn connection(&self) -> Option<ConnectionManager> {
+        let client = self.client.as_ref()?;
+        let mut state = self.state.lock().await;
+        if let Some(connection) = &state.connection {
+            return Some(connection.clone());
+        }
+        if Instant::now() < state.next_attempt {
+            return None;
+        }
+
+        match tokio::time::timeout(CONNECT_TIMEOUT, ConnectionManager::new(client.clone())).await {
+            Ok(Ok(connection)) => {
+                if state.failures > 0 {
+                    info!(attempts = state.failures + 1, "redis connection re-established");
+                } else {
+                    info!("redis connection established");
+                }
+                state.failures = 0;
+                state.connection = Some(connection.clone());
+                self.available.store(true, Ordering::Release);
+                Some(connection)
+            }
+            result => {
+                let error = match result {
+                    Ok(Err(e)) => e.to_string(),
+                    _ => "connection attempt timed out".to_string(),
+                };
+                state.failures += 1;
+                let delay = self.backoff(state.failures);
+                state.next_attempt = Instant::now() + delay;
+                self.available.store(false, Ordering::Release);
+                warn!(
+                    retry_in_secs = delay.as_secs_f64(),
+                    "failed to connect to redis, proceeding without redis: {}",
+                    error
+                );
+                None
+            }
+        }
+    }
+
+    /// Callers report errors from commands here. Connection-level failures drop the
+    /// connection and mark Redis unavailable until a reconnection succeeds; other
+    /// errors are ignored.
+    pub async fn report_error(&self, error: &RedisError) {
+        let dropped = error.is_io_error()
+            || error.is_connection_dropped()
+            || error.is_connection_refusal()
+            || error.is_timeout();
+        if !dropped {
+            return;
+        }
+
+        let mut state = self.state.lock().await;
+        if state.connection.take().is_some() {
+            warn!("redis connection lost, falling back until it reconnects: {}", error);
+            state.failures = 1;
+            state.next_attempt = Instant::now() + self.initial_backoff;
+        }
+        self.available.store(false, Ordering::Release);
+    }
+
+    fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
@@ -0,0 +1,89 @@
+//! Last-run bookkeeping for periodic background tasks, for health reporting.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Shared record of the periodic tasks running in this process. Tasks register with
+/// the interval they run at and report each run's outcome.
+#[derive(Clone, Default)]
+pub struct TaskRegistry {
+    tasks: Arc<Mutex<BTreeMap<String, TaskEntry>>>,
+}
+
+struct TaskEntry {
+    interval: Duration,
+    registered_at: Instant,
+    last_run: Option<(Instant, SystemTime)>,
+    last_result: Option<Result<String, String>>,
+}
+
+/// A task's state as of [`TaskRegistry::snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskStatus {
+    pub name: String,
+    pub interval: Duration,
+    pub last_run: Option<SystemTime>,
+    /// A short summary of the last run, or why it failed.
+    pub last_result: Option<Result<String, String>>,
+    /// No run has finished within twice the interval, counting from registration
+    /// until the first run.
+    pub stale: bool,
+}
+
+impl TaskRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking `name`, expected to run every `interval`. Registering a name
+    /// again resets its history.
+    pub fn register(&self, name: &str, interval: Duration) {
+        self.lock().insert(
+            name.to_string(),
+            TaskEntry {
+                interval,
+                registered_at: Instant::now(),
+                last_run: None,
+                last_result: None,
+            },
+        );
+    }
+
+    /// Record that a run of `name` just finished with `result`.
+    pub fn record(&self, name: &str, result: Result<String, String>) {
+        match self.lock().get_mut(name) {
+            Some(entry) => {
+                entry.last_run = Some((Instant::now(), SystemTime::now()));
+                entry.last_result = Some(result);
+            }
+            None => warn!(task = name, "run recorded for unregistered background task"),
+        }
+    }
+
+    /// Every registered task, ordered by name.
+    pub fn snapshot(&self) -> Vec<TaskStatus> {
+        self.lock()
+            .iter()
+            .map(|(name, entry)| {
+                let since = entry.last_run.map_or(entry.registered_at, |(at, _)| at);
+                TaskStatus {
+                    name: name.clone(),
+                    interval: entry.interval,
+                    last_run: entry.last_run.map(|(_, at)| at),
+                    last_result: entry.last_result.clone(),
+                    stale: since.elapsed() > entry.interval * 2,
+                }
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, TaskEntry>> {
+        self.tasks.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
@@ -2,7 +2,7 @@ use std::{env, fs, fs::File, path::Path, time::Duration};
 
 use anyhow::{Context, Result};
 use sqlx::Row;
-use switchboard_backend_runtime::{self, BackendServices};
+use switchboard_backend_runtime::{self, BackendServices, RedisHandle, Shutdown, TaskRegistry};
 use switchboard_config::AppConfig;
 use tempfile::TempDir;
 use tokio::{
@@ -99,13 +99,43 @@ async fn initialise_logs_and_ignores_redis_connection_failure() -> Result<()> {
     }
 
     assert!(
-        services.redis_conn.is_none(),
+        !services.redis_available(),
         "redis connection errors should be tolerated"
     );
     drop(services);
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn redis_handle_backs_off_after_dropped_connection() -> Result<()> {
+    // A server that hangs up on every connection behaves like a Redis that went away
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("redis://{}", listener.local_addr()?);
+    let server = tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            drop(stream);
+        }
+    });
+
+    let redis =
+        RedisHandle::connect_with_backoff(&url, Duration::from_secs(60), Duration::from_secs(60))
+            .await;
+    assert!(!redis.is_available(), "a dropped connection should be reported");
+
+    // Within the backoff window no reconnection is attempted, so this returns at once
+    let connection = timeout(Duration::from_millis(500), redis.connection()).await?;
+    assert!(connection.is_none(), "callers should fall back while redis is down");
+
+    let dropped = redis::RedisError::from(std::io::Error::from(
+        std::io::ErrorKind::ConnectionReset,
+    ));
+    redis.report_error(&dropped).await;
+    assert!(!redis.is_available());
+
+    server.abort();
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn prepare_database_creates_sqlite_directory_if_missing() -> Result<()> {
     let temp_dir = TempDir::new()?;
@@ -121,6 +151,117 @@ async fn prepare_database_creates_sqlite_directory_if_missing() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn prepare_database_splits_read_and_write_pools_when_configured() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("runtime/split.db");
+    let mut config = build_config(sqlite_url(&db_path), 4);
+    config.database.split_read_write = true;
+    config.database.read_max_connections = 3;
+
+    let services = initialise(&config).await?;
+
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+        .fetch_one(&services.db_pool)
+        .await?;
+    assert_eq!(journal_mode.to_lowercase(), "wal");
+    assert_eq!(services.db_pool.options().get_max_connections(), 1);
+    assert_eq!(services.db_read_pool.options().get_max_connections(), 3);
+
+    sqlx::query(
+        "INSERT INTO users (public_id, created_at, updated_at) VALUES ('split', 'now', 'now')",
+    )
+    .execute(&services.db_pool)
+    .await?;
+    let visible: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE public_id = 'split'")
+        .fetch_one(&services.db_read_pool)
+        .await?;
+    assert_eq!(visible, 1, "read pool should observe committed writes");
+
+    let write_via_reader = sqlx::query("DELETE FROM users")
+        .execute(&services.db_read_pool)
+        .await;
+    assert!(write_via_reader.is_err(), "read pool should be read-only");
+
+    drop(services);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prepare_database_opens_read_only_replicas() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let primary_path = temp_dir.path().join("runtime/primary.db");
+    let replica_path = temp_dir.path().join("runtime/replica.db");
+    let mut config = build_config(sqlite_url(&primary_path), 4);
+
+    let services = initialise(&config).await?;
+    sqlx::query(
+        "INSERT INTO users (public_id, created_at, updated_at) VALUES ('copied', 'now', 'now')",
+    )
+    .execute(&services.db_pool)
+    .await?;
+    assert!(services.db_replica_pools.is_empty());
+    services.db_pool.close().await;
+    fs::copy(&primary_path, &replica_path)?;
+
+    config.database.replica_urls = vec![sqlite_url(&replica_path)];
+    config.database.read_max_connections = 2;
+    let services = initialise(&config).await?;
+    assert_eq!(services.db_replica_pools.len(), 1);
+    let replica = &services.db_replica_pools[0];
+    assert_eq!(replica.options().get_max_connections(), 2);
+
+    sqlx::query(
+        "INSERT INTO users (public_id, created_at, updated_at) VALUES ('primary', 'now', 'now')",
+    )
+    .execute(&services.db_pool)
+    .await?;
+    let users: Vec<String> = sqlx::query_scalar("SELECT public_id FROM users ORDER BY id")
+        .fetch_all(replica)
+        .await?;
+    assert_eq!(users, vec!["copied".to_string()], "writes should go to the primary only");
+
+    let write_via_replica = sqlx::query("DELETE FROM users").execute(replica).await;
+    assert!(write_via_replica.is_err(), "replica should be read-only");
+
+    drop(services);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn initialise_fails_when_a_replica_is_unreachable() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut config = build_config(sqlite_url(&temp_dir.path().join("primary.db")), 2);
+    config.database.replica_urls = vec![sqlite_url(&temp_dir.path().join("missing.db"))];
+
+    let error = match BackendServices::initialise(&config).await {
+        Ok(_) => anyhow::bail!("a missing replica should fail startup"),
+        Err(error) => error,
+    };
+    assert!(error.to_string().contains("missing.db"), "unexpected error: {error:#}");
+
+    let not_a_database = temp_dir.path().join("garbage.db");
+    fs::write(&not_a_database, b"definitely not sqlite")?;
+    config.database.replica_urls = vec![sqlite_url(&not_a_database)];
+    assert!(BackendServices::initialise(&config).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prepare_database_shares_one_pool_by_default() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("runtime/single.db");
+    let config = build_config(sqlite_url(&db_path), 4);
+
+    let services = initialise(&config).await?;
+    assert_eq!(services.db_read_pool.options().get_max_connections(), 4);
+    assert_eq!(services.db_pool.options().get_max_connections(), 4);
+
+    drop(services);
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn prepare_database_enables_sqlite_foreign_keys() -> Result<()> {
     let temp_dir = TempDir::new()?;
@@ -276,3 +417,70 @@ async fn shutdown_signal_completes_on_ctrl_c_notification() -> Result<()> {
     timeout(Duration::from_secs(2), shutdown_task).await??;
     Ok(())
 }
+
+#[tokio::test]
+async fn shutdown_forces_exit_when_server_exceeds_timeout() -> Result<()> {
+    let shutdown = Shutdown::new(Duration::from_millis(100));
+    let hung_server = std::future::pending::<Result<(), std::io::Error>>();
+
+    shutdown.trigger();
+    timeout(Duration::from_secs(2), shutdown.run(hung_server)).await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_joins_cooperative_tasks_and_aborts_stragglers() -> Result<()> {
+    let shutdown = Shutdown::new(Duration::from_millis(200));
+    let (cooperative_tx, cooperative_rx) = tokio::sync::oneshot::channel::<()>();
+    let (straggler_tx, straggler_rx) = tokio::sync::oneshot::channel::<()>();
+
+    let requested = shutdown.requested();
+    shutdown.spawn(async move {
+        requested.await;
+        let _ = cooperative_tx.send(());
+    });
+    shutdown.spawn(async move {
+        let _guard = straggler_tx;
+        std::future::pending::<()>().await;
+    });
+
+    let server = {
+        let requested = shutdown.requested();
+        async move {
+            requested.await;
+            Ok::<(), std::io::Error>(())
+        }
+    };
+    shutdown.trigger();
+    timeout(Duration::from_secs(2), shutdown.run(server)).await??;
+
+    assert!(cooperative_rx.await.is_ok(), "cooperative task should finish");
+    assert!(
+        straggler_rx.await.is_err(),
+        "straggler should be aborted, dropping its sender"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn task_registry_tracks_last_run_and_staleness() -> Result<()> {
+    let registry = TaskRegistry::new();
+    registry.register("sweeper", Duration::from_millis(20));
+    let status = registry.snapshot().remove(0);
+    assert_eq!(status.name, "sweeper");
+    assert_eq!(status.last_run, None);
+    assert!(!status.stale, "a new task is not stale yet");
+
+    sleep(Duration::from_millis(60)).await;
+    assert!(registry.snapshot()[0].stale, "no run within twice the interval");
+
+    registry.record("sweeper", Ok("purged 3".to_string()));
+    let status = registry.snapshot().remove(0);
+    assert!(status.last_run.is_some());
+    assert_eq!(status.last_result, Some(Ok("purged 3".to_string())));
+    assert!(!status.stale, "a run just finished");
+
+    registry.record("unknown", Err("ignored".to_string()));
+    assert_eq!(registry.snapshot().len(), 1);
+    Ok(())
+}
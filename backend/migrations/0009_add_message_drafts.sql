@@ -0,0 +1,10 @@
+-- Unsent message text, one draft per user per chat. Drafts are private to their author.
+CREATE TABLE IF NOT EXISTS message_drafts (
+    user_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, chat_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Optional per-chat retention: messages older than this many days are purged by the
+-- retention sweeper. NULL keeps messages forever.
+ALTER TABLE chats ADD COLUMN message_retention_days INTEGER;
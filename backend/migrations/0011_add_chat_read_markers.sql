@@ -0,0 +1,11 @@
+-- How far each member has read in a chat. Unread counts are derived from the marker.
+CREATE TABLE IF NOT EXISTS chat_read_markers (
+    user_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    last_read_message_id INTEGER,
+    read_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, chat_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE,
+    FOREIGN KEY (last_read_message_id) REFERENCES messages(id) ON DELETE SET NULL
+);
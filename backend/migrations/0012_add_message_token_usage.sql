@@ -0,0 +1,3 @@
+-- Provider token usage for assistant replies; NULL when the provider did not report it.
+ALTER TABLE messages ADD COLUMN prompt_tokens INTEGER;
+ALTER TABLE messages ADD COLUMN completion_tokens INTEGER;
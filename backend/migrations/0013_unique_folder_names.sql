@@ -0,0 +1,18 @@
+-- Folder names are unique per user within a parent. Existing duplicates keep their
+-- oldest copy's name; later copies get their row id appended so the index can build.
+UPDATE folders
+SET name = name || ' (' || id || ')'
+WHERE EXISTS (
+    SELECT 1 FROM folders earlier
+    WHERE earlier.user_id = folders.user_id
+      AND earlier.parent_id IS folders.parent_id
+      AND earlier.name = folders.name
+      AND earlier.id < folders.id
+);
+
+-- NULLs never collide in a unique index, so top-level folders need their own.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_folders_user_parent_name
+    ON folders (user_id, parent_id, name) WHERE parent_id IS NOT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_folders_user_root_name
+    ON folders (user_id, name) WHERE parent_id IS NULL;
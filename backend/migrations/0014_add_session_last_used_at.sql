@@ -0,0 +1,2 @@
+-- When a session last authenticated a request; NULL means not since it was issued.
+ALTER TABLE sessions ADD COLUMN last_used_at TEXT;
@@ -0,0 +1,3 @@
+-- Optimistic concurrency: bumped on every edit so clients can detect lost updates.
+ALTER TABLE chats ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE messages ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
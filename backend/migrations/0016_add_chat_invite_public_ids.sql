@@ -0,0 +1,4 @@
+-- Invites are addressed by public id in the API; the integer id stays internal.
+ALTER TABLE chat_invites ADD COLUMN public_id TEXT;
+UPDATE chat_invites SET public_id = lower(hex(randomblob(12))) WHERE public_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_chat_invites_public_id ON chat_invites (public_id);
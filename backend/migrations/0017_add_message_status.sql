@@ -0,0 +1,5 @@
+-- Assistant replies are saved while they stream in; `status` tracks whether one
+-- finished or was cut off by a restart.
+ALTER TABLE messages ADD COLUMN status TEXT NOT NULL DEFAULT 'complete'
+    CHECK (status IN ('streaming', 'complete', 'interrupted'));
+CREATE INDEX IF NOT EXISTS idx_messages_streaming ON messages (status) WHERE status = 'streaming';
@@ -0,0 +1,13 @@
+-- API key scopes became per resource. Keys created with the old `read` / `write`
+-- scopes keep the same reach: every `:read` or every `:write` scope respectively.
+UPDATE api_keys
+SET scopes = REPLACE(
+    REPLACE(
+        scopes,
+        'read',
+        'chats:read,messages:read,folders:read,notifications:read,permissions:read,models:read'
+    ),
+    'write',
+    'chats:write,messages:write,folders:write,notifications:write,permissions:write'
+)
+WHERE scopes NOT LIKE '%:%';
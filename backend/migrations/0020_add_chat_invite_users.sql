@@ -0,0 +1,43 @@
+-- Invites can name an existing user instead of an email address. Rebuild the table
+-- so `invitee_email` may be NULL; exactly one of the two invitee columns is set.
+
+PRAGMA foreign_keys = OFF;
+
+CREATE TABLE IF NOT EXISTS chat_invites_tmp (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    public_id TEXT,
+    chat_id INTEGER NOT NULL,
+    inviter_id INTEGER NOT NULL,
+    invitee_email TEXT,
+    invitee_user_id INTEGER,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'accepted', 'rejected', 'expired')),
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE,
+    FOREIGN KEY (inviter_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (invitee_user_id) REFERENCES users(id) ON DELETE CASCADE,
+    CHECK ((invitee_email IS NULL) <> (invitee_user_id IS NULL))
+);
+
+INSERT INTO chat_invites_tmp (
+    id,
+    public_id,
+    chat_id,
+    inviter_id,
+    invitee_email,
+    status,
+    created_at,
+    updated_at
+)
+SELECT id, public_id, chat_id, inviter_id, invitee_email, status, created_at, updated_at
+FROM chat_invites;
+
+DROP TABLE chat_invites;
+ALTER TABLE chat_invites_tmp RENAME TO chat_invites;
+
+CREATE INDEX IF NOT EXISTS idx_chat_invites_chat_id ON chat_invites (chat_id);
+CREATE INDEX IF NOT EXISTS idx_chat_invites_status ON chat_invites (status);
+CREATE INDEX IF NOT EXISTS idx_chat_invites_invitee_user_id ON chat_invites (invitee_user_id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_chat_invites_public_id ON chat_invites (public_id);
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,2 @@
+-- USD cost of an assistant reply, priced when it finished; NULL when the model's prices were unknown.
+ALTER TABLE messages ADD COLUMN estimated_cost REAL;
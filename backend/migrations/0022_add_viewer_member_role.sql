@@ -0,0 +1,26 @@
+-- Chats can have read-only `viewer` members. Rebuild the table to widen the role check.
+
+PRAGMA foreign_keys = OFF;
+
+CREATE TABLE IF NOT EXISTS chat_members_tmp (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'admin', 'member', 'viewer')),
+    joined_at TEXT NOT NULL,
+    FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    UNIQUE(chat_id, user_id)
+);
+
+INSERT INTO chat_members_tmp (id, chat_id, user_id, role, joined_at)
+SELECT id, chat_id, user_id, role, joined_at
+FROM chat_members;
+
+DROP TABLE chat_members;
+ALTER TABLE chat_members_tmp RENAME TO chat_members;
+
+CREATE INDEX IF NOT EXISTS idx_chat_members_chat_id ON chat_members (chat_id);
+CREATE INDEX IF NOT EXISTS idx_chat_members_user_id ON chat_members (user_id);
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,12 @@
+-- User messages are `sent` once stored, and assistant replies whose completion failed
+-- are `error`. SQLite can't widen a CHECK in place, and rebuilding `messages` would
+-- cascade into the tables referencing it, so swap in a new status column instead.
+DROP INDEX IF EXISTS idx_messages_streaming;
+
+ALTER TABLE messages ADD COLUMN new_status TEXT NOT NULL DEFAULT 'complete'
+    CHECK (new_status IN ('sent', 'streaming', 'complete', 'interrupted', 'error'));
+UPDATE messages SET new_status = CASE WHEN role = 'user' THEN 'sent' ELSE status END;
+ALTER TABLE messages DROP COLUMN status;
+ALTER TABLE messages RENAME COLUMN new_status TO status;
+
+CREATE INDEX IF NOT EXISTS idx_messages_streaming ON messages (status) WHERE status = 'streaming';
@@ -0,0 +1,5 @@
+-- The chat a notification is about, so it can be marked read along with the chat's others.
+-- NULL for notifications that predate this or concern no chat.
+ALTER TABLE notifications ADD COLUMN chat_id INTEGER REFERENCES chats(id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_chat ON notifications (user_id, chat_id);
@@ -0,0 +1,3 @@
+-- A regenerated assistant reply points at the reply that replaced it. The old reply is
+-- kept so clients can show earlier versions.
+ALTER TABLE messages ADD COLUMN superseded_by INTEGER REFERENCES messages(id) ON DELETE SET NULL;
@@ -0,0 +1,3 @@
+-- Model replies use when a message does not name any; NULL falls back to the
+-- orchestrator's default model.
+ALTER TABLE chats ADD COLUMN default_model TEXT;
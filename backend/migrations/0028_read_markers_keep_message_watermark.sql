@@ -0,0 +1,24 @@
+-- A read marker is a watermark, not a reference: deleting the last-read message must
+-- not reset it, or the whole chat would count as unread again. Rebuild the table
+-- without the foreign key on last_read_message_id.
+
+PRAGMA foreign_keys = OFF;
+
+CREATE TABLE IF NOT EXISTS chat_read_markers_tmp (
+    user_id INTEGER NOT NULL,
+    chat_id INTEGER NOT NULL,
+    last_read_message_id INTEGER,
+    read_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, chat_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE
+);
+
+INSERT INTO chat_read_markers_tmp (user_id, chat_id, last_read_message_id, read_at)
+SELECT user_id, chat_id, last_read_message_id, read_at
+FROM chat_read_markers;
+
+DROP TABLE chat_read_markers;
+ALTER TABLE chat_read_markers_tmp RENAME TO chat_read_markers;
+
+PRAGMA foreign_keys = ON;
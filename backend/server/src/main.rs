@@ -1,12 +1,18 @@
+use std::{future::IntoFuture, net::SocketAddr, sync::Arc, time::Duration};
+
 use anyhow::Context;
 use clap::{Parser, Subcommand};
 use sqlx::Row;
-use switchboard_backend_api::{build_router, AppState};
-use switchboard_backend_runtime::{telemetry, BackendServices};
+use switchboard_backend_api::{
+    build_router,
+    event_bus::{self, RedisEventBus},
+    retention, streaming, AppState,
+};
+use switchboard_backend_runtime::{telemetry, BackendServices, Shutdown};
 use switchboard_config::load as load_config;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::net::TcpListener;
-use tracing::info;
+use tracing::{info, warn};
 
 #[derive(Parser)]
 #[command(name = "switchboard-backend")]
@@ -54,13 +60,35 @@ async fn run_server() -> anyhow::Result<()> {
         .await
         .context("failed to initialise backend services")?;
 
-    let state = AppState::new(
+    let stale_after = Duration::from_secs(config.chat.stream_stale_after_secs);
+    match streaming::mark_interrupted_messages(&services.db_pool, stale_after).await {
+        Ok(0) => {}
+        Ok(interrupted) => warn!(interrupted, "marked replies cut off by the last shutdown"),
+        Err(e) => warn!("failed to mark interrupted replies: {}", e),
+    }
+
+    let redis_conn = services.redis.connection().await;
+    let mut state = AppState::new(
         services.db_pool.clone(),
         services.orchestrator.clone(),
         services.authenticator.clone(),
-        services.redis_conn.clone(),
-    );
-    let app = build_router(state);
+        redis_conn.clone(),
+    )
+    .with_read_pool(services.db_read_pool.clone())
+    .with_replica_pools(services.db_replica_pools.clone())
+    .with_config(config.clone());
+
+    if config.redis.pubsub_events {
+        match redis_conn.map(|conn| RedisEventBus::new(&config.redis.url, conn)) {
+            Some(Ok(bus)) => {
+                state = state.with_event_bus(Arc::new(bus));
+                info!("websocket events fan out through redis pub/sub");
+            }
+            Some(Err(e)) => warn!("failed to set up redis event bus, events stay local: {}", e),
+            None => warn!("redis is unavailable, websocket events stay on this instance"),
+        }
+    }
+    let app = build_router(state.clone());
 
     let address = format!("{}:{}", config.http.address, config.http.port);
     let listener = TcpListener::bind(&address)
@@ -69,8 +97,30 @@ async fn run_server() -> anyhow::Result<()> {
 
     info!(%address, "http server listening");
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(switchboard_backend_runtime::shutdown_signal())
+    let shutdown = Shutdown::new(Duration::from_secs(config.http.shutdown_timeout_secs));
+    let signal = shutdown.clone();
+    tokio::spawn(async move {
+        switchboard_backend_runtime::shutdown_signal().await;
+        signal.trigger();
+    });
+
+    if state.event_bus().is_some() {
+        shutdown.spawn(event_bus::run_event_bus(state.clone(), shutdown.requested()));
+    }
+
+    if config.chat.retention_sweep_interval_secs > 0 {
+        shutdown.spawn(retention::run_retention_sweeper(
+            state,
+            Duration::from_secs(config.chat.retention_sweep_interval_secs),
+            shutdown.requested(),
+        ));
+    }
+
+    // Peer addresses feed client IP resolution for audit entries
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let server = axum::serve(listener, app).with_graceful_shutdown(shutdown.requested());
+    shutdown
+        .run(server.into_future())
         .await
         .context("http server error")?;
 
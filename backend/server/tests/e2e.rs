@@ -293,6 +293,115 @@ async fn folder_crud_flow() {
     );
 }
 
+#[tokio::test]
+async fn folder_names_are_unique_within_a_parent() {
+    let app = TestApp::new().await;
+
+    let create = |name: &'static str, parent_id: Value| {
+        app.authed_request(
+            Method::POST,
+            "/api/folders",
+            Some(json!({ "name": name, "color": Value::Null, "parent_id": parent_id })),
+        )
+    };
+    let public_id = |response: &TestResponse| {
+        response
+            .json
+            .get("folder")
+            .and_then(|folder| folder.get("public_id"))
+            .and_then(Value::as_str)
+            .expect("folder id")
+            .to_string()
+    };
+
+    let projects = create("Projects", Value::Null).await;
+    assert_eq!(projects.status, StatusCode::OK);
+    let projects_id = public_id(&projects);
+
+    let duplicate = create("Projects", Value::Null).await;
+    assert_eq!(duplicate.status, StatusCode::CONFLICT);
+
+    // The same name is fine under a different parent
+    let nested = create("Projects", json!(projects_id)).await;
+    assert_eq!(nested.status, StatusCode::OK);
+
+    let archive = create("Archive", Value::Null).await;
+    assert_eq!(archive.status, StatusCode::OK);
+    let archive_id = public_id(&archive);
+
+    let rename = app
+        .authed_request(
+            Method::PUT,
+            &format!("/api/folders/{}", archive_id),
+            Some(json!({ "name": "Projects" })),
+        )
+        .await;
+    assert_eq!(rename.status, StatusCode::CONFLICT);
+
+    let unchanged = app
+        .authed_request(Method::GET, &format!("/api/folders/{}", archive_id), None)
+        .await;
+    assert_eq!(
+        unchanged
+            .json
+            .get("folder")
+            .and_then(|folder| folder.get("name"))
+            .and_then(Value::as_str),
+        Some("Archive")
+    );
+}
+
+#[tokio::test]
+async fn folder_reparenting_rejects_cycles() {
+    let app = TestApp::new().await;
+    let app = &app;
+
+    let create = |name: &'static str, parent_id: Value| async move {
+        let response = app
+            .authed_request(
+                Method::POST,
+                "/api/folders",
+                Some(json!({ "name": name, "color": Value::Null, "parent_id": parent_id })),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        response
+            .json
+            .get("folder")
+            .and_then(|folder| folder.get("public_id"))
+            .and_then(Value::as_str)
+            .expect("folder id")
+            .to_string()
+    };
+    let reparent = |folder_id: String, parent_id: Value| async move {
+        app.authed_request(
+            Method::PUT,
+            &format!("/api/folders/{}", folder_id),
+            Some(json!({ "parent_id": parent_id })),
+        )
+        .await
+    };
+
+    let grandparent = create("Work", Value::Null).await;
+    let parent = create("Clients", json!(grandparent)).await;
+    let child = create("Acme", json!(parent)).await;
+
+    let response = reparent(grandparent.clone(), json!(child)).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.text.contains("own subfolders"), "{}", response.text);
+
+    let response = reparent(grandparent.clone(), json!(grandparent)).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.text.contains("own parent"), "{}", response.text);
+
+    // Moving a leaf up the tree, or to the top level, is fine
+    let response = reparent(child.clone(), json!(grandparent)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = reparent(child, Value::Null).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json["folder"]["parent_id"], Value::Null);
+}
+
 #[tokio::test]
 async fn chat_creation_persists_messages() {
     let app = TestApp::new().await;
@@ -382,6 +491,57 @@ async fn chat_creation_persists_messages() {
     );
 }
 
+#[tokio::test]
+async fn deleting_a_folder_detaches_its_chats() {
+    let app = TestApp::new().await;
+    let app = &app;
+
+    let create_folder = |name: &'static str, parent_id: Value| async move {
+        let response = app
+            .authed_request(
+                Method::POST,
+                "/api/folders",
+                Some(json!({ "name": name, "color": Value::Null, "parent_id": parent_id })),
+            )
+            .await;
+        response.json["folder"]["public_id"].as_str().expect("folder id").to_string()
+    };
+    let create_chat = |title: &'static str, folder_id: String| async move {
+        let response = app
+            .authed_request(
+                Method::POST,
+                "/api/chats",
+                Some(json!({ "title": title, "folder_id": folder_id })),
+            )
+            .await;
+        response.json["chat"]["public_id"].as_str().expect("chat id").to_string()
+    };
+
+    let inbox = create_folder("Inbox", Value::Null).await;
+    let later = create_folder("Later", json!(inbox)).await;
+    let filed_chat = create_chat("Filed", inbox.clone()).await;
+    let nested_chat = create_chat("Nested", later.clone()).await;
+
+    let response = app
+        .authed_request(Method::DELETE, &format!("/api/folders/{}", inbox), None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    for chat_id in [filed_chat, nested_chat] {
+        let detail = app
+            .authed_request(Method::GET, &format!("/api/chats/{}", chat_id), None)
+            .await;
+        assert_eq!(detail.status, StatusCode::OK);
+        assert_eq!(detail.json["chat"]["folder_id"], Value::Null);
+    }
+
+    // Subfolders go with their parent by default
+    let response = app
+        .authed_request(Method::GET, &format!("/api/folders/{}", later), None)
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
 #[cfg(debug_assertions)]
 #[tokio::test]
 async fn dev_token_endpoint_issues_session() {
@@ -603,9 +763,10 @@ async fn group_invite_flow_allows_acceptance() {
         Some("pending")
     );
     let invite_id = invite
-        .get("id")
-        .and_then(Value::as_i64)
-        .expect("invite id for acceptance");
+        .get("public_id")
+        .and_then(Value::as_str)
+        .expect("invite public id for acceptance")
+        .to_string();
 
     let invited_user_token = "invite-token";
     let invited_user_id = app